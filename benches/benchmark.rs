@@ -100,6 +100,7 @@ pub fn push_pop_small_parcoll_spmc_bounded(c: &mut Criterion) {
     );
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 pub fn push_pop_small_parcoll_spmc_unbounded(c: &mut Criterion) {
     push_pop::<parcoll::spmc::CachePaddedSPMCUnboundedProducer<_>, 8>(
         "small-parcoll_spmc_unbounded",
@@ -115,6 +116,7 @@ pub fn push_pop_small_parcoll_spsc_bounded(c: &mut Criterion) {
     );
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 pub fn push_pop_small_parcoll_spsc_unbounded(c: &mut Criterion) {
     push_pop_spsc::<_, _, _, 8>(
         "small-parcoll_spsc_unbounded",
@@ -164,6 +166,7 @@ pub fn push_pop_large_parcoll_spmc_bounded(c: &mut Criterion) {
     );
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 pub fn push_pop_large_parcoll_spmc_unbounded(c: &mut Criterion) {
     push_pop::<parcoll::spmc::CachePaddedSPMCUnboundedProducer<_>, 256>(
         "large-parcoll_spmc_unbounded",
@@ -179,6 +182,7 @@ pub fn push_pop_large_parcoll_spsc_bounded(c: &mut Criterion) {
     );
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 pub fn push_pop_large_parcoll_spsc_unbounded(c: &mut Criterion) {
     push_pop_spsc::<_, _, _, 256>(
         "large-parcoll_spsc_unbounded",
@@ -246,6 +250,7 @@ pub fn push_pop_steal_parcoll_spmc_bounded(c: &mut Criterion) {
     );
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 pub fn push_pop_steal_parcoll_spmc_unbounded(c: &mut Criterion) {
     push_pop_steal::<u64, parcoll::spmc::CachePaddedSPMCUnboundedProducer<_>>(
         "parcoll_spmc_unbounded batch",
@@ -253,6 +258,7 @@ pub fn push_pop_steal_parcoll_spmc_unbounded(c: &mut Criterion) {
     );
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 criterion_group!(
     push_pop_benchmark,
     push_pop_small_st3_fifo,
@@ -277,6 +283,29 @@ criterion_group!(
     push_pop_large_parcoll_spsc_unbounded
 );
 
+// Without the unbounded queues, only the bounded ones are benchmarked.
+#[cfg(feature = "disable_unbounded")]
+criterion_group!(
+    push_pop_benchmark,
+    push_pop_small_st3_fifo,
+    push_pop_small_st3_lifo,
+    push_pop_small_crossbeam_fifo,
+    push_pop_small_crossbeam_lifo,
+    push_pop_small_crossbeam_array_queue,
+    push_pop_small_crossbeam_seg_queue,
+    push_pop_small_parcoll_spmc_bounded,
+    push_pop_small_parcoll_spsc_bounded,
+    push_pop_large_st3_fifo,
+    push_pop_large_st3_lifo,
+    push_pop_large_crossbeam_fifo,
+    push_pop_large_crossbeam_lifo,
+    push_pop_large_crossbeam_array_queue,
+    push_pop_large_crossbeam_seg_queue,
+    push_pop_large_parcoll_spmc_bounded,
+    push_pop_large_parcoll_spsc_bounded
+);
+
+#[cfg(not(feature = "disable_unbounded"))]
 criterion_group!(
     push_pop_steal_benchmark,
     push_pop_small_st3_fifo,
@@ -307,4 +336,32 @@ criterion_group!(
     push_pop_steal_parcoll_spmc_unbounded
 );
 
+#[cfg(feature = "disable_unbounded")]
+criterion_group!(
+    push_pop_steal_benchmark,
+    push_pop_small_st3_fifo,
+    push_pop_small_st3_lifo,
+    push_pop_small_crossbeam_fifo,
+    push_pop_small_crossbeam_lifo,
+    push_pop_small_crossbeam_array_queue,
+    push_pop_small_crossbeam_seg_queue,
+    push_pop_small_parcoll_spmc_bounded,
+    push_pop_small_parcoll_spsc_bounded,
+    push_pop_large_st3_fifo,
+    push_pop_large_st3_lifo,
+    push_pop_large_crossbeam_fifo,
+    push_pop_large_crossbeam_lifo,
+    push_pop_large_crossbeam_array_queue,
+    push_pop_large_crossbeam_seg_queue,
+    push_pop_large_parcoll_spmc_bounded,
+    push_pop_large_parcoll_spsc_bounded,
+    push_pop_steal_st3_fifo,
+    push_pop_steal_st3_lifo,
+    push_pop_steal_crossbeam_fifo,
+    push_pop_steal_crossbeam_lifo,
+    push_pop_steal_crossbeam_array_queue,
+    push_pop_steal_crossbeam_seg_queue,
+    push_pop_steal_parcoll_spmc_bounded
+);
+
 criterion_main!(push_pop_benchmark, push_pop_steal_benchmark);
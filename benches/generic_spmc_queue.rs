@@ -1,7 +1,6 @@
 //! Generic traits for queue benchmarking.
 
 use parcoll::{spmc, LightArc};
-use st3;
 
 /// Error returned on stealing failure.
 pub enum GenericStealError {
@@ -309,6 +308,7 @@ impl<T: Send, const CAPACITY: usize> GenericStealer<T>
     }
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 impl<T: Send> GenericWorker<T> for spmc::CachePaddedSPMCUnboundedProducer<T> {
     type S = spmc::CachePaddedSPMCUnboundedConsumer<T>;
 
@@ -331,6 +331,7 @@ impl<T: Send> GenericWorker<T> for spmc::CachePaddedSPMCUnboundedProducer<T> {
     }
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 impl<T: Send> GenericStealer<T> for spmc::CachePaddedSPMCUnboundedConsumer<T> {
     type W = spmc::CachePaddedSPMCUnboundedProducer<T>;
 
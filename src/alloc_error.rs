@@ -0,0 +1,83 @@
+//! This module provides the [`AllocError`] of growable structures
+//! and helpers to allocate their buffers.
+use std::alloc::{alloc, handle_alloc_error, Layout};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// The error returned when a queue fails to allocate its buffer.
+///
+/// Fallible methods (like `try_reserve` or `try_new`) return it instead of aborting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocError {
+    /// The requested capacity exceeds the maximum capacity of the structure
+    /// or the maximum size of an allocation.
+    CapacityOverflow,
+    /// The allocator failed to allocate the memory with the layout.
+    AllocFailed(Layout),
+}
+
+impl AllocError {
+    /// Handles the error on the infallible paths:
+    /// it panics on the capacity overflow
+    /// and calls [`handle_alloc_error`] if the allocator failed.
+    #[cold]
+    #[track_caller]
+    pub(crate) fn handle(self) -> ! {
+        match self {
+            Self::CapacityOverflow => panic!("capacity overflow"),
+            Self::AllocFailed(layout) => handle_alloc_error(layout),
+        }
+    }
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityOverflow => f.write_str("capacity overflow"),
+            Self::AllocFailed(layout) => write!(
+                f,
+                "memory allocation of {} bytes with alignment {} failed",
+                layout.size(),
+                layout.align()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// Allocates an uninitialized array of `capacity` values.
+///
+/// It returns a dangling pointer for zero-sized arrays,
+/// so they should not be deallocated.
+pub(crate) fn try_alloc_array<T>(capacity: usize) -> Result<NonNull<T>, AllocError> {
+    let layout = Layout::array::<T>(capacity).map_err(|_| AllocError::CapacityOverflow)?;
+
+    if layout.size() == 0 {
+        return Ok(NonNull::dangling());
+    }
+
+    NonNull::new(unsafe { alloc(layout) }.cast()).ok_or(AllocError::AllocFailed(layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_array() {
+        assert_eq!(
+            try_alloc_array::<u64>(usize::MAX),
+            Err(AllocError::CapacityOverflow)
+        );
+        assert_eq!(try_alloc_array::<()>(usize::MAX), Ok(NonNull::dangling()));
+        assert_eq!(
+            AllocError::AllocFailed(Layout::new::<u64>()).to_string(),
+            "memory allocation of 8 bytes with alignment 8 failed"
+        );
+
+        let ptr = try_alloc_array::<u64>(4).unwrap();
+
+        unsafe { std::alloc::dealloc(ptr.as_ptr().cast(), Layout::array::<u64>(4).unwrap()) };
+    }
+}
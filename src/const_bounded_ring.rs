@@ -0,0 +1,271 @@
+//! This module provides the [`ConstBoundedRing`] trait
+//! that shares the ring buffer logic of the const bounded queues.
+#![allow(
+    clippy::cast_possible_truncation,
+    reason = "LongNumber should be synonymous to usize"
+)]
+use crate::number_types::{LongAtomic, LongNumber};
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::{ptr, slice};
+
+/// The ring buffer of a const bounded queue.
+///
+/// It reads from the head and writes to the tail.
+/// Only one producer can write to the tail,
+/// but the head can be changed by the producer and the consumers.
+///
+/// It is implemented for the SPSC and SPMC const bounded queues
+/// and provides the logic they share, such as the overflow handling.
+pub(crate) trait ConstBoundedRing<T> {
+    /// The capacity of the ring buffer.
+    const CAPACITY: usize;
+
+    /// Indicates how many elements we are taking from the local queue.
+    ///
+    /// This is one less than the number of values pushed to the global
+    /// queue (or any other `SyncBatchReceiver`) as we are also inserting the `value` argument.
+    const NUM_VALUES_TAKEN: LongNumber = Self::CAPACITY as LongNumber / 2;
+
+    /// Returns the head of the ring buffer.
+    fn head(&self) -> &LongAtomic;
+
+    /// Returns the tail of the ring buffer.
+    fn tail(&self) -> &LongAtomic;
+
+    /// Returns a mutable pointer to the buffer.
+    fn buffer_mut_thin_ptr(&self) -> *mut MaybeUninit<T>;
+
+    /// Returns a pointer to the buffer.
+    fn buffer_thin_ptr(&self) -> *const MaybeUninit<T> {
+        self.buffer_mut_thin_ptr().cast_const()
+    }
+
+    /// Returns the number of elements in the queue.
+    #[inline]
+    fn len(head: LongNumber, tail: LongNumber) -> usize {
+        tail.wrapping_sub(head) as usize
+    }
+
+    /// Pushes a slice into the queue. Returns a new tail (not index).
+    fn copy_slice(buffer_ptr: *mut T, start_tail: LongNumber, slice: &[T]) -> LongNumber {
+        let tail_idx = start_tail as usize % Self::CAPACITY;
+
+        if tail_idx + slice.len() <= Self::CAPACITY {
+            unsafe {
+                ptr::copy_nonoverlapping(slice.as_ptr(), buffer_ptr.add(tail_idx), slice.len());
+            };
+        } else {
+            let right = Self::CAPACITY - tail_idx;
+
+            unsafe {
+                ptr::copy_nonoverlapping(slice.as_ptr(), buffer_ptr.add(tail_idx), right);
+                ptr::copy_nonoverlapping(
+                    slice.as_ptr().add(right),
+                    buffer_ptr,
+                    slice.len() - right,
+                );
+            }
+        }
+
+        start_tail.wrapping_add(slice.len() as LongNumber)
+    }
+
+    /// Returns the first `n` values of the queue starting from the `head` as two slices;
+    /// the first slice contains older values than the last one.
+    fn oldest_values(&self, head: LongNumber, n: usize) -> (&[T], &[T]) {
+        debug_assert!(n <= Self::CAPACITY);
+
+        let head_idx = head as usize % Self::CAPACITY;
+        let right = n.min(Self::CAPACITY - head_idx);
+
+        unsafe {
+            (
+                slice::from_raw_parts(self.buffer_thin_ptr().add(head_idx).cast(), right),
+                slice::from_raw_parts(self.buffer_thin_ptr().cast(), n - right),
+            )
+        }
+    }
+
+    /// Returns `n` values that have been taken from the `taken_head` back to the head of the queue,
+    /// so the queue keeps its FIFO order.
+    ///
+    /// It is used when the [`SyncBatchReceiver`](crate::sync_batch_receiver::SyncBatchReceiver)
+    /// rejects the values.
+    ///
+    /// The caller should be the only producer, and the `tail` should be the tail of the queue.
+    #[cold]
+    fn return_taken_values(&self, tail: LongNumber, taken_head: LongNumber, n: usize) {
+        let taken_tail = taken_head.wrapping_add(n as LongNumber);
+
+        // If no consumer has popped since the values were taken, they are still in their slots.
+        if self
+            .head()
+            .compare_exchange(taken_tail, taken_head, Release, Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+
+        // Otherwise, we can't move the head back, because the consumers copy the values
+        // before their CAS, and a consumer could read the overwritten slots with a head
+        // it has loaded before (ABA).
+        // Instead, we take the rest values too and push them after the tail
+        // right after the taken values, so the head is only moved forward.
+        let mut head = self.head().load(Acquire);
+
+        while let Err(new_head) = self
+            .head()
+            .compare_exchange_weak(head, tail, Release, Acquire)
+        {
+            head = new_head;
+        }
+
+        let rest = Self::len(head, tail);
+        let sources = (0..n as LongNumber)
+            .map(|i| taken_head.wrapping_add(i))
+            .chain((0..rest as LongNumber).map(|i| head.wrapping_add(i)));
+
+        // Every value is copied to the slot that is free or whose value has already been copied,
+        // because the values are not further than the capacity from their new positions.
+        for (i, src) in (0..).zip(sources) {
+            unsafe {
+                ptr::copy(
+                    self.buffer_thin_ptr().add(src as usize % Self::CAPACITY),
+                    self.buffer_mut_thin_ptr()
+                        .add(tail.wrapping_add(i) as usize % Self::CAPACITY),
+                    1,
+                );
+            }
+        }
+
+        self.tail()
+            .store(tail.wrapping_add((n + rest) as LongNumber), Release);
+    }
+
+    /// Likely moves `n` oldest values of the queue and one value to the
+    /// [`SyncBatchReceiver`](crate::sync_batch_receiver::SyncBatchReceiver)
+    /// with the `push` function.
+    ///
+    /// If the `push` rejects the values, it returns the oldest values to the queue
+    /// and returns the error.
+    ///
+    /// The caller should be the only producer.
+    #[inline(never)]
+    #[cold]
+    fn handle_overflow_one(
+        &self,
+        tail: LongNumber,
+        mut head: LongNumber,
+        n: usize,
+        push: impl FnOnce(&[T], &[T], T) -> Result<(), T>,
+        value: T,
+    ) -> Result<(), T> {
+        debug_assert!(tail == head.wrapping_add(Self::CAPACITY as LongNumber) && tail > head);
+
+        loop {
+            let n = n.min(Self::len(head, tail));
+            let (first, last) = self.oldest_values(head, n);
+
+            // We haven't read the value yet, so we can use `compare_exchange_weak`.
+            //If it fails, we calculate two slices and try again, it is not a performance issue.
+            let res = self.head().compare_exchange_weak(
+                head,
+                head.wrapping_add(n as LongNumber),
+                Release,
+                Acquire,
+            );
+
+            match res {
+                Ok(_) => {}
+                Err(new_head) => {
+                    head = new_head;
+
+                    if Self::len(head, tail) < Self::CAPACITY {
+                        // Another thread concurrently
+                        // popped from the queue.
+                        // Because we are the one producer,
+                        // we can just insert the value (it can't become full before we return).
+
+                        unsafe {
+                            self.buffer_mut_thin_ptr()
+                                .add(tail as usize % Self::CAPACITY)
+                                .write(MaybeUninit::new(value));
+                        }
+
+                        self.tail().store(tail.wrapping_add(1), Release);
+
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+            }
+
+            return push(first, last, value).inspect_err(|_| {
+                self.return_taken_values(tail, head, n);
+            });
+        }
+    }
+
+    /// Likely moves `n` oldest values of the queue and many values to the
+    /// [`SyncBatchReceiver`](crate::sync_batch_receiver::SyncBatchReceiver)
+    /// with the `push` function.
+    ///
+    /// If the `push` rejects the values, it returns the oldest values to the queue
+    /// and returns the error.
+    ///
+    /// The caller should be the only producer.
+    #[inline(never)]
+    #[cold]
+    fn handle_overflow_many(
+        &self,
+        tail: LongNumber,
+        mut head: LongNumber,
+        n: usize,
+        push: impl FnOnce(&[T], &[T], &[T]) -> Result<(), ()>,
+        slice: &[T],
+    ) -> Result<(), ()> {
+        debug_assert!(Self::len(head, tail) + slice.len() > Self::CAPACITY);
+
+        loop {
+            let n = n.min(Self::len(head, tail));
+            let (first, last) = self.oldest_values(head, n);
+
+            // We haven't read the value yet, so we can use `compare_exchange_weak`.
+            //If it fails, we calculate two slices and try again, it is not a performance issue.
+            let res = self.head().compare_exchange_weak(
+                head,
+                head.wrapping_add(n as LongNumber),
+                Release,
+                Acquire,
+            );
+
+            match res {
+                Ok(_) => {}
+                Err(new_head) => {
+                    head = new_head;
+
+                    if Self::len(head, tail) + slice.len() <= Self::CAPACITY {
+                        // Another thread concurrently
+                        // popped from the queue.
+                        // Because we are the one producer,
+                        // we can just insert the slice (it can't become full before we return).
+
+                        let new_tail =
+                            Self::copy_slice(self.buffer_mut_thin_ptr().cast(), tail, slice);
+                        self.tail().store(new_tail, Release);
+
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+            }
+
+            return push(first, last, slice).inspect_err(|()| {
+                self.return_taken_values(tail, head, n);
+            });
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! This module provides the [`GrowthPolicy`] of unbounded queues.
+use crate::number_types::UnboundedIndex;
+
+/// The number of bits of the capacity of the ring buffer of unbounded queues.
+const MAX_CAPACITY_BITS: u32 = if UnboundedIndex::BITS < usize::BITS {
+    UnboundedIndex::BITS
+} else {
+    usize::BITS
+};
+
+/// The maximum capacity of the ring buffer of unbounded queues:
+/// the largest power of two that fits into both [`UnboundedIndex`] and `usize`.
+///
+/// It is `2^31` by default and `2^63` on 64-bit targets
+/// with the `wide_unbounded_index` feature.
+const MAX_CAPACITY: usize = 1 << (MAX_CAPACITY_BITS - 1);
+
+/// A policy that decides how the ring buffer of an unbounded
+/// [`spsc`](crate::spsc) or [`spmc`](crate::spmc) queue grows and shrinks.
+///
+/// - The queue starts with the [`initial capacity`](Self::initial_capacity).
+/// - On overflow, the capacity is multiplied by the [`factor`](Self::factor)
+///   until the values fit, but it never exceeds the [`maximum`](Self::max_capacity).
+/// - If [`shrink_after`](Self::shrink_after) is not zero, the producer shrinks the buffer
+///   once it has seen low occupancy (no more than a quarter of the capacity)
+///   that many times in a row. The buffer never shrinks below the initial capacity.
+///
+/// All capacities are powers of two.
+///
+/// # Example
+///
+#[cfg_attr(not(feature = "disable_unbounded"), doc = "```")]
+#[cfg_attr(feature = "disable_unbounded", doc = "```ignore")]
+/// use parcoll::GrowthPolicy;
+/// use parcoll::spmc::{new_unbounded_with_growth_policy, Producer};
+///
+/// let policy = GrowthPolicy::new()
+///     .with_initial_capacity(16)
+///     .with_factor(4)
+///     .with_shrink_after(8);
+/// let (producer, _consumer) = new_unbounded_with_growth_policy::<usize>(policy);
+///
+/// assert_eq!(producer.capacity(), 16);
+///
+/// for i in 0..17 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 64);
+///
+/// // The producer sees the low occupancy while it pops the values and shrinks the buffer.
+/// while producer.pop().is_some() {}
+///
+/// assert_eq!(producer.capacity(), 16);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrowthPolicy {
+    factor: usize,
+    initial_capacity: usize,
+    max_capacity: usize,
+    shrink_after: usize,
+}
+
+impl GrowthPolicy {
+    /// Creates the default policy: the queue starts with 4 slots,
+    /// doubles on overflow up to the largest power of two that fits into
+    /// the [`UnboundedIndex`] and never shrinks automatically.
+    pub const fn new() -> Self {
+        Self {
+            factor: 2,
+            initial_capacity: 4,
+            max_capacity: MAX_CAPACITY,
+            shrink_after: 0,
+        }
+    }
+
+    /// Returns the policy with the given growth factor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `factor` is not a power of two greater than one.
+    #[must_use]
+    pub const fn with_factor(mut self, factor: usize) -> Self {
+        assert!(
+            factor > 1 && factor.is_power_of_two(),
+            "the growth factor must be a power of two greater than one"
+        );
+
+        self.factor = factor;
+
+        self
+    }
+
+    /// Returns the policy with the given initial capacity rounded up to a power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded capacity is greater than the maximum capacity.
+    #[must_use]
+    pub const fn with_initial_capacity(mut self, initial_capacity: usize) -> Self {
+        let initial_capacity = match initial_capacity.checked_next_power_of_two() {
+            Some(initial_capacity) if initial_capacity <= self.max_capacity => initial_capacity,
+            _ => panic!("the initial capacity must not be greater than the maximum capacity"),
+        };
+
+        self.initial_capacity = initial_capacity;
+
+        self
+    }
+
+    /// Returns the policy with the given maximum capacity rounded down to a power of two.
+    ///
+    /// Once the queue has reached the maximum capacity, it overflows like a bounded queue:
+    /// [`push`](crate::spmc::Producer::push) moves the values to the
+    /// [`SyncBatchReceiver`](crate::SyncBatchReceiver),
+    /// and [`maybe_push`](crate::spmc::Producer::maybe_push) returns an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded capacity is less than the initial capacity.
+    #[must_use]
+    pub const fn with_max_capacity(mut self, max_capacity: usize) -> Self {
+        let max_capacity = if max_capacity >= MAX_CAPACITY {
+            MAX_CAPACITY
+        } else if max_capacity == 0 {
+            0
+        } else {
+            1 << (usize::BITS - 1 - max_capacity.leading_zeros())
+        };
+
+        assert!(
+            max_capacity >= self.initial_capacity,
+            "the maximum capacity must not be less than the initial capacity"
+        );
+
+        self.max_capacity = max_capacity;
+
+        self
+    }
+
+    /// Returns the policy that shrinks the buffer after it has seen
+    /// low occupancy `shrink_after` times in a row. Zero disables automatic shrinking.
+    #[must_use]
+    pub const fn with_shrink_after(mut self, shrink_after: usize) -> Self {
+        self.shrink_after = shrink_after;
+
+        self
+    }
+
+    /// Returns the growth factor.
+    pub const fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Returns the initial capacity.
+    pub const fn initial_capacity(&self) -> usize {
+        self.initial_capacity
+    }
+
+    /// Returns the maximum capacity.
+    pub const fn max_capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    /// Returns the number of low occupancy observations in a row
+    /// after which the buffer shrinks, or zero if it never shrinks automatically.
+    pub const fn shrink_after(&self) -> usize {
+        self.shrink_after
+    }
+}
+
+#[cfg_attr(
+    feature = "disable_unbounded",
+    allow(dead_code, reason = "Only unbounded queues grow and shrink.")
+)]
+impl GrowthPolicy {
+    /// Returns whether the maximum capacity is less than the largest possible one,
+    /// so the queue is capped by it.
+    pub(crate) const fn is_capped(&self) -> bool {
+        self.max_capacity < MAX_CAPACITY
+    }
+
+    /// Returns the capacity that fits `needed` values after growing from the `capacity`,
+    /// or `None` if the needed capacity is greater than the maximum capacity.
+    pub(crate) fn try_grown_capacity(&self, capacity: usize, needed: usize) -> Option<usize> {
+        if needed > self.max_capacity {
+            return None;
+        }
+
+        let mut new_capacity = capacity;
+
+        while new_capacity < needed {
+            new_capacity = new_capacity.saturating_mul(self.factor);
+        }
+
+        Some(new_capacity.min(self.max_capacity))
+    }
+
+    /// Returns the capacity that fits `needed` values after growing from the `capacity`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the needed capacity is greater than the maximum capacity.
+    pub(crate) fn grown_capacity(&self, capacity: usize, needed: usize) -> usize {
+        self.try_grown_capacity(capacity, needed)
+            .expect("the unbounded queue has reached the maximum capacity of its growth policy")
+    }
+
+    /// Returns whether the producer should track the occupancy of the buffer
+    /// with the given `capacity`.
+    #[inline(always)]
+    pub(crate) fn can_shrink(&self, capacity: usize) -> bool {
+        self.shrink_after != 0 && capacity > self.initial_capacity
+    }
+
+    /// Returns whether `len` values are a low occupancy of the buffer with the given `capacity`.
+    #[inline(always)]
+    pub(crate) fn is_low_occupancy(len: usize, capacity: usize) -> bool {
+        len <= capacity / 4
+    }
+
+    /// Returns the capacity that the buffer with `len` values shrinks to
+    /// when the occupancy stays low.
+    pub(crate) fn shrunk_capacity(&self, len: usize) -> usize {
+        len.saturating_mul(2)
+            .checked_next_power_of_two()
+            .map_or(self.max_capacity, |capacity| {
+                capacity.clamp(self.initial_capacity, self.max_capacity)
+            })
+    }
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_growth_policy() {
+        let policy = GrowthPolicy::new();
+
+        assert_eq!(policy, GrowthPolicy::default());
+        assert_eq!(policy.grown_capacity(4, 5), 8);
+        assert_eq!(policy.grown_capacity(4, 17), 32);
+        assert_eq!(policy.try_grown_capacity(4, MAX_CAPACITY + 1), None);
+        assert!(!policy.can_shrink(1024));
+
+        let policy = GrowthPolicy::new()
+            .with_initial_capacity(5)
+            .with_factor(4)
+            .with_max_capacity(100)
+            .with_shrink_after(3);
+
+        assert_eq!(policy.initial_capacity(), 8);
+        assert_eq!(policy.max_capacity(), 64);
+        assert_eq!(policy.grown_capacity(8, 9), 32);
+        assert_eq!(policy.grown_capacity(32, 33), 64);
+        assert!(policy.can_shrink(16));
+        assert!(!policy.can_shrink(8));
+        assert!(GrowthPolicy::is_low_occupancy(4, 16));
+        assert!(!GrowthPolicy::is_low_occupancy(5, 16));
+        assert_eq!(policy.shrunk_capacity(0), 8);
+        assert_eq!(policy.shrunk_capacity(7), 16);
+        assert_eq!(policy.shrunk_capacity(usize::MAX), 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "maximum capacity of its growth policy")]
+    fn test_growth_policy_max_capacity() {
+        let _ = GrowthPolicy::new()
+            .with_max_capacity(16)
+            .grown_capacity(16, 17);
+    }
+
+    #[test]
+    #[should_panic(expected = "the initial capacity must not be greater than the maximum capacity")]
+    fn test_growth_policy_huge_initial_capacity() {
+        let _ = GrowthPolicy::new().with_initial_capacity(usize::MAX);
+    }
+}
@@ -0,0 +1,364 @@
+//! This module provides the [`Hierarchy`] of queues.
+use crate::light_arc::LightArc;
+use crate::mutex_vec_queue::MutexVecQueue;
+use crate::spmc::{new_bounded, Consumer, Producer, SPMCConsumer, SPMCProducer};
+use crate::sync_batch_receiver::SyncBatchReceiver;
+use crate::sync_batch_source::SyncBatchSource;
+
+/// A hierarchy of queues: local queues of the workers,
+/// intermediate queues of the groups of workers and one global queue.
+///
+/// A group is a set of workers that share some hardware (for example, an L3 cache
+/// or a NUMA node), so the values are cheaper to move between them.
+///
+/// - A worker pushes to its local [`spmc`](crate::spmc) queue. On overflow,
+///   the values flow upward: to the queue of its group,
+///   or to the global queue if the queue of the group is full.
+/// - A worker refills its local queue from the nearest level first:
+///   the queue of its group, the workers of its group, the global queue
+///   and finally the workers of other groups.
+///
+/// It reduces the contention on the global queue on large machines.
+///
+/// # Example
+///
+/// ```
+/// use parcoll::Hierarchy;
+///
+/// let (hierarchy, workers) = Hierarchy::<usize>::new::<8>(&[2, 2], 16);
+///
+/// for i in 0..100 {
+///     workers[0].push(i);
+/// }
+///
+/// // Overflowed values are in the queue of the first group and in the global queue.
+/// assert_eq!(workers[0].len(), 5);
+/// assert_eq!(hierarchy.group_queue(0).len(), 15);
+/// assert_eq!(hierarchy.global_queue().len(), 80);
+///
+/// // The worker of the first group refills from the queue of its group.
+/// assert!(workers[1].pop().is_some());
+/// assert_eq!(hierarchy.group_queue(0).len(), 11);
+///
+/// // The worker of the second group refills from the global queue.
+/// assert!(workers[2].pop().is_some());
+/// ```
+pub struct Hierarchy<T> {
+    global_queue: MutexVecQueue<T>,
+    group_queues: Box<[MutexVecQueue<T>]>,
+    max_group_queue_len: usize,
+}
+
+impl<T: Send> Hierarchy<T> {
+    /// Creates a new [`Hierarchy`] with groups of the given sizes.
+    /// Returns the hierarchy and its workers ordered by their groups.
+    ///
+    /// The local queues of the workers have the `CAPACITY`.
+    /// The queue of a group accepts overflowed values until it contains
+    /// `max_group_queue_len` values, then the values go to the global queue.
+    pub fn new<const CAPACITY: usize>(
+        group_sizes: &[usize],
+        max_group_queue_len: usize,
+    ) -> (LightArc<Self>, Vec<HierarchyWorker<T, CAPACITY>>) {
+        let hierarchy = LightArc::new(Self {
+            global_queue: MutexVecQueue::new(),
+            group_queues: group_sizes.iter().map(|_| MutexVecQueue::new()).collect(),
+            max_group_queue_len,
+        });
+        let (producers, consumers): (Vec<_>, Vec<_>) = group_sizes
+            .iter()
+            .enumerate()
+            .flat_map(|(group, &size)| (0..size).map(move |_| group))
+            .map(|group| {
+                let (producer, consumer) = new_bounded::<T, CAPACITY>();
+
+                ((group, producer), (group, consumer))
+            })
+            .unzip();
+        let workers = producers
+            .into_iter()
+            .enumerate()
+            .map(|(index, (group, producer))| {
+                // Other workers are visited starting from the next one to spread the steals.
+                let others = || consumers[index + 1..].iter().chain(&consumers[..index]);
+                let siblings = others()
+                    .filter(|(other_group, _)| *other_group == group)
+                    .map(|(_, consumer)| consumer.clone())
+                    .collect();
+                let other_groups = others()
+                    .filter(|(other_group, _)| *other_group != group)
+                    .map(|(_, consumer)| consumer.clone())
+                    .collect();
+
+                HierarchyWorker {
+                    hierarchy: hierarchy.clone(),
+                    group,
+                    producer,
+                    siblings,
+                    other_groups,
+                }
+            })
+            .collect();
+
+        (hierarchy, workers)
+    }
+}
+
+impl<T> Hierarchy<T> {
+    /// Returns the number of groups.
+    pub fn groups(&self) -> usize {
+        self.group_queues.len()
+    }
+
+    /// Returns the global queue.
+    pub fn global_queue(&self) -> &MutexVecQueue<T> {
+        &self.global_queue
+    }
+
+    /// Returns the queue of the given group.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the group is out of bounds.
+    pub fn group_queue(&self, group: usize) -> &MutexVecQueue<T> {
+        &self.group_queues[group]
+    }
+
+    /// Pushes the value to the global queue.
+    ///
+    /// It is used to inject values from outside the workers.
+    pub fn push(&self, value: T) {
+        self.global_queue.push(value);
+    }
+}
+
+/// A [`SyncBatchReceiver`] that pushes the batch to the queue of the group
+/// if it has enough space, and to the global queue otherwise.
+struct UpwardReceiver<'hierarchy, T> {
+    hierarchy: &'hierarchy Hierarchy<T>,
+    group: usize,
+}
+
+impl<T> UpwardReceiver<'_, T> {
+    /// Returns the queue that accepts the batch of the given length.
+    fn queue_for(&self, len: usize) -> &MutexVecQueue<T> {
+        let group_queue = self.hierarchy.group_queue(self.group);
+
+        // It is a soft limit, it can be exceeded by concurrent pushes.
+        if group_queue.len() + len <= self.hierarchy.max_group_queue_len {
+            group_queue
+        } else {
+            &self.hierarchy.global_queue
+        }
+    }
+}
+
+unsafe impl<T> SyncBatchReceiver<T> for UpwardReceiver<'_, T> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        self.queue_for(first.len() + last.len() + 1)
+            .push_many_and_one(first, last, value);
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        self.queue_for(first.len() + last.len() + slice.len())
+            .push_many_and_slice(first, last, slice);
+    }
+}
+
+/// A worker of the [`Hierarchy`].
+///
+/// It owns a local bounded [`spmc`](crate::spmc) queue with the given `CAPACITY`
+/// and the consumers of the local queues of other workers to steal from them.
+pub struct HierarchyWorker<T, const CAPACITY: usize> {
+    hierarchy: LightArc<Hierarchy<T>>,
+    group: usize,
+    producer: SPMCProducer<T, CAPACITY>,
+    siblings: Box<[SPMCConsumer<T, CAPACITY>]>,
+    other_groups: Box<[SPMCConsumer<T, CAPACITY>]>,
+}
+
+impl<T: Send, const CAPACITY: usize> HierarchyWorker<T, CAPACITY> {
+    /// Returns the [`Hierarchy`] of the worker.
+    pub fn hierarchy(&self) -> &Hierarchy<T> {
+        &self.hierarchy
+    }
+
+    /// Returns the group of the worker.
+    pub fn group(&self) -> usize {
+        self.group
+    }
+
+    /// Returns the number of values in the local queue.
+    pub fn len(&self) -> usize {
+        self.producer.len()
+    }
+
+    /// Returns whether the local queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.producer.is_empty()
+    }
+
+    /// Pushes the value to the local queue.
+    ///
+    /// On overflow, it moves a half of the local queue upward:
+    /// to the queue of the group or to the global queue if the first one is full.
+    pub fn push(&self, value: T) {
+        self.producer.push(
+            value,
+            &UpwardReceiver {
+                hierarchy: &self.hierarchy,
+                group: self.group,
+            },
+        );
+    }
+
+    /// Pops a value from the local queue.
+    /// If the local queue is empty, it [`refills`](Self::refill) it first.
+    pub fn pop(&self) -> Option<T> {
+        self.producer.pop().or_else(|| {
+            if self.refill() > 0 {
+                self.producer.pop()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Refills the local queue preferring the nearest level:
+    /// the queue of the group, the workers of the group, the global queue
+    /// and finally the workers of other groups.
+    ///
+    /// Returns the number of moved values.
+    pub fn refill(&self) -> usize {
+        let limit = (CAPACITY / 2).max(1);
+        let moved = self
+            .hierarchy
+            .group_queue(self.group)
+            .move_batch_to(&self.producer, limit);
+
+        if moved > 0 {
+            return moved;
+        }
+
+        for sibling in &self.siblings {
+            let stolen = sibling.steal_into(&self.producer);
+
+            if stolen > 0 {
+                return stolen;
+            }
+        }
+
+        let moved = self
+            .hierarchy
+            .global_queue
+            .move_batch_to(&self.producer, limit);
+
+        if moved > 0 {
+            return moved;
+        }
+
+        for other in &self.other_groups {
+            let stolen = other.steal_into(&self.producer);
+
+            if stolen > 0 {
+                return stolen;
+            }
+        }
+
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    #[test]
+    fn test_hierarchy_prefers_nearest_level() {
+        let (hierarchy, workers) = Hierarchy::<usize>::new::<4>(&[2, 1], 6);
+
+        assert_eq!(hierarchy.groups(), 2);
+        assert_eq!(
+            workers
+                .iter()
+                .map(HierarchyWorker::group)
+                .collect::<Vec<_>>(),
+            [0, 0, 1]
+        );
+
+        for i in 0..12 {
+            workers[0].push(i);
+        }
+
+        // 3 + 3 values went to the queue of the group, the rest 3 to the global queue.
+        assert_eq!(workers[0].len(), 3);
+        assert_eq!(hierarchy.group_queue(0).len(), 6);
+        assert_eq!(hierarchy.global_queue().len(), 3);
+
+        // The sibling refills from the queue of its group.
+        assert_eq!(workers[1].pop(), Some(0));
+        assert_eq!(hierarchy.group_queue(0).len(), 4);
+
+        // The worker of another group refills from the global queue.
+        assert_eq!(workers[2].pop(), Some(5));
+        assert_eq!(hierarchy.global_queue().len(), 1);
+
+        let (hierarchy, workers) = Hierarchy::<usize>::new::<16>(&[2, 1], 64);
+
+        for i in 0..16 {
+            workers[0].push(i);
+        }
+
+        hierarchy.push(100);
+
+        // The sibling steals from the workers of its group before the global queue.
+        assert_eq!(workers[1].pop(), Some(0));
+        assert_eq!(workers[0].len(), 8);
+        assert_eq!(hierarchy.global_queue().len(), 1);
+
+        // The worker of another group steals from the workers of other groups at last.
+        assert_eq!(workers[2].pop(), Some(100));
+        assert_eq!(workers[2].pop(), Some(8));
+        assert_eq!(workers[0].len(), 4);
+    }
+
+    #[test]
+    fn test_hierarchy_multi_threaded() {
+        const N: usize = 10_000;
+
+        let (hierarchy, workers) = Hierarchy::<usize>::new::<16>(&[2, 2], 64);
+        let popped = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for worker in workers {
+                let popped = &popped;
+
+                scope.spawn(move || {
+                    for i in 0..N {
+                        worker.push(i);
+
+                        if i % 3 == 0 && worker.pop().is_some() {
+                            popped.fetch_add(1, Relaxed);
+                        }
+                    }
+
+                    while worker.pop().is_some() {
+                        popped.fetch_add(1, Relaxed);
+                    }
+                });
+            }
+        });
+
+        let mut rest = 0;
+
+        for group in 0..hierarchy.groups() {
+            rest += hierarchy.group_queue(group).len();
+        }
+
+        rest += hierarchy.global_queue().len();
+
+        assert_eq!(popped.load(Relaxed) + rest, N * 4);
+    }
+}
@@ -0,0 +1,647 @@
+//! This module provides the [`Injector`].
+//!
+//! The algorithm is based on the injector of `crossbeam-deque`,
+//! but pushes and steals reserve runs of slots instead of single slots.
+use crate::backoff::Backoff;
+use crate::cache_padded::CachePaddedAtomicUsize;
+use crate::loom_bindings::sync::atomic::{AtomicPtr, AtomicUsize};
+use crate::spmc::{reserve_slots_up_to, Producer};
+use crate::sync_batch_receiver::{BatchDrain, SyncBatchReceiver};
+use crate::sync_batch_source::SyncBatchSource;
+use std::alloc::{alloc_zeroed, handle_alloc_error, Layout};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::fence;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst};
+
+/// The slot has been written.
+const WRITE: usize = 1;
+/// The slot has been read.
+const READ: usize = 2;
+/// The block should be destroyed by the reader of the slot.
+const DESTROY: usize = 4;
+
+/// Each block covers one "lap" of indices.
+const LAP: usize = 64;
+/// The maximum number of values a block can hold.
+const BLOCK_CAP: usize = LAP - 1;
+/// How many lower bits are reserved for metadata.
+const SHIFT: usize = 1;
+/// Indicates that the block is not the last one (it is set only in the head index).
+const HAS_NEXT: usize = 1;
+
+/// A slot in a block.
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicUsize,
+}
+
+impl<T> Slot<T> {
+    /// Waits until a value is written into the slot.
+    fn wait_write(&self) {
+        let backoff = Backoff::new();
+
+        while self.state.load(Acquire) & WRITE == 0 {
+            backoff.snooze();
+        }
+    }
+}
+
+/// A block in a linked list.
+///
+/// Each block in the list can hold up to [`BLOCK_CAP`] values.
+struct Block<T> {
+    next: AtomicPtr<Self>,
+    slots: [Slot<T>; BLOCK_CAP],
+}
+
+impl<T> Block<T> {
+    /// Allocates a new empty block.
+    fn new() -> *mut Self {
+        let layout = Layout::new::<Self>();
+        // All fields of the block are valid when zeroed:
+        // atomics are zeros, the pointer is null, and the values are uninitialized.
+        let ptr = unsafe { alloc_zeroed(layout) }.cast::<Self>();
+
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+
+        ptr
+    }
+
+    /// Waits until the next pointer is set.
+    fn wait_next(&self) -> *mut Self {
+        let backoff = Backoff::new();
+
+        loop {
+            let next = self.next.load(Acquire);
+
+            if !next.is_null() {
+                return next;
+            }
+
+            backoff.snooze();
+        }
+    }
+
+    /// Sets the `DESTROY` bit in the first `count` slots and destroys the block
+    /// if no one reads from them.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have read all slots of the block after the first `count` slots.
+    unsafe fn destroy(this: *mut Self, count: usize) {
+        for i in (0..count).rev() {
+            let slot = unsafe { (*this).slots.get_unchecked(i) };
+
+            // Mark the `DESTROY` bit if a thread is still reading from the slot.
+            if slot.state.load(Acquire) & READ == 0
+                && slot.state.fetch_or(DESTROY, AcqRel) & READ == 0
+            {
+                // The reader of the slot will continue destruction of the block.
+                return;
+            }
+        }
+
+        // No thread uses the block, now it is safe to destroy it.
+        drop(unsafe { Box::from_raw(this) });
+    }
+}
+
+/// A position in the queue.
+struct Position<T> {
+    index: CachePaddedAtomicUsize,
+    block: AtomicPtr<Block<T>>,
+}
+
+/// A lock-free unbounded multi-producer multi-consumer queue
+/// that is used as the global queue.
+///
+/// It is a lock-free replacement of the [`MutexVecQueue`](crate::MutexVecQueue)
+/// in the same role: it implements the [`SyncBatchReceiver`] to accept overflowed
+/// values of local queues and the [`SyncBatchSource`] to refill them.
+///
+/// The values are stored in a linked list of fixed-size blocks.
+/// A batch push reserves a contiguous run of slots with one atomic operation per block,
+/// and a refill claims a contiguous run of slots of one block with one atomic operation,
+/// so the threads contend on the atomics once per batch instead of once per value.
+///
+/// # Example
+///
+/// ```
+/// use parcoll::spmc::{new_bounded, Producer};
+/// use parcoll::{Injector, SyncBatchSource};
+///
+/// let injector = Injector::new();
+/// let (producer, _) = new_bounded::<_, 8>();
+///
+/// for i in 0..20 {
+///     producer.push(i, &injector);
+/// }
+///
+/// assert_eq!(injector.len(), 15);
+///
+/// let (producer2, _) = new_bounded::<_, 8>();
+///
+/// assert_eq!(injector.move_batch_to(&producer2, 8), 8);
+/// assert_eq!(producer2.pop(), Some(0));
+/// ```
+pub struct Injector<T> {
+    head: Position<T>,
+    tail: Position<T>,
+}
+
+unsafe impl<T: Send> Send for Injector<T> {}
+unsafe impl<T: Send> Sync for Injector<T> {}
+
+impl<T> Injector<T> {
+    /// Creates a new empty [`Injector`].
+    pub fn new() -> Self {
+        let block = Block::new();
+
+        Self {
+            head: Position {
+                index: CachePaddedAtomicUsize::new(),
+                block: AtomicPtr::new(block),
+            },
+            tail: Position {
+                index: CachePaddedAtomicUsize::new(),
+                block: AtomicPtr::new(block),
+            },
+        }
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.index.load(SeqCst);
+        let tail = self.tail.index.load(SeqCst);
+
+        head >> SHIFT == tail >> SHIFT
+    }
+
+    /// Returns the number of values in the queue.
+    pub fn len(&self) -> usize {
+        loop {
+            // Load the tail index, then load the head index.
+            let mut tail = self.tail.index.load(SeqCst);
+            let mut head = self.head.index.load(SeqCst);
+
+            // If the tail index didn't change, we've got consistent indices to work with.
+            if self.tail.index.load(SeqCst) == tail {
+                // Erase the lower bits.
+                tail &= !((1 << SHIFT) - 1);
+                head &= !((1 << SHIFT) - 1);
+
+                // Fix up indices if they fall onto block ends.
+                if (tail >> SHIFT) & (LAP - 1) == LAP - 1 {
+                    tail = tail.wrapping_add(1 << SHIFT);
+                }
+                if (head >> SHIFT) & (LAP - 1) == LAP - 1 {
+                    head = head.wrapping_add(1 << SHIFT);
+                }
+
+                // Rotate indices so that head falls into the first block.
+                let lap = (head >> SHIFT) / LAP;
+                tail = tail.wrapping_sub((lap * LAP) << SHIFT);
+                head = head.wrapping_sub((lap * LAP) << SHIFT);
+
+                // Remove the lower bits.
+                tail >>= SHIFT;
+                head >>= SHIFT;
+
+                // Return the difference minus the number of blocks between tail and head.
+                return tail - head - tail / LAP;
+            }
+        }
+    }
+
+    /// Pushes the values of the `batch` in order.
+    ///
+    /// It reserves a run of slots with one atomic operation per block.
+    fn push_batch(&self, mut batch: BatchDrain<'_, T>) {
+        let backoff = Backoff::new();
+        let mut next_block: Option<*mut Block<T>> = None;
+        let mut tail = self.tail.index.load(Acquire);
+        let mut block = self.tail.block.load(Acquire);
+
+        while batch.len() > 0 {
+            let offset = (tail >> SHIFT) % LAP;
+
+            // If we reached the end of the block, wait until the next one is installed.
+            if offset == BLOCK_CAP {
+                backoff.snooze();
+                tail = self.tail.index.load(Acquire);
+                block = self.tail.block.load(Acquire);
+
+                continue;
+            }
+
+            let n = batch.len().min(BLOCK_CAP - offset);
+            let fills_block = offset + n == BLOCK_CAP;
+
+            // If we are going to fill the block, allocate the next one in advance.
+            if fills_block && next_block.is_none() {
+                next_block = Some(Block::new());
+            }
+
+            let new_tail = tail + (n << SHIFT);
+
+            match self
+                .tail
+                .index
+                .compare_exchange_weak(tail, new_tail, SeqCst, Acquire)
+            {
+                Ok(_) => unsafe {
+                    // If we've filled the block, install the next one.
+                    if fills_block {
+                        let next_block = next_block.take().unwrap_unchecked();
+
+                        self.tail.block.store(next_block, Release);
+                        self.tail
+                            .index
+                            .store(new_tail.wrapping_add(1 << SHIFT), Release);
+                        (*block).next.store(next_block, Release);
+                    }
+
+                    // Write the values into the reserved run of slots.
+                    for i in offset..offset + n {
+                        let slot = (*block).slots.get_unchecked(i);
+                        let value = batch.next().unwrap_unchecked();
+
+                        slot.value.get().write(MaybeUninit::new(value));
+                        slot.state.fetch_or(WRITE, Release);
+                    }
+
+                    tail = self.tail.index.load(Acquire);
+                    block = self.tail.block.load(Acquire);
+                },
+                Err(current_tail) => {
+                    tail = current_tail;
+                    block = self.tail.block.load(Acquire);
+
+                    backoff.spin();
+                }
+            }
+        }
+
+        if let Some(next_block) = next_block {
+            // The block was allocated in advance, but another thread has installed the next one.
+            drop(unsafe { Box::from_raw(next_block) });
+        }
+    }
+
+    /// Pushes the value to the queue.
+    pub fn push(&self, value: T) {
+        self.push_batch(unsafe { BatchDrain::from_many_and_one(&[], &[], value) });
+    }
+
+    /// Claims a contiguous run of up to `limit` values of one block,
+    /// and passes them to `f` in order.
+    /// Returns the number of claimed values.
+    ///
+    /// The `limit` must be greater than zero.
+    fn claim_run(&self, limit: usize, mut f: impl FnMut(usize, T)) -> usize {
+        let backoff = Backoff::new();
+
+        loop {
+            let head = self.head.index.load(Acquire);
+            let block = self.head.block.load(Acquire);
+            let offset = (head >> SHIFT) % LAP;
+
+            // If we reached the end of the block, wait until the next one is installed.
+            if offset == BLOCK_CAP {
+                backoff.snooze();
+
+                continue;
+            }
+
+            let mut new_head = head;
+            let available = if head & HAS_NEXT == 0 {
+                fence(SeqCst);
+
+                let tail = self.tail.index.load(Relaxed);
+
+                // If the tail equals the head, that means the queue is empty.
+                if head >> SHIFT == tail >> SHIFT {
+                    return 0;
+                }
+
+                // If head and tail are not in the same block, set `HAS_NEXT` in head.
+                if (head >> SHIFT) / LAP != (tail >> SHIFT) / LAP {
+                    new_head |= HAS_NEXT;
+
+                    BLOCK_CAP - offset
+                } else {
+                    (tail >> SHIFT) - (head >> SHIFT)
+                }
+            } else {
+                BLOCK_CAP - offset
+            };
+
+            let n = available.min(limit);
+            let new_offset = offset + n;
+
+            new_head += n << SHIFT;
+
+            // Try moving the head index forward.
+            if self
+                .head
+                .index
+                .compare_exchange_weak(head, new_head, SeqCst, Acquire)
+                .is_err()
+            {
+                backoff.spin();
+
+                continue;
+            }
+
+            unsafe {
+                // If we've reached the end of the block, move to the next one.
+                if new_offset == BLOCK_CAP {
+                    let next = (*block).wait_next();
+                    let mut next_index = (new_head & !HAS_NEXT).wrapping_add(1 << SHIFT);
+
+                    if !(*next).next.load(Relaxed).is_null() {
+                        next_index |= HAS_NEXT;
+                    }
+
+                    self.head.block.store(next, Release);
+                    self.head.index.store(next_index, Release);
+                }
+
+                // Read the values of the claimed run.
+                for i in offset..new_offset {
+                    let slot = (*block).slots.get_unchecked(i);
+
+                    slot.wait_write();
+
+                    f(i - offset, slot.value.get().read().assume_init());
+                }
+
+                // Destroy the block if we've reached the end, or if another thread wanted
+                // to destroy but couldn't because we were busy reading from the slot.
+                if new_offset == BLOCK_CAP {
+                    Block::destroy(block, offset);
+                } else {
+                    for i in offset..new_offset {
+                        let slot = (*block).slots.get_unchecked(i);
+
+                        if slot.state.fetch_or(READ, AcqRel) & DESTROY != 0 {
+                            Block::destroy(block, offset);
+
+                            break;
+                        }
+                    }
+                }
+            }
+
+            return n;
+        }
+    }
+
+    /// Pops a value from the queue.
+    pub fn pop(&self) -> Option<T> {
+        let mut popped = None;
+
+        self.claim_run(1, |_, value| popped = Some(value));
+
+        popped
+    }
+
+    /// Moves up to `limit` values of the queue to the `producer`.
+    /// Returns the number of moved values.
+    ///
+    /// It claims one contiguous run of values, so it can move fewer values
+    /// than the queue contains even if the `producer` has enough free slots.
+    pub fn move_batch_to_producer(&self, producer: &impl Producer<T>, limit: usize) -> usize {
+        // The reserved slots are used before any other method of the `producer` is called.
+        let (first, last, limit) = unsafe { reserve_slots_up_to(producer, limit) };
+        let first_len = limit.min(first.len());
+        let (first, last) = (&mut first[..first_len], &mut last[..limit - first_len]);
+
+        if limit == 0 {
+            return 0;
+        }
+
+        let n = self.claim_run(limit, |i, value| {
+            if i < first_len {
+                first[i].write(value);
+            } else {
+                last[i - first_len].write(value);
+            }
+        });
+
+        unsafe { producer.commit_reserved_slots(n) };
+
+        n
+    }
+}
+
+impl<T> Default for Injector<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T> SyncBatchReceiver<T> for Injector<T> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        self.push_batch(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        self.push_batch(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+    }
+}
+
+impl<T> SyncBatchSource<T> for Injector<T> {
+    fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize {
+        self.move_batch_to_producer(producer, limit)
+    }
+}
+
+impl<T> Drop for Injector<T> {
+    fn drop(&mut self) {
+        let mut head = self.head.index.load(Relaxed) & !((1 << SHIFT) - 1);
+        let tail = self.tail.index.load(Relaxed) & !((1 << SHIFT) - 1);
+        let mut block = self.head.block.load(Relaxed);
+
+        unsafe {
+            // Drop all values between `head` and `tail` and deallocate the blocks.
+            while head != tail {
+                let offset = (head >> SHIFT) % LAP;
+
+                if offset < BLOCK_CAP {
+                    let slot = (*block).slots.get_unchecked(offset);
+
+                    ptr::drop_in_place((*slot.value.get()).as_mut_ptr());
+                } else {
+                    let next = (*block).next.load(Relaxed);
+
+                    drop(Box::from_raw(block));
+
+                    block = next;
+                }
+
+                head = head.wrapping_add(1 << SHIFT);
+            }
+
+            drop(Box::from_raw(block));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spmc::new_bounded;
+    use crate::zst_test::test_zst_queue;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_injector() {
+        let injector = Injector::new();
+
+        assert!(injector.is_empty());
+
+        for i in 0..BLOCK_CAP * 3 {
+            injector.push(i.to_string());
+        }
+
+        let mut values = (BLOCK_CAP * 3..BLOCK_CAP * 6)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+
+        injector.push_many_and_slice(&values[..10], &values[10..100], &values[100..]);
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(injector.len(), BLOCK_CAP * 6);
+
+        let (producer, _) = new_bounded::<_, 256>();
+        let mut expected = 0;
+
+        // Each refill claims a run of one block.
+        while injector.move_batch_to(&producer, 40) > 0 {
+            while let Some(value) = producer.pop() {
+                assert_eq!(value, expected.to_string());
+
+                expected += 1;
+            }
+        }
+
+        assert_eq!(expected, BLOCK_CAP * 6);
+        assert!(injector.is_empty());
+        assert_eq!(injector.pop(), None);
+
+        // Not popped values are dropped with the injector
+        for i in 0..BLOCK_CAP + 5 {
+            injector.push(i.to_string());
+        }
+
+        assert_eq!(injector.pop(), Some("0".to_string()));
+    }
+
+    #[test]
+    fn test_injector_multi_threaded() {
+        const PRODUCERS: usize = 3;
+        const CONSUMERS: usize = 3;
+        const N: usize = 20_000;
+
+        let injector = Injector::new();
+        let finished_producers = AtomicUsize::new(0);
+        let popped = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for p in 0..PRODUCERS {
+                let injector = &injector;
+                let finished_producers = &finished_producers;
+
+                scope.spawn(move || {
+                    let mut i = 0;
+
+                    while i < N {
+                        let batch = (i..(i + 1 + i % 70).min(N))
+                            .map(|i| p * N + i)
+                            .collect::<Vec<_>>();
+
+                        i += batch.len();
+
+                        if batch.len() == 1 {
+                            injector.push(batch[0]);
+                        } else {
+                            let middle = batch.len() / 2;
+
+                            injector.push_many_and_one(
+                                &batch[..middle],
+                                &batch[middle..batch.len() - 1],
+                                batch[batch.len() - 1],
+                            );
+                        }
+                    }
+
+                    finished_producers.fetch_add(1, Relaxed);
+                });
+            }
+
+            for _ in 0..CONSUMERS {
+                let injector = &injector;
+                let finished_producers = &finished_producers;
+                let popped = &popped;
+
+                scope.spawn(move || {
+                    let (producer, _) = new_bounded::<_, 64>();
+                    let mut local = Vec::new();
+
+                    loop {
+                        let is_finished = finished_producers.load(Relaxed) == PRODUCERS;
+
+                        if injector.move_batch_to(&producer, 64) == 0 {
+                            if let Some(value) = injector.pop() {
+                                local.push(value);
+                            } else if is_finished {
+                                break;
+                            }
+                        }
+
+                        while let Some(value) = producer.pop() {
+                            local.push(value);
+                        }
+                    }
+
+                    popped.lock().unwrap().extend(local);
+                });
+            }
+        });
+
+        let mut popped = popped.into_inner().unwrap();
+
+        popped.sort_unstable();
+
+        assert_eq!(popped, (0..PRODUCERS * N).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_injector_zst() {
+        let injector = Injector::new();
+
+        test_zst_queue(
+            None,
+            |value| {
+                injector.push(value);
+
+                Ok(())
+            },
+            |dst| {
+                let (producer, _) = new_bounded::<_, 16>();
+
+                injector.move_batch_to(&producer, dst.len());
+
+                producer.pop_many(dst)
+            },
+            || injector.len(),
+            || injector.move_batch_to(&new_bounded::<_, 256>().0, 100),
+        );
+    }
+}
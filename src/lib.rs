@@ -40,22 +40,46 @@
     clippy::result_unit_err,
     reason = "The function's doc should explain what it returns."
 )]
+pub(crate) mod alloc_error;
 pub mod backoff;
 pub mod cache_padded;
+pub(crate) mod const_bounded_ring;
+pub(crate) mod growth_policy;
+pub(crate) mod hierarchy;
 pub mod hints;
+pub(crate) mod injector;
 mod light_arc;
 #[cfg(all(parcoll_loom, test))]
 mod loom;
 pub mod loom_bindings;
 pub(crate) mod mutex_vec_queue;
+#[cfg(not(feature = "disable_unbounded"))]
 pub(crate) mod naive_rw_lock;
 pub mod number_types;
+pub(crate) mod queue_builder;
+pub(crate) mod sharded_mutex_vec_queue;
 pub mod spmc;
 pub mod spsc;
+pub(crate) mod sync_batch_combinators;
 pub(crate) mod sync_batch_receiver;
+pub(crate) mod sync_batch_source;
+#[cfg(not(feature = "disable_unbounded"))]
+mod tail_and_version;
 #[cfg(not(parcoll_loom))]
 mod test_lock;
+#[cfg(test)]
+mod zst_test;
 
+pub use alloc_error::AllocError;
+pub use growth_policy::GrowthPolicy;
+pub use hierarchy::{Hierarchy, HierarchyWorker};
+pub use injector::Injector;
 pub use light_arc::LightArc;
 pub use mutex_vec_queue::MutexVecQueue;
-pub use sync_batch_receiver::SyncBatchReceiver;
+pub use queue_builder::QueueBuilder;
+pub use sharded_mutex_vec_queue::ShardedMutexVecQueue;
+pub use sync_batch_combinators::{Counting, Fallback, Filter, Map, Tee};
+pub use sync_batch_receiver::{
+    BatchDrain, DiscardingReceiver, FnSyncBatchReceiver, PanickingReceiver, SyncBatchReceiver,
+};
+pub use sync_batch_source::SyncBatchSource;
@@ -2,6 +2,7 @@
 use crate::hints::unlikely;
 use crate::loom_bindings::sync::atomic::AtomicUsize;
 use std::alloc::{dealloc, Layout};
+use std::mem::ManuallyDrop;
 use std::ptr;
 use std::ptr::NonNull;
 use std::sync::atomic::Ordering;
@@ -35,6 +36,31 @@ impl<T> LightArc<T> {
         }
     }
 
+    /// Returns the inner value if this is the only reference to it.
+    ///
+    /// Otherwise, it only drops this reference and returns `None`
+    /// like [`Arc::into_inner`](std::sync::Arc::into_inner).
+    pub fn into_inner(this: Self) -> Option<T> {
+        let mut this = ManuallyDrop::new(this);
+
+        if this.inner().ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+
+        std::sync::atomic::fence(Ordering::Acquire);
+
+        unsafe {
+            let value = ptr::read(&raw const this.inner.as_mut().value);
+
+            dealloc(
+                this.inner.as_ptr().cast(),
+                Layout::new::<LightArcInner<T>>(),
+            );
+
+            Some(value)
+        }
+    }
+
     /// Returns a reference to the inner value.
     fn inner(&self) -> &LightArcInner<T> {
         unsafe { self.inner.as_ref() }
@@ -50,7 +76,7 @@ impl<T> LightArc<T> {
         std::sync::atomic::fence(Ordering::Acquire);
 
         unsafe {
-            ptr::drop_in_place(&mut self.inner.as_mut().value);
+            ptr::drop_in_place(&raw mut self.inner.as_mut().value);
 
             dealloc(
                 self.inner.as_ptr().cast(),
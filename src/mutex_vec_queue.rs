@@ -1,8 +1,11 @@
+use crate::alloc_error::{try_alloc_array, AllocError};
 use crate::hints::{assert_hint, unlikely};
 use crate::loom_bindings::sync::{Arc, Mutex};
 use crate::spmc::Producer;
 use crate::sync_batch_receiver::SyncBatchReceiver;
-use std::ptr::slice_from_raw_parts;
+use crate::sync_batch_source::SyncBatchSource;
+use std::mem::MaybeUninit;
+use std::ptr::{slice_from_raw_parts, NonNull};
 use std::{mem, ptr};
 
 pub(crate) struct VecQueue<T> {
@@ -11,23 +14,25 @@ pub(crate) struct VecQueue<T> {
     tail: usize,
     capacity: usize,
     mask: usize,
+    /// The capacity that the queue never shrinks below.
+    min_capacity: usize,
+    /// The maximum number of values, pushes that exceed it fail.
+    max_len: usize,
 }
 
 impl<T> VecQueue<T> {
-    fn allocate(capacity: usize) -> *mut T {
+    fn try_allocate(capacity: usize) -> Result<*mut T, AllocError> {
         debug_assert!(capacity > 0 && capacity.is_power_of_two());
 
-        unsafe {
-            std::alloc::alloc(std::alloc::Layout::array::<T>(capacity).unwrap_unchecked()).cast()
-        }
+        try_alloc_array(capacity).map(NonNull::as_ptr)
     }
 
     fn deallocate(ptr: *mut T, capacity: usize) {
-        unsafe {
-            std::alloc::dealloc(
-                ptr.cast(),
-                std::alloc::Layout::array::<T>(capacity).unwrap_unchecked(),
-            );
+        let layout = unsafe { std::alloc::Layout::array::<T>(capacity).unwrap_unchecked() };
+
+        // Zero-sized arrays are not allocated.
+        if layout.size() != 0 {
+            unsafe { std::alloc::dealloc(ptr.cast(), layout) };
         }
     }
 
@@ -47,13 +52,59 @@ impl<T> VecQueue<T> {
     pub(crate) fn new() -> Self {
         const DEFAULT_CAPACITY: usize = 16;
 
-        Self {
-            ptr: Self::allocate(DEFAULT_CAPACITY),
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new queue with at least the given capacity.
+    /// The queue never shrinks below this capacity.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self::try_with_capacity(capacity).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Creates a new queue with at least the given capacity
+    /// or returns the [`AllocError`] if the allocation fails.
+    /// The queue never shrinks below this capacity.
+    pub(crate) fn try_with_capacity(capacity: usize) -> Result<Self, AllocError> {
+        let capacity = capacity
+            .max(1)
+            .checked_next_power_of_two()
+            .ok_or(AllocError::CapacityOverflow)?;
+
+        Ok(Self {
+            ptr: Self::try_allocate(capacity)?,
             head: 0,
             tail: 0,
-            capacity: DEFAULT_CAPACITY,
-            mask: Self::get_mask_for_capacity(DEFAULT_CAPACITY),
-        }
+            capacity,
+            mask: Self::get_mask_for_capacity(capacity),
+            min_capacity: capacity,
+            max_len: usize::MAX,
+        })
+    }
+
+    /// Creates a new empty queue with the same capacity limits.
+    pub(crate) fn new_like(&self) -> Self {
+        let mut queue = Self::with_capacity(self.min_capacity);
+
+        queue.max_len = self.max_len;
+
+        queue
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub(crate) fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    /// Returns whether the queue can accept `additional` values without exceeding `max_len`.
+    pub(crate) fn has_room_for(&self, additional: usize) -> bool {
+        self.max_len.saturating_sub(self.len()) >= additional
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -64,31 +115,27 @@ impl<T> VecQueue<T> {
         self.head == self.tail
     }
 
-    #[inline(never)]
-    #[cold]
-    #[track_caller]
-    pub(crate) fn extend_to(&mut self, capacity: usize) {
-        debug_assert!(capacity > self.capacity);
+    /// Moves the values to a new buffer with the given capacity.
+    fn reallocate(&mut self, capacity: usize) {
+        if let Err(err) = self.try_reallocate(capacity) {
+            err.handle();
+        }
+    }
+
+    /// Moves the values to a new buffer with the given capacity
+    /// or returns the [`AllocError`] if the allocation fails.
+    fn try_reallocate(&mut self, capacity: usize) -> Result<(), AllocError> {
+        debug_assert!(capacity >= self.len());
 
-        let new_ptr = Self::allocate(capacity);
+        let new_ptr = Self::try_allocate(capacity)?;
         let len = self.len();
 
         unsafe {
             let phys_head = self.get_physical_index(self.head);
-            let phys_tail = self.get_physical_index(self.tail);
-            let src = self.ptr.add(phys_head);
-            let dst = new_ptr;
+            let right_occupied = len.min(self.capacity - phys_head);
 
-            if phys_head < phys_tail {
-                ptr::copy(src, dst, len);
-            } else {
-                ptr::copy(src, dst, self.capacity - phys_head);
-
-                let src = self.ptr;
-                let dst = new_ptr.add(self.capacity - phys_head);
-
-                ptr::copy(src, dst, phys_tail);
-            }
+            ptr::copy_nonoverlapping(self.ptr.add(phys_head), new_ptr, right_occupied);
+            ptr::copy_nonoverlapping(self.ptr, new_ptr.add(right_occupied), len - right_occupied);
         }
 
         Self::deallocate(self.ptr, self.capacity);
@@ -98,6 +145,64 @@ impl<T> VecQueue<T> {
         self.ptr = new_ptr;
         self.capacity = capacity;
         self.mask = Self::get_mask_for_capacity(capacity);
+
+        Ok(())
+    }
+
+    #[inline(never)]
+    #[cold]
+    #[track_caller]
+    pub(crate) fn extend_to(&mut self, capacity: usize) {
+        debug_assert!(capacity > self.capacity);
+
+        self.reallocate(capacity);
+    }
+
+    /// Reserves capacity for at least `additional` more values.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            err.handle();
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more values
+    /// or returns the [`AllocError`] if the allocation fails.
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        let needed = self
+            .len()
+            .checked_add(additional)
+            .ok_or(AllocError::CapacityOverflow)?;
+
+        if needed > self.capacity {
+            let capacity = needed
+                .checked_next_power_of_two()
+                .ok_or(AllocError::CapacityOverflow)?;
+
+            self.try_reallocate(capacity)?;
+        }
+
+        Ok(())
+    }
+
+    /// Shrinks the capacity as much as possible,
+    /// but not below the `min_capacity` and the length.
+    pub(crate) fn shrink_to(&mut self, min_capacity: usize) {
+        let capacity = self.len().max(min_capacity).max(1).next_power_of_two();
+
+        if capacity < self.capacity {
+            self.reallocate(capacity);
+        }
+    }
+
+    /// Shrinks the capacity by half or more if the queue is less than a quarter full.
+    ///
+    /// The gap between growing at full occupancy and shrinking at a quarter
+    /// prevents reallocating on every push and pop around the same length.
+    #[inline]
+    pub(crate) fn shrink_if_sparse(&mut self) {
+        if unlikely(self.capacity > self.min_capacity && self.len() <= self.capacity / 4) {
+            self.shrink_to(self.min_capacity.max(self.len() * 2));
+        }
     }
 
     #[inline]
@@ -129,6 +234,42 @@ impl<T> VecQueue<T> {
         Some(value)
     }
 
+    /// Pops up to `dst.len()` values into the `dst` in order.
+    /// Returns the number of popped values.
+    pub(crate) fn pop_many(&mut self, dst: &mut [MaybeUninit<T>]) -> usize {
+        let n = self.len().min(dst.len());
+        let phys_head = self.get_physical_index(self.head);
+        let right_occupied = self.capacity - phys_head;
+        let dst_ptr = dst.as_mut_ptr().cast::<T>();
+
+        unsafe {
+            if n <= right_occupied {
+                ptr::copy_nonoverlapping(self.ptr.add(phys_head), dst_ptr, n);
+            } else {
+                ptr::copy_nonoverlapping(self.ptr.add(phys_head), dst_ptr, right_occupied);
+                ptr::copy_nonoverlapping(self.ptr, dst_ptr.add(right_occupied), n - right_occupied);
+            }
+        }
+
+        self.head = self.head.wrapping_add(n);
+
+        n
+    }
+
+    /// Pops up to `limit` values and appends them to the `vec`.
+    /// Returns the number of popped values.
+    pub(crate) fn pop_into_vec(&mut self, vec: &mut Vec<T>, limit: usize) -> usize {
+        let n = self.len().min(limit);
+
+        vec.reserve(n);
+
+        let popped = self.pop_many(&mut vec.spare_capacity_mut()[..n]);
+
+        unsafe { vec.set_len(vec.len() + popped) };
+
+        popped
+    }
+
     #[inline]
     pub(crate) fn extend_from_slice(&mut self, slice: &[T]) {
         let needed = self.len() + slice.len();
@@ -166,10 +307,10 @@ impl<T> VecQueue<T> {
 
     pub(crate) fn move_batch_to_producer(
         &mut self,
-        producer: &mut impl Producer<T>,
+        producer: &impl Producer<T>,
         mut limit: usize,
-    ) {
-        limit = self.len().min(limit);
+    ) -> usize {
+        limit = self.len().min(limit).min(producer.free_slots());
 
         let phys_head = self.get_physical_index(self.head);
         let right_occupied = self.capacity - phys_head;
@@ -187,7 +328,7 @@ impl<T> VecQueue<T> {
 
             // The head is already updated.
 
-            return;
+            return limit;
         }
 
         let slice1 = unsafe { &*slice_from_raw_parts(self.ptr.add(phys_head), right_occupied) };
@@ -196,9 +337,16 @@ impl<T> VecQueue<T> {
         unsafe { producer.push_many_unchecked(slice1, slice2) };
 
         // The head is already updated.
+
+        limit
     }
 }
 
+// SAFETY: `VecQueue` owns the values behind `ptr` like `Vec<T>` does,
+// and it has no shared state, so sending it sends only the owned values.
+// It is required to share `MutexVecQueue` between threads.
+unsafe impl<T: Send> Send for VecQueue<T> {}
+
 impl<T> Drop for VecQueue<T> {
     fn drop(&mut self) {
         if mem::needs_drop::<T>() {
@@ -218,9 +366,84 @@ pub struct MutexVecQueue<T> {
 
 impl<T> MutexVecQueue<T> {
     pub fn new() -> Self {
+        Self::from_vec_queue(VecQueue::new())
+    }
+
+    /// Creates a new queue with at least the given capacity.
+    ///
+    /// The queue grows when it is full and shrinks when it becomes sparse,
+    /// but it never shrinks below this capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_vec_queue(VecQueue::with_capacity(capacity))
+    }
+
+    /// Creates a new queue with at least the given capacity
+    /// or returns the [`AllocError`] if the allocation fails.
+    ///
+    /// The queue grows when it is full and shrinks when it becomes sparse,
+    /// but it never shrinks below this capacity.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, AllocError> {
+        VecQueue::try_with_capacity(capacity).map(Self::from_vec_queue)
+    }
+
+    /// Creates a new queue with at least the given capacity
+    /// that never contains more than `max_len` values.
+    ///
+    /// Pushes that exceed the `max_len` fail: [`try_push`](Self::try_push)
+    /// and the `try_*` methods of the [`SyncBatchReceiver`] return an error,
+    /// and [`push`](Self::push) panics.
+    ///
+    /// The infallible methods of the [`SyncBatchReceiver`] treat the `max_len` as a soft limit:
+    /// they accept the batch past it, because the values have already been taken
+    /// from the local queue, and the producers' `push` must not fail.
+    pub fn with_capacity_and_max_len(capacity: usize, max_len: usize) -> Self {
+        let mut vec_queue = VecQueue::with_capacity(capacity);
+
+        vec_queue.set_max_len(max_len);
+
+        Self::from_vec_queue(vec_queue)
+    }
+
+    fn from_vec_queue(vec_queue: VecQueue<T>) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(VecQueue::new())),
+            inner: Arc::new(Mutex::new(vec_queue)),
+        }
+    }
+
+    /// Grows the locked queue to fit `additional` more values
+    /// or returns `false` if they exceed the `max_len`.
+    fn reserve_locked(inner: &mut VecQueue<T>, additional: usize) -> bool {
+        if !inner.has_room_for(additional) {
+            return false;
         }
+
+        Self::grow_locked(inner, additional);
+
+        true
+    }
+
+    /// Grows the locked queue to fit `additional` more values even if they exceed the `max_len`.
+    fn grow_locked(inner: &mut VecQueue<T>, additional: usize) {
+        while inner.len() + additional > inner.capacity {
+            let new_capacity = inner.capacity * 2;
+
+            inner.extend_to(new_capacity);
+        }
+
+        assert_hint(
+            inner.len() + additional <= inner.capacity,
+            "capacity was not updated",
+        );
+    }
+
+    /// Locks the queue, calls `f` that pops values and shrinks the queue if it became sparse.
+    fn pop_with<R>(&self, f: impl FnOnce(&mut VecQueue<T>) -> R) -> R {
+        let mut inner = self.inner.lock();
+        let res = f(&mut inner);
+
+        inner.shrink_if_sparse();
+
+        res
     }
 
     pub fn len(&self) -> usize {
@@ -231,12 +454,115 @@ impl<T> MutexVecQueue<T> {
         self.inner.lock().is_empty()
     }
 
+    /// Returns the number of values the queue can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().capacity()
+    }
+
+    /// Returns the maximum number of values if it is set.
+    pub fn max_len(&self) -> Option<usize> {
+        let max_len = self.inner.lock().max_len();
+
+        (max_len != usize::MAX).then_some(max_len)
+    }
+
+    /// Reserves capacity for at least `additional` more values.
+    pub fn reserve(&self, additional: usize) {
+        self.inner.lock().reserve(additional);
+    }
+
+    /// Reserves capacity for at least `additional` more values
+    /// or returns the [`AllocError`] if the allocation fails.
+    pub fn try_reserve(&self, additional: usize) -> Result<(), AllocError> {
+        self.inner.lock().try_reserve(additional)
+    }
+
+    /// Shrinks the capacity as much as possible.
+    ///
+    /// Unlike the automatic shrinking, it can shrink below the initial capacity.
+    pub fn shrink_to_fit(&self) {
+        self.inner.lock().shrink_to(0);
+    }
+
+    /// Pushes the value to the queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue contains [`max_len`](Self::max_len) values.
     pub fn push(&self, task: T) {
-        self.inner.lock().push(task);
+        let mut inner = self.inner.lock();
+
+        assert!(inner.has_room_for(1), "MutexVecQueue is full");
+
+        inner.push(task);
+    }
+
+    /// Pushes the value to the queue
+    /// or returns it back if the queue contains [`max_len`](Self::max_len) values.
+    pub fn try_push(&self, task: T) -> Result<(), T> {
+        let mut inner = self.inner.lock();
+
+        if !inner.has_room_for(1) {
+            return Err(task);
+        }
+
+        inner.push(task);
+
+        // Clippy wants it
+        drop(inner);
+
+        Ok(())
+    }
+
+    pub fn move_batch_to_producer(&self, producer: &impl Producer<T>, limit: usize) -> usize {
+        self.pop_with(|inner| inner.move_batch_to_producer(producer, limit))
+    }
+
+    /// Pops a value from the queue.
+    pub fn pop(&self) -> Option<T> {
+        self.pop_with(VecQueue::pop)
     }
 
-    pub fn move_batch_to_producer(&self, producer: &mut impl Producer<T>, limit: usize) {
-        self.inner.lock().move_batch_to_producer(producer, limit);
+    /// Pops up to `dst.len()` values into the `dst` in order.
+    /// Returns the number of popped values.
+    ///
+    /// It locks the queue once.
+    pub fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+        self.pop_with(|inner| inner.pop_many(dst))
+    }
+
+    /// Pops up to `limit` values and appends them to the `vec`.
+    /// Returns the number of popped values.
+    ///
+    /// It locks the queue once.
+    pub fn pop_into_vec(&self, vec: &mut Vec<T>, limit: usize) -> usize {
+        self.pop_with(|inner| inner.pop_into_vec(vec, limit))
+    }
+
+    /// Takes all values out of the queue and returns an iterator over them.
+    ///
+    /// It locks the queue once, so the values pushed after the call
+    /// are not returned by the iterator.
+    /// The values that are not consumed by the iterator are dropped with it.
+    pub fn drain(&self) -> impl Iterator<Item = T> {
+        let mut values = self.pop_with(|inner| {
+            let new_inner = inner.new_like();
+
+            mem::replace(inner, new_inner)
+        });
+
+        std::iter::from_fn(move || values.pop())
+    }
+
+    /// Takes all values out of the queue in order.
+    ///
+    /// It locks the queue once.
+    pub fn take_all(&self) -> Vec<T> {
+        let mut values = Vec::new();
+
+        self.pop_with(|inner| inner.pop_into_vec(&mut values, usize::MAX));
+
+        values
     }
 }
 
@@ -246,53 +572,81 @@ impl<T> Default for MutexVecQueue<T> {
     }
 }
 
-impl<T> SyncBatchReceiver<T> for MutexVecQueue<T> {
+unsafe impl<T> SyncBatchReceiver<T> for MutexVecQueue<T> {
+    /// Pushes the values to the queue even if they exceed the
+    /// [`max_len`](MutexVecQueue::max_len), because it must not fail.
     fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
         let mut inner = self.inner.lock();
 
-        while inner.len() + first.len() + last.len() + 1 > inner.capacity {
-            let new_capacity = inner.capacity * 2;
+        Self::grow_locked(&mut inner, first.len() + last.len() + 1);
 
-            inner.extend_to(new_capacity);
-        }
+        inner.extend_from_slice(first);
+        inner.extend_from_slice(last);
 
-        assert_hint(
-            inner.len() + first.len() + last.len() < inner.capacity,
-            "capacity was not updated",
-        );
+        inner.push(value);
+    }
+
+    /// Pushes the values to the queue even if they exceed the
+    /// [`max_len`](MutexVecQueue::max_len), because it must not fail.
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        let mut inner = self.inner.lock();
+
+        Self::grow_locked(&mut inner, first.len() + last.len() + slice.len());
 
         inner.extend_from_slice(first);
         inner.extend_from_slice(last);
+        inner.extend_from_slice(slice);
+    }
 
-        inner.push(unsafe { ptr::read(&value) });
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        let mut inner = self.inner.lock();
+
+        if !Self::reserve_locked(&mut inner, first.len() + last.len() + 1) {
+            return Err(value);
+        }
+
+        inner.extend_from_slice(first);
+        inner.extend_from_slice(last);
+
+        inner.push(value);
 
         // Clippy wants it
         drop(inner);
+
+        Ok(())
     }
 
-    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
         let mut inner = self.inner.lock();
 
-        while inner.len() + first.len() + last.len() + slice.len() > inner.capacity {
-            let new_capacity = inner.capacity * 2;
-
-            inner.extend_to(new_capacity);
+        if !Self::reserve_locked(&mut inner, first.len() + last.len() + slice.len()) {
+            return Err(());
         }
 
-        assert_hint(
-            inner.len() + first.len() + last.len() + slice.len() <= inner.capacity,
-            "capacity was not updated",
-        );
-
         inner.extend_from_slice(first);
         inner.extend_from_slice(last);
         inner.extend_from_slice(slice);
+
+        // Clippy wants it
+        drop(inner);
+
+        Ok(())
+    }
+}
+
+impl<T> SyncBatchSource<T> for MutexVecQueue<T> {
+    fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize {
+        self.pop_with(|inner| inner.move_batch_to_producer(producer, limit))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::spmc::new_bounded;
+    use crate::zst_test::test_zst_queue;
+    use std::mem::ManuallyDrop;
+    use std::rc::Rc;
 
     const N: usize = 100_000;
     const BATCH_SIZE: usize = 10;
@@ -330,7 +684,7 @@ mod tests {
     #[test]
     fn test_global_queue_with_local() {
         let global_queue = MutexVecQueue::new();
-        let (mut producer, _) = crate::spmc::new_bounded::<_, 256>();
+        let (producer, _) = crate::spmc::new_bounded::<_, 256>();
 
         for i in 0..N / BATCH_SIZE {
             let slice = (0..BATCH_SIZE - 1)
@@ -338,11 +692,11 @@ mod tests {
                 .collect::<Vec<_>>();
             let one_more_value = i * BATCH_SIZE + BATCH_SIZE - 1;
 
-            let _ = global_queue.push_many_and_one(&slice[..2], &slice[2..], one_more_value);
+            global_queue.push_many_and_one(&slice[..2], &slice[2..], one_more_value);
         }
 
         for i in 0..N / BATCH_SIZE {
-            global_queue.move_batch_to_producer(&mut producer, BATCH_SIZE);
+            global_queue.move_batch_to_producer(&producer, BATCH_SIZE);
 
             for j in 0..BATCH_SIZE {
                 let index = i * BATCH_SIZE + j;
@@ -351,4 +705,198 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_global_queue_push_many_and_one_drops_value_once() {
+        let global_queue = MutexVecQueue::new();
+        let value = Rc::new(());
+        // The receiver takes the values of the slices, so they must not be dropped here.
+        let batch = ManuallyDrop::new([value.clone(), value.clone()]);
+
+        global_queue.push_many_and_one(&batch[..1], &batch[1..], value.clone());
+
+        assert_eq!(Rc::strong_count(&value), 4);
+
+        let batch = ManuallyDrop::new([value.clone(), value.clone()]);
+
+        global_queue
+            .try_push_many_and_one(&batch[..1], &batch[1..], value.clone())
+            .unwrap();
+
+        assert_eq!(Rc::strong_count(&value), 7);
+
+        drop(global_queue);
+
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_mutex_vec_queue_consumer_api() {
+        let queue = MutexVecQueue::new();
+
+        for i in 0..100 {
+            queue.push(i);
+        }
+
+        assert_eq!(queue.pop(), Some(0));
+
+        let mut slice = [MaybeUninit::uninit(); 10];
+
+        assert_eq!(queue.pop_many(&mut slice), 10);
+
+        for (i, value) in slice[..10].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, i + 1);
+        }
+
+        let mut vec = vec![0];
+
+        assert_eq!(queue.pop_into_vec(&mut vec, 9), 9);
+        assert_eq!(vec, [0, 11, 12, 13, 14, 15, 16, 17, 18, 19]);
+
+        let mut drain = queue.drain();
+
+        assert!(queue.is_empty());
+        assert_eq!(drain.next(), Some(20));
+
+        queue.push(100);
+
+        assert_eq!(drain.collect::<Vec<_>>(), (21..100).collect::<Vec<_>>());
+        assert_eq!(queue.take_all(), [100]);
+        assert_eq!(queue.take_all(), []);
+        assert_eq!(queue.pop(), None);
+
+        // Not drained values are dropped
+        let queue = MutexVecQueue::new();
+
+        for i in 0..10 {
+            queue.push(i.to_string());
+        }
+
+        assert_eq!(queue.drain().next().as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn test_mutex_vec_queue_capacity() {
+        let queue = MutexVecQueue::with_capacity(20);
+
+        assert_eq!(queue.capacity(), 32);
+        assert_eq!(queue.max_len(), None);
+
+        queue.reserve(100);
+
+        assert_eq!(queue.capacity(), 128);
+
+        for i in 0..1000 {
+            queue.push(i);
+        }
+
+        assert_eq!(queue.capacity(), 1024);
+
+        // It doesn't shrink until the queue is less than a quarter full.
+        let mut values = Vec::new();
+
+        queue.pop_into_vec(&mut values, 700);
+
+        assert_eq!(queue.capacity(), 1024);
+
+        queue.pop_into_vec(&mut values, 100);
+
+        assert_eq!(queue.capacity(), 512);
+        assert_eq!(queue.pop(), Some(800));
+
+        // It never shrinks below the initial capacity automatically.
+        queue.take_all();
+
+        assert_eq!(queue.capacity(), 32);
+
+        queue.shrink_to_fit();
+
+        assert_eq!(queue.capacity(), 1);
+
+        for i in 0..3 {
+            queue.push(i);
+        }
+
+        queue.shrink_to_fit();
+
+        assert_eq!(queue.capacity(), 4);
+        assert_eq!(queue.take_all(), [0, 1, 2]);
+        assert_eq!(queue.try_reserve(usize::MAX), Err(AllocError::CapacityOverflow));
+        assert_eq!(queue.try_reserve(10), Ok(()));
+        assert_eq!(queue.capacity(), 16);
+        assert_eq!(
+            MutexVecQueue::<u64>::try_with_capacity(usize::MAX).err(),
+            Some(AllocError::CapacityOverflow)
+        );
+        assert_eq!(
+            MutexVecQueue::<u64>::try_with_capacity(20).map(|queue| queue.capacity()),
+            Ok(32)
+        );
+    }
+
+    #[test]
+    fn test_mutex_vec_queue_zst() {
+        let queue = MutexVecQueue::with_capacity(4);
+
+        test_zst_queue(
+            None,
+            |value| queue.try_push(value),
+            |dst| queue.pop_many(dst),
+            || queue.len(),
+            || queue.move_batch_to(&new_bounded::<_, 256>().0, BATCH_SIZE),
+        );
+
+        queue.shrink_to_fit();
+
+        assert_eq!(queue.capacity(), 1);
+    }
+
+    #[test]
+    fn test_mutex_vec_queue_max_len() {
+        let queue = MutexVecQueue::with_capacity_and_max_len(4, 10);
+
+        assert_eq!(queue.max_len(), Some(10));
+
+        for i in 0..8 {
+            queue.push(i);
+        }
+
+        assert_eq!(queue.try_push_many_and_one(&[8], &[9], 10), Err(10));
+        assert_eq!(queue.try_push_many_and_slice(&[8], &[9], &[10]), Err(()));
+        assert_eq!(queue.try_push_many_and_one(&[8], &[], 9), Ok(()));
+        assert_eq!(queue.try_push(10), Err(10));
+
+        let result = std::panic::catch_unwind(|| queue.push(10));
+
+        assert!(result.is_err());
+        assert_eq!(queue.take_all(), (0..10).collect::<Vec<_>>());
+
+        // A drained queue keeps the limits.
+        for i in 0..10 {
+            queue.push(i);
+        }
+
+        assert_eq!(queue.drain().count(), 10);
+        assert_eq!(queue.max_len(), Some(10));
+
+        // The overflow of a local queue exceeds the `max_len` instead of failing.
+        let (producer, _) = new_bounded::<_, 4>();
+
+        for i in 0..10 {
+            queue.push(i);
+        }
+
+        for i in 10..15 {
+            producer.push(i, &queue);
+        }
+
+        assert_eq!(queue.len(), 13);
+        assert_eq!(queue.try_push(15), Err(15));
+        assert_eq!(
+            queue.take_all(),
+            (0..10).chain([10, 11, 14]).collect::<Vec<_>>()
+        );
+        assert_eq!(producer.pop(), Some(12));
+        assert_eq!(producer.pop(), Some(13));
+    }
 }
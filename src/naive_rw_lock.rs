@@ -87,12 +87,13 @@ impl<T, AtomicWrapper: Deref<Target = AtomicI32> + Default> NaiveRWLock<T, Atomi
     }
 
     /// Returns a mutable reference to the underlying data.
+    #[allow(dead_code, reason = "It is a part of the lock API")]
     pub fn get_mut(&mut self) -> &mut T {
         unsafe { &mut *self.data.get() }
     }
 
     /// Tries to acquire a read lock. Returns `None` if a write lock is held.
-    pub fn try_read(&self) -> Option<NaiveRWLockReadGuard<T, AtomicWrapper>> {
+    pub fn try_read(&self) -> Option<NaiveRWLockReadGuard<'_, T, AtomicWrapper>> {
         let mut state = self.state.load(Ordering::Acquire);
 
         loop {
@@ -115,7 +116,7 @@ impl<T, AtomicWrapper: Deref<Target = AtomicI32> + Default> NaiveRWLock<T, Atomi
     }
 
     /// Acquires a write lock. Blocks until the lock is available.
-    pub fn write(&self) -> NaiveRWLockWriteGuard<T, AtomicWrapper> {
+    pub fn write(&self) -> NaiveRWLockWriteGuard<'_, T, AtomicWrapper> {
         let backoff = Backoff::new();
 
         loop {
@@ -1,4 +1,4 @@
-use crate::loom_bindings::sync::atomic::{AtomicI32, AtomicU32, AtomicU64};
+use crate::loom_bindings::sync::atomic::{AtomicI32, AtomicU32, AtomicU64, AtomicUsize};
 use std::ops::Deref;
 
 #[cfg(target_has_atomic = "64")]
@@ -40,6 +40,38 @@ pub type CachePaddedLongAtomic = crate::cache_padded::CachePaddedAtomicU32;
 /// Synonym for the longest atomic.
 pub type CachePaddedLongAtomic = crate::cache_padded::CachePaddedAtomicU16;
 
+#[cfg(not(feature = "wide_unbounded_index"))]
+/// Synonym for the unsigned number of the head and the tail of unbounded queues.
+///
+/// It is `u64` with the `wide_unbounded_index` feature.
+pub type UnboundedIndex = u32;
+#[cfg(feature = "wide_unbounded_index")]
+/// Synonym for the unsigned number of the head and the tail of unbounded queues.
+///
+/// It is `u32` without the `wide_unbounded_index` feature.
+pub type UnboundedIndex = u64;
+
+#[cfg(not(feature = "wide_unbounded_index"))]
+/// Synonym for the atomic [`UnboundedIndex`].
+pub type AtomicUnboundedIndex = AtomicU32;
+#[cfg(feature = "wide_unbounded_index")]
+/// Synonym for the atomic [`UnboundedIndex`].
+pub type AtomicUnboundedIndex = AtomicU64;
+
+#[cfg(not(feature = "wide_unbounded_index"))]
+/// Synonym for the cache padded [`AtomicUnboundedIndex`].
+pub type CachePaddedAtomicUnboundedIndex = crate::cache_padded::CachePaddedAtomicU32;
+#[cfg(feature = "wide_unbounded_index")]
+/// Synonym for the cache padded [`AtomicUnboundedIndex`].
+pub type CachePaddedAtomicUnboundedIndex = crate::cache_padded::CachePaddedAtomicU64;
+
+#[cfg(not(feature = "wide_unbounded_index"))]
+/// Synonym for the non-cache padded [`AtomicUnboundedIndex`].
+pub type NotCachePaddedAtomicUnboundedIndex = NotCachePaddedAtomicU32;
+#[cfg(feature = "wide_unbounded_index")]
+/// Synonym for the non-cache padded [`AtomicUnboundedIndex`].
+pub type NotCachePaddedAtomicUnboundedIndex = NotCachePaddedAtomicU64;
+
 /// Synonym for the non-cache padded longest atomic.
 pub struct NotCachePaddedLongAtomic(LongAtomic);
 
@@ -91,6 +123,23 @@ impl Default for NotCachePaddedAtomicU64 {
     }
 }
 
+/// Synonym for the non-cache padded [`AtomicUsize`].
+pub struct NotCachePaddedAtomicUsize(AtomicUsize);
+
+impl Deref for NotCachePaddedAtomicUsize {
+    type Target = AtomicUsize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Default for NotCachePaddedAtomicUsize {
+    fn default() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+}
+
 pub struct NonCachePaddedAtomicI32 {
     atomic: AtomicI32,
 }
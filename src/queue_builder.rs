@@ -0,0 +1,404 @@
+//! This module provides the [`QueueBuilder`] of [`spsc`](crate::spsc)
+//! and [`spmc`](crate::spmc) queues.
+use crate::growth_policy::GrowthPolicy;
+use crate::{spmc, spsc};
+
+/// The default minimum number of values a consumer steals at once.
+///
+/// We don't steal less than 4 by default
+/// because else we may lose more because of cache locality and NUMA awareness.
+/// With the `always_steal` feature, consumers steal even one value.
+pub(crate) const DEFAULT_STEAL_THRESHOLD: usize =
+    if cfg!(feature = "always_steal") { 1 } else { 4 };
+
+/// A builder of [`spsc`](crate::spsc) and [`spmc`](crate::spmc) queues.
+///
+/// It collects the configuration of the queue,
+/// and the `spsc_*` and `spmc_*` methods select the family, the kind of the queue
+/// and the cache padding.
+/// They return the same producers and consumers as the `new_*` functions of these modules.
+///
+/// - The [`growth policy`](Self::with_growth_policy) and
+///   the [`initial capacity`](Self::with_initial_capacity) are used only by unbounded queues.
+///   Bounded and segmented queues ignore them: bounded queues accept the capacity
+///   as a const generic parameter, and segmented queues grow by fixed-size segments.
+/// - The [`steal threshold`](Self::with_steal_threshold) is the minimum number of values
+///   a consumer steals at once.
+/// - An unbounded queue overflows like a bounded queue at the maximum capacity
+///   if it is built by [`spmc_capped_unbounded`](Self::spmc_capped_unbounded)
+///   or its growth policy has a [`maximum capacity`](GrowthPolicy::with_max_capacity).
+/// - The builder doesn't select the [`OverflowPolicy`](spmc::OverflowPolicy),
+///   and the built producers don't store it: it is passed to each call of
+///   [`push_with_policy`](spmc::Producer::push_with_policy) and
+///   [`push_many_with_policy`](spmc::Producer::push_many_with_policy).
+///
+/// # Example
+///
+#[cfg_attr(not(feature = "disable_unbounded"), doc = "```")]
+#[cfg_attr(feature = "disable_unbounded", doc = "```ignore")]
+/// use parcoll::{GrowthPolicy, QueueBuilder};
+/// use parcoll::spmc::{Consumer, Producer};
+///
+/// let builder = QueueBuilder::new()
+///     .with_initial_capacity(64)
+///     .with_steal_threshold(1);
+/// let (producer, consumer) = builder.spmc_cache_padded_unbounded();
+/// let (producer2, _) = builder.spmc_cache_padded_unbounded::<usize>();
+///
+/// assert_eq!(producer.capacity(), 64);
+///
+/// producer.maybe_push(1).unwrap();
+/// producer.maybe_push(2).unwrap();
+///
+/// assert_eq!(consumer.steal_into(&producer2), 1);
+///
+/// let (bounded_producer, _) = builder.spmc_bounded::<usize, 256>();
+///
+/// assert_eq!(bounded_producer.capacity(), 256);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueBuilder {
+    growth_policy: GrowthPolicy,
+    steal_threshold: usize,
+}
+
+impl QueueBuilder {
+    /// Creates a builder with the default [`GrowthPolicy`] and the default steal threshold
+    /// (4 values or 1 value with the `always_steal` feature).
+    pub const fn new() -> Self {
+        Self {
+            growth_policy: GrowthPolicy::new(),
+            steal_threshold: DEFAULT_STEAL_THRESHOLD,
+        }
+    }
+
+    /// Returns the builder with the given [`GrowthPolicy`] of unbounded queues.
+    /// Bounded and segmented queues ignore it.
+    ///
+    /// It replaces the initial capacity set before.
+    #[must_use]
+    pub const fn with_growth_policy(mut self, growth_policy: GrowthPolicy) -> Self {
+        self.growth_policy = growth_policy;
+
+        self
+    }
+
+    /// Returns the builder with the given initial capacity of unbounded queues
+    /// rounded up to a power of two.
+    /// Bounded and segmented queues ignore it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded capacity is greater than the maximum capacity
+    /// of the [`GrowthPolicy`].
+    #[must_use]
+    pub const fn with_initial_capacity(mut self, initial_capacity: usize) -> Self {
+        self.growth_policy = self.growth_policy.with_initial_capacity(initial_capacity);
+
+        self
+    }
+
+    /// Returns the builder with the given minimum number of values a consumer steals at once.
+    ///
+    /// Stealing a few values can cost more than it saves
+    /// because of cache locality and NUMA awareness.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `steal_threshold` is zero.
+    #[must_use]
+    pub const fn with_steal_threshold(mut self, steal_threshold: usize) -> Self {
+        assert!(
+            steal_threshold > 0,
+            "the steal threshold must be greater than zero"
+        );
+
+        self.steal_threshold = steal_threshold;
+
+        self
+    }
+
+    /// Returns the [`GrowthPolicy`] of unbounded queues.
+    pub const fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy
+    }
+
+    /// Returns the minimum number of values a consumer steals at once.
+    pub const fn steal_threshold(&self) -> usize {
+        self.steal_threshold
+    }
+
+    /// Builds a single-producer, multi-consumer bounded queue.
+    /// It ignores the growth policy and the initial capacity of the builder.
+    ///
+    /// Read more about the queue in [`spmc::new_bounded`].
+    pub fn spmc_bounded<T, const CAPACITY: usize>(
+        self,
+    ) -> (
+        spmc::SPMCProducer<T, CAPACITY>,
+        spmc::SPMCConsumer<T, CAPACITY>,
+    ) {
+        spmc::SPMCProducer::new_queue(self.steal_threshold)
+    }
+
+    /// Builds a single-producer, multi-consumer cache-padded bounded queue.
+    /// It ignores the growth policy and the initial capacity of the builder.
+    ///
+    /// Read more about the queue in [`spmc::new_cache_padded_bounded`].
+    pub fn spmc_cache_padded_bounded<T, const CAPACITY: usize>(
+        self,
+    ) -> (
+        spmc::CachePaddedSPMCProducer<T, CAPACITY>,
+        spmc::CachePaddedSPMCConsumer<T, CAPACITY>,
+    ) {
+        spmc::CachePaddedSPMCProducer::new_queue(self.steal_threshold)
+    }
+
+    /// Builds a single-producer, multi-consumer unbounded queue.
+    ///
+    /// Read more about the queue in [`spmc::new_unbounded`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spmc_unbounded<T>(
+        self,
+    ) -> (
+        spmc::SPMCUnboundedProducer<T>,
+        spmc::SPMCUnboundedConsumer<T>,
+    ) {
+        spmc::SPMCUnboundedProducer::new_queue(self.growth_policy, false, self.steal_threshold)
+    }
+
+    /// Builds a single-producer, multi-consumer cache-padded unbounded queue.
+    ///
+    /// Read more about the queue in [`spmc::new_cache_padded_unbounded`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spmc_cache_padded_unbounded<T>(
+        self,
+    ) -> (
+        spmc::CachePaddedSPMCUnboundedProducer<T>,
+        spmc::CachePaddedSPMCUnboundedConsumer<T>,
+    ) {
+        spmc::CachePaddedSPMCUnboundedProducer::new_queue(
+            self.growth_policy,
+            false,
+            self.steal_threshold,
+        )
+    }
+
+    /// Builds a single-producer, multi-consumer capped unbounded queue
+    /// that overflows like a bounded queue at the maximum capacity.
+    ///
+    /// Read more about the queue in [`spmc::new_capped_unbounded`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spmc_capped_unbounded<T>(
+        self,
+    ) -> (
+        spmc::SPMCUnboundedProducer<T>,
+        spmc::SPMCUnboundedConsumer<T>,
+    ) {
+        spmc::SPMCUnboundedProducer::new_queue(self.growth_policy, true, self.steal_threshold)
+    }
+
+    /// Builds a single-producer, multi-consumer cache-padded capped unbounded queue
+    /// that overflows like a bounded queue at the maximum capacity.
+    ///
+    /// Read more about the queue in [`spmc::new_cache_padded_capped_unbounded`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spmc_cache_padded_capped_unbounded<T>(
+        self,
+    ) -> (
+        spmc::CachePaddedSPMCUnboundedProducer<T>,
+        spmc::CachePaddedSPMCUnboundedConsumer<T>,
+    ) {
+        spmc::CachePaddedSPMCUnboundedProducer::new_queue(
+            self.growth_policy,
+            true,
+            self.steal_threshold,
+        )
+    }
+
+    /// Builds a single-producer, multi-consumer unbounded queue of linked fixed-size segments.
+    /// It ignores the growth policy and the initial capacity of the builder.
+    ///
+    /// Read more about the queue in [`spmc::new_segmented`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spmc_segmented<T>(
+        self,
+    ) -> (
+        spmc::SPMCSegmentedProducer<T>,
+        spmc::SPMCSegmentedConsumer<T>,
+    ) {
+        spmc::SPMCSegmentedProducer::new_queue(self.steal_threshold)
+    }
+
+    /// Builds a single-producer, multi-consumer cache-padded unbounded queue
+    /// of linked fixed-size segments.
+    /// It ignores the growth policy and the initial capacity of the builder.
+    ///
+    /// Read more about the queue in [`spmc::new_cache_padded_segmented`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spmc_cache_padded_segmented<T>(
+        self,
+    ) -> (
+        spmc::CachePaddedSPMCSegmentedProducer<T>,
+        spmc::CachePaddedSPMCSegmentedConsumer<T>,
+    ) {
+        spmc::CachePaddedSPMCSegmentedProducer::new_queue(self.steal_threshold)
+    }
+
+    /// Builds a single-producer, single-consumer bounded queue.
+    /// It ignores the growth policy and the initial capacity of the builder.
+    ///
+    /// Read more about the queue in [`spsc::new_bounded`].
+    pub fn spsc_bounded<T, const CAPACITY: usize>(
+        self,
+    ) -> (
+        spsc::SPSCProducer<T, CAPACITY>,
+        spsc::SPSCConsumer<T, CAPACITY>,
+    ) {
+        spsc::SPSCProducer::new_queue(self.steal_threshold)
+    }
+
+    /// Builds a single-producer, single-consumer cache-padded bounded queue.
+    /// It ignores the growth policy and the initial capacity of the builder.
+    ///
+    /// Read more about the queue in [`spsc::new_cache_padded_bounded`].
+    pub fn spsc_cache_padded_bounded<T, const CAPACITY: usize>(
+        self,
+    ) -> (
+        spsc::CachePaddedSPSCProducer<T, CAPACITY>,
+        spsc::CachePaddedSPSCConsumer<T, CAPACITY>,
+    ) {
+        spsc::CachePaddedSPSCProducer::new_queue(self.steal_threshold)
+    }
+
+    /// Builds a single-producer, single-consumer unbounded queue.
+    ///
+    /// Read more about the queue in [`spsc::new_unbounded`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spsc_unbounded<T>(
+        self,
+    ) -> (
+        spsc::SPSCUnboundedProducer<T>,
+        spsc::SPSCUnboundedConsumer<T>,
+    ) {
+        spsc::SPSCUnboundedProducer::new_queue(self.growth_policy, self.steal_threshold)
+    }
+
+    /// Builds a single-producer, single-consumer cache-padded unbounded queue.
+    ///
+    /// Read more about the queue in [`spsc::new_cache_padded_unbounded`].
+    #[cfg(not(feature = "disable_unbounded"))]
+    pub fn spsc_cache_padded_unbounded<T>(
+        self,
+    ) -> (
+        spsc::CachePaddedSPSCUnboundedProducer<T>,
+        spsc::CachePaddedSPSCUnboundedConsumer<T>,
+    ) {
+        spsc::CachePaddedSPSCUnboundedProducer::new_queue(self.growth_policy, self.steal_threshold)
+    }
+}
+
+impl Default for QueueBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spmc::{Consumer as _, Producer as _};
+    use crate::spsc::{Consumer as _, Producer as _};
+
+    #[test]
+    fn test_queue_builder_steal_threshold() {
+        let builder = QueueBuilder::new()
+            .with_initial_capacity(16)
+            .with_steal_threshold(8);
+
+        let (producer, consumer) = builder.spmc_bounded::<usize, 64>();
+        let (producer2, _) = builder.spmc_bounded::<usize, 64>();
+
+        for i in 0..10 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(consumer.steal_into(&producer2), 0);
+
+        #[cfg(not(feature = "disable_unbounded"))]
+        {
+            let (producer, consumer) = builder.with_steal_threshold(5).spmc_unbounded();
+            let (producer2, _) = builder.spmc_unbounded::<usize>();
+
+            for i in 0..10 {
+                producer.maybe_push(i).unwrap();
+            }
+
+            assert_eq!(consumer.steal_into(&producer2), 5);
+
+            let (producer, consumer) = builder.with_steal_threshold(3).spmc_segmented();
+            let (producer2, _) = builder.spmc_segmented::<usize>();
+
+            for i in 0..4 {
+                producer.maybe_push(i).unwrap();
+            }
+
+            assert_eq!(consumer.steal_into(&producer2), 0);
+
+            for i in 4..6 {
+                producer.maybe_push(i).unwrap();
+            }
+
+            assert_eq!(consumer.steal_into(&producer2), 3);
+        }
+
+        let (producer, consumer) = builder.spsc_bounded::<usize, 64>();
+        let (producer2, _) = builder.spsc_bounded::<usize, 64>();
+
+        for i in 0..10 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(consumer.steal_into(&producer2), 0);
+
+        #[cfg(not(feature = "disable_unbounded"))]
+        {
+            let (producer, consumer) = builder.with_steal_threshold(1).spsc_unbounded();
+            let (producer2, consumer2) = builder.spsc_unbounded::<usize>();
+
+            for i in 0..2 {
+                producer.maybe_push(i).unwrap();
+            }
+
+            assert_eq!(consumer.steal_into(&producer2), 1);
+            assert_eq!(consumer2.pop(), Some(0));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable_unbounded"))]
+    fn test_queue_builder_growth_policy() {
+        let builder = QueueBuilder::new()
+            .with_growth_policy(GrowthPolicy::new().with_max_capacity(32))
+            .with_initial_capacity(10);
+
+        assert_eq!(builder.growth_policy().initial_capacity(), 16);
+        assert_eq!(builder.growth_policy().max_capacity(), 32);
+
+        let (producer, _) = builder.spmc_cache_padded_capped_unbounded();
+
+        assert_eq!(producer.capacity(), 16);
+
+        for i in 0..32 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(producer.capacity(), 32);
+        assert_eq!(producer.maybe_push(32), Err(32));
+
+        let (producer, _) = builder.spsc_cache_padded_unbounded::<usize>();
+
+        assert_eq!(producer.capacity(), 16);
+        assert_eq!(producer.growth_policy(), builder.growth_policy());
+    }
+}
@@ -0,0 +1,272 @@
+//! This module provides the [`ShardedMutexVecQueue`].
+use crate::loom_bindings::rand;
+use crate::loom_bindings::sync::atomic::AtomicUsize;
+use crate::loom_bindings::sync::{Arc, Mutex};
+use crate::loom_bindings::sys::num_cpus;
+use crate::mutex_vec_queue::VecQueue;
+use crate::spmc::Producer;
+use crate::sync_batch_receiver::SyncBatchReceiver;
+use crate::sync_batch_source::SyncBatchSource;
+use std::cell::Cell;
+use std::sync::atomic::Ordering::Relaxed;
+
+thread_local! {
+    /// A random seed of the current thread that selects its shard.
+    static THREAD_SEED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Returns the random seed of the current thread.
+fn thread_seed() -> u64 {
+    THREAD_SEED.with(|seed| {
+        seed.get().unwrap_or_else(|| {
+            let new_seed = rand::seed();
+
+            seed.set(Some(new_seed));
+
+            new_seed
+        })
+    })
+}
+
+/// A shard of the [`ShardedMutexVecQueue`].
+/// It is aligned to avoid false sharing between the locks of different shards.
+#[repr(align(128))]
+struct Shard<T> {
+    queue: Mutex<VecQueue<T>>,
+}
+
+struct Inner<T> {
+    shards: Box<[Shard<T>]>,
+    next_refill_shard: AtomicUsize,
+}
+
+/// A multi-producer multi-consumer queue that consists of
+/// independently locked shards.
+///
+/// It is used as the global queue in the same role as the
+/// [`MutexVecQueue`](crate::MutexVecQueue), but it cuts the lock contention:
+///
+/// - each thread pushes to its own shard, which is selected randomly once per thread,
+///   so the batches of one thread are kept in order;
+/// - refills scan the shards round-robin starting from the next shard for each refill.
+///
+/// The queue is FIFO only per shard, so the global order is best-effort.
+#[derive(Clone)]
+pub struct ShardedMutexVecQueue<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> ShardedMutexVecQueue<T> {
+    /// Creates a new [`ShardedMutexVecQueue`] with one shard per available CPU.
+    pub fn new() -> Self {
+        Self::with_shards(num_cpus())
+    }
+
+    /// Creates a new [`ShardedMutexVecQueue`] with the given number of shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number_of_shards` is zero.
+    pub fn with_shards(number_of_shards: usize) -> Self {
+        assert!(
+            number_of_shards > 0,
+            "number of shards must be greater than zero"
+        );
+
+        Self {
+            inner: Arc::new(Inner {
+                shards: (0..number_of_shards)
+                    .map(|_| Shard {
+                        queue: Mutex::new(VecQueue::new()),
+                    })
+                    .collect(),
+                next_refill_shard: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn number_of_shards(&self) -> usize {
+        self.inner.shards.len()
+    }
+
+    /// Returns the shard of the current thread.
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "The remainder is less than the number of shards"
+    )]
+    fn current_shard(&self) -> &Mutex<VecQueue<T>> {
+        let shards = &self.inner.shards;
+
+        &shards[(thread_seed() % shards.len() as u64) as usize].queue
+    }
+
+    /// Returns the number of values in all shards.
+    ///
+    /// The shards are locked one by one, so the result can be stale.
+    pub fn len(&self) -> usize {
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| shard.queue.lock().len())
+            .sum()
+    }
+
+    /// Returns whether all shards are empty.
+    ///
+    /// The shards are locked one by one, so the result can be stale.
+    pub fn is_empty(&self) -> bool {
+        self.inner
+            .shards
+            .iter()
+            .all(|shard| shard.queue.lock().is_empty())
+    }
+
+    /// Pushes the value to the shard of the current thread.
+    pub fn push(&self, value: T) {
+        self.current_shard().lock().push(value);
+    }
+
+    /// Moves up to `limit` values to the `producer`.
+    /// Returns the number of moved values.
+    ///
+    /// It scans the shards round-robin and locks each shard at most once.
+    /// It moves no more than [`producer.free_slots()`](Producer::free_slots) values.
+    pub fn move_batch_to_producer(&self, producer: &impl Producer<T>, limit: usize) -> usize {
+        let shards = &self.inner.shards;
+        let start = self.inner.next_refill_shard.fetch_add(1, Relaxed);
+        let mut moved = 0;
+
+        for i in 0..shards.len() {
+            if moved == limit || producer.free_slots() == 0 {
+                break;
+            }
+
+            let shard = &shards[start.wrapping_add(i) % shards.len()];
+
+            let mut queue = shard.queue.lock();
+
+            moved += queue.move_batch_to_producer(producer, limit - moved);
+
+            queue.shrink_if_sparse();
+        }
+
+        moved
+    }
+}
+
+impl<T> Default for ShardedMutexVecQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T> SyncBatchReceiver<T> for ShardedMutexVecQueue<T> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        let mut shard = self.current_shard().lock();
+
+        shard.extend_from_slice(first);
+        shard.extend_from_slice(last);
+        shard.push(value);
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        let mut shard = self.current_shard().lock();
+
+        shard.extend_from_slice(first);
+        shard.extend_from_slice(last);
+        shard.extend_from_slice(slice);
+    }
+}
+
+impl<T> SyncBatchSource<T> for ShardedMutexVecQueue<T> {
+    fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize {
+        self.move_batch_to_producer(producer, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spmc::new_bounded;
+    use crate::zst_test::test_zst_queue;
+
+    #[test]
+    fn test_sharded_mutex_vec_queue() {
+        const THREADS: usize = 4;
+        const N: usize = 10_000;
+
+        let queue = ShardedMutexVecQueue::with_shards(3);
+
+        assert_eq!(queue.number_of_shards(), 3);
+
+        // Values of one thread are in one shard, so they are in order.
+        for i in 0..10 {
+            queue.push(i);
+        }
+
+        queue.push_many_and_slice(&[10, 11], &[12], &[13, 14]);
+
+        let (producer, _) = new_bounded::<_, 16>();
+
+        assert_eq!(queue.move_batch_to_producer(&producer, 100), 15);
+
+        for i in 0..15 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+
+        assert!(queue.is_empty());
+
+        std::thread::scope(|scope| {
+            for t in 0..THREADS {
+                let queue = &queue;
+
+                scope.spawn(move || {
+                    for i in (0..N).step_by(4) {
+                        let value = t * N + i;
+
+                        queue.push_many_and_one(&[value], &[value + 1, value + 2], value + 3);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(queue.len(), THREADS * N);
+
+        let (producer, _) = new_bounded::<_, 64>();
+        let mut popped = Vec::with_capacity(THREADS * N);
+
+        while queue.move_batch_to(&producer, 48) > 0 {
+            while let Some(value) = producer.pop() {
+                popped.push(value);
+            }
+        }
+
+        popped.sort_unstable();
+
+        assert_eq!(popped, (0..THREADS * N).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sharded_mutex_vec_queue_zst() {
+        let queue = ShardedMutexVecQueue::with_shards(3);
+
+        test_zst_queue(
+            None,
+            |value| {
+                queue.push(value);
+
+                Ok(())
+            },
+            |dst| {
+                let (producer, _) = new_bounded::<_, 16>();
+
+                queue.move_batch_to(&producer, dst.len());
+
+                producer.pop_many(dst)
+            },
+            || queue.len(),
+            || queue.move_batch_to(&new_bounded::<_, 256>().0, 100),
+        );
+    }
+}
@@ -6,15 +6,23 @@
     clippy::cast_possible_truncation,
     reason = "LongNumber should be synonymous to usize"
 )]
-use crate::hints::unlikely;
+use crate::alloc_error::{try_alloc_array, AllocError};
+use crate::const_bounded_ring::ConstBoundedRing;
+use crate::hints::{likely, unlikely};
 use crate::light_arc::LightArc;
 use crate::number_types::{
     CachePaddedLongAtomic, LongAtomic, LongNumber, NotCachePaddedLongAtomic,
 };
-use crate::spmc::{Consumer, ConsumerSpawner, Producer};
+use crate::queue_builder::DEFAULT_STEAL_THRESHOLD;
+use crate::spmc::{
+    copy_to_reserved_slots, reserve_slots_up_to, Consumer, ConsumerSpawner, OverflowAction,
+    OverflowPolicy, Producer,
+};
 use crate::sync_batch_receiver::SyncBatchReceiver;
+use crate::sync_batch_source::{move_batch_from_consumer, SyncBatchSource};
+use std::cell::UnsafeCell;
 use std::marker::PhantomData;
-use std::mem::{needs_drop, MaybeUninit};
+use std::mem::{self, needs_drop, MaybeUninit};
 use std::ops::Deref;
 use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
 use std::{ptr, slice};
@@ -60,26 +68,51 @@ pub struct SPMCBoundedQueue<
     tail: AtomicWrapper,
     head: AtomicWrapper,
     buffer: *mut [MaybeUninit<T>; CAPACITY],
+    /// Consumers don't steal less than this number of values at once.
+    steal_threshold: usize,
+    /// The number of slots returned by the last
+    /// [`producer_reserve_slots`](Self::producer_reserve_slots)
+    /// that are not committed yet.
+    /// Only the producer can access it.
+    reserved_slots: UnsafeCell<usize>,
 }
 
 impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default>
     SPMCBoundedQueue<T, CAPACITY, AtomicWrapper>
 {
-    /// Indicates how many elements we are taking from the local queue.
-    ///
-    /// This is one less than the number of values pushed to the global
-    /// queue (or any other `SyncBatchReceiver`) as we are also inserting the `value` argument.
-    const NUM_VALUES_TAKEN: LongNumber = CAPACITY as LongNumber / 2;
-
     /// Creates a new [`SPMCBoundedQueue`].
+    ///
+    /// It calls [`handle_alloc_error`](std::alloc::handle_alloc_error) if the allocation fails.
     pub fn new() -> Self {
+        Self::with_steal_threshold(DEFAULT_STEAL_THRESHOLD)
+    }
+
+    /// Creates a new [`SPMCBoundedQueue`] or returns the [`AllocError`] if the allocation fails.
+    pub fn try_new() -> Result<Self, AllocError> {
+        Self::try_with_steal_threshold(DEFAULT_STEAL_THRESHOLD)
+    }
+
+    /// Creates a new [`SPMCBoundedQueue`] whose consumers
+    /// don't steal less than `steal_threshold` values at once.
+    pub(crate) fn with_steal_threshold(steal_threshold: usize) -> Self {
+        Self::try_with_steal_threshold(steal_threshold).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Creates a new [`SPMCBoundedQueue`] whose consumers
+    /// don't steal less than `steal_threshold` values at once,
+    /// or returns the [`AllocError`] if the allocation fails.
+    pub(crate) fn try_with_steal_threshold(steal_threshold: usize) -> Result<Self, AllocError> {
         debug_assert!(size_of::<MaybeUninit<T>>() == size_of::<T>()); // Assume that we can just cast it
 
-        Self {
-            buffer: Box::into_raw(Box::new([const { MaybeUninit::uninit() }; CAPACITY])),
+        Ok(Self {
+            buffer: try_alloc_array::<MaybeUninit<T>>(CAPACITY)?
+                .as_ptr()
+                .cast(),
             tail: AtomicWrapper::default(),
             head: AtomicWrapper::default(),
-        }
+            steal_threshold,
+            reserved_slots: UnsafeCell::new(0),
+        })
     }
 
     /// Returns the capacity of the queue.
@@ -87,21 +120,23 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
     pub fn capacity(&self) -> usize {
         CAPACITY
     }
+}
 
-    /// Returns a pointer to the buffer.
-    fn buffer_thin_ptr(&self) -> *const MaybeUninit<T> {
-        unsafe { &*self.buffer }.as_ptr()
+impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default>
+    ConstBoundedRing<T> for SPMCBoundedQueue<T, CAPACITY, AtomicWrapper>
+{
+    const CAPACITY: usize = CAPACITY;
+
+    fn head(&self) -> &LongAtomic {
+        &self.head
     }
 
-    /// Returns a mutable pointer to the buffer.
-    fn buffer_mut_thin_ptr(&self) -> *mut MaybeUninit<T> {
-        unsafe { &mut *self.buffer }.as_mut_ptr()
+    fn tail(&self) -> &LongAtomic {
+        &self.tail
     }
 
-    /// Returns the number of elements in the queue.
-    #[inline]
-    fn len(head: LongNumber, tail: LongNumber) -> usize {
-        tail.wrapping_sub(head) as usize
+    fn buffer_mut_thin_ptr(&self) -> *mut MaybeUninit<T> {
+        unsafe { &mut *self.buffer }.as_mut_ptr()
     }
 }
 
@@ -109,30 +144,6 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
 impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default>
     SPMCBoundedQueue<T, CAPACITY, AtomicWrapper>
 {
-    /// Pushes a slice into the queue. Returns a new tail (not index).
-    fn copy_slice(buffer_ptr: *mut T, start_tail: LongNumber, slice: &[T]) -> LongNumber {
-        let tail_idx = start_tail as usize % CAPACITY;
-
-        if tail_idx + slice.len() <= CAPACITY {
-            unsafe {
-                ptr::copy_nonoverlapping(slice.as_ptr(), buffer_ptr.add(tail_idx), slice.len());
-            };
-        } else {
-            let right = CAPACITY - tail_idx;
-
-            unsafe {
-                ptr::copy_nonoverlapping(slice.as_ptr(), buffer_ptr.add(tail_idx), right);
-                ptr::copy_nonoverlapping(
-                    slice.as_ptr().add(right),
-                    buffer_ptr,
-                    slice.len() - right,
-                );
-            }
-        }
-
-        start_tail.wrapping_add(slice.len() as LongNumber)
-    }
-
     /// Return the number of elements in the queue.
     ///
     /// # Safety
@@ -268,157 +279,125 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
         self.tail.store(tail.wrapping_add(1), Release);
     }
 
-    /// Likely moves a half of the queue and one value to the [`SyncBatchReceiver`].
+    /// Drops the oldest values of the queue to make space for the `slice`,
+    /// passes them to the [`OverflowPolicy::on_drop`] and pushes the `slice`.
+    ///
+    /// If the `slice` is longer than the capacity, its first values are dropped too.
     #[inline(never)]
     #[cold]
-    fn handle_overflow_one<SBR: SyncBatchReceiver<T>>(
+    fn drop_oldest_and_push_many<OP: OverflowPolicy<T>>(
         &self,
         tail: LongNumber,
         mut head: LongNumber,
-        sbr: &SBR,
-        value: T,
+        slice: &[T],
+        policy: &OP,
     ) {
-        debug_assert!(tail == head.wrapping_add(CAPACITY as LongNumber) && tail > head);
+        let (dropped_slice, slice) = slice.split_at(slice.len().saturating_sub(CAPACITY));
 
         loop {
-            let head_idx = head as usize % CAPACITY;
-            let values_slice = unsafe { &*(self.buffer.cast::<[T; CAPACITY]>()) };
-
-            let (right, left): (&[T], &[T]) = if head_idx < Self::NUM_VALUES_TAKEN as usize {
-                // we can return only the right half of the queue
-                (
-                    &values_slice[head_idx..head_idx + Self::NUM_VALUES_TAKEN as usize],
-                    &[],
-                )
-            } else {
-                let left_part_len = head_idx - Self::NUM_VALUES_TAKEN as usize;
+            let len = Self::len(head, tail);
+            let n = (len + slice.len()).saturating_sub(CAPACITY);
 
-                (&values_slice[head_idx..], &values_slice[..left_part_len])
-            };
+            if n == 0 {
+                break;
+            }
 
-            // We haven't read the value yet, so we can use `compare_exchange_weak`.
-            //If it fails, we calculate two slices and try again, it is not a performance issue.
-            let res = self.head.compare_exchange_weak(
+            // We haven't read the values yet, so we can use `compare_exchange_weak`.
+            match self.head.compare_exchange_weak(
                 head,
-                head.wrapping_add(Self::NUM_VALUES_TAKEN),
+                head.wrapping_add(n as LongNumber),
                 Release,
                 Acquire,
-            );
-
-            match res {
-                Ok(_) => {}
-                Err(new_head) => {
-                    head = new_head;
-
-                    if Self::len(head, tail) < Self::NUM_VALUES_TAKEN as usize {
-                        // Another thread concurrently
-                        // stole from the queue.
-                        // Because we are the one producer,
-                        // we can just insert the value (it can't become full before we return).
-
-                        unsafe { self.push_unchecked(value, tail) };
+            ) {
+                Ok(_) => {
+                    // We are the only producer,
+                    // so we can don't worry about someone overwriting the values before we read them
+                    let (first, last) = self.oldest_values(head, n);
 
-                        return;
+                    for value in first.iter().chain(last) {
+                        policy.on_drop(unsafe { ptr::read(value) });
                     }
 
-                    continue;
+                    break;
+                }
+                Err(new_head) => {
+                    head = new_head;
                 }
             }
+        }
 
-            sbr.push_many_and_one(left, right, value);
-
-            return;
+        for value in dropped_slice {
+            policy.on_drop(unsafe { ptr::read(value) });
         }
+
+        let new_tail = Self::copy_slice(self.buffer_mut_thin_ptr().cast(), tail, slice);
+        self.tail.store(new_tail, Release);
     }
 
-    /// Likely moves a half of the queue and many values to the [`SyncBatchReceiver`].
-    #[inline(never)]
-    #[cold]
-    fn handle_overflow_many<SBR: SyncBatchReceiver<T>>(
+    /// Pushes a value to the queue or to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push<SBR: SyncBatchReceiver<T>>(
         &self,
-        tail: LongNumber,
-        mut head: LongNumber,
-        sbr: &SBR,
-        slice: &[T],
+        value: T,
+        sync_batch_receiver: &SBR,
     ) {
-        debug_assert!(tail == head.wrapping_add(CAPACITY as LongNumber) && tail > head);
-
-        loop {
-            let head_idx = head as usize % CAPACITY;
-            let values_slice = unsafe { &*(self.buffer.cast::<[T; CAPACITY]>()) };
-
-            let (right, left): (&[T], &[T]) = if head_idx < Self::NUM_VALUES_TAKEN as usize {
-                // we can return only the right half of the queue
-                (
-                    &values_slice[head_idx..head_idx + Self::NUM_VALUES_TAKEN as usize],
-                    &[],
-                )
-            } else {
-                let left_part_len = head_idx - Self::NUM_VALUES_TAKEN as usize;
-
-                (&values_slice[head_idx..], &values_slice[..left_part_len])
-            };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
 
-            // We haven't read the value yet, so we can use `compare_exchange_weak`.
-            //If it fails, we calculate two slices and try again, it is not a performance issue.
-            let res = self.head.compare_exchange_weak(
+        if unlikely(Self::len(head, tail) == CAPACITY) {
+            let _ = self.handle_overflow_one(
+                tail,
                 head,
-                head.wrapping_add(Self::NUM_VALUES_TAKEN),
-                Release,
-                Acquire,
-            );
-
-            match res {
-                Ok(_) => {}
-                Err(new_head) => {
-                    head = new_head;
-
-                    let len = Self::len(head, tail);
-
-                    if (len < Self::NUM_VALUES_TAKEN as usize) && len + slice.len() <= CAPACITY {
-                        // Another thread concurrently
-                        // stole from the queue.
-                        // Because we are the one producer,
-                        // we can just insert the slice (it can't become full before we return).
-
-                        let new_tail =
-                            Self::copy_slice(self.buffer_mut_thin_ptr().cast(), tail, slice);
-                        self.tail.store(new_tail, Release);
-
-                        return;
-                    }
-
-                    continue;
-                }
-            }
+                Self::NUM_VALUES_TAKEN as usize,
+                |first, last, value| {
+                    sync_batch_receiver.push_many_and_one(first, last, value);
 
-            sbr.push_many_and_slice(left, right, slice);
+                    Ok(())
+                },
+                value,
+            );
 
             return;
         }
+
+        unsafe { self.push_unchecked(value, tail) };
     }
 
-    /// Pushes a value to the queue or to the [`SyncBatchReceiver`].
+    /// Pushes a value to the queue or to the [`SyncBatchReceiver`]
+    /// with [`SyncBatchReceiver::try_push_many_and_one`].
+    ///
+    /// If the [`SyncBatchReceiver`] rejects the batch, the values taken from the head
+    /// are returned to the head of the queue, and the value is returned.
     ///
     /// # Safety
     ///
     /// The called should be the only producer.
     #[inline]
-    pub unsafe fn producer_push<SBR: SyncBatchReceiver<T>>(
+    pub unsafe fn producer_try_push<SBR: SyncBatchReceiver<T>>(
         &self,
         value: T,
         sync_batch_receiver: &SBR,
-    ) {
+    ) -> Result<(), T> {
         let head = self.head.load(Acquire);
         let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
 
         if unlikely(Self::len(head, tail) == CAPACITY) {
-            self.handle_overflow_one(tail, head, sync_batch_receiver, value);
-
-            return;
+            return self.handle_overflow_one(
+                tail,
+                head,
+                Self::NUM_VALUES_TAKEN as usize,
+                |first, last, value| sync_batch_receiver.try_push_many_and_one(first, last, value),
+                value,
+            );
         }
 
         unsafe { self.push_unchecked(value, tail) };
+
+        Ok(())
     }
 
     /// Pushes a value to the queue or returns an error.
@@ -482,7 +461,17 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
         let mut tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
 
         if unlikely(Self::len(head, tail) + slice.len() > CAPACITY) {
-            self.handle_overflow_many(tail, head, sync_batch_receiver, slice);
+            let _ = self.handle_overflow_many(
+                tail,
+                head,
+                Self::NUM_VALUES_TAKEN as usize,
+                |first, last, slice| {
+                    sync_batch_receiver.push_many_and_slice(first, last, slice);
+
+                    Ok(())
+                },
+                slice,
+            );
 
             return;
         }
@@ -492,6 +481,43 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
         self.tail.store(tail, Release);
     }
 
+    /// Pushes many values to the queue or to the [`SyncBatchReceiver`]
+    /// with [`SyncBatchReceiver::try_push_many_and_slice`].
+    ///
+    /// If the [`SyncBatchReceiver`] rejects the batch, the values taken from the head
+    /// are returned to the head of the queue, and the error is returned.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_try_push_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+    ) -> Result<(), ()> {
+        let head = self.head.load(Acquire);
+        let mut tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) + slice.len() > CAPACITY) {
+            return self.handle_overflow_many(
+                tail,
+                head,
+                Self::NUM_VALUES_TAKEN as usize,
+                |first, last, slice| {
+                    sync_batch_receiver.try_push_many_and_slice(first, last, slice)
+                },
+                slice,
+            );
+        }
+
+        tail = Self::copy_slice(self.buffer_mut_thin_ptr().cast(), tail, slice);
+
+        self.tail.store(tail, Release);
+
+        Ok(())
+    }
+
     /// Pushes many values to the queue or returns an error.
     ///
     /// # Safety
@@ -514,6 +540,212 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
 
         Ok(())
     }
+
+    /// Pushes a value to the queue. If the queue is full, it performs the action
+    /// of the [`OverflowPolicy`]. Read more in [`Producer::push_with_policy`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+    ) -> Result<(), T> {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        if likely(Self::len(head, tail) < CAPACITY) {
+            unsafe { self.push_unchecked(value, tail) };
+
+            return Ok(());
+        }
+
+        match overflow_policy.action(CAPACITY) {
+            OverflowAction::MoveToReceiver(n) => {
+                let _ = self.handle_overflow_one(
+                    tail,
+                    head,
+                    n,
+                    |first, last, value| {
+                        sync_batch_receiver.push_many_and_one(first, last, value);
+
+                        Ok(())
+                    },
+                    value,
+                );
+            }
+            OverflowAction::Reject => return Err(value),
+            OverflowAction::DropNewest => overflow_policy.on_drop(value),
+            OverflowAction::DropOldest => {
+                self.drop_oldest_and_push_many(
+                    tail,
+                    head,
+                    slice::from_ref(&value),
+                    overflow_policy,
+                );
+
+                mem::forget(value);
+            }
+            OverflowAction::Spill => overflow_policy.spill(value, sync_batch_receiver),
+        }
+
+        Ok(())
+    }
+
+    /// Pushes many values to the queue. If the queue doesn't have enough space,
+    /// it performs the action of the [`OverflowPolicy`].
+    /// Read more in [`Producer::push_many_with_policy`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push_many_with_policy<
+        SBR: SyncBatchReceiver<T>,
+        OP: OverflowPolicy<T>,
+    >(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+    ) -> Result<(), ()> {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        if likely(Self::len(head, tail) + slice.len() <= CAPACITY) {
+            let new_tail = Self::copy_slice(self.buffer_mut_thin_ptr().cast(), tail, slice);
+            self.tail.store(new_tail, Release);
+
+            return Ok(());
+        }
+
+        match overflow_policy.action(CAPACITY) {
+            OverflowAction::MoveToReceiver(n) => {
+                let _ = self.handle_overflow_many(
+                    tail,
+                    head,
+                    n,
+                    |first, last, slice| {
+                        sync_batch_receiver.push_many_and_slice(first, last, slice);
+
+                        Ok(())
+                    },
+                    slice,
+                );
+            }
+            OverflowAction::Reject => return Err(()),
+            OverflowAction::DropNewest => {
+                for value in slice {
+                    overflow_policy.on_drop(unsafe { ptr::read(value) });
+                }
+            }
+            OverflowAction::DropOldest => {
+                self.drop_oldest_and_push_many(tail, head, slice, overflow_policy);
+            }
+            OverflowAction::Spill => unsafe {
+                overflow_policy.spill_many(slice, sync_batch_receiver);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Moves all values of the queue except for the `keep` newest ones
+    /// to the [`SyncBatchReceiver`] in one batch.
+    /// Returns the number of moved values.
+    /// Read more in [`Producer::offload_into`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_offload_into<SBR: SyncBatchReceiver<T>>(
+        &self,
+        sync_batch_receiver: &SBR,
+        keep: usize,
+    ) -> usize {
+        let mut head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        loop {
+            let n = Self::len(head, tail).saturating_sub(keep);
+
+            if n == 0 {
+                return 0;
+            }
+
+            let (first, last) = self.oldest_values(head, n);
+
+            // We haven't read the values yet, so we can use `compare_exchange_weak`.
+            //If it fails, we calculate two slices and try again, it is not a performance issue.
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(n as LongNumber),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => {
+                    sync_batch_receiver.push_many_and_slice(first, last, &[]);
+
+                    return n;
+                }
+                Err(new_head) => {
+                    head = new_head;
+                }
+            }
+        }
+    }
+
+    /// Reserves up to `max` free slots after the tail and returns them.
+    /// Read more in [`Producer::reserve_slots`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer,
+    /// and the returned slices should not be used after any other producer method is called.
+    #[allow(
+        clippy::mut_from_ref,
+        reason = "The producer is the only writer of the free slots"
+    )]
+    #[inline]
+    pub unsafe fn producer_reserve_slots(
+        &self,
+        max: usize,
+    ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+        let n = max.min(CAPACITY - Self::len(head, tail));
+        let tail_idx = tail as usize % CAPACITY;
+        let right = n.min(CAPACITY - tail_idx);
+
+        unsafe { *self.reserved_slots.get() = n };
+
+        unsafe {
+            (
+                slice::from_raw_parts_mut(self.buffer_mut_thin_ptr().add(tail_idx), right),
+                slice::from_raw_parts_mut(self.buffer_mut_thin_ptr(), n - right),
+            )
+        }
+    }
+
+    /// Makes the first `n` reserved slots visible for consumers.
+    /// It commits no more than the number of the reserved slots.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer, and the first `n` slots
+    /// returned by [`producer_reserve_slots`](Self::producer_reserve_slots)
+    /// should be initialized.
+    #[inline]
+    pub unsafe fn producer_commit_reserved_slots(&self, n: usize) {
+        let n = n.min(unsafe { mem::take(&mut *self.reserved_slots.get()) });
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        self.tail.store(tail.wrapping_add(n as LongNumber), Release);
+    }
 }
 
 // Consumers
@@ -611,21 +843,15 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
     /// Steals many values from the consumer to the `dst`.
     /// Returns the number of values stolen.
     ///
-    /// # Panics
-    ///
-    /// If `dst` is not empty.
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
     pub fn steal_into(&self, dst: &Self) -> usize {
         let mut src_head = self.head.load(Acquire);
         let dst_tail = unsafe { dst.tail.unsync_load() }; // only producer can change tail
 
-        if cfg!(debug_assertions) {
-            let dst_head = dst.head.load(Relaxed);
-
-            assert_eq!(
-                dst_head, dst_tail,
-                "steal_into should not be called when dst is not empty"
-            );
-        }
+        // Consumers of the `dst` can only increase the number of free slots,
+        // so it is safe to load the head only once.
+        let dst_free_slots = CAPACITY - Self::len(dst.head.load(Acquire), dst_tail);
 
         loop {
             let src_tail = self.tail.load(Acquire);
@@ -642,14 +868,19 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
                 continue;
             }
 
-            if !cfg!(feature = "always_steal") && n < 4 || n == 0 {
-                // we don't steal less than 4 by default
+            if n < self.steal_threshold || n == 0 {
+                // we don't steal less than the threshold (4 by default)
                 // because else we may lose more because of cache locality and NUMA awareness
                 return 0;
             }
 
-            let src_head_idx = src_head as usize % CAPACITY;
-
+            let n = n.min(dst_free_slots);
+            if n == 0 {
+                return 0;
+            }
+
+            let src_head_idx = src_head as usize % CAPACITY;
+
             let (src_right, src_left): (&[T], &[T]) = unsafe {
                 let right_occupied = CAPACITY - src_head_idx;
                 if n <= right_occupied {
@@ -705,6 +936,93 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
             }
         }
     }
+
+    /// Steals many values from the consumer to the `dst`,
+    /// that can be a [`Producer`] of any single-producer, multi-consumer queue.
+    /// Returns the number of values stolen.
+    ///
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
+    pub fn steal_into_any<P: Producer<T>>(&self, dst: &P) -> usize {
+        let mut src_head = self.head.load(Acquire);
+
+        loop {
+            let src_tail = self.tail.load(Acquire);
+            let n = Self::len(src_head, src_tail) / 2;
+
+            if n > CAPACITY / 2 {
+                // Inconsistent state (this thread has been preempted
+                // after we have loaded `src_head`,
+                // and before we have loaded `src_tail`),
+                // try again
+
+                src_head = self.head.load(Acquire);
+
+                continue;
+            }
+
+            if n < self.steal_threshold || n == 0 {
+                // we don't steal less than the threshold (4 by default)
+                // because else we may lose more because of cache locality and NUMA awareness
+                return 0;
+            }
+
+            // We are the only user of `dst`, read the safety of `Producer::reserve_slots`.
+            let (dst_first, dst_last, n) = unsafe { reserve_slots_up_to(dst, n) };
+            if n == 0 {
+                return 0;
+            }
+
+            let src_head_idx = src_head as usize % CAPACITY;
+
+            let (src_right, src_left): (&[T], &[T]) = unsafe {
+                let right_occupied = CAPACITY - src_head_idx;
+                if n <= right_occupied {
+                    (
+                        slice::from_raw_parts(self.buffer_thin_ptr().add(src_head_idx).cast(), n),
+                        &[],
+                    )
+                } else {
+                    (
+                        slice::from_raw_parts(
+                            self.buffer_thin_ptr().add(src_head_idx).cast(),
+                            right_occupied,
+                        ),
+                        slice::from_raw_parts(self.buffer_thin_ptr().cast(), n - right_occupied),
+                    )
+                }
+            };
+
+            // We optimistically copy the values from the buffer into the dst.
+            // On CAS failure, we forget the copied values and try again.
+            // It is safe because we can concurrently read from the head.
+            unsafe {
+                copy_to_reserved_slots(src_right, 0, dst_first, dst_last);
+                copy_to_reserved_slots(src_left, src_right.len(), dst_first, dst_last);
+            }
+
+            // CAS is strong, because we don't want to recopy the values
+            let res = self.head.compare_exchange(
+                src_head,
+                src_head.wrapping_add(n as LongNumber),
+                Release,
+                Acquire,
+            );
+
+            match res {
+                Ok(_) => {
+                    // Success, we can commit the reserved slots and return
+                    unsafe { dst.commit_reserved_slots(n) };
+
+                    return n;
+                }
+                Err(current_head) => {
+                    // another thread has read the same values, full retry
+                    src_head = current_head;
+                }
+            }
+        }
+    }
 }
 
 impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default> Default
@@ -768,6 +1086,25 @@ macro_rules! generate_spmc_producer_and_consumer {
             _non_sync: PhantomData<*const ()>,
         }
 
+        impl<T, const CAPACITY: usize> $producer_name<T, CAPACITY> {
+            /// Creates a new queue whose consumers don't steal less than `steal_threshold`
+            /// values at once and returns its producer and consumer.
+            pub(crate) fn new_queue(steal_threshold: usize) -> (Self, $consumer_name<T, CAPACITY>) {
+                let queue = LightArc::new(SPMCBoundedQueue::with_steal_threshold(steal_threshold));
+
+                (
+                    Self {
+                        inner: queue.clone(),
+                        _non_sync: PhantomData,
+                    },
+                    $consumer_name {
+                        inner: queue,
+                        _non_sync: PhantomData,
+                    },
+                )
+            }
+        }
+
         impl<T: Send, const CAPACITY: usize> Producer<T> for $producer_name<T, CAPACITY> {
             #[inline]
             fn capacity(&self) -> usize {
@@ -784,6 +1121,15 @@ macro_rules! generate_spmc_producer_and_consumer {
                 unsafe { self.inner.producer_push(value, sync_batch_receiver) };
             }
 
+            #[inline]
+            fn try_push<SBR: SyncBatchReceiver<T>>(
+                &self,
+                value: T,
+                sync_batch_receiver: &SBR,
+            ) -> Result<(), T> {
+                unsafe { self.inner.producer_try_push(value, sync_batch_receiver) }
+            }
+
             #[inline]
             fn maybe_push(&self, value: T) -> Result<(), T> {
                 unsafe { self.inner.producer_maybe_push(value) }
@@ -817,6 +1163,72 @@ macro_rules! generate_spmc_producer_and_consumer {
             ) {
                 unsafe { self.inner.producer_push_many(slice, sync_batch_receiver) };
             }
+
+            #[inline]
+            unsafe fn try_push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+            ) -> Result<(), ()> {
+                unsafe {
+                    self.inner
+                        .producer_try_push_many(slice, sync_batch_receiver)
+                }
+            }
+
+            #[inline]
+            fn push_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+                &self,
+                value: T,
+                sync_batch_receiver: &SBR,
+                overflow_policy: &OP,
+            ) -> Result<(), T> {
+                unsafe {
+                    self.inner.producer_push_with_policy(
+                        value,
+                        sync_batch_receiver,
+                        overflow_policy,
+                    )
+                }
+            }
+
+            #[inline]
+            unsafe fn push_many_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+                overflow_policy: &OP,
+            ) -> Result<(), ()> {
+                unsafe {
+                    self.inner.producer_push_many_with_policy(
+                        slice,
+                        sync_batch_receiver,
+                        overflow_policy,
+                    )
+                }
+            }
+
+            #[inline]
+            fn offload_into<SBR: SyncBatchReceiver<T>>(
+                &self,
+                sync_batch_receiver: &SBR,
+                keep: usize,
+            ) -> usize {
+                unsafe { self.inner.producer_offload_into(sync_batch_receiver, keep) }
+            }
+
+            #[inline]
+            unsafe fn reserve_slots(
+                &self,
+                max: usize,
+            ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+                unsafe { self.inner.producer_reserve_slots(max) }
+            }
+
+            #[inline]
+            unsafe fn commit_reserved_slots(&self, n: usize) {
+                unsafe { self.inner.producer_commit_reserved_slots(n) };
+            }
         }
 
         impl<T: Send, const CAPACITY: usize> ConsumerSpawner<T> for $producer_name<T, CAPACITY> {
@@ -860,6 +1272,18 @@ macro_rules! generate_spmc_producer_and_consumer {
             fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize {
                 self.inner.steal_into(&*dst.inner)
             }
+
+            #[inline]
+            fn steal_into_any<P: Producer<T>>(&self, dst: &P) -> usize {
+                self.inner.steal_into_any(dst)
+            }
+        }
+
+        impl<T: Send, const CAPACITY: usize> SyncBatchSource<T> for $consumer_name<T, CAPACITY> {
+            #[inline]
+            fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize {
+                move_batch_from_consumer(self, producer, limit)
+            }
         }
 
         impl<T, const CAPACITY: usize> Clone for $consumer_name<T, CAPACITY> {
@@ -933,18 +1357,7 @@ generate_spmc_producer_and_consumer!(SPMCProducer, SPMCConsumer);
 /// ```
 pub fn new_bounded<T, const CAPACITY: usize>(
 ) -> (SPMCProducer<T, CAPACITY>, SPMCConsumer<T, CAPACITY>) {
-    let queue = LightArc::new(SPMCBoundedQueue::new());
-
-    (
-        SPMCProducer {
-            inner: queue.clone(),
-            _non_sync: PhantomData,
-        },
-        SPMCConsumer {
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
+    SPMCProducer::new_queue(DEFAULT_STEAL_THRESHOLD)
 }
 
 generate_spmc_producer_and_consumer!(
@@ -1003,24 +1416,15 @@ pub fn new_cache_padded_bounded<T, const CAPACITY: usize>() -> (
     CachePaddedSPMCProducer<T, CAPACITY>,
     CachePaddedSPMCConsumer<T, CAPACITY>,
 ) {
-    let queue = LightArc::new(SPMCBoundedQueue::new());
-
-    (
-        CachePaddedSPMCProducer {
-            inner: queue.clone(),
-            _non_sync: PhantomData,
-        },
-        CachePaddedSPMCConsumer {
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
+    CachePaddedSPMCProducer::new_queue(DEFAULT_STEAL_THRESHOLD)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mutex_vec_queue::MutexVecQueue;
+    use crate::spmc::{DropNewest, DropOldest, MoveAll, MoveFixed, Reject, Spill};
+    use crate::zst_test::{test_zst_queue, Zst};
     use std::collections::VecDeque;
 
     const CAPACITY: usize = 256;
@@ -1031,15 +1435,18 @@ mod tests {
 
         assert_eq!(
             size_of_val(&queue),
-            size_of::<usize>() + size_of::<LongAtomic>() * 2
+            size_of::<usize>() * 3 + size_of::<LongAtomic>() * 2
         );
 
         let cache_padded_queue = SPMCBoundedQueue::<(), CAPACITY, CachePaddedLongAtomic>::new();
 
         assert_eq!(
             size_of_val(&cache_padded_queue),
-            size_of::<CachePaddedLongAtomic>() * 2 + size_of::<usize>()
+            size_of::<CachePaddedLongAtomic>() * 2 + size_of::<usize>() * 3
         );
+        let queue = SPMCBoundedQueue::<u64, CAPACITY>::try_new().unwrap();
+
+        assert_eq!(queue.capacity(), CAPACITY);
     }
 
     #[test]
@@ -1051,10 +1458,10 @@ mod tests {
             producer.push(i, &global_queue);
         }
 
-        let (mut new_producer, _) = new_bounded::<_, CAPACITY>();
+        let (new_producer, _) = new_bounded::<_, CAPACITY>();
 
         global_queue
-            .move_batch_to_producer(&mut new_producer, producer.capacity() - producer.len());
+            .move_batch_to_producer(&new_producer, producer.capacity() - producer.len());
 
         assert_eq!(
             producer.len() + new_producer.len() + global_queue.len(),
@@ -1076,7 +1483,7 @@ mod tests {
 
         let global_queue = MutexVecQueue::new();
         let (producer1, consumer) = new_bounded::<_, CAPACITY>();
-        let (mut producer2, _) = new_bounded::<_, CAPACITY>();
+        let (producer2, _) = new_bounded::<_, CAPACITY>();
 
         let mut stolen = VecDeque::new();
 
@@ -1085,7 +1492,7 @@ mod tests {
                 producer1.push(i, &global_queue);
             }
 
-            consumer.steal_into(&mut producer2);
+            consumer.steal_into(&producer2);
 
             while let Some(task) = producer2.pop() {
                 stolen.push_back(task);
@@ -1098,13 +1505,450 @@ mod tests {
 
         let mut count = 0;
 
-        while let Some(_) = producer1.pop() {
+        while producer1.pop().is_some() {
             count += 1;
         }
 
         assert_eq!(count + stolen.len() + global_queue.len(), CAPACITY * TRIES);
     }
 
+    #[test]
+    fn test_spmc_bounded_stealing_into_non_empty() {
+        let global_queue = MutexVecQueue::new();
+        let (producer1, consumer) = new_bounded::<_, CAPACITY>();
+        let (producer2, _) = new_bounded::<_, CAPACITY>();
+
+        for i in 0..CAPACITY {
+            producer1.push(i, &global_queue);
+        }
+
+        for i in 0..CAPACITY - 10 {
+            producer2.push(CAPACITY + i, &global_queue);
+        }
+
+        assert_eq!(consumer.steal_into(&producer2), 10);
+        assert_eq!(producer2.len(), CAPACITY);
+        assert_eq!(consumer.steal_into(&producer2), 0);
+
+        for i in 0..CAPACITY - 10 {
+            assert_eq!(producer2.pop(), Some(CAPACITY + i));
+        }
+
+        for i in 0..10 {
+            assert_eq!(producer2.pop(), Some(i));
+        }
+
+        assert!(global_queue.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_bounded_stealing_into_any() {
+        let global_queue = MutexVecQueue::new();
+        let (producer1, consumer) = new_bounded::<_, CAPACITY>();
+        let (producer2, _) = new_cache_padded_bounded::<_, 64>();
+
+        for i in 0..CAPACITY - 1 {
+            producer1.push(i, &global_queue);
+        }
+
+        for i in 0..60 {
+            producer2.push(CAPACITY + i, &global_queue);
+        }
+
+        assert_eq!(consumer.steal_into_any(&producer2), 4);
+        assert_eq!(producer2.len(), 64);
+
+        for i in 0..60 {
+            assert_eq!(producer2.pop(), Some(CAPACITY + i));
+        }
+
+        for i in 0..4 {
+            assert_eq!(producer2.pop(), Some(i));
+        }
+
+        #[cfg(not(feature = "disable_unbounded"))]
+        {
+            let (producer3, _) = crate::spmc::new_unbounded();
+
+            producer3.reserve(256);
+
+            let stolen = consumer.steal_into_any(&producer3);
+
+            assert_eq!(stolen, (CAPACITY - 5) / 2);
+
+            for i in 0..stolen {
+                assert_eq!(producer3.pop(), Some(4 + i));
+            }
+        }
+
+        assert!(global_queue.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_bounded_overflow_policies() {
+        const SMALL_CAPACITY: usize = 16;
+
+        let global_queue = MutexVecQueue::new();
+        let (producer, _) = new_bounded::<_, SMALL_CAPACITY>();
+        let (checker, _) = new_bounded::<_, 64>();
+
+        // Move all
+
+        for i in 0..SMALL_CAPACITY {
+            producer.push(i, &global_queue);
+        }
+
+        producer
+            .push_with_policy(SMALL_CAPACITY, &global_queue, &MoveAll)
+            .unwrap();
+
+        assert!(producer.is_empty());
+
+        global_queue.move_batch_to_producer(&checker, 64);
+
+        for i in 0..=SMALL_CAPACITY {
+            assert_eq!(checker.pop(), Some(i));
+        }
+
+        // Move fixed with the wrapped head
+
+        for i in 0..SMALL_CAPACITY {
+            producer.push(i, &global_queue);
+        }
+
+        for i in 0..10 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+
+        for i in SMALL_CAPACITY..SMALL_CAPACITY + 10 {
+            producer.push(i, &global_queue);
+        }
+
+        producer
+            .push_with_policy(100, &global_queue, &MoveFixed(8))
+            .unwrap();
+
+        assert_eq!(producer.len(), SMALL_CAPACITY - 8);
+
+        global_queue.move_batch_to_producer(&checker, 64);
+
+        for i in 10..18 {
+            assert_eq!(checker.pop(), Some(i));
+        }
+
+        assert_eq!(checker.pop(), Some(100));
+
+        // Reject
+
+        for i in 101..109 {
+            producer.push(i, &global_queue);
+        }
+
+        assert_eq!(
+            producer.push_with_policy(109, &global_queue, &Reject),
+            Err(109)
+        );
+        assert_eq!(
+            unsafe { producer.push_many_with_policy(&[110, 111], &global_queue, &Reject) },
+            Err(())
+        );
+
+        // Drop newest
+
+        let dropped = std::cell::RefCell::new(Vec::new());
+        let drop_newest = DropNewest(|value| dropped.borrow_mut().push(value));
+
+        producer
+            .push_with_policy(112, &global_queue, &drop_newest)
+            .unwrap();
+        unsafe {
+            producer
+                .push_many_with_policy(&[113, 114], &global_queue, &drop_newest)
+                .unwrap();
+        };
+
+        assert_eq!(dropped.take(), [112, 113, 114]);
+
+        // Drop oldest
+
+        let drop_oldest = DropOldest(|value| dropped.borrow_mut().push(value));
+
+        producer
+            .push_with_policy(115, &global_queue, &drop_oldest)
+            .unwrap();
+
+        assert_eq!(dropped.take(), [18]);
+
+        let slice = (200..200 + SMALL_CAPACITY + 4).collect::<Vec<_>>();
+
+        unsafe {
+            producer
+                .push_many_with_policy(&slice, &global_queue, &drop_oldest)
+                .unwrap();
+        };
+
+        let mut expected_dropped = (19..SMALL_CAPACITY + 10).collect::<Vec<_>>();
+        expected_dropped.extend(101..109);
+        expected_dropped.extend([115, 200, 201, 202, 203]);
+
+        assert_eq!(dropped.take(), expected_dropped);
+
+        for i in 204..200 + SMALL_CAPACITY + 4 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+
+        // Spill
+
+        for i in 0..SMALL_CAPACITY {
+            producer.push(i, &global_queue);
+        }
+
+        let spill = Spill(new_bounded::<_, SMALL_CAPACITY>().0);
+
+        producer
+            .push_with_policy(300, &global_queue, &spill)
+            .unwrap();
+        unsafe {
+            producer
+                .push_many_with_policy(&[301, 302], &global_queue, &spill)
+                .unwrap();
+        };
+
+        assert_eq!(producer.len(), SMALL_CAPACITY);
+        assert_eq!(spill.0.pop(), Some(300));
+        assert_eq!(spill.0.pop(), Some(301));
+        assert_eq!(spill.0.pop(), Some(302));
+
+        assert!(global_queue.is_empty());
+        assert!(checker.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_bounded_offload() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_bounded::<_, CAPACITY>();
+        let (checker, _) = new_bounded::<_, CAPACITY>();
+
+        assert_eq!(producer.offload_into(&global_queue, 0), 0);
+
+        for i in 0..CAPACITY {
+            producer.push(i, &global_queue);
+        }
+
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(producer.offload_into(&global_queue, CAPACITY), 0);
+        assert_eq!(producer.offload_into(&global_queue, 10), CAPACITY - 11);
+        assert_eq!(producer.len(), 10);
+        assert_eq!(producer.offload_into(&global_queue, 10), 0);
+
+        global_queue.move_batch_to_producer(&checker, CAPACITY);
+
+        for i in 1..CAPACITY - 10 {
+            assert_eq!(checker.pop(), Some(i));
+        }
+
+        for i in CAPACITY - 10..CAPACITY {
+            producer.push(i + CAPACITY, &global_queue);
+        }
+
+        assert_eq!(producer.offload_into(&global_queue, 0), 20);
+        assert!(producer.is_empty());
+
+        global_queue.move_batch_to_producer(&checker, CAPACITY);
+
+        for i in (CAPACITY - 10..CAPACITY).chain(CAPACITY * 2 - 10..CAPACITY * 2) {
+            assert_eq!(checker.pop(), Some(i));
+        }
+
+        assert!(global_queue.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_bounded_try_push() {
+        struct ClosableReceiver {
+            queue: MutexVecQueue<usize>,
+            closed: std::cell::Cell<bool>,
+        }
+
+        unsafe impl SyncBatchReceiver<usize> for ClosableReceiver {
+            fn push_many_and_one(&self, first: &[usize], last: &[usize], value: usize) {
+                self.queue.push_many_and_one(first, last, value);
+            }
+
+            fn push_many_and_slice(&self, first: &[usize], last: &[usize], slice: &[usize]) {
+                self.queue.push_many_and_slice(first, last, slice);
+            }
+
+            fn try_push_many_and_one(
+                &self,
+                first: &[usize],
+                last: &[usize],
+                value: usize,
+            ) -> Result<(), usize> {
+                if self.closed.get() {
+                    return Err(value);
+                }
+
+                self.queue.try_push_many_and_one(first, last, value)
+            }
+
+            fn try_push_many_and_slice(
+                &self,
+                first: &[usize],
+                last: &[usize],
+                slice: &[usize],
+            ) -> Result<(), ()> {
+                if self.closed.get() {
+                    return Err(());
+                }
+
+                self.queue.try_push_many_and_slice(first, last, slice)
+            }
+        }
+
+        let receiver = ClosableReceiver {
+            queue: MutexVecQueue::new(),
+            closed: std::cell::Cell::new(true),
+        };
+        let (producer, consumer) = new_bounded::<_, CAPACITY>();
+
+        for i in 0..CAPACITY {
+            producer.try_push(i, &receiver).unwrap();
+        }
+
+        assert_eq!(producer.try_push(CAPACITY, &receiver), Err(CAPACITY));
+        assert_eq!(producer.len(), CAPACITY);
+
+        // The rejected values are returned to the head, so the order is kept.
+        for i in 0..10 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        let slice = (CAPACITY..CAPACITY + 20).collect::<Vec<_>>();
+
+        assert_eq!(
+            unsafe { producer.try_push_many(&slice, &receiver) },
+            Err(())
+        );
+        assert_eq!(producer.len(), CAPACITY - 10);
+
+        for i in 10..CAPACITY {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert!(receiver.queue.is_empty());
+
+        receiver.closed.set(false);
+
+        for i in 0..CAPACITY {
+            producer.try_push(i, &receiver).unwrap();
+        }
+
+        unsafe { producer.try_push_many(&slice, &receiver).unwrap() };
+
+        assert_eq!(producer.len(), CAPACITY / 2);
+        assert_eq!(receiver.queue.len(), CAPACITY / 2 + 20);
+    }
+
+    #[test]
+    fn test_spmc_bounded_try_push_keeps_order_after_concurrent_pops() {
+        /// Pops values from the queue while the batch is taken and rejects the batch.
+        struct PoppingReceiver<'consumer> {
+            consumer: &'consumer SPMCConsumer<usize, CAPACITY>,
+            popped: std::cell::RefCell<Vec<usize>>,
+        }
+
+        impl PoppingReceiver<'_> {
+            fn pop_ten(&self) {
+                for _ in 0..10 {
+                    self.popped.borrow_mut().push(self.consumer.pop().unwrap());
+                }
+            }
+        }
+
+        unsafe impl SyncBatchReceiver<usize> for PoppingReceiver<'_> {
+            fn push_many_and_one(&self, _: &[usize], _: &[usize], _: usize) {
+                unreachable!()
+            }
+
+            fn push_many_and_slice(&self, _: &[usize], _: &[usize], _: &[usize]) {
+                unreachable!()
+            }
+
+            fn try_push_many_and_one(
+                &self,
+                _: &[usize],
+                _: &[usize],
+                value: usize,
+            ) -> Result<(), usize> {
+                self.pop_ten();
+
+                Err(value)
+            }
+
+            fn try_push_many_and_slice(
+                &self,
+                _: &[usize],
+                _: &[usize],
+                _: &[usize],
+            ) -> Result<(), ()> {
+                self.pop_ten();
+
+                Err(())
+            }
+        }
+
+        let (producer, consumer) = new_bounded::<_, CAPACITY>();
+        let receiver = PoppingReceiver {
+            consumer: &consumer,
+            popped: std::cell::RefCell::new(Vec::new()),
+        };
+
+        for i in 0..CAPACITY {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(producer.try_push(CAPACITY, &receiver), Err(CAPACITY));
+        assert_eq!(producer.len(), CAPACITY - 10);
+
+        for i in 0..10 {
+            producer.maybe_push(CAPACITY + i).unwrap();
+        }
+
+        assert_eq!(
+            unsafe { producer.try_push_many(&[0; 20], &receiver) },
+            Err(())
+        );
+        assert_eq!(producer.len(), CAPACITY - 10);
+
+        // The consumer has popped the values that were newer than the taken ones,
+        // but the taken values are still popped before the rest values.
+        assert!(receiver
+            .popped
+            .take()
+            .into_iter()
+            .eq(CAPACITY / 2..CAPACITY / 2 + 20));
+
+        for i in (0..CAPACITY / 2).chain(CAPACITY / 2 + 20..CAPACITY + 10) {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_bounded_zst() {
+        let (producer, consumer) = new_bounded::<Zst, CAPACITY>();
+
+        test_zst_queue(
+            Some(CAPACITY),
+            |value| producer.maybe_push(value),
+            |dst| consumer.pop_many(dst),
+            || consumer.len(),
+            || consumer.steal_into(&new_bounded().0),
+        );
+    }
+
     #[test]
     fn test_spmc_bounded_many() {
         const BATCH_SIZE: usize = 30;
@@ -1119,16 +1963,16 @@ mod tests {
                 .collect::<Vec<_>>();
 
             unsafe {
-                producer.maybe_push_many(&*slice).unwrap();
+                producer.maybe_push_many(&slice).unwrap();
             }
 
             let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
             producer.pop_many(slice.as_mut_slice());
 
-            for j in 0..BATCH_SIZE {
+            for (j, value) in slice.iter().enumerate() {
                 let index = i * BATCH_SIZE + j;
 
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
+                assert_eq!(unsafe { value.assume_init() }, index);
             }
         }
 
@@ -1138,7 +1982,7 @@ mod tests {
                 .collect::<Vec<_>>();
 
             unsafe {
-                producer.push_many(&*slice, &global_queue);
+                producer.push_many(&slice, &global_queue);
             }
 
             assert!(global_queue.is_empty());
@@ -1146,10 +1990,10 @@ mod tests {
             let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
             consumer.pop_many(slice.as_mut_slice());
 
-            for j in 0..BATCH_SIZE {
+            for (j, value) in slice.iter().enumerate() {
                 let index = i * BATCH_SIZE + j;
 
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
+                assert_eq!(unsafe { value.assume_init() }, index);
             }
         }
     }
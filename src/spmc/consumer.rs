@@ -43,8 +43,19 @@ pub trait Consumer<T>: Clone {
     /// Steals some values from the consumer and places them into `dst`.
     /// Returns the number of stolen values.
     ///
-    /// It requires that the other queue to be empty.
+    /// It steals no more than [`dst.free_slots()`](Producer::free_slots) values
+    /// and appends them after the values that are already in `dst`.
     /// Expected to steal the half of the queue,
     /// but other implementations may steal another number of values.
     fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize;
+
+    /// Steals some values from the consumer and places them into `dst`,
+    /// that can be a [`Producer`] of any single-producer, multi-consumer queue,
+    /// for example, a bounded queue with another capacity or an unbounded queue.
+    /// Returns the number of stolen values.
+    ///
+    /// It works as [`steal_into`](Self::steal_into),
+    /// but it writes the values into the [`reserved slots`](Producer::reserve_slots) of the `dst`,
+    /// therefore, it is a bit slower.
+    fn steal_into_any<P: Producer<T>>(&self, dst: &P) -> usize;
 }
@@ -1,16 +1,25 @@
 //! This module provides implementations of a single-producer multi-consumer queues.
 //!
-//! It contains two implementations:
+//! It contains three implementations:
 //!
 //! * [`const_bounded`]: A const bounded ring buffer.
 //!   Use [`new_bounded`] or [`new_cache_padded_bounded`] or [`SPMCBoundedQueue`].
 //! * [`unbounded`]: An unbounded ring buffer.
-//!   Use [`new_unbounded`] or [`new_cache_padded_unbounded`].
+//!   Use [`new_unbounded`] or [`new_cache_padded_unbounded`],
+//!   or [`new_capped_unbounded`] to spill values like a bounded queue at the maximum capacity.
+//! * [`segmented`]: An unbounded queue of linked fixed-size segments.
+//!   Use [`new_segmented`] or [`new_cache_padded_segmented`].
 //!
-//! And it also contains the [`Producer`], [`Consumer`] and [`ConsumerSpawner`] traits.
+//! And it also contains the [`Producer`], [`Consumer`] and [`ConsumerSpawner`] traits
+//! and the [`OverflowPolicy`] of bounded producers.
 mod const_bounded;
 mod consumer;
+mod overflow_policy;
+#[cfg(not(feature = "disable_unbounded"))]
+mod pinned_consumers;
 mod producer;
+#[cfg(not(feature = "disable_unbounded"))]
+mod segmented;
 #[cfg(test)]
 mod tests;
 #[cfg(not(feature = "disable_unbounded"))]
@@ -18,6 +27,9 @@ mod unbounded;
 
 pub use const_bounded::*;
 pub use consumer::*;
+pub use overflow_policy::*;
 pub use producer::*;
 #[cfg(not(feature = "disable_unbounded"))]
+pub use segmented::*;
+#[cfg(not(feature = "disable_unbounded"))]
 pub use unbounded::*;
@@ -0,0 +1,192 @@
+//! This module provides the [`OverflowPolicy`] trait and its implementations.
+//!
+//! An overflow policy decides what a bounded [`Producer`] does when its queue is full.
+//! Use it with [`Producer::push_with_policy`] and [`Producer::push_many_with_policy`].
+use crate::spmc::Producer;
+use crate::sync_batch_receiver::SyncBatchReceiver;
+use std::mem;
+use std::slice;
+
+/// An action that a bounded [`Producer`] performs when its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowAction {
+    /// Moves the given number of the oldest values of the queue and the pushed values
+    /// to the [`SyncBatchReceiver`].
+    ///
+    /// The number is clamped to the length of the queue.
+    MoveToReceiver(usize),
+    /// Returns the pushed values back to the caller.
+    Reject,
+    /// Drops the pushed values and passes them to [`OverflowPolicy::on_drop`].
+    DropNewest,
+    /// Drops the oldest values of the queue to make space for the pushed values
+    /// and passes them to [`OverflowPolicy::on_drop`].
+    DropOldest,
+    /// Passes the pushed values to [`OverflowPolicy::spill_many`].
+    Spill,
+}
+
+/// A policy that decides what a bounded [`Producer`] does when its queue is full.
+///
+/// This library provides the following policies:
+///
+/// - [`MoveHalf`] is used by [`Producer::push`] and [`Producer::push_many`];
+/// - [`MoveAll`] and [`MoveFixed`] move another number of values to the [`SyncBatchReceiver`];
+/// - [`Reject`] makes the push fail;
+/// - [`DropNewest`] and [`DropOldest`] drop values and pass them to a callback;
+/// - [`Spill`] pushes the values to another (likely unbounded) [`Producer`].
+///
+/// Unbounded producers use the policy only if they are capped (read more in [`Producer::push`]).
+pub trait OverflowPolicy<T> {
+    /// Returns the action to perform when the queue with the given `capacity` is full.
+    fn action(&self, capacity: usize) -> OverflowAction;
+
+    /// Accepts a value dropped by [`OverflowAction::DropNewest`] or [`OverflowAction::DropOldest`].
+    #[inline]
+    fn on_drop(&self, value: T) {
+        drop(value);
+    }
+
+    /// Accepts the values spilled by [`OverflowAction::Spill`].
+    ///
+    /// By default, it pushes the values to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// It takes ownership of the values, so the caller must [`forget`](mem::forget) the slice.
+    #[inline]
+    unsafe fn spill_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        values: &[T],
+        sync_batch_receiver: &SBR,
+    ) {
+        sync_batch_receiver.push_many_and_slice(&[], &[], values);
+    }
+
+    /// Accepts the value spilled by [`OverflowAction::Spill`].
+    ///
+    /// By default, it calls [`spill_many`](Self::spill_many) with the only value.
+    #[inline]
+    fn spill<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR) {
+        unsafe { self.spill_many(slice::from_ref(&value), sync_batch_receiver) };
+
+        mem::forget(value);
+    }
+}
+
+/// Moves a half of the queue and the pushed values to the [`SyncBatchReceiver`].
+///
+/// It is the policy of [`Producer::push`] and [`Producer::push_many`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MoveHalf;
+
+impl<T> OverflowPolicy<T> for MoveHalf {
+    #[inline]
+    fn action(&self, capacity: usize) -> OverflowAction {
+        OverflowAction::MoveToReceiver(capacity / 2)
+    }
+}
+
+/// Moves the whole queue and the pushed values to the [`SyncBatchReceiver`].
+///
+/// It is useful for throughput-oriented workers,
+/// because it makes the next overflow as late as possible.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MoveAll;
+
+impl<T> OverflowPolicy<T> for MoveAll {
+    #[inline]
+    fn action(&self, capacity: usize) -> OverflowAction {
+        OverflowAction::MoveToReceiver(capacity)
+    }
+}
+
+/// Moves the fixed number of the oldest values and the pushed values
+/// to the [`SyncBatchReceiver`].
+///
+/// It is useful for latency-sensitive workers,
+/// because it holds the lock of the receiver for the bounded time.
+#[derive(Debug, Clone, Copy)]
+pub struct MoveFixed(pub usize);
+
+impl<T> OverflowPolicy<T> for MoveFixed {
+    #[inline]
+    fn action(&self, _capacity: usize) -> OverflowAction {
+        OverflowAction::MoveToReceiver(self.0)
+    }
+}
+
+/// Returns the pushed values back to the caller.
+///
+/// It works as [`Producer::maybe_push`] and [`Producer::maybe_push_many`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Reject;
+
+impl<T> OverflowPolicy<T> for Reject {
+    #[inline]
+    fn action(&self, _capacity: usize) -> OverflowAction {
+        OverflowAction::Reject
+    }
+}
+
+/// Drops the pushed values and passes them to the callback.
+#[derive(Debug, Clone, Copy)]
+pub struct DropNewest<F>(pub F);
+
+impl<T, F: Fn(T)> OverflowPolicy<T> for DropNewest<F> {
+    #[inline]
+    fn action(&self, _capacity: usize) -> OverflowAction {
+        OverflowAction::DropNewest
+    }
+
+    #[inline]
+    fn on_drop(&self, value: T) {
+        (self.0)(value);
+    }
+}
+
+/// Drops the oldest values of the queue to make space for the pushed values
+/// and passes them to the callback.
+#[derive(Debug, Clone, Copy)]
+pub struct DropOldest<F>(pub F);
+
+impl<T, F: Fn(T)> OverflowPolicy<T> for DropOldest<F> {
+    #[inline]
+    fn action(&self, _capacity: usize) -> OverflowAction {
+        OverflowAction::DropOldest
+    }
+
+    #[inline]
+    fn on_drop(&self, value: T) {
+        (self.0)(value);
+    }
+}
+
+/// Pushes the values to the attached [`Producer`].
+///
+/// It is expected to be used with an unbounded producer
+/// to let the bounded queue grow into it on overflow.
+/// If the attached producer is bounded and full,
+/// it moves its values to the [`SyncBatchReceiver`] as [`Producer::push`] does.
+pub struct Spill<P>(pub P);
+
+impl<T, P: Producer<T>> OverflowPolicy<T> for Spill<P> {
+    #[inline]
+    fn action(&self, _capacity: usize) -> OverflowAction {
+        OverflowAction::Spill
+    }
+
+    #[inline]
+    unsafe fn spill_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        values: &[T],
+        sync_batch_receiver: &SBR,
+    ) {
+        unsafe { self.0.push_many(values, sync_batch_receiver) };
+    }
+
+    #[inline]
+    fn spill<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR) {
+        self.0.push(value, sync_batch_receiver);
+    }
+}
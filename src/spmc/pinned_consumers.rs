@@ -0,0 +1,97 @@
+//! This module provides [`PinnedConsumers`].
+use crate::cache_padded::CachePaddedAtomicUsize;
+use crate::loom_bindings::sync::atomic::AtomicUsize;
+use std::sync::atomic::fence;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+
+/// Counters of consumers that are reading the buffers of an unbounded queue right now.
+///
+/// Consumers are pinned in the current epoch, and the producer frees superseded buffers
+/// in grace periods:
+///
+/// - The producer first unlinks the buffer (publishes a new version or a new first segment),
+///   and only then [`advances`](Self::try_advance) the epoch.
+/// - The consumer first [`pins`](Self::pin) itself in the current epoch,
+///   and only then loads the published value with [`SeqCst`].
+/// - The producer frees the buffer only after the next successful advance,
+///   that is, when all consumers pinned before the epoch has been advanced are unpinned.
+///
+/// Then either the consumer is pinned in the epoch the producer waits for,
+/// or the consumer sees the published value and doesn't read the unlinked buffer.
+///
+/// Unlike a single counter of pinned consumers, it doesn't need a moment
+/// when no consumer is pinned, so continuous stealing can't make the producer
+/// keep retired buffers forever.
+/// The producer keeps only the buffers retired during the operations of consumers
+/// that are pinned in the previous epoch: each consumer is pinned for one operation.
+///
+/// Every pin and unpin modifies a shared counter, so consumers still contend on it.
+/// The counters and the epoch are cache-padded to not share cache lines with each other.
+pub(crate) struct PinnedConsumers {
+    epoch: CachePaddedAtomicUsize,
+    counts: [CachePaddedAtomicUsize; 2],
+}
+
+impl PinnedConsumers {
+    /// Creates new counters without pinned consumers.
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: CachePaddedAtomicUsize::new(),
+            counts: [CachePaddedAtomicUsize::new(), CachePaddedAtomicUsize::new()],
+        }
+    }
+
+    /// Pins the consumer in the current epoch until the returned guard is dropped.
+    #[inline(always)]
+    pub(crate) fn pin(&self) -> PinnedConsumerGuard<'_> {
+        loop {
+            let epoch = self.epoch.load(SeqCst);
+            let count = &self.counts[epoch & 1];
+
+            count.fetch_add(1, SeqCst);
+
+            // If the epoch has been advanced, the producer may not wait for this counter.
+            if self.epoch.load(SeqCst) == epoch {
+                return PinnedConsumerGuard { count };
+            }
+
+            count.fetch_sub(1, Release);
+        }
+    }
+
+    /// Advances the epoch if all consumers pinned in the previous epoch are unpinned.
+    /// Returns whether it has advanced.
+    ///
+    /// If it returns `true`, the producer can free the buffers
+    /// that had been unlinked before the previous successful call.
+    ///
+    /// It is called only by the producer after it unlinks the buffers.
+    #[inline]
+    pub(crate) fn try_advance(&self) -> bool {
+        // Pairs with the `SeqCst` increment in `pin`.
+        fence(SeqCst);
+
+        let epoch = self.epoch.load(Relaxed); // only the producer changes the epoch
+
+        if self.counts[(epoch & 1) ^ 1].load(Acquire) != 0 {
+            return false;
+        }
+
+        self.epoch.store(epoch.wrapping_add(1), SeqCst);
+
+        true
+    }
+}
+
+/// A RAII guard of a pinned consumer.
+pub(crate) struct PinnedConsumerGuard<'counter> {
+    count: &'counter AtomicUsize,
+}
+
+impl Drop for PinnedConsumerGuard<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        // Release: all reads of the buffer happen before the producer frees it.
+        self.count.fetch_sub(1, Release);
+    }
+}
@@ -1,8 +1,10 @@
 //! This module provides the [`Producer`] and [`ConsumerSpawner`] traits for the single-producer,
 //! multi-consumer queue.
 use crate::spmc;
+use crate::spmc::OverflowPolicy;
 use crate::sync_batch_receiver::SyncBatchReceiver;
 use std::mem::MaybeUninit;
+use std::ptr;
 
 /// A producer of the single-producer, multi-consumer queue.
 /// It can push values and pop them.
@@ -29,8 +31,26 @@ pub trait Producer<T> {
 
     /// Pushes a value into the queue. If the queue is full, up to half of the queue values
     /// are pushed into the global queue (or any other [`SyncBatchReceiver`]).
+    ///
+    /// An unbounded queue grows instead. It overflows like a bounded one only if it is capped
+    /// (created by [`new_capped_unbounded`](crate::spmc::new_capped_unbounded)
+    /// or with a [`maximum capacity`](crate::GrowthPolicy::with_max_capacity))
+    /// and has reached the maximum capacity.
     fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR);
 
+    /// Pushes a value into the queue. If the queue is full, up to half of the queue values
+    /// are pushed into the global queue (or any other [`SyncBatchReceiver`])
+    /// with [`SyncBatchReceiver::try_push_many_and_one`].
+    ///
+    /// If the receiver rejects the batch, the values of the queue stay in the queue
+    /// in the same order, and the value is returned.
+    /// Unbounded producers overflow only if they are capped (read more in [`push`](Self::push)).
+    fn try_push<SBR: SyncBatchReceiver<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+    ) -> Result<(), T>;
+
     /// Pushes a value only if the queue is not full.
     /// It returns an error if the queue is full.
     fn maybe_push(&self, value: T) -> Result<(), T>;
@@ -66,6 +86,172 @@ pub trait Producer<T> {
     ///
     /// If the `T` is not `Copy`, the caller must [`forget`](core::mem::forget) the provided slice.
     unsafe fn push_many<SBR: SyncBatchReceiver<T>>(&self, values: &[T], sync_batch_receiver: &SBR);
+
+    /// Pushes a slice of value into the queue.
+    /// If the queue doesn't have enough space, up to half of the queue values
+    /// are pushed into the global queue (or any other [`SyncBatchReceiver`])
+    /// with [`SyncBatchReceiver::try_push_many_and_slice`].
+    ///
+    /// If the receiver rejects the batch, the values of the queue stay in the queue
+    /// in the same order, and the error is returned.
+    /// Unbounded producers overflow only if they are capped (read more in [`push`](Self::push)).
+    ///
+    /// # Safety
+    ///
+    /// If the `T` is not `Copy`, the caller must [`forget`](core::mem::forget) the provided slice
+    /// if and only if it returns `Ok`.
+    unsafe fn try_push_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        values: &[T],
+        sync_batch_receiver: &SBR,
+    ) -> Result<(), ()>;
+
+    /// Moves all values of the queue except for the `keep` newest ones
+    /// to the global queue (or any other [`SyncBatchReceiver`]) in one batch.
+    /// Returns the number of moved values.
+    ///
+    /// It moves the oldest values and keeps the newest ones to preserve the cache locality.
+    /// It is useful to hand off the local work before the worker blocks or parks,
+    /// so other workers can proceed with it.
+    fn offload_into<SBR: SyncBatchReceiver<T>>(
+        &self,
+        sync_batch_receiver: &SBR,
+        keep: usize,
+    ) -> usize;
+
+    /// Pushes a value into the queue.
+    /// If the queue is full, the [`OverflowPolicy`] decides what to do with the value
+    /// and the values of the queue.
+    ///
+    /// It returns an error only if the policy rejects the value.
+    /// Unbounded producers overflow only if they are capped (read more in [`push`](Self::push)).
+    fn push_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+    ) -> Result<(), T>;
+
+    /// Pushes a slice of value into the queue.
+    /// If the queue doesn't have enough space, the [`OverflowPolicy`] decides what to do
+    /// with the values of the slice and the values of the queue.
+    ///
+    /// It returns an error only if the policy rejects the values.
+    /// Unbounded producers overflow only if they are capped (read more in [`push`](Self::push)).
+    ///
+    /// # Safety
+    ///
+    /// If the `T` is not `Copy`, the caller must [`forget`](core::mem::forget) the provided slice
+    /// if and only if it returns `Ok`.
+    unsafe fn push_many_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+        &self,
+        values: &[T],
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+    ) -> Result<(), ()>;
+
+    /// Reserves up to `max` free slots after the tail of the queue and returns them.
+    /// It returns two slices to allow using it for ring-based queues;
+    /// the first slice is filled before the last one.
+    ///
+    /// The reserved slots are invisible for consumers until
+    /// [`commit_reserved_slots`](Self::commit_reserved_slots) is called.
+    ///
+    /// It is used to steal values from the queues of other types,
+    /// read [`Consumer::steal_into_any`](spmc::Consumer::steal_into_any).
+    ///
+    /// # Safety
+    ///
+    /// The caller should be the only user of the producer until it commits the slots.
+    /// It holds for any caller that has a reference to a producer of this crate,
+    /// because they are not `Sync`.
+    ///
+    /// The returned slices should not be used after any other method of the `Producer` is called.
+    ///
+    /// The caller should use no more than `max` slots even if the total length
+    /// of the returned slices is greater.
+    #[allow(
+        clippy::mut_from_ref,
+        reason = "The producer is the only writer of the free slots"
+    )]
+    unsafe fn reserve_slots(&self, max: usize) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]);
+
+    /// Makes the first `n` reserved slots visible for consumers.
+    ///
+    /// # Safety
+    ///
+    /// It should be called only after [`reserve_slots`](Self::reserve_slots)
+    /// with the `n` that is not greater than the number of the reserved slots
+    /// and the `max` passed to it, and the first `n` slots should be initialized.
+    ///
+    /// The producers of this crate commit no more than the number of the reserved slots
+    /// if the `n` is greater.
+    unsafe fn commit_reserved_slots(&self, n: usize);
+}
+
+/// Reserves up to `max` free slots in the `dst` with [`Producer::reserve_slots`]
+/// and returns them with the number of slots the caller should fill and commit.
+///
+/// The returned number is not greater than `max`:
+/// [`Producer`] is a safe trait, so we don't trust its implementations
+/// to reserve not more than `max` slots.
+///
+/// # Safety
+///
+/// The same as for [`Producer::reserve_slots`].
+#[allow(
+    clippy::mut_from_ref,
+    reason = "The producer is the only writer of the free slots"
+)]
+#[allow(
+    clippy::type_complexity,
+    reason = "It returns the result of `reserve_slots` with its usable length."
+)]
+pub(crate) unsafe fn reserve_slots_up_to<T>(
+    dst: &impl Producer<T>,
+    max: usize,
+) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>], usize) {
+    let (first, last) = unsafe { dst.reserve_slots(max) };
+    let n = max.min(first.len() + last.len());
+
+    (first, last, n)
+}
+
+/// Copies the `src` into the reserved slots starting from the `offset`.
+///
+/// # Safety
+///
+/// The reserved slots should have enough space for the `src` after the `offset`.
+pub(crate) unsafe fn copy_to_reserved_slots<T>(
+    src: &[T],
+    offset: usize,
+    first: &mut [MaybeUninit<T>],
+    last: &mut [MaybeUninit<T>],
+) {
+    debug_assert!(offset + src.len() <= first.len() + last.len());
+
+    let in_first = first.len().saturating_sub(offset).min(src.len());
+    let in_last = src.len() - in_first;
+
+    unsafe {
+        if in_first > 0 {
+            ptr::copy_nonoverlapping(
+                src.as_ptr(),
+                first.as_mut_ptr().add(offset).cast(),
+                in_first,
+            );
+        }
+
+        if in_last > 0 {
+            ptr::copy_nonoverlapping(
+                src.as_ptr().add(in_first),
+                last.as_mut_ptr()
+                    .add(offset + in_first - first.len())
+                    .cast(),
+                in_last,
+            );
+        }
+    }
 }
 
 /// A consumer spawner for the single-producer, multi-consumer queue.
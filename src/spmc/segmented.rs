@@ -0,0 +1,1463 @@
+//! This module provides a single-producer multi-consumer unbounded queue
+//! of linked fixed-size segments. Read more in [`new_segmented`].
+use crate::cache_padded::CachePaddedAtomicUsize;
+use crate::light_arc::LightArc;
+use crate::loom_bindings::sync::atomic::{AtomicPtr, AtomicUsize};
+use crate::number_types::NotCachePaddedAtomicUsize;
+use crate::queue_builder::DEFAULT_STEAL_THRESHOLD;
+use crate::spmc::pinned_consumers::PinnedConsumers;
+use crate::spmc::{
+    copy_to_reserved_slots, reserve_slots_up_to, Consumer, ConsumerSpawner, OverflowPolicy,
+    Producer,
+};
+use crate::sync_batch_receiver::SyncBatchReceiver;
+use crate::sync_batch_source::{move_batch_from_consumer, SyncBatchSource};
+use std::alloc::{alloc, handle_alloc_error, Layout};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::{self, needs_drop, MaybeUninit};
+use std::ops::Deref;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+use std::{ptr, slice};
+
+/// How many lower bits of the index are the offset in the segment of non-zero-sized values.
+const SEGMENT_SHIFT: usize = 8;
+/// The number of slots of a segment.
+const SEGMENT_CAPACITY: usize = 1 << SEGMENT_SHIFT;
+
+/// A segment in a linked list.
+///
+/// The segment with the number `n` holds the values with indexes
+/// from `n * Segment::CAPACITY` to `(n + 1) * Segment::CAPACITY`.
+/// The producer writes each slot only once, so consumers can read the slots optimistically.
+struct Segment<T> {
+    next: AtomicPtr<Self>,
+    number: usize,
+    slots: UnsafeCell<[MaybeUninit<T>; SEGMENT_CAPACITY]>,
+}
+
+impl<T> Segment<T> {
+    /// How many lower bits of the index are the offset in the segment.
+    ///
+    /// Zero-sized values need no slots, so one segment holds the values
+    /// with almost any indexes, and the producer doesn't allocate segments for them.
+    const SHIFT: usize = if size_of::<T>() == 0 {
+        usize::BITS as usize - 1
+    } else {
+        SEGMENT_SHIFT
+    };
+    /// The number of values the segment can hold.
+    const CAPACITY: usize = 1 << Self::SHIFT;
+    /// The mask of the offset in the segment.
+    const MASK: usize = Self::CAPACITY - 1;
+
+    /// Allocates a new empty segment with the given `number`.
+    fn alloc_new(number: usize) -> *mut Self {
+        let layout = Layout::new::<Self>();
+        // The slots don't need initialization, so only the header is written.
+        let ptr = unsafe { alloc(layout) }.cast::<Self>();
+
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+
+        unsafe {
+            (&raw mut (*ptr).next).write(AtomicPtr::new(ptr::null_mut()));
+            (&raw mut (*ptr).number).write(number);
+        }
+
+        ptr
+    }
+
+    /// Returns a raw pointer to the slot with the given `offset`.
+    ///
+    /// # Safety
+    ///
+    /// The segment should be alive, and the `offset` should be less than [`Self::CAPACITY`].
+    #[inline(always)]
+    unsafe fn slot(this: *mut Self, offset: usize) -> *mut MaybeUninit<T> {
+        unsafe { (*this).slots.get().cast::<MaybeUninit<T>>().add(offset) }
+    }
+
+    /// Returns the segment that holds the value with the given `index`,
+    /// walking the list from `this`.
+    ///
+    /// # Safety
+    ///
+    /// The segments should be alive and linked up to the returned one,
+    /// and `this` should not be after the returned one.
+    #[inline]
+    unsafe fn find(mut this: *mut Self, index: usize) -> *mut Self {
+        let number = index >> Self::SHIFT;
+
+        while unsafe { (*this).number } != number {
+            this = unsafe { (*this).next.load(Acquire) };
+
+            debug_assert!(!this.is_null(), "the segment is not linked yet");
+        }
+
+        this
+    }
+
+    /// Calls `f` with the contiguous runs of `n` values starting from the `index`,
+    /// walking the list from `this`.
+    ///
+    /// # Safety
+    ///
+    /// The segments should be alive and linked up to the value with the index `index + n - 1`,
+    /// and `this` should hold the value with the `index`.
+    #[inline]
+    unsafe fn for_each_run<'segment>(
+        mut this: *mut Self,
+        mut index: usize,
+        mut n: usize,
+        mut f: impl FnMut(&'segment [T]),
+    ) where
+        T: 'segment,
+    {
+        while n > 0 {
+            let offset = index & Self::MASK;
+            let run = n.min(Self::CAPACITY - offset);
+
+            f(unsafe { slice::from_raw_parts(Self::slot(this, offset).cast(), run) });
+
+            index = index.wrapping_add(run);
+            n -= run;
+
+            if n > 0 {
+                this = unsafe { (*this).next.load(Acquire) };
+            }
+        }
+    }
+}
+
+/// The single-producer, multi-consumer _unbounded_ queue of linked fixed-size segments.
+///
+/// It is safe to use when and only when only one thread is writing to the queue at the same time.
+///
+/// You can call `producer_` methods for the producer and `consumer_` methods for the consumers.
+///
+/// It accepts an atomic wrapper as a generic parameter.
+/// It allows using cache-padded atomics or not.
+/// You should create types aliases not to write this large type name.
+///
+/// Unlike the [`SPMCUnboundedQueue`](crate::spmc::SPMCUnboundedProducer),
+/// it never copies the values to grow: the producer links a new segment instead.
+/// Consumers never wait for the producer, so they never return zero
+/// if the queue is not empty.
+///
+/// Consumers move the first segment forward when they pop its last value,
+/// and the producer frees the segments before the first one
+/// after a grace period of [`PinnedConsumers`].
+///
+/// It doesn't implement the [`Producer`] and [`Consumer`] traits because all producer methods
+/// are unsafe (can be called only by one thread).
+pub(crate) struct SPMCSegmentedQueue<T, AtomicUsizeWrapper = NotCachePaddedAtomicUsize>
+where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default,
+{
+    tail: AtomicUsizeWrapper,
+    head: AtomicUsizeWrapper,
+    /// The first segment that consumers may read. It holds the value with the head index
+    /// or a value before it.
+    first_segment: AtomicPtr<Segment<T>>,
+    pinned_consumers: PinnedConsumers,
+    /// The oldest segment that is not freed yet.
+    /// Only the producer can access it.
+    oldest_segment: UnsafeCell<*mut Segment<T>>,
+    /// The first segment loaded before the last advance of the epoch of pinned consumers.
+    /// The segments before it can be freed after the next advance.
+    /// Only the producer can access it.
+    freeable_segment: UnsafeCell<*mut Segment<T>>,
+    /// The segment that holds the slot with the tail index.
+    /// Only the producer can access it.
+    tail_segment: UnsafeCell<*mut Segment<T>>,
+    /// The number of slots returned by the last
+    /// [`producer_reserve_slots`](Self::producer_reserve_slots)
+    /// that are not committed yet.
+    /// Only the producer can access it.
+    reserved_slots: UnsafeCell<usize>,
+    /// Consumers don't steal less than this number of values at once.
+    steal_threshold: usize,
+}
+
+impl<T, AtomicUsizeWrapper> SPMCSegmentedQueue<T, AtomicUsizeWrapper>
+where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default,
+{
+    /// Creates a new empty queue whose consumers don't steal less than `steal_threshold`
+    /// values at once.
+    fn with_steal_threshold(steal_threshold: usize) -> Self {
+        let segment = Segment::alloc_new(0);
+
+        Self {
+            tail: AtomicUsizeWrapper::default(),
+            head: AtomicUsizeWrapper::default(),
+            first_segment: AtomicPtr::new(segment),
+            pinned_consumers: PinnedConsumers::new(),
+            oldest_segment: UnsafeCell::new(segment),
+            freeable_segment: UnsafeCell::new(segment),
+            tail_segment: UnsafeCell::new(segment),
+            reserved_slots: UnsafeCell::new(0),
+            steal_threshold,
+        }
+    }
+
+    /// Returns the length of the queue by the given `head` and `tail`.
+    #[inline]
+    fn len(head: usize, tail: usize) -> usize {
+        tail.wrapping_sub(head)
+    }
+
+    /// Returns the capacity of the queue by the given `head` and `tail`:
+    /// the number of slots in the segments from the head one to the tail one.
+    #[inline]
+    fn capacity(head: usize, tail: usize) -> usize {
+        let segments = (tail >> Segment::<T>::SHIFT).wrapping_sub(head >> Segment::<T>::SHIFT) + 1;
+
+        // Zero-sized values can make it overflow.
+        segments.saturating_mul(Segment::<T>::CAPACITY)
+    }
+
+    /// Moves the first segment forward to the `segment` if it is before it.
+    ///
+    /// # Safety
+    ///
+    /// The caller should be pinned or be the producer,
+    /// and the `segment` should hold the value with the head index or a value before it.
+    #[inline]
+    unsafe fn advance_first_segment(&self, segment: *mut Segment<T>) {
+        let number = unsafe { (*segment).number };
+        let mut first = self.first_segment.load(Acquire);
+
+        // The numbers can wrap around, so we compare the distance.
+        while (1..=usize::MAX / 2).contains(&number.wrapping_sub(unsafe { (*first).number })) {
+            // `SeqCst` pairs with the `SeqCst` advance of the epoch of pinned consumers.
+            match self
+                .first_segment
+                .compare_exchange(first, segment, SeqCst, Acquire)
+            {
+                Ok(_) => return,
+                Err(current) => first = current,
+            }
+        }
+    }
+}
+
+// Producer
+impl<T, AtomicUsizeWrapper> SPMCSegmentedQueue<T, AtomicUsizeWrapper>
+where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default,
+{
+    /// Returns the length of the queue.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_len(&self) -> usize {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        Self::len(head, tail)
+    }
+
+    /// Returns the capacity of the queue.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_capacity(&self) -> usize {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        Self::capacity(head, tail)
+    }
+
+    /// Returns the segment after the `segment`. Links a new one if there is no next segment.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer, and the `segment` should be the tail one
+    /// or a segment after it.
+    #[inline]
+    unsafe fn producer_next_segment(segment: *mut Segment<T>) -> *mut Segment<T> {
+        let next = unsafe { (*segment).next.load(Relaxed) }; // only producer links segments
+
+        if !next.is_null() {
+            return next;
+        }
+
+        let next = Segment::alloc_new(unsafe { (*segment).number }.wrapping_add(1));
+
+        // Consumers load the next segment only after they load the tail,
+        // so they always see it.
+        unsafe { (*segment).next.store(next, Release) };
+
+        next
+    }
+
+    /// Writes the `values` starting from the `tail` that is held by the `segment`,
+    /// but doesn't update the tail.
+    /// Returns the segment that holds the new tail.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer, and the slots after the `tail` should be free.
+    #[inline]
+    unsafe fn producer_write(
+        mut tail: usize,
+        mut segment: *mut Segment<T>,
+        mut values: &[T],
+    ) -> *mut Segment<T> {
+        while !values.is_empty() {
+            let offset = tail & Segment::<T>::MASK;
+            let n = values.len().min(Segment::<T>::CAPACITY - offset);
+
+            unsafe {
+                ptr::copy_nonoverlapping(values.as_ptr(), Segment::slot(segment, offset).cast(), n);
+            }
+
+            tail = tail.wrapping_add(n);
+            values = &values[n..];
+
+            if tail & Segment::<T>::MASK == 0 {
+                segment = unsafe { Self::producer_next_segment(segment) };
+            }
+        }
+
+        segment
+    }
+
+    /// Makes the values before the `tail` visible for consumers.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer, the values before the `tail` should be written,
+    /// and the `segment` should hold the `tail`.
+    #[inline]
+    unsafe fn producer_commit(&self, tail: usize, segment: *mut Segment<T>) {
+        unsafe { *self.tail_segment.get() = segment };
+
+        self.tail.store(tail, Release);
+    }
+
+    /// Pushes many values to the queue.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_push_many(&self, first: &[T], last: &[T]) {
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+        let mut segment = unsafe { *self.tail_segment.get() };
+
+        segment = unsafe { Self::producer_write(tail, segment, first) };
+        segment = unsafe { Self::producer_write(tail.wrapping_add(first.len()), segment, last) };
+
+        let new_tail = tail.wrapping_add(first.len() + last.len());
+
+        unsafe { self.producer_commit(new_tail, segment) };
+
+        if (new_tail ^ tail) >> Segment::<T>::SHIFT != 0 {
+            // A new segment is linked, it is time to free the popped ones.
+            unsafe { self.producer_maybe_free_segments() };
+        }
+    }
+
+    /// Pushes a value to the queue.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_push(&self, value: T) {
+        unsafe { self.producer_push_many(slice::from_ref(&value), &[]) };
+
+        // The value is moved into the queue.
+        std::mem::forget(value);
+    }
+
+    /// Frees the segments before the first one if there are any and their grace period is over.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_maybe_free_segments(&self) {
+        let first = self.first_segment.load(Acquire);
+
+        if unsafe { *self.oldest_segment.get() } != first {
+            unsafe { self.producer_free_segments(first) };
+        }
+    }
+
+    /// Frees the segments that had been before the first one at the previous advance
+    /// of the epoch of pinned consumers if it advances the epoch again.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer, and the `first` should be
+    /// the loaded first segment.
+    #[inline(never)]
+    unsafe fn producer_free_segments(&self, first: *mut Segment<T>) {
+        let oldest = unsafe { &mut *self.oldest_segment.get() };
+        let freeable = unsafe { &mut *self.freeable_segment.get() };
+
+        // The second advance waits only for consumers pinned after the first one,
+        // so if no consumer is pinned, it frees all segments before the `first` at once.
+        for _ in 0..2 {
+            // Consumers that are pinned after the epoch has been advanced
+            // load the new first segment, so they never read the segments before it.
+            // Otherwise, it retries when it links the next segment or pops.
+            if !self.pinned_consumers.try_advance() {
+                return;
+            }
+
+            while *oldest != *freeable {
+                let next = unsafe { (**oldest).next.load(Relaxed) };
+
+                // All values of the segment have been popped.
+                drop(unsafe { Box::from_raw(*oldest) });
+
+                *oldest = next;
+            }
+
+            // The segments before the `first` have been unlinked before this advance.
+            *freeable = first;
+
+            if *oldest == first {
+                return;
+            }
+        }
+    }
+
+    /// Claims up to `max` values from the head.
+    /// Returns the segment that holds the head, the old head and the number of claimed values.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_claim(&self, max: usize) -> (*mut Segment<T>, usize, usize) {
+        // The first segment is loaded before the head, so it is never after the head one.
+        let first = self.first_segment.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+        let mut head = self.head.load(Acquire);
+
+        loop {
+            let n = max.min(Self::len(head, tail));
+
+            if n == 0 {
+                return (ptr::null_mut(), head, 0);
+            }
+
+            // We haven't read the values yet, so we can use `compare_exchange_weak`.
+            match self
+                .head
+                .compare_exchange_weak(head, head.wrapping_add(n), Release, Acquire)
+            {
+                Ok(_) => {
+                    // We are the only producer, so the claimed values
+                    // can't be overwritten or freed before we read them.
+                    // The caller should free the segments only after reading.
+                    let segment = unsafe { Segment::find(first, head) };
+                    let new_head = head.wrapping_add(n);
+
+                    if (new_head ^ head) >> Segment::<T>::SHIFT != 0 {
+                        unsafe { self.advance_first_segment(Segment::find(segment, new_head)) };
+                    }
+
+                    return (segment, head, n);
+                }
+                Err(new_head) => {
+                    head = new_head;
+                }
+            }
+        }
+    }
+
+    /// Pops many values from the queue.
+    /// Returns the number of popped values.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+        let (segment, head, n) = unsafe { self.producer_claim(dst.len()) };
+        let mut copied = 0;
+
+        unsafe {
+            Segment::for_each_run(segment, head, n, |run| {
+                ptr::copy_nonoverlapping(
+                    run.as_ptr(),
+                    dst.as_mut_ptr().add(copied).cast(),
+                    run.len(),
+                );
+
+                copied += run.len();
+            });
+
+            // The claimed values are read, so their segments can be freed.
+            self.producer_maybe_free_segments();
+        }
+
+        n
+    }
+
+    /// Pops a value from the queue.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline]
+    unsafe fn producer_pop(&self) -> Option<T> {
+        let mut value = MaybeUninit::uninit();
+
+        if unsafe { self.producer_pop_many(slice::from_mut(&mut value)) } == 1 {
+            Some(unsafe { value.assume_init() })
+        } else {
+            None
+        }
+    }
+
+    /// Moves all values of the queue except for the `keep` newest ones
+    /// to the [`SyncBatchReceiver`].
+    /// Returns the number of moved values.
+    ///
+    /// The values of up to three segments are moved in one batch.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_offload_into<SBR: SyncBatchReceiver<T>>(
+        &self,
+        sync_batch_receiver: &SBR,
+        keep: usize,
+    ) -> usize {
+        let n = unsafe { self.producer_len() }.saturating_sub(keep);
+        let (segment, head, n) = unsafe { self.producer_claim(n) };
+        let mut runs: [&[T]; 3] = [&[]; 3];
+        let mut collected = 0;
+
+        unsafe {
+            Segment::for_each_run(segment, head, n, |run| {
+                runs[collected] = run;
+                collected += 1;
+
+                if collected == runs.len() {
+                    sync_batch_receiver.push_many_and_slice(runs[0], runs[1], runs[2]);
+
+                    runs = [&[]; 3];
+                    collected = 0;
+                }
+            });
+        }
+
+        if collected > 0 {
+            sync_batch_receiver.push_many_and_slice(runs[0], runs[1], runs[2]);
+        }
+
+        // The claimed values are moved, so their segments can be freed.
+        unsafe { self.producer_maybe_free_segments() };
+
+        n
+    }
+
+    /// Reserves up to `max` free slots after the tail and returns them.
+    /// Read more in [`Producer::reserve_slots`].
+    ///
+    /// It reserves the slots of the tail segment and the next one.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer,
+    /// and the returned slices should not be used after any other producer method is called.
+    #[allow(
+        clippy::mut_from_ref,
+        reason = "The producer is the only writer of the free slots"
+    )]
+    #[inline]
+    unsafe fn producer_reserve_slots(
+        &self,
+        max: usize,
+    ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+        let segment = unsafe { *self.tail_segment.get() };
+        let offset = tail & Segment::<T>::MASK;
+        let right = max.min(Segment::<T>::CAPACITY - offset);
+        let left = (max - right).min(Segment::<T>::CAPACITY);
+        let next = if left > 0 {
+            unsafe { Self::producer_next_segment(segment) }
+        } else {
+            segment
+        };
+
+        unsafe { *self.reserved_slots.get() = right + left };
+
+        unsafe {
+            (
+                slice::from_raw_parts_mut(Segment::slot(segment, offset), right),
+                slice::from_raw_parts_mut(Segment::slot(next, 0), left),
+            )
+        }
+    }
+
+    /// Makes the first `n` reserved slots visible for consumers.
+    /// It commits no more than the number of the reserved slots.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer, and the first `n` slots
+    /// returned by [`producer_reserve_slots`](Self::producer_reserve_slots)
+    /// should be initialized.
+    #[inline]
+    unsafe fn producer_commit_reserved_slots(&self, n: usize) {
+        let n = n.min(unsafe { mem::take(&mut *self.reserved_slots.get()) });
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+        let mut segment = unsafe { *self.tail_segment.get() };
+        let new_tail = tail.wrapping_add(n);
+
+        if (new_tail ^ tail) >> Segment::<T>::SHIFT != 0 {
+            segment = unsafe { Self::producer_next_segment(segment) };
+        }
+
+        unsafe { self.producer_commit(new_tail, segment) };
+    }
+}
+
+// Consumers
+impl<T, AtomicUsizeWrapper> SPMCSegmentedQueue<T, AtomicUsizeWrapper>
+where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default,
+{
+    /// Returns the capacity of the queue.
+    #[inline]
+    fn consumer_capacity(&self) -> usize {
+        let head = self.head.load(Acquire);
+        let tail = self.tail.load(Acquire);
+
+        Self::capacity(head, tail)
+    }
+
+    /// Returns the length of the queue.
+    #[inline]
+    fn consumer_len(&self) -> usize {
+        // The head is loaded first, so it is never after the tail.
+        let head = self.head.load(Acquire);
+        let tail = self.tail.load(Acquire);
+
+        Self::len(head, tail)
+    }
+
+    /// Claims `n` values starting from the `head` that have been read optimistically.
+    /// Returns `Err` with the current head if another consumer has claimed them.
+    ///
+    /// # Safety
+    ///
+    /// The caller should be pinned, and the `segment` should hold the `head`.
+    #[inline]
+    unsafe fn consumer_claim(
+        &self,
+        segment: *mut Segment<T>,
+        head: usize,
+        n: usize,
+    ) -> Result<(), usize> {
+        // CAS is strong because we don't want to recopy the values
+        self.head
+            .compare_exchange(head, head.wrapping_add(n), Release, Acquire)?;
+
+        let new_head = head.wrapping_add(n);
+
+        if (new_head ^ head) >> Segment::<T>::SHIFT != 0 {
+            // We have popped the last value of the segment, so consumers can skip it.
+            unsafe { self.advance_first_segment(Segment::find(segment, new_head)) };
+        }
+
+        Ok(())
+    }
+
+    /// Pops many values from the queue to the `dst`.
+    /// Returns the number of values popped.
+    ///
+    /// It returns zero only if the queue is empty.
+    #[inline]
+    fn consumer_pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+        let _pinned = self.pinned_consumers.pin();
+        let mut segment = self.first_segment.load(SeqCst);
+        let mut head = self.head.load(Acquire);
+
+        loop {
+            // The tail is loaded after the head, so the head is never after the tail.
+            let tail = self.tail.load(Acquire);
+            let n = dst.len().min(Self::len(head, tail));
+
+            if n == 0 {
+                return 0;
+            }
+
+            segment = unsafe { Segment::find(segment, head) };
+
+            // We optimistically copy the values from the segments into the dst.
+            // On CAS failure, we forget the copied values and try again.
+            // It is safe because the producer never overwrites the slots.
+            let mut copied = 0;
+
+            unsafe {
+                Segment::for_each_run(segment, head, n, |run| {
+                    ptr::copy_nonoverlapping(
+                        run.as_ptr(),
+                        dst.as_mut_ptr().add(copied).cast(),
+                        run.len(),
+                    );
+
+                    copied += run.len();
+                });
+            }
+
+            match unsafe { self.consumer_claim(segment, head, n) } {
+                Ok(()) => return n,
+                Err(current_head) => head = current_head,
+            }
+        }
+    }
+
+    /// Returns the number of values to steal from the queue with the given length,
+    /// or zero if it is too few to steal.
+    #[inline]
+    fn steal_count(&self, len: usize) -> usize {
+        let n = len / 2;
+
+        if n < self.steal_threshold || n == 0 {
+            // we don't steal less than the threshold (4 by default)
+            // because else we may lose more because of cache locality and NUMA awareness
+            return 0;
+        }
+
+        n
+    }
+
+    /// Steals the half of the values from the consumer to the `dst`.
+    /// Returns the number of values stolen.
+    ///
+    /// The `dst` is unbounded, so it appends the stolen values after the values of the `dst`,
+    /// linking new segments of the `dst` if needed.
+    fn steal_into(&self, dst: &Self) -> usize {
+        let _pinned = self.pinned_consumers.pin();
+        let mut segment = self.first_segment.load(SeqCst);
+        let mut head = self.head.load(Acquire);
+        let dst_tail = unsafe { dst.tail.unsync_load() }; // only producer can change tail
+        let dst_segment = unsafe { *dst.tail_segment.get() };
+
+        loop {
+            let tail = self.tail.load(Acquire);
+            let n = self.steal_count(Self::len(head, tail));
+
+            if n == 0 {
+                return 0;
+            }
+
+            segment = unsafe { Segment::find(segment, head) };
+
+            // We optimistically copy the values into the free slots of the `dst`.
+            // On CAS failure, we forget the copied values and try again.
+            // We are the only user of `dst` as in `Producer::reserve_slots`,
+            // so the linked segments are reused by its next writes.
+            let mut new_dst_tail = dst_tail;
+            let mut new_dst_segment = dst_segment;
+
+            unsafe {
+                Segment::for_each_run(segment, head, n, |run| {
+                    new_dst_segment = Self::producer_write(new_dst_tail, new_dst_segment, run);
+                    new_dst_tail = new_dst_tail.wrapping_add(run.len());
+                });
+            }
+
+            match unsafe { self.consumer_claim(segment, head, n) } {
+                Ok(()) => {
+                    unsafe { dst.producer_commit(new_dst_tail, new_dst_segment) };
+
+                    return n;
+                }
+                Err(current_head) => head = current_head,
+            }
+        }
+    }
+
+    /// Steals the half of the values from the consumer to the `dst`,
+    /// that can be a [`Producer`] of any single-producer, multi-consumer queue.
+    /// Returns the number of values stolen.
+    ///
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
+    fn steal_into_any<P: Producer<T>>(&self, dst: &P) -> usize {
+        let _pinned = self.pinned_consumers.pin();
+        let mut segment = self.first_segment.load(SeqCst);
+        let mut head = self.head.load(Acquire);
+
+        loop {
+            let tail = self.tail.load(Acquire);
+            let n = self.steal_count(Self::len(head, tail));
+
+            if n == 0 {
+                return 0;
+            }
+
+            // We are the only user of `dst`, read the safety of `Producer::reserve_slots`.
+            let (dst_first, dst_last, n) = unsafe { reserve_slots_up_to(dst, n) };
+
+            if n == 0 {
+                return 0;
+            }
+
+            segment = unsafe { Segment::find(segment, head) };
+
+            // We optimistically copy the values into the reserved slots.
+            // On CAS failure, we forget the copied values and try again.
+            let mut copied = 0;
+
+            unsafe {
+                Segment::for_each_run(segment, head, n, |run| {
+                    copy_to_reserved_slots(run, copied, dst_first, dst_last);
+
+                    copied += run.len();
+                });
+            }
+
+            match unsafe { self.consumer_claim(segment, head, n) } {
+                Ok(()) => {
+                    unsafe { dst.commit_reserved_slots(n) };
+
+                    return n;
+                }
+                Err(current_head) => head = current_head,
+            }
+        }
+    }
+}
+
+#[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
+unsafe impl<T, AtomicUsizeWrapper> Send for SPMCSegmentedQueue<T, AtomicUsizeWrapper> where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default
+{
+}
+unsafe impl<T, AtomicUsizeWrapper> Sync for SPMCSegmentedQueue<T, AtomicUsizeWrapper> where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default
+{
+}
+
+impl<T, AtomicUsizeWrapper> Drop for SPMCSegmentedQueue<T, AtomicUsizeWrapper>
+where
+    AtomicUsizeWrapper: Deref<Target = AtomicUsize> + Default,
+{
+    fn drop(&mut self) {
+        // While dropping there is no concurrency
+
+        let mut head = unsafe { self.head.unsync_load() };
+        let tail = unsafe { self.tail.unsync_load() };
+        let mut segment = *self.oldest_segment.get_mut();
+
+        unsafe {
+            if needs_drop::<T>() {
+                let mut head_segment = Segment::find(segment, head);
+
+                while head != tail {
+                    ptr::drop_in_place(
+                        Segment::slot(head_segment, head & Segment::<T>::MASK).cast::<T>(),
+                    );
+
+                    head = head.wrapping_add(1);
+
+                    if head & Segment::<T>::MASK == 0 {
+                        head_segment = (*head_segment).next.load(Relaxed);
+                    }
+                }
+            }
+
+            // Free all segments including the linked but unused ones.
+            while !segment.is_null() {
+                let next = (*segment).next.load(Relaxed);
+
+                drop(Box::from_raw(segment));
+
+                segment = next;
+            }
+        }
+    }
+}
+
+/// Generates SPMC segmented producer and consumer.
+macro_rules! generate_spmc_segmented_producer_and_consumer {
+    ($producer_name:ident, $consumer_name:ident, $atomic_usize_wrapper:ty) => {
+        /// The producer of the [`SPMCSegmentedQueue`].
+        pub struct $producer_name<T> {
+            inner: LightArc<SPMCSegmentedQueue<T, $atomic_usize_wrapper>>,
+            _non_sync: PhantomData<*const ()>,
+        }
+
+        impl<T> $producer_name<T> {
+            /// Creates a new queue whose consumers don't steal less than `steal_threshold`
+            /// values at once and returns its producer and consumer.
+            pub(crate) fn new_queue(steal_threshold: usize) -> (Self, $consumer_name<T>) {
+                let queue =
+                    LightArc::new(SPMCSegmentedQueue::with_steal_threshold(steal_threshold));
+
+                (
+                    Self {
+                        inner: queue.clone(),
+                        _non_sync: PhantomData,
+                    },
+                    $consumer_name {
+                        inner: queue,
+                        _non_sync: PhantomData,
+                    },
+                )
+            }
+        }
+
+        impl<T: Send> Producer<T> for $producer_name<T> {
+            #[inline]
+            fn capacity(&self) -> usize {
+                unsafe { self.inner.producer_capacity() }
+            }
+
+            #[inline]
+            fn len(&self) -> usize {
+                unsafe { self.inner.producer_len() }
+            }
+
+            #[inline]
+            fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, _sync_batch_receiver: &SBR) {
+                unsafe { self.inner.producer_push(value) };
+            }
+
+            #[inline]
+            fn try_push<SBR: SyncBatchReceiver<T>>(
+                &self,
+                value: T,
+                _sync_batch_receiver: &SBR,
+            ) -> Result<(), T> {
+                unsafe { self.inner.producer_push(value) };
+
+                Ok(())
+            }
+
+            #[inline]
+            fn maybe_push(&self, value: T) -> Result<(), T> {
+                unsafe { self.inner.producer_push(value) };
+
+                Ok(())
+            }
+
+            #[inline]
+            fn pop(&self) -> Option<T> {
+                unsafe { self.inner.producer_pop() }
+            }
+
+            #[inline]
+            fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+                unsafe { self.inner.producer_pop_many(dst) }
+            }
+
+            #[inline]
+            unsafe fn push_many_unchecked(&self, first: &[T], last: &[T]) {
+                unsafe { self.inner.producer_push_many(first, last) };
+            }
+
+            #[inline]
+            unsafe fn maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
+                unsafe { self.inner.producer_push_many(slice, &[]) };
+
+                Ok(())
+            }
+
+            #[inline]
+            unsafe fn push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                _sync_batch_receiver: &SBR,
+            ) {
+                unsafe { self.inner.producer_push_many(slice, &[]) };
+            }
+
+            #[inline]
+            unsafe fn try_push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                _sync_batch_receiver: &SBR,
+            ) -> Result<(), ()> {
+                unsafe { self.inner.producer_push_many(slice, &[]) };
+
+                Ok(())
+            }
+
+            #[inline]
+            fn push_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+                &self,
+                value: T,
+                _sync_batch_receiver: &SBR,
+                _overflow_policy: &OP,
+            ) -> Result<(), T> {
+                unsafe { self.inner.producer_push(value) };
+
+                Ok(())
+            }
+
+            #[inline]
+            unsafe fn push_many_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+                &self,
+                slice: &[T],
+                _sync_batch_receiver: &SBR,
+                _overflow_policy: &OP,
+            ) -> Result<(), ()> {
+                unsafe { self.inner.producer_push_many(slice, &[]) };
+
+                Ok(())
+            }
+
+            #[inline]
+            fn offload_into<SBR: SyncBatchReceiver<T>>(
+                &self,
+                sync_batch_receiver: &SBR,
+                keep: usize,
+            ) -> usize {
+                unsafe { self.inner.producer_offload_into(sync_batch_receiver, keep) }
+            }
+
+            #[inline]
+            unsafe fn reserve_slots(
+                &self,
+                max: usize,
+            ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+                unsafe { self.inner.producer_reserve_slots(max) }
+            }
+
+            #[inline]
+            unsafe fn commit_reserved_slots(&self, n: usize) {
+                unsafe { self.inner.producer_commit_reserved_slots(n) };
+            }
+        }
+
+        impl<T: Send> ConsumerSpawner<T> for $producer_name<T> {
+            type Consumer = $consumer_name<T>;
+
+            fn spawn_consumer(&self) -> Self::Consumer {
+                $consumer_name {
+                    inner: self.inner.clone(),
+                    _non_sync: PhantomData,
+                }
+            }
+        }
+
+        #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
+        unsafe impl<T: Send> Send for $producer_name<T> {}
+
+        /// The consumer of the [`SPMCSegmentedQueue`].
+        pub struct $consumer_name<T> {
+            inner: LightArc<SPMCSegmentedQueue<T, $atomic_usize_wrapper>>,
+            _non_sync: PhantomData<*const ()>,
+        }
+
+        impl<T: Send> Consumer<T> for $consumer_name<T> {
+            type AssociatedProducer = $producer_name<T>;
+
+            #[inline]
+            fn capacity(&self) -> usize {
+                self.inner.consumer_capacity()
+            }
+
+            #[inline]
+            fn len(&self) -> usize {
+                self.inner.consumer_len()
+            }
+
+            #[inline]
+            fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+                self.inner.consumer_pop_many(dst)
+            }
+
+            #[inline]
+            fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize {
+                self.inner.steal_into(&*dst.inner)
+            }
+
+            #[inline]
+            fn steal_into_any<P: Producer<T>>(&self, dst: &P) -> usize {
+                self.inner.steal_into_any(dst)
+            }
+        }
+
+        impl<T: Send> SyncBatchSource<T> for $consumer_name<T> {
+            #[inline]
+            fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize {
+                move_batch_from_consumer(self, producer, limit)
+            }
+        }
+
+        impl<T> Clone for $consumer_name<T> {
+            fn clone(&self) -> Self {
+                Self {
+                    inner: self.inner.clone(),
+                    _non_sync: PhantomData,
+                }
+            }
+        }
+
+        #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
+        unsafe impl<T: Send> Send for $consumer_name<T> {}
+    };
+
+    ($producer_name:ident, $consumer_name:ident) => {
+        generate_spmc_segmented_producer_and_consumer!(
+            $producer_name,
+            $consumer_name,
+            NotCachePaddedAtomicUsize
+        );
+    };
+}
+
+generate_spmc_segmented_producer_and_consumer!(SPMCSegmentedProducer, SPMCSegmentedConsumer);
+
+/// Creates a new single-producer, multi-consumer unbounded queue of linked fixed-size segments.
+/// Returns [`producer`](SPMCSegmentedProducer) and [`consumer`](SPMCSegmentedConsumer).
+///
+/// The producer can push and pop values, consumers can pop and steal values.
+///
+/// Unlike the queue of [`new_unbounded`](crate::spmc::new_unbounded),
+/// it grows in O(1) by linking a new segment instead of copying all values,
+/// and consumers never wait for the producer to finish the growth.
+/// Therefore, consumers return zero only if the queue is empty,
+/// and they steal values across segment boundaries.
+///
+/// The popped segments are freed by the producer when no consumer reads the queue.
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::spmc::{new_segmented, Producer, Consumer};
+///
+/// let (producer, consumer) = new_segmented();
+///
+/// for i in 0..1000 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// let (producer2, _) = new_segmented();
+///
+/// assert_eq!(consumer.steal_into(&producer2), 500);
+///
+/// let mut slice = [std::mem::MaybeUninit::uninit(); 300];
+///
+/// assert_eq!(consumer.pop_many(&mut slice), 300);
+/// assert_eq!(unsafe { slice[0].assume_init() }, 500);
+/// assert_eq!(producer2.pop(), Some(0));
+/// assert_eq!(producer.pop(), Some(800));
+/// ```
+pub fn new_segmented<T>() -> (SPMCSegmentedProducer<T>, SPMCSegmentedConsumer<T>) {
+    SPMCSegmentedProducer::new_queue(DEFAULT_STEAL_THRESHOLD)
+}
+
+generate_spmc_segmented_producer_and_consumer!(
+    CachePaddedSPMCSegmentedProducer,
+    CachePaddedSPMCSegmentedConsumer,
+    CachePaddedAtomicUsize
+);
+
+/// Creates a new single-producer, multi-consumer unbounded queue of linked fixed-size segments.
+/// Returns [`producer`](CachePaddedSPMCSegmentedProducer)
+/// and [`consumer`](CachePaddedSPMCSegmentedConsumer).
+///
+/// It is the same as [`new_segmented`], but the head and the tail are cache-padded.
+pub fn new_cache_padded_segmented<T>() -> (
+    CachePaddedSPMCSegmentedProducer<T>,
+    CachePaddedSPMCSegmentedConsumer<T>,
+) {
+    CachePaddedSPMCSegmentedProducer::new_queue(DEFAULT_STEAL_THRESHOLD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutex_vec_queue::MutexVecQueue;
+    use crate::zst_test::{test_zst_queue, Zst};
+
+    const N: usize = 16000;
+    const BATCH_SIZE: usize = 10;
+
+    #[test]
+    fn test_spmc_segmented_seq_insertions() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, _) = new_segmented();
+
+        for i in 0..N {
+            producer.push(i.to_string(), &global_queue);
+        }
+
+        assert!(global_queue.is_empty());
+        assert_eq!(producer.len(), N);
+        assert_eq!(producer.capacity(), N.next_multiple_of(SEGMENT_CAPACITY));
+
+        for i in 0..N {
+            assert_eq!(producer.pop().unwrap(), i.to_string());
+        }
+
+        let (producer, consumer) = new_segmented();
+
+        for i in 0..N {
+            producer.maybe_push(i.to_string()).unwrap();
+        }
+
+        for i in 0..N / BATCH_SIZE / 2 {
+            let mut slice = [const { MaybeUninit::uninit() }; BATCH_SIZE];
+
+            assert_eq!(consumer.pop_many(slice.as_mut_slice()), BATCH_SIZE);
+
+            for (j, value) in slice.iter().enumerate() {
+                assert_eq!(
+                    unsafe { value.assume_init_read() },
+                    (i * BATCH_SIZE + j).to_string()
+                );
+            }
+        }
+
+        // Not popped values are dropped with the queue
+    }
+
+    #[test]
+    fn test_spmc_segmented_stealing() {
+        let (producer1, consumer) = new_segmented();
+        let (producer2, _) = new_segmented();
+
+        for i in 0..SEGMENT_CAPACITY * 3 {
+            producer1.maybe_push(i).unwrap();
+        }
+
+        for i in 0..SEGMENT_CAPACITY - 10 {
+            producer2.maybe_push(SEGMENT_CAPACITY * 3 + i).unwrap();
+        }
+
+        for i in 0..SEGMENT_CAPACITY / 2 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        // Both the source and the destination runs cross segment boundaries.
+        let n = SEGMENT_CAPACITY * 5 / 4;
+
+        assert_eq!(consumer.steal_into(&producer2), n);
+        assert_eq!(producer2.len(), SEGMENT_CAPACITY - 10 + n);
+        assert_eq!(consumer.len(), SEGMENT_CAPACITY * 5 / 4);
+
+        for i in 0..SEGMENT_CAPACITY - 10 {
+            assert_eq!(producer2.pop(), Some(SEGMENT_CAPACITY * 3 + i));
+        }
+
+        for i in 0..n {
+            assert_eq!(producer2.pop(), Some(SEGMENT_CAPACITY / 2 + i));
+        }
+
+        for i in SEGMENT_CAPACITY / 2 + n..SEGMENT_CAPACITY * 3 {
+            assert_eq!(producer1.pop(), Some(i));
+        }
+
+        assert_eq!(consumer.steal_into(&producer2), 0);
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_spmc_segmented_stealing_into_any() {
+        let global_queue = MutexVecQueue::new();
+        let (producer1, consumer) = new_segmented();
+        let (producer2, _) = crate::spmc::new_bounded::<_, 16>();
+
+        for i in 0..SEGMENT_CAPACITY + 8 {
+            producer1.push(i, &global_queue);
+        }
+
+        assert_eq!(consumer.steal_into_any(&producer2), 16);
+
+        for i in 0..16 {
+            assert_eq!(producer2.pop(), Some(i));
+        }
+
+        let (producer3, _) = new_segmented();
+
+        for i in 0..SEGMENT_CAPACITY - 4 {
+            producer3.maybe_push(i).unwrap();
+        }
+
+        // The reserved slots of the segmented producer cross a segment boundary.
+        assert_eq!(
+            consumer.steal_into_any(&producer3),
+            SEGMENT_CAPACITY / 2 - 4
+        );
+        assert_eq!(producer3.len(), SEGMENT_CAPACITY * 3 / 2 - 8);
+
+        for i in 0..SEGMENT_CAPACITY - 4 {
+            assert_eq!(producer3.pop(), Some(i));
+        }
+
+        for i in 16..SEGMENT_CAPACITY / 2 + 12 {
+            assert_eq!(producer3.pop(), Some(i));
+        }
+
+        assert!(global_queue.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_segmented_offload() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, _) = new_segmented();
+        let (checker, _) = new_segmented();
+
+        for i in 0..SEGMENT_CAPACITY * 4 + 10 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(producer.pop(), Some(0));
+
+        // The offloaded values take five segments, so they are moved in two batches.
+        assert_eq!(
+            producer.offload_into(&global_queue, 5),
+            SEGMENT_CAPACITY * 4 + 4
+        );
+        assert_eq!(producer.len(), 5);
+        assert_eq!(producer.offload_into(&global_queue, 5), 0);
+
+        // The global queue moves no more than the free slots of the checker at a time.
+        let mut moved = 0;
+
+        loop {
+            let n = global_queue.move_batch_to(&checker, usize::MAX);
+
+            if n == 0 {
+                break;
+            }
+
+            moved += n;
+        }
+
+        assert_eq!(moved, SEGMENT_CAPACITY * 4 + 4);
+
+        for i in 1..SEGMENT_CAPACITY * 4 + 5 {
+            assert_eq!(checker.pop(), Some(i));
+        }
+
+        for i in SEGMENT_CAPACITY * 4 + 5..SEGMENT_CAPACITY * 4 + 10 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_spmc_segmented_frees_popped_segments() {
+        let (producer, consumer) = new_segmented();
+        let idle_consumer = consumer.clone();
+        let segments = || {
+            let mut count = 0;
+            let mut segment = unsafe { *producer.inner.oldest_segment.get() };
+
+            while !segment.is_null() {
+                count += 1;
+                segment = unsafe { (*segment).next.load(Relaxed) };
+            }
+
+            count
+        };
+
+        for i in 0..SEGMENT_CAPACITY * 4 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(segments(), 5);
+
+        let mut slice = [MaybeUninit::uninit(); SEGMENT_CAPACITY];
+
+        for _ in 0..3 {
+            assert_eq!(consumer.pop_many(&mut slice), SEGMENT_CAPACITY);
+        }
+
+        // The producer frees the popped segments when it links the next one.
+        for i in 0..SEGMENT_CAPACITY {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(segments(), 3);
+
+        // A pinned consumer delays freeing.
+        {
+            let _pinned = producer.inner.pinned_consumers.pin();
+
+            assert_eq!(consumer.pop_many(&mut slice), SEGMENT_CAPACITY);
+
+            for i in 0..SEGMENT_CAPACITY {
+                producer.maybe_push(i).unwrap();
+            }
+
+            assert_eq!(segments(), 4);
+        }
+
+        assert_eq!(producer.pop(), Some(0));
+        assert_eq!(segments(), 3);
+        assert_eq!(idle_consumer.len(), SEGMENT_CAPACITY * 2 - 1);
+    }
+
+    #[test]
+    fn test_spmc_segmented_never_returns_spurious_empty() {
+        const ROUNDS: usize = 2000;
+        const BURST: usize = SEGMENT_CAPACITY / 3;
+
+        let (producer, consumer) = new_segmented();
+        let pushed = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            let pushed = &pushed;
+
+            scope.spawn(move || {
+                let mut popped = Vec::with_capacity(ROUNDS * BURST);
+                let mut slice = [MaybeUninit::uninit(); 7];
+
+                while popped.len() < ROUNDS * BURST {
+                    let seen = pushed.load(Acquire);
+                    let n = consumer.pop_many(&mut slice);
+
+                    // It is the only consumer and the producer doesn't pop,
+                    // so the queue can be empty only if all seen values are popped.
+                    assert!(n > 0 || popped.len() >= seen, "spurious empty");
+
+                    for value in &slice[..n] {
+                        popped.push(unsafe { value.assume_init() });
+                    }
+                }
+
+                assert_eq!(popped, (0..ROUNDS * BURST).collect::<Vec<_>>());
+            });
+
+            for round in 0..ROUNDS {
+                for i in 0..BURST {
+                    producer.maybe_push(round * BURST + i).unwrap();
+                }
+
+                pushed.fetch_add(BURST, Release);
+            }
+        });
+    }
+
+    #[test]
+    fn test_spmc_segmented_zst() {
+        let (producer, consumer) = new_segmented::<Zst>();
+        let segment = producer.inner.first_segment.load(Relaxed);
+
+        test_zst_queue(
+            None,
+            |value| producer.maybe_push(value),
+            |dst| consumer.pop_many(dst),
+            || consumer.len(),
+            || consumer.steal_into(&new_segmented().0),
+        );
+
+        // Zero-sized values need no slots, so no segment is linked.
+        assert_eq!(producer.inner.first_segment.load(Relaxed), segment);
+        assert!(unsafe { (*segment).next.load(Relaxed) }.is_null());
+    }
+}
@@ -1,7 +1,10 @@
 use crate::backoff::Backoff;
 use crate::spmc::{
-    new_bounded, new_cache_padded_bounded, new_cache_padded_unbounded, new_unbounded,
-    Consumer as ConsumerExt, Producer as ProducerExt,
+    new_bounded, new_cache_padded_bounded, Consumer as ConsumerExt, Producer as ProducerExt,
+};
+#[cfg(not(feature = "disable_unbounded"))]
+use crate::spmc::{
+    new_cache_padded_segmented, new_cache_padded_unbounded, new_segmented, new_unbounded,
 };
 use crate::test_lock::TEST_LOCK;
 use std::mem::MaybeUninit;
@@ -66,7 +69,7 @@ where
 
         'outer: loop {
             for _ in 0..RAND.fetch_add(1, Ordering::Relaxed) % 10 {
-                while let Err(_) = producer.maybe_push(TestValue::new(i)) {}
+                while producer.maybe_push(TestValue::new(i)).is_err() {}
 
                 i += 1;
 
@@ -91,10 +94,10 @@ where
     let steal_periodically =
         move |consumer: &mut Consumer, counter: Arc<AtomicUsize>| -> Vec<usize> {
             let mut stats = vec![0; N];
-            let (mut dest_producer, _) = creator();
+            let (dest_producer, _) = creator();
 
             loop {
-                consumer.steal_into(&mut dest_producer);
+                consumer.steal_into(&dest_producer);
 
                 while let Some(i) = dest_producer.pop() {
                     stats[*i] += 1;
@@ -115,11 +118,7 @@ where
 
     let t1 = spawn(move || steal_periodically(&mut consumer1, counter1));
     let t2 = spawn(move || steal_periodically(&mut consumer2, counter2));
-    let mut stats = Vec::new();
-
-    stats.push(t0.join().unwrap());
-    stats.push(t1.join().unwrap());
-    stats.push(t2.join().unwrap());
+    let mut stats = [t0.join().unwrap(), t1.join().unwrap(), t2.join().unwrap()];
 
     let check_to = if cfg!(feature = "always_steal") {
         N
@@ -145,11 +144,11 @@ where
     for i in 0..check_to {
         let mut count = 0;
 
-        for j in 0..stats.len() {
-            count += stats[j][i];
+        for thread_stats in &stats {
+            count += thread_stats[i];
         }
 
-        assert_eq!(count, 1, "stats[{i}] = {}", count);
+        assert_eq!(count, 1, "stats[{i}] = {count}");
     }
 }
 
@@ -169,8 +168,8 @@ where
         for _ in 0..N {
             let popped = consumer.pop_many(&mut slice);
 
-            for i in 0..popped {
-                let res = count.fetch_add(unsafe { *slice[i].assume_init() }, Ordering::Relaxed);
+            for value in &slice[..popped] {
+                let res = count.fetch_add(unsafe { *value.assume_init() }, Ordering::Relaxed);
 
                 if res == RES {
                     break;
@@ -187,7 +186,7 @@ where
 
     let (producer, consumer) = creator();
     let mut consumer1 = consumer.clone();
-    let mut consumer2 = consumer.clone();
+    let mut consumer2 = consumer;
     let count = Arc::new(AtomicUsize::new(0));
     let count1 = count.clone();
     let count2 = count.clone();
@@ -197,8 +196,8 @@ where
 
     let mut slice = [TestValue(0); BATCH_SIZE];
     for i in 0..N / BATCH_SIZE {
-        for j in 0..BATCH_SIZE {
-            slice[j] = TestValue(i * BATCH_SIZE + j);
+        for (j, value) in slice.iter_mut().enumerate() {
+            *value = TestValue(i * BATCH_SIZE + j);
         }
 
         let backoff = Backoff::new();
@@ -227,6 +226,7 @@ fn test_bounded_spmc_multi_threaded_steal() {
     drop(test_guard);
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 #[test]
 fn test_unbounded_spmc_multi_threaded_steal() {
     let test_guard = TEST_LOCK.lock();
@@ -265,6 +265,7 @@ fn test_bounded_spmc_multi_threaded_pop_many() {
     drop(test_guard);
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 #[test]
 fn test_unbounded_spmc_multi_threaded_pop_many() {
     let test_guard = TEST_LOCK.lock();
@@ -277,3 +278,77 @@ fn test_unbounded_spmc_multi_threaded_pop_many() {
 
     drop(test_guard);
 }
+
+#[cfg(not(feature = "disable_unbounded"))]
+#[test]
+fn test_segmented_spmc_multi_threaded_steal() {
+    let test_guard = TEST_LOCK.lock();
+
+    test_spmc_multi_threaded_steal(new_segmented);
+
+    println!("Non cache padded done, start cache padded");
+
+    test_spmc_multi_threaded_steal(new_cache_padded_segmented);
+
+    drop(test_guard);
+}
+
+#[cfg(not(feature = "disable_unbounded"))]
+#[test]
+fn test_segmented_spmc_multi_threaded_pop_many() {
+    let test_guard = TEST_LOCK.lock();
+
+    test_spmc_multi_threaded_pop_many(new_segmented);
+
+    println!("Non cache padded done, start cache padded");
+
+    test_spmc_multi_threaded_pop_many(new_cache_padded_segmented);
+
+    drop(test_guard);
+}
+
+fn test_spmc_commit_more_than_reserved<Producer, Consumer>(creator: fn() -> (Producer, Consumer))
+where
+    Producer: ProducerExt<usize>,
+{
+    let (producer, _consumer) = creator();
+
+    producer.maybe_push(0).unwrap();
+
+    unsafe {
+        let (first, last) = producer.reserve_slots(2);
+
+        assert_eq!(first.len() + last.len(), 2);
+
+        for (i, slot) in first.iter_mut().chain(last.iter_mut()).enumerate() {
+            slot.write(1 + i);
+        }
+
+        // Only the reserved slots are committed.
+        producer.commit_reserved_slots(10);
+    }
+
+    assert_eq!(producer.len(), 3);
+
+    // Nothing is reserved after the commit.
+    unsafe { producer.commit_reserved_slots(1) };
+
+    assert_eq!(producer.len(), 3);
+
+    for i in 0..3 {
+        assert_eq!(producer.pop(), Some(i));
+    }
+
+    assert!(producer.is_empty());
+}
+
+#[test]
+fn test_spmc_commit_more_than_reserved_for_all_queues() {
+    test_spmc_commit_more_than_reserved(new_bounded::<usize, 256>);
+
+    #[cfg(not(feature = "disable_unbounded"))]
+    {
+        test_spmc_commit_more_than_reserved(new_unbounded);
+        test_spmc_commit_more_than_reserved(new_segmented);
+    }
+}
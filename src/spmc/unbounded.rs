@@ -4,71 +4,97 @@
     clippy::cast_possible_truncation,
     reason = "LongNumber should be synonymous to usize"
 )]
-use crate::cache_padded::{CachePaddedAtomicU32, CachePaddedAtomicU64};
-use crate::hints::{cold_path, unlikely};
+use crate::alloc_error::{try_alloc_array, AllocError};
+use crate::backoff::Backoff;
+use crate::cache_padded::CachePaddedAtomicU64;
+use crate::growth_policy::GrowthPolicy;
+use crate::hints::{cold_path, likely, unlikely};
 use crate::light_arc::LightArc;
-use crate::loom_bindings::sync::atomic::{AtomicU32, AtomicU64};
+use crate::loom_bindings::sync::atomic::AtomicU64;
 use crate::naive_rw_lock::NaiveRWLock;
-use crate::number_types::{NotCachePaddedAtomicU32, NotCachePaddedAtomicU64};
-use crate::spmc::{Consumer, ConsumerSpawner, Producer};
-use crate::sync_batch_receiver::SyncBatchReceiver;
-use std::alloc::{alloc, Layout};
+use crate::number_types::{
+    AtomicUnboundedIndex, CachePaddedAtomicUnboundedIndex, NotCachePaddedAtomicU64,
+    NotCachePaddedAtomicUnboundedIndex, UnboundedIndex,
+};
+use crate::queue_builder::DEFAULT_STEAL_THRESHOLD;
+use crate::spmc::pinned_consumers::PinnedConsumers;
+use crate::spmc::{
+    copy_to_reserved_slots, reserve_slots_up_to, Consumer, ConsumerSpawner, MoveHalf,
+    OverflowAction, OverflowPolicy, Producer, Reject,
+};
+use crate::sync_batch_receiver::{lock_ignoring_poison, PanickingReceiver, SyncBatchReceiver};
+use crate::sync_batch_source::{move_batch_from_consumer, SyncBatchSource};
+use crate::tail_and_version::TailAndVersion;
 use std::cell::UnsafeCell;
 use std::marker::PhantomData;
-use std::mem::{needs_drop, MaybeUninit};
+use std::mem::{self, needs_drop, ManuallyDrop, MaybeUninit};
 use std::ops::Deref;
 use std::sync::atomic::Ordering;
-use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
 use std::{ptr, slice};
 
-/// Packs the version and the tail into a single 64-bit value.
-#[inline(always)]
-fn pack_version_and_tail(version: u32, tail: u32) -> u64 {
-    (u64::from(version) << 32) | u64::from(tail)
-}
-
-/// Unpacks the version and the tail from a single 64-bit value.
-#[inline(always)]
-fn unpack_version_and_tail(value: u64) -> (u32, u32) {
-    ((value >> 32) as u32, value as u32)
-}
-
 /// A version of the ring-based queue.
 #[repr(C)]
 struct Version<T> {
     ptr: *mut [MaybeUninit<T>],
-    mask: u32,
+    mask: UnboundedIndex,
     id: u32,
 }
 
 impl<T> Version<T> {
     /// Returns the mask for the capacity of the underlying buffer.
     #[inline(always)]
-    fn mask(&self) -> u32 {
+    fn mask(&self) -> UnboundedIndex {
         self.mask
     }
 
     /// Allocates a new version with the given `capacity` and `id`.
-    fn alloc_new(capacity: usize, id: u32) -> LightArc<Self> {
+    fn try_alloc_new(capacity: usize, id: u32) -> Result<LightArc<Self>, AllocError> {
         debug_assert!(
-            capacity > 0 && u32::try_from(capacity).is_ok() && capacity.is_power_of_two()
+            capacity > 0
+                && UnboundedIndex::try_from(capacity).is_ok()
+                && capacity.is_power_of_two()
         );
 
-        let slice_ptr = unsafe {
-            slice::from_raw_parts_mut(
-                alloc(Layout::array::<MaybeUninit<T>>(capacity).unwrap_unchecked())
-                    .cast::<MaybeUninit<T>>(),
-                capacity,
-            )
-        };
+        let slice_ptr = ptr::slice_from_raw_parts_mut(
+            try_alloc_array::<MaybeUninit<T>>(capacity)?.as_ptr(),
+            capacity,
+        );
+
+        Ok(Self::from_buffer(unsafe { Box::from_raw(slice_ptr) }, id))
+    }
+
+    /// Creates a new version with the given `buffer` and `id`.
+    fn from_buffer(buffer: Box<[MaybeUninit<T>]>, id: u32) -> LightArc<Self> {
+        let capacity = buffer.len();
+
+        debug_assert!(
+            capacity > 0
+                && UnboundedIndex::try_from(capacity).is_ok()
+                && capacity.is_power_of_two()
+        );
 
         LightArc::new(Self {
-            ptr: slice_ptr,
-            mask: (capacity - 1) as u32,
+            ptr: Box::into_raw(buffer),
+            mask: (capacity - 1) as UnboundedIndex,
             id,
         })
     }
 
+    /// Returns the underlying buffer to reuse it.
+    /// It doesn't drop the values in the buffer.
+    fn into_buffer(self) -> Box<[MaybeUninit<T>]> {
+        let this = ManuallyDrop::new(self);
+
+        unsafe { Box::from_raw(this.ptr) }
+    }
+
+    /// Allocates a new version with the given `capacity` and `id`
+    /// and [`handles`](AllocError::handle) the error.
+    fn alloc_new(capacity: usize, id: u32) -> LightArc<Self> {
+        Self::try_alloc_new(capacity, id).unwrap_or_else(|err| err.handle())
+    }
+
     /// Returns a raw pointer to the underlying buffer.
     #[inline(always)]
     unsafe fn thin_mut_ptr(&self) -> *mut T {
@@ -83,23 +109,27 @@ impl<T> Drop for Version<T> {
 }
 
 /// A cached [`Version`].
+///
+/// It doesn't own the version: the queue keeps the last version in `last_version`
+/// and superseded versions in `retired_versions` until no consumer can read them.
+/// Therefore, an idle consumer doesn't pin old buffers.
+///
+/// A consumer can read the buffer only while it is [`pinned`](PinnedConsumers::pin)
+/// and only after it checks that the cached version id is the last one.
 #[repr(C)]
 struct CachedVersion<T> {
     ptr: *const [MaybeUninit<T>],
-    mask: u32,
+    mask: UnboundedIndex,
     id: u32,
-    /// Needs to be dropped to release the memory.
-    real: LightArc<Version<T>>,
 }
 
 impl<T> CachedVersion<T> {
-    /// Returns a cached version from the given `arc` version.
-    fn from_arc_version(arc: LightArc<Version<T>>) -> Self {
+    /// Returns a cached version from the given version.
+    fn from_version(version: &Version<T>) -> Self {
         Self {
-            ptr: arc.ptr,
-            mask: arc.mask,
-            id: arc.id,
-            real: arc,
+            ptr: version.ptr,
+            mask: version.mask,
+            id: version.id,
         }
     }
 
@@ -117,7 +147,7 @@ impl<T> CachedVersion<T> {
 
     /// Returns the mask for the capacity of the underlying buffer.
     #[inline(always)]
-    fn mask(&self) -> u32 {
+    fn mask(&self) -> UnboundedIndex {
         self.mask
     }
 
@@ -140,64 +170,153 @@ impl<T> Clone for CachedVersion<T> {
             ptr: self.ptr,
             mask: self.mask,
             id: self.id,
-            real: self.real.clone(),
         }
     }
 }
 
+/// The cursor of a consumer of the [`SPMCUnboundedQueue`].
+///
+/// It caches the version of the buffer that the consumer reads,
+/// so the consumer doesn't load the version for every operation.
+/// Each consumer should have its own cursor that is created by
+/// [`SPMCUnboundedQueue::consumer_cursor`] and used only with the same queue.
+pub struct ConsumerCursor<T> {
+    version: CachedVersion<T>,
+}
+
+impl<T> Clone for ConsumerCursor<T> {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version.clone(),
+        }
+    }
+}
+
+#[allow(
+    clippy::non_send_fields_in_send_ty,
+    reason = "The cursor only points to the buffer of the queue"
+)]
+unsafe impl<T: Send> Send for ConsumerCursor<T> {}
+
 /// The single-producer, multi-consumer ring-based _unbounded_ queue.
 ///
 /// It is safe to use when and only when only one thread is writing to the queue at the same time.
 ///
 /// You can call `producer_` methods for the producer and `consumer_` methods for the consumers.
+/// Consumers pass their own [`ConsumerCursor`] to `consumer_` methods,
+/// because it is too expansive to load the last version of the buffer for every call.
 ///
 /// It accepts two atomic wrappers as generic parameters.
 /// It allows using cache-padded atomics or not.
 /// You should create types aliases not to write this large type name.
 ///
-/// # Why it is private?
+/// # Using directly the [`SPMCUnboundedQueue`] vs. using [`new_unbounded`] or [`new_cache_padded_unbounded`].
 ///
-/// It is private because it needs [`CachedVersion`] to work,
-/// and it is useless to use [`CachedVersion`] without separate consumers.
-/// It is too expansive to load the [`Version`] for any consumer method.
-/// This behavior may be changed in the future.
+/// Functions [`new_unbounded`] and [`new_cache_padded_unbounded`] allocate the
+/// [`SPMCUnboundedQueue`] on the heap in [`LightArc`]
+/// and provide separate producer and consumer.
+/// It hurts the performance if you don't need to allocate the queue separately, but improve
+/// the readability when you need to separate producer and consumer logic and share them.
 ///
 /// It doesn't implement the [`Producer`] and [`Consumer`] traits because all producer methods
 /// are unsafe (can be called only by one thread).
 #[repr(C)]
-pub(crate) struct SPMCUnboundedQueue<
+pub struct SPMCUnboundedQueue<
     T,
-    AtomicU32Wrapper = NotCachePaddedAtomicU32,
+    AtomicIndexWrapper = NotCachePaddedAtomicUnboundedIndex,
     AtomicU64Wrapper = NotCachePaddedAtomicU64,
 > where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
     /// First the producer updates the real version,
     /// and next sets a new id. The version id is monotonic.
-    tail_and_version: AtomicU64Wrapper,
-    head: AtomicU32Wrapper,
+    tail_and_version: TailAndVersion<AtomicU64Wrapper>,
+    head: AtomicIndexWrapper,
     last_version: NaiveRWLock<LightArc<Version<T>>>,
+    /// Superseded versions that consumers may still read.
+    /// The producer frees them after a grace period of [`PinnedConsumers`]
+    /// and keeps their buffers to reuse.
+    /// Only the producer can access it.
+    retired_versions: UnsafeCell<Vec<LightArc<Version<T>>>>,
+    /// The number of the first retired versions that had been retired
+    /// before the last advance of the epoch of pinned consumers,
+    /// so they can be freed after the next advance.
+    /// Only the producer can access it.
+    freeable_retired_versions: UnsafeCell<usize>,
+    /// Buffers of freed versions that the producer reuses when it resizes the queue,
+    /// at most one buffer of each capacity that is not larger than the current one.
+    /// Only the producer can access it.
+    free_buffers: UnsafeCell<Vec<Box<[MaybeUninit<T>]>>>,
+    pinned_consumers: PinnedConsumers,
+    growth_policy: GrowthPolicy,
+    /// Whether the queue stops growing at the maximum capacity of the growth policy
+    /// and overflows like a bounded queue instead of panicking.
+    is_capped: bool,
+    /// Consumers don't steal less than this number of values at once.
+    steal_threshold: usize,
+    /// The number of low occupancy observations in a row.
+    /// Only the producer can access it.
+    low_occupancy_streak: UnsafeCell<usize>,
+    /// The number of slots returned by the last
+    /// [`producer_reserve_slots`](Self::producer_reserve_slots)
+    /// that are not committed yet.
+    /// Only the producer can access it.
+    reserved_slots: UnsafeCell<usize>,
+    /// The cached last version. Only the producer can access it.
+    producer_version: UnsafeCell<CachedVersion<T>>,
 }
 
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper>
-    SPMCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+    SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
-    /// Creates a new queue with the given capacity.
-    fn with_capacity(capacity: usize) -> Self {
+    /// Creates a new [`SPMCUnboundedQueue`] with the default [`GrowthPolicy`].
+    pub fn new() -> Self {
+        Self::with_growth_policy(GrowthPolicy::default())
+    }
+
+    /// Creates a new [`SPMCUnboundedQueue`] with the given [`GrowthPolicy`].
+    pub fn with_growth_policy(growth_policy: GrowthPolicy) -> Self {
+        Self::with_options(growth_policy, false, DEFAULT_STEAL_THRESHOLD)
+    }
+
+    /// Creates a new queue with the given growth policy.
+    /// If `is_capped` is `true` or the growth policy has a lower
+    /// [`maximum capacity`](GrowthPolicy::with_max_capacity),
+    /// the queue overflows at the maximum capacity.
+    fn with_options(growth_policy: GrowthPolicy, is_capped: bool, steal_threshold: usize) -> Self {
+        let last_version = Version::alloc_new(Self::initial_capacity(growth_policy), 0);
+
         Self {
-            tail_and_version: AtomicU64Wrapper::default(),
-            head: AtomicU32Wrapper::default(),
-            last_version: NaiveRWLock::new(Version::alloc_new(capacity, 0)),
+            tail_and_version: TailAndVersion::default(),
+            head: AtomicIndexWrapper::default(),
+            producer_version: UnsafeCell::new(CachedVersion::from_version(&last_version)),
+            last_version: NaiveRWLock::new(last_version),
+            retired_versions: UnsafeCell::new(Vec::new()),
+            freeable_retired_versions: UnsafeCell::new(0),
+            free_buffers: UnsafeCell::new(Vec::new()),
+            pinned_consumers: PinnedConsumers::new(),
+            growth_policy,
+            is_capped: is_capped || growth_policy.is_capped(),
+            steal_threshold,
+            low_occupancy_streak: UnsafeCell::new(0),
+            reserved_slots: UnsafeCell::new(0),
         }
     }
 
-    /// Creates a new queue with the default capacity.
-    fn new() -> Self {
-        Self::with_capacity(4)
+    /// Returns the capacity of the first version.
+    ///
+    /// Zero-sized values need no buffer, so the queue starts with the maximum capacity
+    /// and only moves its indexes.
+    const fn initial_capacity(growth_policy: GrowthPolicy) -> usize {
+        if size_of::<T>() == 0 {
+            growth_policy.max_capacity()
+        } else {
+            growth_policy.initial_capacity()
+        }
     }
 
     /// Updates the version of the queue or returns `false`.
@@ -237,48 +356,82 @@ where
         // the consumer can read B or C.
         // But obviously we should return the version C not to load it again.
 
-        *version = CachedVersion::from_arc_version(new_version.clone());
+        *version = CachedVersion::from_version(&new_version);
 
         true
     }
 
+    /// Returns the [`GrowthPolicy`] of the queue.
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy
+    }
+
+    /// Returns a new [`ConsumerCursor`] for a new consumer of the queue.
+    pub fn consumer_cursor(&self) -> ConsumerCursor<T> {
+        let backoff = Backoff::new();
+
+        loop {
+            // The producer holds the lock only while it replaces the version.
+            if let Some(version) = self.last_version.try_read() {
+                return ConsumerCursor {
+                    version: CachedVersion::from_version(&version),
+                };
+            }
+
+            backoff.snooze();
+        }
+    }
+
+    /// Returns a mutable reference to the cached version of the producer.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer,
+    /// and the producer never gets two mutable references to the version at the same time.
+    #[allow(clippy::mut_from_ref, reason = "Only the producer can access it")]
+    #[inline(always)]
+    unsafe fn producer_version(&self) -> &mut CachedVersion<T> {
+        unsafe { &mut *self.producer_version.get() }
+    }
+
     /// Returns the length of the queue by the given `head` and `tail`.
     #[inline]
-    fn len(head: u32, tail: u32) -> usize {
+    fn len(head: UnboundedIndex, tail: UnboundedIndex) -> usize {
         tail.wrapping_sub(head) as usize
     }
 
+    /// Returns whether the queue can grow to hold `needed` values.
+    /// Only a capped queue can't grow beyond the maximum capacity.
+    #[inline(always)]
+    fn can_grow_to(&self, needed: usize) -> bool {
+        !self.is_capped || needed <= self.growth_policy.max_capacity()
+    }
+
     /// Unsynchronously loads the tail.
     ///
     /// # Safety
     ///
     /// It is called only by the producer.
-    unsafe fn unsync_load_tail(&self) -> u32 {
-        let tail_and_version = unsafe { self.tail_and_version.unsync_load() };
-
-        tail_and_version as u32
+    unsafe fn unsync_load_tail(&self) -> UnboundedIndex {
+        unsafe { self.tail_and_version.unsync_load_tail() }
     }
 
     /// Synchronously loads the tail and version.
-    fn sync_load_version_and_tail(&self, ordering: Ordering) -> (u32, u32) {
-        let tail_and_version = self.tail_and_version.load(ordering);
-
-        unpack_version_and_tail(tail_and_version)
+    fn sync_load_version_and_tail(&self, ordering: Ordering) -> (u32, UnboundedIndex) {
+        self.tail_and_version.load(ordering)
     }
 
     /// Synchronously loads the version.
     fn sync_load_version(&self, ordering: Ordering) -> u32 {
-        let tail_and_version = self.tail_and_version.load(ordering);
-
-        (tail_and_version >> 32) as u32
+        self.tail_and_version.load_version(ordering)
     }
 }
 
 // Producer
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper>
-    SPMCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+    SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
     /// Returns the length of the queue.
@@ -287,7 +440,7 @@ where
     ///
     /// It is called only by the producer.
     #[inline]
-    unsafe fn producer_len(&self) -> usize {
+    pub unsafe fn producer_len(&self) -> usize {
         let head = self.head.load(Acquire);
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
@@ -303,18 +456,18 @@ where
     ///
     /// It is called only by the producer.
     #[inline]
-    unsafe fn producer_capacity(version: &CachedVersion<T>) -> usize {
+    pub unsafe fn producer_capacity(&self) -> usize {
         // The producer always has the latest version.
-        version.capacity()
+        unsafe { self.producer_version() }.capacity()
     }
 
     /// Pushes a slice into the queue. Returns a new tail (not index).
     fn copy_slice(
         buffer_ptr: *mut T,
-        start_tail: u32,
+        start_tail: UnboundedIndex,
         slice: &[T],
         version: &CachedVersion<T>,
-    ) -> u32 {
+    ) -> UnboundedIndex {
         let tail_idx = (start_tail & version.mask) as usize;
 
         if tail_idx + slice.len() <= version.capacity() {
@@ -334,20 +487,19 @@ where
             }
         }
 
-        start_tail.wrapping_add(slice.len() as u32)
+        start_tail.wrapping_add(slice.len() as UnboundedIndex)
     }
 
-    /// Creates a new version and writes it but not updates the tail.
+    /// Copies the values to the allocated `new_version` and writes it but not updates the tail.
     /// Returns the new version and the new tail.
     fn create_new_version_and_write_it_but_not_update_tail(
         &self,
-        head: u32,
-        mut tail: u32,
-        new_capacity: usize,
+        head: UnboundedIndex,
+        mut tail: UnboundedIndex,
+        new_version: LightArc<Version<T>>,
         old_version: &CachedVersion<T>,
-    ) -> (CachedVersion<T>, u32) {
-        let new_version: LightArc<Version<T>> =
-            Version::alloc_new(new_capacity, old_version.id() + 1);
+    ) -> (CachedVersion<T>, UnboundedIndex) {
+        debug_assert_eq!(new_version.id, old_version.id() + 1);
 
         // The key idea is to transform the buffer viewed as:
         // [ 7 8 1 2 3 4 5 6 ]
@@ -386,7 +538,7 @@ where
             }
         };
 
-        let cached_version = CachedVersion::from_arc_version(new_version.clone());
+        let cached_version = CachedVersion::from_version(&new_version);
 
         tail = Self::copy_slice(
             unsafe { cached_version.thin_mut_ptr() }.cast::<T>(),
@@ -401,44 +553,295 @@ where
             &cached_version,
         );
 
-        *self.last_version.write() = new_version;
+        let old_version = mem::replace(&mut *self.last_version.write(), new_version);
+
+        // Consumers may still read the old version, so the producer frees it later.
+        unsafe { (*self.retired_versions.get()).push(old_version) };
 
         (cached_version, tail)
     }
 
+    /// Returns a new version with the given `capacity` and `id`.
+    /// It reuses a freed buffer of this capacity if there is one.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_try_alloc_version(
+        &self,
+        capacity: usize,
+        id: u32,
+    ) -> Result<LightArc<Version<T>>, AllocError> {
+        let free_buffers = unsafe { &mut *self.free_buffers.get() };
+
+        if let Some(idx) = free_buffers
+            .iter()
+            .position(|buffer| buffer.len() == capacity)
+        {
+            return Ok(Version::from_buffer(free_buffers.swap_remove(idx), id));
+        }
+
+        Version::try_alloc_new(capacity, id)
+    }
+
+    /// Returns a new version with the given `capacity` and `id`
+    /// and [`handles`](AllocError::handle) the error.
+    /// It reuses a freed buffer of this capacity if there is one.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_alloc_version(&self, capacity: usize, id: u32) -> LightArc<Version<T>> {
+        unsafe { self.producer_try_alloc_version(capacity, id) }.unwrap_or_else(|err| err.handle())
+    }
+
+    /// Frees the buffers that the producer keeps to reuse.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_free_buffers(&self) {
+        unsafe { (*self.free_buffers.get()).clear() };
+    }
+
     /// Updates the capacity of the queue.
     ///
     /// # Safety
     ///
     /// It is called only by the producer,
     /// and the provided capacity should be more than the current capacity,
-    /// and less than `u32::MAX` and be a power of two.
-    unsafe fn producer_reserve(&self, new_capacity: usize, version: &mut CachedVersion<T>) {
+    /// fit into the [`UnboundedIndex`] and be a power of two.
+    unsafe fn producer_grow_version_to(&self, new_capacity: usize, version: &mut CachedVersion<T>) {
         debug_assert!(
             new_capacity > version.capacity(),
             "new_capacity should be more than version.capacity()"
         );
         debug_assert!(
-            u32::try_from(new_capacity).is_ok(),
-            "new_capacity should be less than u32::MAX"
+            UnboundedIndex::try_from(new_capacity).is_ok(),
+            "new_capacity should fit into UnboundedIndex"
         );
         debug_assert!(
             new_capacity.is_power_of_two(),
             "new_capacity should be power of two"
         );
 
+        unsafe {
+            self.producer_grow_into(
+                self.producer_alloc_version(new_capacity, version.id() + 1),
+                version,
+            );
+        }
+    }
+
+    /// Updates the capacity of the queue to the given value rounded up to a power of two.
+    /// It does nothing if the capacity is already not less.
+    ///
+    /// It returns [`AllocError::CapacityOverflow`] if the rounded capacity is greater than
+    /// the maximum capacity of the [`GrowthPolicy`].
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_try_grow_version_to(
+        &self,
+        capacity: usize,
+        version: &mut CachedVersion<T>,
+    ) -> Result<(), AllocError> {
+        let new_capacity = capacity
+            .checked_next_power_of_two()
+            .filter(|&new_capacity| new_capacity <= self.growth_policy.max_capacity())
+            .ok_or(AllocError::CapacityOverflow)?;
+
+        if new_capacity <= version.capacity() {
+            return Ok(());
+        }
+
+        let new_version =
+            unsafe { self.producer_try_alloc_version(new_capacity, version.id() + 1) }?;
+
+        unsafe { self.producer_grow_into(new_version, version) };
+
+        Ok(())
+    }
+
+    /// Moves the values to the allocated `new_version` that has a greater capacity.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_grow_into(
+        &self,
+        new_version: LightArc<Version<T>>,
+        version: &mut CachedVersion<T>,
+    ) {
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
         let (cached_version, tail) = self.create_new_version_and_write_it_but_not_update_tail(
             self.head.load(Acquire),
             tail,
-            new_capacity,
+            new_version,
+            version,
+        );
+
+        unsafe {
+            self.tail_and_version
+                .store(cached_version.id(), tail, Release);
+        }
+
+        *version = cached_version;
+
+        unsafe { self.producer_free_retired_versions(version.capacity()) };
+    }
+
+    /// Shrinks the capacity of the queue to the given value rounded up to a power of two,
+    /// but never below the length of the queue.
+    /// It does nothing if the capacity is already not greater.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_shrink_version_to(&self, capacity: usize, version: &mut CachedVersion<T>) {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+        let new_capacity = capacity
+            .max(Self::len(head, tail))
+            .max(1)
+            .checked_next_power_of_two();
+
+        unsafe { *self.low_occupancy_streak.get() = 0 };
+
+        // The capacity that overflows `usize` is not less than the current one either.
+        let Some(new_capacity) =
+            new_capacity.filter(|&new_capacity| new_capacity < version.capacity())
+        else {
+            return;
+        };
+
+        // Shrinking works like growing: the values keep their indexes,
+        // and consumers that still use the old version read the same values from it.
+        // Consumers can only increase the head, so the values still fit into the new version.
+        let (cached_version, tail) = self.create_new_version_and_write_it_but_not_update_tail(
+            head,
+            tail,
+            unsafe { self.producer_alloc_version(new_capacity, version.id() + 1) },
             version,
         );
 
-        self.tail_and_version
-            .store(pack_version_and_tail(cached_version.id(), tail), Release);
+        unsafe {
+            self.tail_and_version
+                .store(cached_version.id(), tail, Release);
+        }
 
         *version = cached_version;
+
+        // The larger buffers would be kept until the queue grows again, so they are freed.
+        unsafe { &mut *self.free_buffers.get() }.retain(|buffer| buffer.len() <= new_capacity);
+
+        unsafe { self.producer_free_retired_versions(new_capacity) };
+    }
+
+    /// Shrinks the queue if its occupancy has been low
+    /// for [`GrowthPolicy::shrink_after`] observations in a row.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    unsafe fn producer_track_occupancy(&self, version: &mut CachedVersion<T>) {
+        // Zero-sized values occupy no memory, so there is nothing to free.
+        if size_of::<T>() != 0 && self.growth_policy.can_shrink(version.capacity()) {
+            unsafe { self.producer_track_low_occupancy(version) };
+        }
+    }
+
+    /// The slow path of [`producer_track_occupancy`](Self::producer_track_occupancy).
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(never)]
+    unsafe fn producer_track_low_occupancy(&self, version: &mut CachedVersion<T>) {
+        let len = unsafe { self.producer_len() };
+        let streak = unsafe { &mut *self.low_occupancy_streak.get() };
+
+        if !GrowthPolicy::is_low_occupancy(len, version.capacity()) {
+            *streak = 0;
+
+            return;
+        }
+
+        *streak += 1;
+
+        if *streak >= self.growth_policy.shrink_after() {
+            let capacity = self.growth_policy.shrunk_capacity(len);
+
+            unsafe { self.producer_shrink_version_to(capacity, version) };
+        }
+    }
+
+    /// Frees retired versions if there are any and their grace period is over.
+    /// The `capacity` is the capacity of the current version.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    unsafe fn producer_maybe_free_retired_versions(&self, capacity: usize) {
+        if unlikely(!unsafe { &*self.retired_versions.get() }.is_empty()) {
+            unsafe { self.producer_free_retired_versions(capacity) };
+        }
+    }
+
+    /// The slow path of
+    /// [`producer_maybe_free_retired_versions`](Self::producer_maybe_free_retired_versions).
+    ///
+    /// Consumers that are pinned after the epoch has been advanced load the new version id,
+    /// so they never read the versions retired before it.
+    /// The versions retired before the previous advance are freed,
+    /// and the others wait for the next advance.
+    /// If consumers pinned in the previous epoch are still reading,
+    /// it retries on the next producer operation.
+    ///
+    /// The buffers of the freed versions are kept to reuse
+    /// only if they are not larger than the `capacity` of the current version.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(never)]
+    unsafe fn producer_free_retired_versions(&self, capacity: usize) {
+        let retired_versions = unsafe { &mut *self.retired_versions.get() };
+        let freeable = unsafe { &mut *self.freeable_retired_versions.get() };
+        let free_buffers = unsafe { &mut *self.free_buffers.get() };
+
+        // The second advance waits only for consumers pinned after the first one,
+        // so if no consumer is pinned, it frees all retired versions at once.
+        for _ in 0..2 {
+            // The version id is published before we advance the epoch.
+            if !self.pinned_consumers.try_advance() {
+                return;
+            }
+
+            for version in retired_versions.drain(..*freeable) {
+                // Only the queue holds versions, so it is the last reference.
+                let Some(version) = LightArc::into_inner(version) else {
+                    continue;
+                };
+                let buffer = version.into_buffer();
+
+                if buffer.len() <= capacity
+                    && free_buffers.iter().all(|free| free.len() != buffer.len())
+                {
+                    free_buffers.push(buffer);
+                }
+            }
+
+            // The rest versions have been retired before this advance.
+            *freeable = retired_versions.len();
+
+            if *freeable == 0 {
+                return;
+            }
+        }
     }
 
     /// Pops a value from the queue.
@@ -447,14 +850,19 @@ where
     ///
     /// The called should be the only producer.
     #[inline]
-    unsafe fn producer_pop(&self, version: &CachedVersion<T>) -> Option<T> {
+    pub unsafe fn producer_pop(&self) -> Option<T> {
         // The producer always has the latest version.
+        let version = unsafe { self.producer_version() };
+
+        unsafe { self.producer_maybe_free_retired_versions(version.capacity()) };
 
         let mut head = self.head.load(Acquire);
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
         loop {
             if unlikely(head == tail) {
+                unsafe { self.producer_track_occupancy(version) };
+
                 return None;
             }
 
@@ -466,13 +874,17 @@ where
                     // We are the only producer,
                     // so we can don't worry
                     // about someone overwriting the value before we read it
-                    return Some(unsafe {
+                    let value = unsafe {
                         version
                             .thin_ptr()
                             .add((head & version.mask()) as usize)
                             .read()
                             .assume_init()
-                    });
+                    };
+
+                    unsafe { self.producer_track_occupancy(version) };
+
+                    return Some(value);
                 }
                 Err(new_head) => {
                     head = new_head;
@@ -488,12 +900,11 @@ where
     ///
     /// The called should be the only producer.
     #[inline]
-    unsafe fn producer_pop_many(
-        &self,
-        dst: &mut [MaybeUninit<T>],
-        version: &CachedVersion<T>,
-    ) -> usize {
+    pub unsafe fn producer_pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
         // The producer always has the latest version.
+        let version = unsafe { self.producer_version() };
+
+        unsafe { self.producer_maybe_free_retired_versions(version.capacity()) };
 
         let mut head = self.head.load(Acquire);
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
@@ -503,6 +914,8 @@ where
             let n = dst.len().min(available);
 
             if n == 0 {
+                unsafe { self.producer_track_occupancy(version) };
+
                 return 0;
             }
 
@@ -510,7 +923,7 @@ where
 
             match self.head.compare_exchange_weak(
                 head,
-                head.wrapping_add(n as u32),
+                head.wrapping_add(n as UnboundedIndex),
                 Release,
                 Acquire,
             ) {
@@ -544,6 +957,8 @@ where
                         }
                     }
 
+                    unsafe { self.producer_track_occupancy(version) };
+
                     return n;
                 }
                 Err(new_head) => {
@@ -559,7 +974,7 @@ where
     ///
     /// The called should be the only producer and the queue should not be full.
     #[inline(always)]
-    unsafe fn push_unchecked(&self, value: T, tail: u32, version: &CachedVersion<T>) {
+    unsafe fn push_unchecked(&self, value: T, tail: UnboundedIndex, version: &CachedVersion<T>) {
         // The producer always has the latest version.
 
         unsafe {
@@ -570,13 +985,13 @@ where
                 .write(MaybeUninit::new(value));
         }
 
-        self.tail_and_version.store(
-            pack_version_and_tail(version.id, tail.wrapping_add(1)),
-            Release,
-        );
+        unsafe {
+            self.tail_and_version
+                .store(version.id, tail.wrapping_add(1), Release);
+        }
     }
 
-    /// Updates the version and resizes the queue to the capacity * 2.
+    /// Updates the version and resizes the queue according to the [`GrowthPolicy`].
     /// Then it insets the provided slice.
     ///
     /// # Safety
@@ -586,20 +1001,21 @@ where
     #[cold]
     unsafe fn handle_overflow(
         &self,
-        head: u32,
-        tail: u32,
+        head: UnboundedIndex,
+        tail: UnboundedIndex,
         version: &mut CachedVersion<T>,
         values: &[T],
     ) {
-        let mut new_capacity = version.capacity() * 2;
-        while new_capacity <= version.capacity() + values.len() {
-            new_capacity *= 2;
-        }
+        let new_capacity = self
+            .growth_policy
+            .grown_capacity(version.capacity(), Self::len(head, tail) + values.len());
+
+        unsafe { *self.low_occupancy_streak.get() = 0 };
 
         let (cached_version, tail) = self.create_new_version_and_write_it_but_not_update_tail(
             head,
             tail,
-            new_capacity,
+            unsafe { self.producer_alloc_version(new_capacity, version.id() + 1) },
             version,
         );
 
@@ -609,867 +1025,3071 @@ where
             values,
             &cached_version,
         );
-        self.tail_and_version.store(
-            pack_version_and_tail(cached_version.id(), new_tail),
-            Release,
-        );
+        unsafe {
+            self.tail_and_version
+                .store(cached_version.id(), new_tail, Release);
+        }
 
         // Here we don't need the previous version anymore.
         *version = cached_version;
+
+        unsafe { self.producer_free_retired_versions(version.capacity()) };
     }
 
-    /// Pushes a value to the queue.
-    /// Because the queue is unbounded, this method always succeeds.
+    /// Grows the queue to the maximum capacity if it is less.
+    /// Returns the loaded head and tail.
     ///
     /// # Safety
     ///
     /// The called should be the only producer.
-    #[inline]
-    unsafe fn producer_push(&self, value: T, version: &mut CachedVersion<T>) {
+    unsafe fn producer_grow_to_max_capacity(
+        &self,
+        version: &mut CachedVersion<T>,
+    ) -> (UnboundedIndex, UnboundedIndex) {
+        let max_capacity = self.growth_policy.max_capacity();
+
+        if version.capacity() < max_capacity {
+            unsafe { self.producer_grow_version_to(max_capacity, version) };
+        }
+
         let head = self.head.load(Acquire);
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
-        if unlikely(Self::len(head, tail) == version.capacity()) {
-            unsafe { self.handle_overflow(head, tail, version, &[value]) };
+        (head, tail)
+    }
 
-            return;
-        }
+    /// Returns the first `n` values of the queue starting from the `head` as two slices;
+    /// the first slice contains older values than the last one.
+    fn oldest_values(head: UnboundedIndex, n: usize, version: &CachedVersion<T>) -> (&[T], &[T]) {
+        debug_assert!(n <= version.capacity());
 
-        unsafe { self.push_unchecked(value, tail, version) };
+        let head_idx = (head & version.mask()) as usize;
+        let right = n.min(version.capacity() - head_idx);
+
+        unsafe {
+            (
+                slice::from_raw_parts(version.thin_ptr().add(head_idx).cast(), right),
+                slice::from_raw_parts(version.thin_ptr().cast(), n - right),
+            )
+        }
     }
 
-    /// Pushes many values to the queue.
+    /// Returns `n` values that have been taken from the `taken_head` back to the head of the queue,
+    /// so the queue keeps its FIFO order.
     ///
-    /// # Safety
-    ///
-    /// The called should be the only producer and the space is enough.
-    #[inline]
-    unsafe fn producer_push_many_unchecked(
+    /// It is used when the [`SyncBatchReceiver`] rejects the values.
+    #[cold]
+    fn return_taken_values(
         &self,
-        first: &[T],
-        last: &[T],
+        tail: UnboundedIndex,
+        taken_head: UnboundedIndex,
+        n: usize,
         version: &CachedVersion<T>,
     ) {
-        if cfg!(debug_assertions) {
-            let head = self.head.load(Acquire);
-            let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
-
-            debug_assert!(Self::len(head, tail) + first.len() + last.len() <= version.capacity());
+        let taken_tail = taken_head.wrapping_add(n as UnboundedIndex);
+
+        // If no consumer has popped since the values were taken, they are still in their slots.
+        if self
+            .head
+            .compare_exchange(taken_tail, taken_head, Release, Relaxed)
+            .is_ok()
+        {
+            return;
         }
 
-        // It is SPMC, and it is expected that the capacity is enough.
+        // Otherwise, we can't move the head back, because the consumers copy the values
+        // before their CAS (read `ConstBoundedRing::return_taken_values`).
+        // Instead, we take the rest values too and push them after the tail
+        // right after the taken values.
+        let mut head = self.head.load(Acquire);
 
-        let mut tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+        while let Err(new_head) = self
+            .head
+            .compare_exchange_weak(head, tail, Release, Acquire)
+        {
+            head = new_head;
+        }
 
-        tail = Self::copy_slice(
-            unsafe { version.thin_mut_ptr().cast() },
-            tail,
-            first,
-            version,
-        );
-        tail = Self::copy_slice(
-            unsafe { version.thin_mut_ptr().cast() },
-            tail,
-            last,
-            version,
-        );
+        let rest = Self::len(head, tail);
+        let sources = (0..n as UnboundedIndex)
+            .map(|i| taken_head.wrapping_add(i))
+            .chain((0..rest as UnboundedIndex).map(|i| head.wrapping_add(i)));
 
-        self.tail_and_version
-            .store(pack_version_and_tail(version.id(), tail), Release);
-    }
+        for (i, src) in (0..).zip(sources) {
+            unsafe {
+                ptr::copy(
+                    version.thin_ptr().add((src & version.mask()) as usize),
+                    version
+                        .thin_mut_ptr()
+                        .add((tail.wrapping_add(i) & version.mask()) as usize),
+                    1,
+                );
+            }
+        }
 
-    /// Pushes many values to the queue.
+        unsafe {
+            self.tail_and_version.store(
+                version.id(),
+                tail.wrapping_add((n + rest) as UnboundedIndex),
+                Release,
+            );
+        }
+    }
+
+    /// Likely moves `n` oldest values of the capped queue and one value
+    /// to the [`SyncBatchReceiver`] with the `push` function.
+    ///
+    /// If the `push` rejects the values, it returns the oldest values to the queue
+    /// and returns the error.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer, and the queue should be full.
+    #[inline(never)]
+    #[cold]
+    unsafe fn handle_capped_overflow_one(
+        &self,
+        tail: UnboundedIndex,
+        mut head: UnboundedIndex,
+        n: usize,
+        push: impl FnOnce(&[T], &[T], T) -> Result<(), T>,
+        value: T,
+        version: &CachedVersion<T>,
+    ) -> Result<(), T> {
+        debug_assert!(Self::len(head, tail) == version.capacity());
+
+        loop {
+            let n = n.min(Self::len(head, tail));
+            let (first, last) = Self::oldest_values(head, n, version);
+
+            // We haven't read the value yet, so we can use `compare_exchange_weak`.
+            // If it fails, we calculate two slices and try again, it is not a performance issue.
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => {}
+                Err(new_head) => {
+                    head = new_head;
+
+                    if Self::len(head, tail) < version.capacity() {
+                        // Another thread concurrently stole from the queue.
+                        // Because we are the one producer,
+                        // we can just insert the value (it can't become full before we return).
+                        unsafe { self.push_unchecked(value, tail, version) };
+
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+            }
+
+            return push(first, last, value).inspect_err(|_| {
+                self.return_taken_values(tail, head, n, version);
+            });
+        }
+    }
+
+    /// Likely moves `n` oldest values of the capped queue and many values
+    /// to the [`SyncBatchReceiver`] with the `push` function.
+    ///
+    /// If the `push` rejects the values, it returns the oldest values to the queue
+    /// and returns the error.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer, and the `slice` should not fit into the queue.
+    #[inline(never)]
+    #[cold]
+    unsafe fn handle_capped_overflow_many(
+        &self,
+        tail: UnboundedIndex,
+        mut head: UnboundedIndex,
+        n: usize,
+        push: impl FnOnce(&[T], &[T], &[T]) -> Result<(), ()>,
+        slice: &[T],
+        version: &CachedVersion<T>,
+    ) -> Result<(), ()> {
+        debug_assert!(Self::len(head, tail) + slice.len() > version.capacity());
+
+        loop {
+            let n = n.min(Self::len(head, tail));
+            let (first, last) = Self::oldest_values(head, n, version);
+
+            // We haven't read the value yet, so we can use `compare_exchange_weak`.
+            // If it fails, we calculate two slices and try again, it is not a performance issue.
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => {}
+                Err(new_head) => {
+                    head = new_head;
+
+                    if Self::len(head, tail) + slice.len() <= version.capacity() {
+                        // Another thread concurrently stole from the queue.
+                        // Because we are the one producer,
+                        // we can just insert the slice (it can't become full before we return).
+                        let new_tail = Self::copy_slice(
+                            unsafe { version.thin_mut_ptr().cast() },
+                            tail,
+                            slice,
+                            version,
+                        );
+
+                        unsafe { self.tail_and_version.store(version.id(), new_tail, Release) };
+
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+            }
+
+            return push(first, last, slice).inspect_err(|()| {
+                self.return_taken_values(tail, head, n, version);
+            });
+        }
+    }
+
+    /// Drops the oldest values of the capped queue to make space for the `slice`,
+    /// passes them to the [`OverflowPolicy::on_drop`] and pushes the `slice`.
+    ///
+    /// If the `slice` is longer than the capacity, its first values are dropped too.
     ///
     /// # Safety
     ///
     /// The called should be the only producer.
-    #[inline]
-    unsafe fn producer_push_many(&self, slice: &[T], version: &mut CachedVersion<T>) {
-        let head = self.head.load(Acquire);
-        let mut tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+    #[inline(never)]
+    #[cold]
+    unsafe fn drop_oldest_and_push_many<OP: OverflowPolicy<T>>(
+        &self,
+        tail: UnboundedIndex,
+        mut head: UnboundedIndex,
+        slice: &[T],
+        overflow_policy: &OP,
+        version: &CachedVersion<T>,
+    ) {
+        let (dropped_slice, slice) = slice.split_at(slice.len().saturating_sub(version.capacity()));
 
-        if unlikely(Self::len(head, tail) + slice.len() > version.capacity()) {
-            unsafe { self.handle_overflow(head, tail, version, slice) };
+        loop {
+            let n = (Self::len(head, tail) + slice.len()).saturating_sub(version.capacity());
 
-            return;
+            if n == 0 {
+                break;
+            }
+
+            // We haven't read the values yet, so we can use `compare_exchange_weak`.
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => {
+                    // We are the only producer,
+                    // so we can don't worry about someone overwriting the values before we read them
+                    let (first, last) = Self::oldest_values(head, n, version);
+
+                    for value in first.iter().chain(last) {
+                        overflow_policy.on_drop(unsafe { ptr::read(value) });
+                    }
+
+                    break;
+                }
+                Err(new_head) => {
+                    head = new_head;
+                }
+            }
         }
 
-        tail = Self::copy_slice(
+        for value in dropped_slice {
+            overflow_policy.on_drop(unsafe { ptr::read(value) });
+        }
+
+        let new_tail = Self::copy_slice(
             unsafe { version.thin_mut_ptr().cast() },
             tail,
             slice,
             version,
         );
 
-        self.tail_and_version
-            .store(pack_version_and_tail(version.id(), tail), Release);
+        unsafe { self.tail_and_version.store(version.id(), new_tail, Release) };
     }
-}
 
-// Consumers
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper>
-    SPMCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
-where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
-    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
-{
-    /// Returns the capacity of the queue.
-    #[inline]
-    fn consumer_capacity(&self, version: &mut CachedVersion<T>) -> usize {
-        let last_version_id = self.sync_load_version(Relaxed);
-        if version.id() == last_version_id {
-            return version.capacity();
+    /// Grows the queue and pushes a value,
+    /// or performs the action of the [`OverflowPolicy`] if the capped queue can't grow.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer, and the queue should be full.
+    #[inline(never)]
+    #[cold]
+    unsafe fn handle_overflow_one_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+        &self,
+        head: UnboundedIndex,
+        tail: UnboundedIndex,
+        value: T,
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+        version: &mut CachedVersion<T>,
+    ) -> Result<(), T> {
+        if self.can_grow_to(version.capacity() + 1) {
+            unsafe { self.handle_overflow(head, tail, version, slice::from_ref(&value)) };
+
+            // The value is moved into the queue.
+            mem::forget(value);
+
+            return Ok(());
         }
 
-        cold_path();
+        match overflow_policy.action(version.capacity()) {
+            OverflowAction::MoveToReceiver(n) => {
+                let _ = unsafe {
+                    self.handle_capped_overflow_one(
+                        tail,
+                        head,
+                        n,
+                        |first, last, value| {
+                            sync_batch_receiver.push_many_and_one(first, last, value);
+
+                            Ok(())
+                        },
+                        value,
+                        version,
+                    )
+                };
+            }
+            OverflowAction::Reject => return Err(value),
+            OverflowAction::DropNewest => overflow_policy.on_drop(value),
+            OverflowAction::DropOldest => {
+                unsafe {
+                    self.drop_oldest_and_push_many(
+                        tail,
+                        head,
+                        slice::from_ref(&value),
+                        overflow_policy,
+                        version,
+                    );
+                }
 
-        let _was_updated = self.update_version(version);
+                mem::forget(value);
+            }
+            OverflowAction::Spill => overflow_policy.spill(value, sync_batch_receiver),
+        }
 
-        // If was_updated, the capacity is valid.
-        // If !was_updated we should guess that the producer has been preempted,
-        // and for some time the capacity of the current version is valid.
-        version.capacity()
+        Ok(())
     }
 
-    /// Returns the length of the queue.
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and has reached the maximum capacity,
+    /// it performs the action of the [`OverflowPolicy`] like a bounded queue.
+    /// Read more in [`Producer::push_with_policy`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
     #[inline]
-    fn consumer_len(&self, version: &mut CachedVersion<T>) -> usize {
-        loop {
-            let (last_version_id, tail) = self.sync_load_version_and_tail(Relaxed);
-            let head = self.head.load(Relaxed);
-            let len = Self::len(head, tail);
+    pub unsafe fn producer_push_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+    ) -> Result<(), T> {
+        let version = unsafe { self.producer_version() };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
-            if unlikely(len > version.capacity()) {
-                // Three possible reasons:
-                // 1. Inconsistent state (this thread has been preempted
-                //    after we have loaded `tail`,
-                //    and before we have loaded `head`);
-                // 2. The new version was created and has more capacity;
-                // 3. The first reason and the second reason simultaneously.
+        if unlikely(Self::len(head, tail) == version.capacity()) {
+            return unsafe {
+                self.handle_overflow_one_with_policy(
+                    head,
+                    tail,
+                    value,
+                    sync_batch_receiver,
+                    overflow_policy,
+                    version,
+                )
+            };
+        }
 
-                if unlikely(last_version_id == version.id()) {
-                    // Case 1, we can retry.
-                    continue;
-                }
+        unsafe { self.push_unchecked(value, tail, version) };
+        unsafe { self.producer_track_occupancy(version) };
+        unsafe { self.producer_maybe_free_retired_versions(version.capacity()) };
 
-                let was_updated = self.update_version(version);
-                if unlikely(!was_updated) {
-                    // We can't reliably return the length in this situation.
-                    // But it is not a problem.
-                    // This method can be used for two purposes:
-                    // 1. In tests to check if all values were pushed;
-                    // 2. To check if the queue is empty -> the reading is possible.
-                    //
-                    // The first case is impossible, because not to fail test accidentally,
-                    // this method can be called
-                    // only when concurrent work with the queue is impossible;
-                    // therefore, in this case we can't be here
-                    // (the update_version method always returns `true`
-                    // without the concurrent work).
-                    //
-                    // For the second case, we can return zero,
-                    // because the reading is impossible.
-                    return 0;
-                }
+        Ok(())
+    }
+
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and has reached the maximum capacity,
+    /// it moves a half of the queue and the value to the [`SyncBatchReceiver`]
+    /// with [`SyncBatchReceiver::try_push_many_and_one`].
+    /// If the [`SyncBatchReceiver`] rejects the batch, the values taken from the head
+    /// are returned to the head of the queue, and the value is returned.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_try_push<SBR: SyncBatchReceiver<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+    ) -> Result<(), T> {
+        let version = unsafe { self.producer_version() };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) == version.capacity()) {
+            if self.can_grow_to(version.capacity() + 1) {
+                unsafe { self.handle_overflow(head, tail, version, slice::from_ref(&value)) };
+
+                // The value is moved into the queue.
+                mem::forget(value);
+
+                return Ok(());
             }
 
-            return len;
+            return unsafe {
+                self.handle_capped_overflow_one(
+                    tail,
+                    head,
+                    version.capacity() / 2,
+                    |first, last, value| {
+                        sync_batch_receiver.try_push_many_and_one(first, last, value)
+                    },
+                    value,
+                    version,
+                )
+            };
         }
+
+        unsafe { self.push_unchecked(value, tail, version) };
+        unsafe { self.producer_track_occupancy(version) };
+        unsafe { self.producer_maybe_free_retired_versions(version.capacity()) };
+
+        Ok(())
     }
 
-    /// Pops many values from the queue to the `dst`.
-    /// Returns the number of values popped.
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
     ///
-    /// It can return zero even if the queue is not empty,
-    /// if the producer is preempted while pushing.
+    /// It returns the value back with the [`AllocError`] if the queue can't grow.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
     #[inline]
-    fn consumer_pop_many(
-        &self,
-        dst: &mut [MaybeUninit<T>],
-        version: &mut CachedVersion<T>,
-    ) -> usize {
-        let mut head = self.head.load(Acquire);
-        let (mut last_version_id, mut tail) = self.sync_load_version_and_tail(Acquire);
+    pub unsafe fn producer_fallible_push(&self, value: T) -> Result<(), (T, AllocError)> {
+        let version = unsafe { self.producer_version() };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) == version.capacity()) {
+            let reserved = self
+                .growth_policy
+                .try_grown_capacity(version.capacity(), version.capacity() + 1)
+                .ok_or(AllocError::CapacityOverflow)
+                .and_then(|new_capacity| unsafe {
+                    self.producer_try_grow_version_to(new_capacity, version)
+                });
+
+            if let Err(err) = reserved {
+                return Err((value, err));
+            }
+
+            unsafe { *self.low_occupancy_streak.get() = 0 };
+        }
+
+        // Growing keeps the indexes of the values, so the loaded tail is still valid.
+        unsafe { self.push_unchecked(value, tail, version) };
+        unsafe { self.producer_track_occupancy(version) };
+        unsafe { self.producer_maybe_free_retired_versions(version.capacity()) };
+
+        Ok(())
+    }
+
+    /// Pushes many values to the queue.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer and the space is enough.
+    #[inline]
+    pub unsafe fn producer_push_many_unchecked(&self, first: &[T], last: &[T]) {
+        let version = unsafe { self.producer_version() };
+        if cfg!(debug_assertions) {
+            let head = self.head.load(Acquire);
+            let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+            debug_assert!(Self::len(head, tail) + first.len() + last.len() <= version.capacity());
+        }
+
+        // It is SPMC, and it is expected that the capacity is enough.
+
+        let mut tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        tail = Self::copy_slice(
+            unsafe { version.thin_mut_ptr().cast() },
+            tail,
+            first,
+            version,
+        );
+        tail = Self::copy_slice(
+            unsafe { version.thin_mut_ptr().cast() },
+            tail,
+            last,
+            version,
+        );
+
+        unsafe { self.tail_and_version.store(version.id(), tail, Release) };
+    }
+
+    /// Grows the queue and pushes many values,
+    /// or performs the action of the [`OverflowPolicy`] if the capped queue can't grow enough.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer, and the `slice` should not fit into the queue.
+    #[inline(never)]
+    #[cold]
+    unsafe fn handle_overflow_many_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+        &self,
+        head: UnboundedIndex,
+        tail: UnboundedIndex,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+        version: &mut CachedVersion<T>,
+    ) -> Result<(), ()> {
+        if self.can_grow_to(Self::len(head, tail) + slice.len()) {
+            unsafe { self.handle_overflow(head, tail, version, slice) };
+
+            return Ok(());
+        }
+
+        let (head, tail) = unsafe { self.producer_grow_to_max_capacity(version) };
+
+        if Self::len(head, tail) + slice.len() <= version.capacity() {
+            // Consumers have popped enough values while the queue was growing.
+            let new_tail = Self::copy_slice(
+                unsafe { version.thin_mut_ptr().cast() },
+                tail,
+                slice,
+                version,
+            );
+
+            unsafe { self.tail_and_version.store(version.id(), new_tail, Release) };
+
+            return Ok(());
+        }
+
+        match overflow_policy.action(version.capacity()) {
+            OverflowAction::MoveToReceiver(n) => {
+                let _ = unsafe {
+                    self.handle_capped_overflow_many(
+                        tail,
+                        head,
+                        n,
+                        |first, last, slice| {
+                            sync_batch_receiver.push_many_and_slice(first, last, slice);
+
+                            Ok(())
+                        },
+                        slice,
+                        version,
+                    )
+                };
+            }
+            OverflowAction::Reject => return Err(()),
+            OverflowAction::DropNewest => {
+                for value in slice {
+                    overflow_policy.on_drop(unsafe { ptr::read(value) });
+                }
+            }
+            OverflowAction::DropOldest => unsafe {
+                self.drop_oldest_and_push_many(tail, head, slice, overflow_policy, version);
+            },
+            OverflowAction::Spill => unsafe {
+                overflow_policy.spill_many(slice, sync_batch_receiver);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and can't grow enough,
+    /// it performs the action of the [`OverflowPolicy`] like a bounded queue.
+    /// Read more in [`Producer::push_many_with_policy`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    unsafe fn producer_push_many_with_policy_to_version<
+        SBR: SyncBatchReceiver<T>,
+        OP: OverflowPolicy<T>,
+    >(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+        version: &mut CachedVersion<T>,
+    ) -> Result<(), ()> {
+        let head = self.head.load(Acquire);
+        let mut tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) + slice.len() > version.capacity()) {
+            return unsafe {
+                self.handle_overflow_many_with_policy(
+                    head,
+                    tail,
+                    slice,
+                    sync_batch_receiver,
+                    overflow_policy,
+                    version,
+                )
+            };
+        }
+
+        tail = Self::copy_slice(
+            unsafe { version.thin_mut_ptr().cast() },
+            tail,
+            slice,
+            version,
+        );
+
+        unsafe { self.tail_and_version.store(version.id(), tail, Release) };
+
+        unsafe { self.producer_track_occupancy(version) };
+        unsafe { self.producer_maybe_free_retired_versions(version.capacity()) };
+
+        Ok(())
+    }
+
+    /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and can't grow enough,
+    /// it moves a half of the queue and the values to the [`SyncBatchReceiver`]
+    /// with [`SyncBatchReceiver::try_push_many_and_slice`].
+    /// If the [`SyncBatchReceiver`] rejects the batch, the values taken from the head
+    /// are returned to the head of the queue, and the error is returned.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_try_push_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+    ) -> Result<(), ()> {
+        let version = unsafe { self.producer_version() };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        if likely(Self::len(head, tail) + slice.len() <= version.capacity())
+            || self.can_grow_to(Self::len(head, tail) + slice.len())
+        {
+            return unsafe {
+                self.producer_push_many_with_policy_to_version(
+                    slice,
+                    sync_batch_receiver,
+                    &MoveHalf,
+                    version,
+                )
+            };
+        }
+
+        let (head, tail) = unsafe { self.producer_grow_to_max_capacity(version) };
+
+        if Self::len(head, tail) + slice.len() <= version.capacity() {
+            // Consumers have popped enough values while the queue was growing.
+            return unsafe {
+                self.producer_push_many_with_policy_to_version(
+                    slice,
+                    sync_batch_receiver,
+                    &MoveHalf,
+                    version,
+                )
+            };
+        }
+
+        unsafe {
+            self.handle_capped_overflow_many(
+                tail,
+                head,
+                version.capacity() / 2,
+                |first, last, slice| {
+                    sync_batch_receiver.try_push_many_and_slice(first, last, slice)
+                },
+                slice,
+                version,
+            )
+        }
+    }
+
+    /// Moves all values of the queue except for the `keep` newest ones
+    /// to the [`SyncBatchReceiver`] in one batch.
+    /// Returns the number of moved values.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_offload_into<SBR: SyncBatchReceiver<T>>(
+        &self,
+        sync_batch_receiver: &SBR,
+        keep: usize,
+    ) -> usize {
+        // The producer always has the latest version.
+        let version = unsafe { self.producer_version() };
+
+        let mut head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        loop {
+            let n = Self::len(head, tail).saturating_sub(keep);
+
+            if n == 0 {
+                return 0;
+            }
+
+            let head_idx = (head & version.mask()) as usize;
+            let right = n.min(version.capacity() - head_idx);
+            let (first, last): (&[T], &[T]) = unsafe {
+                (
+                    slice::from_raw_parts(version.thin_ptr().add(head_idx).cast(), right),
+                    slice::from_raw_parts(version.thin_ptr().cast(), n - right),
+                )
+            };
+
+            // We haven't read the values yet, so we can use `compare_exchange_weak`.
+            match self.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => {
+                    // We are the only producer,
+                    // so we can don't worry
+                    // about someone overwriting the values before we read them.
+                    sync_batch_receiver.push_many_and_slice(first, last, &[]);
+
+                    return n;
+                }
+                Err(new_head) => {
+                    head = new_head;
+                }
+            }
+        }
+    }
+
+    /// Reserves up to `max` free slots after the tail and returns them.
+    /// Read more in [`Producer::reserve_slots`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer,
+    /// and the returned slices should not be used after any other producer method is called.
+    #[allow(
+        clippy::mut_from_ref,
+        reason = "The producer is the only writer of the free slots"
+    )]
+    #[inline]
+    pub unsafe fn producer_reserve_slots(
+        &self,
+        max: usize,
+    ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let version = unsafe { self.producer_version() };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+        let n = max.min(version.capacity() - Self::len(head, tail));
+        let tail_idx = (tail & version.mask()) as usize;
+        let right = n.min(version.capacity() - tail_idx);
+
+        unsafe { *self.reserved_slots.get() = n };
+
+        unsafe {
+            (
+                slice::from_raw_parts_mut(version.thin_mut_ptr().add(tail_idx), right),
+                slice::from_raw_parts_mut(version.thin_mut_ptr(), n - right),
+            )
+        }
+    }
+
+    /// Makes the first `n` reserved slots visible for consumers.
+    /// It commits no more than the number of the reserved slots.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer, and the first `n` slots
+    /// returned by [`producer_reserve_slots`](Self::producer_reserve_slots)
+    /// should be initialized.
+    #[inline]
+    pub unsafe fn producer_commit_reserved_slots(&self, n: usize) {
+        let n = n.min(unsafe { mem::take(&mut *self.reserved_slots.get()) });
+        let version = unsafe { self.producer_version() };
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        unsafe {
+            self.tail_and_version.store(
+                version.id(),
+                tail.wrapping_add(n as UnboundedIndex),
+                Release,
+            );
+        }
+    }
+}
+
+// Public producer methods that don't have internal equivalents
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+    SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and has reached the maximum capacity,
+    /// it moves a half of the queue and the value to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push<SBR: SyncBatchReceiver<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+    ) {
+        let _ = unsafe { self.producer_push_with_policy(value, sync_batch_receiver, &MoveHalf) };
+    }
+
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and has reached the maximum capacity,
+    /// it returns the value back.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_maybe_push(&self, value: T) -> Result<(), T> {
+        unsafe { self.producer_push_with_policy(value, &PanickingReceiver, &Reject) }
+    }
+
+    /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and can't grow enough,
+    /// it performs the action of the [`OverflowPolicy`] like a bounded queue.
+    /// Read more in [`Producer::push_many_with_policy`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push_many_with_policy<
+        SBR: SyncBatchReceiver<T>,
+        OP: OverflowPolicy<T>,
+    >(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+        overflow_policy: &OP,
+    ) -> Result<(), ()> {
+        unsafe {
+            self.producer_push_many_with_policy_to_version(
+                slice,
+                sync_batch_receiver,
+                overflow_policy,
+                self.producer_version(),
+            )
+        }
+    }
+
+    /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and can't grow enough,
+    /// it moves a half of the queue and the values to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+    ) {
+        let _ =
+            unsafe { self.producer_push_many_with_policy(slice, sync_batch_receiver, &MoveHalf) };
+    }
+
+    /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue is capped and can't grow enough, it returns an error.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
+        unsafe { self.producer_push_many_with_policy(slice, &PanickingReceiver, &Reject) }
+    }
+
+    /// Updates the capacity of the queue to the given value rounded up to a power of two.
+    /// It does nothing if the capacity is already not less.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded capacity is greater than
+    /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy.
+    /// It calls [`handle_alloc_error`](std::alloc::handle_alloc_error)
+    /// if the allocation fails.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    pub unsafe fn producer_reserve(&self, capacity: usize) {
+        if let Err(err) = unsafe { self.producer_try_reserve(capacity) } {
+            err.handle();
+        }
+    }
+
+    /// Updates the capacity of the queue to the given value rounded up to a power of two.
+    /// It does nothing if the capacity is already not less.
+    ///
+    /// It returns [`AllocError::CapacityOverflow`] if the rounded capacity is greater than
+    /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy,
+    /// and [`AllocError::AllocFailed`] if the allocation fails.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    pub unsafe fn producer_try_reserve(&self, capacity: usize) -> Result<(), AllocError> {
+        unsafe { self.producer_try_grow_version_to(capacity, self.producer_version()) }
+    }
+
+    /// Shrinks the capacity of the queue to the given value rounded up to a power of two.
+    ///
+    /// The capacity never becomes less than the length of the queue.
+    /// It does nothing if the capacity is already not greater.
+    ///
+    /// It also frees the buffers that the queue keeps to reuse when it grows again.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    pub unsafe fn producer_shrink_to(&self, capacity: usize) {
+        unsafe {
+            self.producer_shrink_version_to(capacity, self.producer_version());
+            self.producer_free_buffers();
+        }
+    }
+}
+
+// Consumers
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+    SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    /// Returns the capacity of the queue.
+    ///
+    /// # Safety
+    ///
+    /// The the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
+    #[inline]
+    pub unsafe fn consumer_capacity(&self, cursor: &mut ConsumerCursor<T>) -> usize {
+        let version = &mut cursor.version;
+        let last_version_id = self.sync_load_version(Relaxed);
+        if version.id() == last_version_id {
+            return version.capacity();
+        }
+
+        cold_path();
+
+        let _was_updated = self.update_version(version);
+
+        // If was_updated, the capacity is valid.
+        // If !was_updated we should guess that the producer has been preempted,
+        // and for some time the capacity of the current version is valid.
+        version.capacity()
+    }
+
+    /// Returns the length of the queue.
+    ///
+    /// # Safety
+    ///
+    /// The the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
+    #[inline]
+    pub unsafe fn consumer_len(&self, cursor: &mut ConsumerCursor<T>) -> usize {
+        let version = &mut cursor.version;
+        loop {
+            let (last_version_id, tail) = self.sync_load_version_and_tail(Relaxed);
+            let head = self.head.load(Relaxed);
+            let len = Self::len(head, tail);
+
+            if unlikely(len > version.capacity()) {
+                // Three possible reasons:
+                // 1. Inconsistent state (this thread has been preempted
+                //    after we have loaded `tail`,
+                //    and before we have loaded `head`);
+                // 2. The new version was created and has more capacity;
+                // 3. The first reason and the second reason simultaneously.
+
+                if unlikely(last_version_id == version.id()) {
+                    // Case 1, we can retry.
+                    continue;
+                }
+
+                let was_updated = self.update_version(version);
+                if unlikely(!was_updated) {
+                    // We can't reliably return the length in this situation.
+                    // But it is not a problem.
+                    // This method can be used for two purposes:
+                    // 1. In tests to check if all values were pushed;
+                    // 2. To check if the queue is empty -> the reading is possible.
+                    //
+                    // The first case is impossible, because not to fail test accidentally,
+                    // this method can be called
+                    // only when concurrent work with the queue is impossible;
+                    // therefore, in this case we can't be here
+                    // (the update_version method always returns `true`
+                    // without the concurrent work).
+                    //
+                    // For the second case, we can return zero,
+                    // because the reading is impossible.
+                    return 0;
+                }
+            }
+
+            return len;
+        }
+    }
+
+    /// Pops many values from the queue to the `dst`.
+    /// Returns the number of values popped.
+    ///
+    /// It can return zero even if the queue is not empty,
+    /// if the producer is preempted while pushing.
+    ///
+    /// # Safety
+    ///
+    /// The the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
+    #[inline]
+    pub unsafe fn consumer_pop_many(
+        &self,
+        dst: &mut [MaybeUninit<T>],
+        cursor: &mut ConsumerCursor<T>,
+    ) -> usize {
+        let version = &mut cursor.version;
+        let _pinned = self.pinned_consumers.pin();
+        let mut head = self.head.load(Acquire);
+        let (mut last_version_id, mut tail) = self.sync_load_version_and_tail(SeqCst);
+
+        loop {
+            if unlikely(version.id() < last_version_id) {
+                if unlikely(!self.update_version(version)) {
+                    // We can't reliably calculate the length in this situation.
+                    return 0;
+                }
+
+                (last_version_id, tail) = self.sync_load_version_and_tail(Acquire);
+
+                continue;
+            }
+
+            let available = Self::len(head, tail);
+            let n = dst.len().min(available);
+
+            if n == 0 {
+                return 0;
+            }
+
+            if unlikely(n > version.capacity()) {
+                // Inconsistent state (this thread has been preempted
+                // after we have loaded `head`,
+                // and before we have loaded `tail`).
+
+                head = self.head.load(Acquire);
+                (last_version_id, tail) = self.sync_load_version_and_tail(Acquire);
+
+                continue;
+            }
+
+            let dst_ptr = dst.as_mut_ptr();
+            let head_idx = (head & version.mask()) as usize;
+            let right = version.capacity() - head_idx;
+
+            // We optimistically copy the values from the buffer into the dst.
+            // On CAS failure, we forget the copied values and try again.
+            // It is safe because we can concurrently read from the head.
+
+            if n <= right {
+                // No wraparound, copy in one shot
+                unsafe {
+                    ptr::copy_nonoverlapping(version.thin_mut_ptr().add(head_idx), dst_ptr, n);
+                }
+            } else {
+                unsafe {
+                    // Wraparound: copy right half then left half
+                    ptr::copy_nonoverlapping(version.thin_ptr().add(head_idx), dst_ptr, right);
+                    ptr::copy_nonoverlapping(version.thin_ptr(), dst_ptr.add(right), n - right);
+                }
+            }
+
+            // Now claim ownership
+            // CAS is strong because we don't want to recopy the values
+            match self.head.compare_exchange(
+                head,
+                head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => return n,
+                Err(actual_head) => {
+                    // CAS failed, forget read values (they're MaybeUninit, so it's fine)
+                    // But don't try to drop, just retry
+
+                    head = actual_head;
+                    (last_version_id, tail) = self.sync_load_version_and_tail(Acquire);
+                }
+            }
+        }
+    }
+
+    /// Steals many values from the consumer to the `dst`.
+    /// Returns the number of values stolen.
+    ///
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
+    ///
+    /// It can return zero even if the source queue is not empty,
+    /// if the producer is preempted while pushing.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer of the `dst`,
+    /// and the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
+    pub unsafe fn steal_into(&self, dst: &Self, cursor: &mut ConsumerCursor<T>) -> usize {
+        let src_version = &mut cursor.version;
+        let dst_version = unsafe { dst.producer_version() };
+        let _pinned = self.pinned_consumers.pin();
+        let mut src_head = self.head.load(Acquire);
+        let (mut src_last_version_id, mut src_tail) = self.sync_load_version_and_tail(SeqCst);
+        let dst_tail = unsafe { dst.unsync_load_tail() }; // only producer can change tail
+
+        // Consumers of the `dst` can only increase the number of free slots,
+        // so it is safe to load the head only once.
+        let dst_free_slots = dst_version.capacity() - Self::len(dst.head.load(Acquire), dst_tail);
+
+        loop {
+            if unlikely(src_version.id() < src_last_version_id) {
+                if unlikely(!self.update_version(src_version)) {
+                    // We can't reliably calculate the length in this situation.
+                    return 0;
+                }
+
+                (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+
+                continue;
+            }
+
+            let n = Self::len(src_head, src_tail) / 2;
+            if n > src_version.capacity() / 2 {
+                // Inconsistent state (this thread has been preempted
+                // after we have loaded `src_head`,
+                // and before we have loaded `src_tail`);
+
+                src_head = self.head.load(Acquire);
+                (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+
+                continue;
+            }
+
+            if n < self.steal_threshold || n == 0 {
+                // we don't steal less than the threshold (4 by default)
+                // because else we may lose more because of cache locality and NUMA awareness
+                return 0;
+            }
+
+            let n = n.min(dst_free_slots);
+            if n == 0 {
+                return 0;
+            }
+
+            let src_head_idx = (src_head & src_version.mask()) as usize;
+
+            let (src_right, src_left): (&[T], &[T]) = unsafe {
+                let right_occupied = src_version.capacity() - src_head_idx;
+                if n <= right_occupied {
+                    (
+                        slice::from_raw_parts(src_version.thin_ptr().add(src_head_idx).cast(), n),
+                        &[],
+                    )
+                } else {
+                    (
+                        slice::from_raw_parts(
+                            src_version.thin_ptr().add(src_head_idx).cast(),
+                            right_occupied,
+                        ),
+                        slice::from_raw_parts(src_version.thin_ptr().cast(), n - right_occupied),
+                    )
+                }
+            };
+
+            // We optimistically copy the values from the buffer into the dst.
+            // On CAS failure, we forget the copied values and try again.
+            // It is safe because we can concurrently read from the head.
+            Self::copy_slice(
+                unsafe { dst_version.thin_mut_ptr() }.cast::<T>(),
+                dst_tail,
+                src_right,
+                dst_version,
+            );
+            Self::copy_slice(
+                unsafe { dst_version.thin_mut_ptr() }.cast::<T>(),
+                dst_tail.wrapping_add(src_right.len() as UnboundedIndex),
+                src_left,
+                dst_version,
+            );
+
+            // CAS is strong because we don't want to recopy the values
+            let res = self.head.compare_exchange(
+                src_head,
+                src_head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            );
+
+            match res {
+                Ok(_) => {
+                    // Success, we can move dst tail and return
+                    unsafe {
+                        dst.tail_and_version.store(
+                            dst_version.id(),
+                            dst_tail.wrapping_add(n as UnboundedIndex),
+                            Release,
+                        );
+                    }
+
+                    return n;
+                }
+                Err(current_head) => {
+                    // Another thread has read the same values,
+                    // forget them and full retry
+
+                    src_head = current_head;
+                    (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+                }
+            }
+        }
+    }
+
+    /// Steals many values from the consumer to the `dst`,
+    /// that can be a [`Producer`] of any single-producer, multi-consumer queue.
+    /// Returns the number of values stolen.
+    ///
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
+    ///
+    /// It can return zero even if the source queue is not empty,
+    /// if the producer is preempted while pushing.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only user of the `dst` as [`Producer::reserve_slots`] requires,
+    /// and the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
+    pub unsafe fn steal_into_any<P: Producer<T>>(
+        &self,
+        dst: &P,
+        cursor: &mut ConsumerCursor<T>,
+    ) -> usize {
+        let src_version = &mut cursor.version;
+        let _pinned = self.pinned_consumers.pin();
+        let mut src_head = self.head.load(Acquire);
+        let (mut src_last_version_id, mut src_tail) = self.sync_load_version_and_tail(SeqCst);
+
+        loop {
+            if unlikely(src_version.id() < src_last_version_id) {
+                if unlikely(!self.update_version(src_version)) {
+                    // We can't reliably calculate the length in this situation.
+                    return 0;
+                }
+
+                (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+
+                continue;
+            }
+
+            let n = Self::len(src_head, src_tail) / 2;
+            if n > src_version.capacity() / 2 {
+                // Inconsistent state (this thread has been preempted
+                // after we have loaded `src_head`,
+                // and before we have loaded `src_tail`);
+
+                src_head = self.head.load(Acquire);
+                (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+
+                continue;
+            }
+
+            if n < self.steal_threshold || n == 0 {
+                // we don't steal less than the threshold (4 by default)
+                // because else we may lose more because of cache locality and NUMA awareness
+                return 0;
+            }
+
+            // We are the only user of `dst`, read the safety of `Producer::reserve_slots`.
+            let (dst_first, dst_last, n) = unsafe { reserve_slots_up_to(dst, n) };
+            if n == 0 {
+                return 0;
+            }
+
+            let src_head_idx = (src_head & src_version.mask()) as usize;
+
+            let (src_right, src_left): (&[T], &[T]) = unsafe {
+                let right_occupied = src_version.capacity() - src_head_idx;
+                if n <= right_occupied {
+                    (
+                        slice::from_raw_parts(src_version.thin_ptr().add(src_head_idx).cast(), n),
+                        &[],
+                    )
+                } else {
+                    (
+                        slice::from_raw_parts(
+                            src_version.thin_ptr().add(src_head_idx).cast(),
+                            right_occupied,
+                        ),
+                        slice::from_raw_parts(src_version.thin_ptr().cast(), n - right_occupied),
+                    )
+                }
+            };
+
+            // We optimistically copy the values from the buffer into the dst.
+            // On CAS failure, we forget the copied values and try again.
+            // It is safe because we can concurrently read from the head.
+            unsafe {
+                copy_to_reserved_slots(src_right, 0, dst_first, dst_last);
+                copy_to_reserved_slots(src_left, src_right.len(), dst_first, dst_last);
+            }
+
+            // CAS is strong because we don't want to recopy the values
+            let res = self.head.compare_exchange(
+                src_head,
+                src_head.wrapping_add(n as UnboundedIndex),
+                Release,
+                Acquire,
+            );
+
+            match res {
+                Ok(_) => {
+                    // Success, we can commit the reserved slots and return
+                    unsafe { dst.commit_reserved_slots(n) };
+
+                    return n;
+                }
+                Err(current_head) => {
+                    // Another thread has read the same values,
+                    // forget them and full retry
+
+                    src_head = current_head;
+                    (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+                }
+            }
+        }
+    }
+}
+
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Default
+    for SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
+unsafe impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Send
+    for SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+}
+unsafe impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Sync
+    for SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+}
+
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Drop
+    for SPMCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    fn drop(&mut self) {
+        // While dropping there is no concurrency
+
+        if needs_drop::<T>() {
+            let version = self.last_version.try_read().unwrap();
+            let mut head = unsafe { self.head.unsync_load() };
+            let tail = unsafe { self.unsync_load_tail() };
+
+            while head != tail {
+                unsafe {
+                    ptr::drop_in_place(
+                        version
+                            .thin_mut_ptr()
+                            .add((head & version.mask()) as usize)
+                            .cast::<T>(),
+                    );
+                }
+
+                head = head.wrapping_add(1);
+            }
+        }
+    }
+}
+
+/// An unbounded producer behind a lock that can be used as the global queue
+/// (the [`SyncBatchReceiver`]).
+///
+/// It grows to accept the whole batch, so it never uses the [`SyncBatchReceiver`] of its own.
+/// It can be created only from a producer that is not capped by
+/// [`SPMCUnboundedProducer::into_locked`] or [`CachePaddedSPMCUnboundedProducer::into_locked`],
+/// because a capped producer can't accept the batch in
+/// [`push_many_and_one`](SyncBatchReceiver::push_many_and_one)
+/// and [`push_many_and_slice`](SyncBatchReceiver::push_many_and_slice) when it is full.
+///
+/// # Example
+///
+/// ```
+/// use parcoll::spmc::{new_unbounded, Consumer, Producer};
+///
+/// let (global_producer, global_consumer) = new_unbounded();
+/// let global_queue = global_producer.into_locked().ok().unwrap();
+/// let (producer, _consumer) = parcoll::spmc::new_bounded::<_, 4>();
+///
+/// for i in 0..5 {
+///     producer.push(i, &global_queue);
+/// }
+///
+/// assert_eq!(global_consumer.len(), 3);
+/// assert_eq!(global_queue.lock().len(), 3);
+/// ```
+pub struct LockedProducer<P> {
+    producer: std::sync::Mutex<P>,
+}
+
+impl<P> LockedProducer<P> {
+    /// Locks the producer.
+    pub fn lock(&self) -> std::sync::MutexGuard<'_, P> {
+        lock_ignoring_poison(&self.producer)
+    }
+
+    /// Returns the producer.
+    pub fn into_inner(self) -> P {
+        self.producer
+            .into_inner()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Generates SPMC producer and consumer.
+macro_rules! generate_spmc_producer_and_consumer {
+    ($producer_name:ident, $consumer_name:ident, $atomic_index_wrapper:ty, $long_atomic_wrapper:ty) => {
+        /// The producer of the [`SPMCUnboundedQueue`].
+        pub struct $producer_name<T> {
+            inner: LightArc<SPMCUnboundedQueue<T, $atomic_index_wrapper, $long_atomic_wrapper>>,
+            _non_sync: PhantomData<*const ()>,
+        }
+
+        impl<T> $producer_name<T> {
+            /// Creates a new queue with the given growth policy
+            /// and returns its producer and consumer.
+            pub(crate) fn new_queue(
+                growth_policy: GrowthPolicy,
+                is_capped: bool,
+                steal_threshold: usize,
+            ) -> (Self, $consumer_name<T>) {
+                let queue =
+                    SPMCUnboundedQueue::with_options(growth_policy, is_capped, steal_threshold);
+                let cursor = queue.consumer_cursor();
+                let queue = LightArc::new(queue);
+
+                (
+                    Self {
+                        inner: queue.clone(),
+                        _non_sync: PhantomData,
+                    },
+                    $consumer_name {
+                        cursor: UnsafeCell::new(cursor),
+                        inner: queue,
+                        _non_sync: PhantomData,
+                    },
+                )
+            }
+
+            /// Updates the capacity of the queue to the given value rounded up to a power of two.
+            /// It does nothing if the capacity is already not less.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the rounded capacity is greater than
+            /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy.
+            /// It calls [`handle_alloc_error`](std::alloc::handle_alloc_error)
+            /// if the allocation fails.
+            pub fn reserve(&self, capacity: usize) {
+                unsafe { self.inner.producer_reserve(capacity) };
+            }
+
+            /// Updates the capacity of the queue to the given value rounded up to a power of two.
+            /// It does nothing if the capacity is already not less.
+            ///
+            /// It returns [`AllocError::CapacityOverflow`] if the rounded capacity is greater than
+            /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy,
+            /// and [`AllocError::AllocFailed`] if the allocation fails.
+            pub fn try_reserve(&self, capacity: usize) -> Result<(), AllocError> {
+                unsafe { self.inner.producer_try_reserve(capacity) }
+            }
+
+            /// Pushes the value to the queue.
+            /// If the queue is full, it grows according to the [`GrowthPolicy`].
+            ///
+            /// Unlike [`Producer::push`], it never panics or aborts if the queue can't grow:
+            /// it returns the value back with the [`AllocError`].
+            pub fn fallible_push(&self, value: T) -> Result<(), (T, AllocError)> {
+                unsafe { self.inner.producer_fallible_push(value) }
+            }
+
+            /// Shrinks the capacity of the queue to the given value rounded up to a power of two.
+            ///
+            /// The capacity never becomes less than the length of the queue.
+            /// It does nothing if the capacity is already not greater.
+            ///
+            /// It also frees the buffers that the queue keeps to reuse when it grows again.
+            pub fn shrink_to(&self, capacity: usize) {
+                unsafe { self.inner.producer_shrink_to(capacity) };
+            }
+
+            /// Shrinks the capacity of the queue as much as possible.
+            pub fn shrink_to_fit(&self) {
+                self.shrink_to(0);
+            }
+
+            /// Returns the [`GrowthPolicy`] of the queue.
+            pub fn growth_policy(&self) -> GrowthPolicy {
+                self.inner.growth_policy()
+            }
+
+            /// Puts the producer behind a lock to use it as the global queue.
+            /// Read more in [`LockedProducer`].
+            ///
+            /// It returns the producer back if it is capped
+            /// (created by [`new_capped_unbounded`] or with
+            /// the [`maximum capacity`](GrowthPolicy::with_max_capacity)),
+            /// because a capped producer can't accept every batch.
+            pub fn into_locked(self) -> Result<LockedProducer<Self>, Self> {
+                if self.inner.is_capped {
+                    return Err(self);
+                }
+
+                Ok(LockedProducer {
+                    producer: std::sync::Mutex::new(self),
+                })
+            }
+        }
+
+        impl<T: Send> Producer<T> for $producer_name<T> {
+            #[inline]
+            fn capacity(&self) -> usize {
+                unsafe { self.inner.producer_capacity() }
+            }
+
+            #[inline]
+            fn len(&self) -> usize {
+                unsafe { self.inner.producer_len() }
+            }
+
+            #[inline]
+            fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR) {
+                unsafe { self.inner.producer_push(value, sync_batch_receiver) };
+            }
+
+            #[inline]
+            fn try_push<SBR: SyncBatchReceiver<T>>(
+                &self,
+                value: T,
+                sync_batch_receiver: &SBR,
+            ) -> Result<(), T> {
+                unsafe { self.inner.producer_try_push(value, sync_batch_receiver) }
+            }
+
+            #[inline]
+            fn maybe_push(&self, value: T) -> Result<(), T> {
+                unsafe { self.inner.producer_maybe_push(value) }
+            }
+
+            #[inline]
+            fn pop(&self) -> Option<T> {
+                unsafe { self.inner.producer_pop() }
+            }
+
+            #[inline]
+            fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+                unsafe { self.inner.producer_pop_many(dst) }
+            }
+
+            #[inline]
+            unsafe fn push_many_unchecked(&self, first: &[T], last: &[T]) {
+                unsafe { self.inner.producer_push_many_unchecked(first, last) }
+            }
+
+            #[inline]
+            unsafe fn maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
+                unsafe { self.inner.producer_maybe_push_many(slice) }
+            }
+
+            #[inline]
+            unsafe fn push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+            ) {
+                unsafe { self.inner.producer_push_many(slice, sync_batch_receiver) };
+            }
+
+            #[inline]
+            unsafe fn try_push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+            ) -> Result<(), ()> {
+                unsafe {
+                    self.inner
+                        .producer_try_push_many(slice, sync_batch_receiver)
+                }
+            }
+
+            #[inline]
+            fn push_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+                &self,
+                value: T,
+                sync_batch_receiver: &SBR,
+                overflow_policy: &OP,
+            ) -> Result<(), T> {
+                unsafe {
+                    self.inner.producer_push_with_policy(
+                        value,
+                        sync_batch_receiver,
+                        overflow_policy,
+                    )
+                }
+            }
+
+            #[inline]
+            unsafe fn push_many_with_policy<SBR: SyncBatchReceiver<T>, OP: OverflowPolicy<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+                overflow_policy: &OP,
+            ) -> Result<(), ()> {
+                unsafe {
+                    self.inner.producer_push_many_with_policy(
+                        slice,
+                        sync_batch_receiver,
+                        overflow_policy,
+                    )
+                }
+            }
+
+            #[inline]
+            fn offload_into<SBR: SyncBatchReceiver<T>>(
+                &self,
+                sync_batch_receiver: &SBR,
+                keep: usize,
+            ) -> usize {
+                unsafe { self.inner.producer_offload_into(sync_batch_receiver, keep) }
+            }
+
+            #[inline]
+            unsafe fn reserve_slots(
+                &self,
+                max: usize,
+            ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+                unsafe { self.inner.producer_reserve_slots(max) }
+            }
+
+            #[inline]
+            unsafe fn commit_reserved_slots(&self, n: usize) {
+                unsafe { self.inner.producer_commit_reserved_slots(n) };
+            }
+        }
+
+        impl<T: Send> ConsumerSpawner<T> for $producer_name<T> {
+            type Consumer = $consumer_name<T>;
+
+            fn spawn_consumer(&self) -> Self::Consumer {
+                $consumer_name {
+                    cursor: UnsafeCell::new(self.inner.consumer_cursor()),
+                    inner: self.inner.clone(),
+                    _non_sync: PhantomData,
+                }
+            }
+        }
+
+        #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
+        unsafe impl<T: Send> Send for $producer_name<T> {}
+
+        unsafe impl<T: Send> SyncBatchReceiver<T> for LockedProducer<$producer_name<T>> {
+            fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+                let producer = self.lock();
+
+                // The producer is not capped, so it never uses the receiver.
+                unsafe {
+                    producer.push_many(first, &PanickingReceiver);
+                    producer.push_many(last, &PanickingReceiver);
+                }
+
+                producer.push(value, &PanickingReceiver);
+            }
+
+            fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+                let producer = self.lock();
+
+                // The producer is not capped, so it never uses the receiver.
+                unsafe {
+                    producer.push_many(first, &PanickingReceiver);
+                    producer.push_many(last, &PanickingReceiver);
+                    producer.push_many(slice, &PanickingReceiver);
+                }
+            }
+        }
+
+        /// The consumer of the [`SPMCUnboundedQueue`].
+        pub struct $consumer_name<T> {
+            inner: LightArc<SPMCUnboundedQueue<T, $atomic_index_wrapper, $long_atomic_wrapper>>,
+            cursor: UnsafeCell<ConsumerCursor<T>>,
+            _non_sync: PhantomData<*const ()>,
+        }
+
+        impl<T> $consumer_name<T> {
+            /// Returns a mutable reference to the cursor.
+            #[allow(clippy::mut_from_ref, reason = "It improves readability")]
+            #[inline]
+            fn cursor(&self) -> &mut ConsumerCursor<T> {
+                unsafe { &mut *self.cursor.get() }
+            }
+        }
+
+        impl<T: Send> Consumer<T> for $consumer_name<T> {
+            type AssociatedProducer = $producer_name<T>;
+
+            #[inline]
+            fn capacity(&self) -> usize {
+                unsafe { self.inner.consumer_capacity(self.cursor()) }
+            }
+
+            #[inline]
+            fn len(&self) -> usize {
+                unsafe { self.inner.consumer_len(self.cursor()) }
+            }
+
+            #[inline]
+            fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
+                unsafe { self.inner.consumer_pop_many(dst, self.cursor()) }
+            }
+
+            #[inline]
+            fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize {
+                // We are the only user of `dst`, read the safety of `Producer::reserve_slots`.
+                unsafe { self.inner.steal_into(&dst.inner, self.cursor()) }
+            }
+
+            #[inline]
+            fn steal_into_any<P: Producer<T>>(&self, dst: &P) -> usize {
+                unsafe { self.inner.steal_into_any(dst, self.cursor()) }
+            }
+        }
+
+        impl<T: Send> SyncBatchSource<T> for $consumer_name<T> {
+            #[inline]
+            fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize {
+                move_batch_from_consumer(self, producer, limit)
+            }
+        }
+
+        impl<T> Clone for $consumer_name<T> {
+            fn clone(&self) -> Self {
+                Self {
+                    cursor: UnsafeCell::new(self.cursor().clone()),
+                    inner: self.inner.clone(),
+                    _non_sync: PhantomData,
+                }
+            }
+        }
+
+        #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
+        unsafe impl<T: Send> Send for $consumer_name<T> {}
+    };
+
+    ($producer_name:ident, $consumer_name:ident) => {
+        generate_spmc_producer_and_consumer!(
+            $producer_name,
+            $consumer_name,
+            NotCachePaddedAtomicUnboundedIndex,
+            NotCachePaddedAtomicU64
+        );
+    };
+}
+
+generate_spmc_producer_and_consumer!(SPMCUnboundedProducer, SPMCUnboundedConsumer);
+
+/// Creates a new single-producer, multi-consumer unbounded queue.
+/// Returns [`producer`](SPMCUnboundedProducer) and [`consumer`](SPMCUnboundedConsumer).
+///
+/// The producer __should__ be only one while consumers can be cloned.
+/// If you want to use more than one producer, don't use this queue.
+///
+/// If you want to use only one consumer, look at the single-producer, single-consumer queue.
+///
+/// # Unbounded queue vs. [`bounded queue`](crate::spmc::new_bounded).
+///
+/// - [`maybe_push`](Producer::maybe_push), [`maybe_push_many`](Producer::maybe_push_many)
+///   can return an error only for `bounded` queue
+///   (and for a [`capped`](new_capped_unbounded) queue at the maximum capacity).
+/// - [`push`](Producer::push), [`push_many`](Producer::push_many)
+///   writes to the [`SyncBatchReceiver`] only for `bounded` queue
+///   (and for a [`capped`](new_capped_unbounded) queue at the maximum capacity).
+/// - [`Consumer::steal_into`] and [`Consumer::pop_many`] can pop zero values even if the source
+///   queue is not empty for `unbounded` queue.
+/// - [`Consumer::capacity`] and [`Consumer::len`] can return old values for `unbounded` queue.
+/// - All methods of `bounded` queue work much faster than all methods of `unbounded` queue.
+///
+/// # Capacity
+///
+/// By default, the head and the tail are `u32`, so the queue holds up to `2^31` values.
+/// Enable the `wide_unbounded_index` feature to use `u64` indexes and lift this limit.
+///
+/// # Cache padding
+///
+/// Cache padding can improve the performance of the queue many times, but it also requires
+/// much more memory (likely 128 or 256 more bytes for the queue).
+/// If you can sacrifice some memory for the performance, use [`new_cache_padded_unbounded`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::spmc::{Producer, Consumer, new_unbounded};
+///
+/// let (mut producer, mut consumer) = new_unbounded();
+/// let consumer2 = consumer.clone(); // You can clone the consumer
+///
+/// producer.maybe_push(1).unwrap();
+/// producer.maybe_push(2).unwrap();
+///
+/// let mut slice = [std::mem::MaybeUninit::uninit(); 3];
+/// let popped = consumer.pop_many(&mut slice);
+///
+/// assert_eq!(popped, 2);
+/// assert_eq!(unsafe { slice[0].assume_init() }, 1);
+/// assert_eq!(unsafe { slice[1].assume_init() }, 2);
+/// ```
+pub fn new_unbounded<T>() -> (SPMCUnboundedProducer<T>, SPMCUnboundedConsumer<T>) {
+    new_unbounded_with_growth_policy(GrowthPolicy::new())
+}
+
+/// Creates a new single-producer, multi-consumer unbounded queue with the [`GrowthPolicy`].
+/// Returns [`producer`](SPMCUnboundedProducer) and [`consumer`](SPMCUnboundedConsumer).
+///
+/// Read more about the queue in [`new_unbounded`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::GrowthPolicy;
+/// use parcoll::spmc::{Producer, new_unbounded_with_growth_policy};
+///
+/// let (producer, _consumer) = new_unbounded_with_growth_policy(
+///     GrowthPolicy::new().with_initial_capacity(64),
+/// );
+///
+/// assert_eq!(producer.capacity(), 64);
+///
+/// for i in 0..100 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 128);
+///
+/// while producer.len() > 10 {
+///     producer.pop();
+/// }
+///
+/// producer.shrink_to_fit();
+///
+/// assert_eq!(producer.capacity(), 16);
+/// ```
+pub fn new_unbounded_with_growth_policy<T>(
+    growth_policy: GrowthPolicy,
+) -> (SPMCUnboundedProducer<T>, SPMCUnboundedConsumer<T>) {
+    SPMCUnboundedProducer::new_queue(growth_policy, false, DEFAULT_STEAL_THRESHOLD)
+}
+
+/// Creates a new single-producer, multi-consumer unbounded queue with the initial capacity.
+/// Returns [`producer`](SPMCUnboundedProducer)
+/// and [`consumer`](SPMCUnboundedConsumer).
+///
+/// The capacity is rounded up to a power of two.
+/// It is a shortcut for [`new_unbounded_with_growth_policy`]
+/// with [`GrowthPolicy::with_initial_capacity`].
+///
+/// # Panics
+///
+/// Panics if the rounded capacity is greater than the maximum capacity of [`GrowthPolicy::new`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::spmc::{Producer, new_unbounded_with_capacity};
+///
+/// let (producer, _consumer) = new_unbounded_with_capacity(100);
+///
+/// assert_eq!(producer.capacity(), 128);
+///
+/// for i in 0..128 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 128);
+/// ```
+pub fn new_unbounded_with_capacity<T>(
+    capacity: usize,
+) -> (SPMCUnboundedProducer<T>, SPMCUnboundedConsumer<T>) {
+    new_unbounded_with_growth_policy(GrowthPolicy::new().with_initial_capacity(capacity))
+}
+
+/// Creates a new single-producer, multi-consumer capped queue with the [`GrowthPolicy`].
+/// Returns [`producer`](SPMCUnboundedProducer) and [`consumer`](SPMCUnboundedConsumer).
+///
+/// The queue grows like [`new_unbounded_with_growth_policy`] up to
+/// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy.
+/// After that, it behaves like a [`bounded queue`](crate::spmc::new_bounded):
+/// [`push`](Producer::push) moves a half of the queue to the [`SyncBatchReceiver`],
+/// [`maybe_push`](Producer::maybe_push) returns an error,
+/// and [`push_with_policy`](Producer::push_with_policy) follows the [`OverflowPolicy`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::GrowthPolicy;
+/// use parcoll::MutexVecQueue;
+/// use parcoll::spmc::{Producer, new_capped_unbounded};
+///
+/// let global_queue = MutexVecQueue::new();
+/// let (producer, _consumer) = new_capped_unbounded(
+///     GrowthPolicy::new()
+///         .with_initial_capacity(4)
+///         .with_max_capacity(8),
+/// );
+///
+/// for i in 0..8 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 8);
+/// assert_eq!(producer.maybe_push(8), Err(8));
+///
+/// producer.push(8, &global_queue);
+///
+/// assert_eq!(producer.capacity(), 8);
+/// assert_eq!(producer.len(), 4);
+/// assert_eq!(global_queue.len(), 5);
+/// ```
+pub fn new_capped_unbounded<T>(
+    growth_policy: GrowthPolicy,
+) -> (SPMCUnboundedProducer<T>, SPMCUnboundedConsumer<T>) {
+    SPMCUnboundedProducer::new_queue(growth_policy, true, DEFAULT_STEAL_THRESHOLD)
+}
+
+generate_spmc_producer_and_consumer!(
+    CachePaddedSPMCUnboundedProducer,
+    CachePaddedSPMCUnboundedConsumer,
+    CachePaddedAtomicUnboundedIndex,
+    CachePaddedAtomicU64
+);
+
+/// Creates a new single-producer, multi-consumer unbounded queue.
+/// Returns [`producer`](SPMCUnboundedProducer) and [`consumer`](SPMCUnboundedConsumer).
+///
+/// The producer __should__ be only one while consumers can be cloned.
+/// If you want to use more than one producer, don't use this queue.
+///
+/// If you want to use only one consumer, look at the single-producer, single-consumer queue.
+///
+/// # Unbounded queue vs. [`bounded queue`](crate::spmc::new_bounded).
+///
+/// - [`maybe_push`](Producer::maybe_push), [`maybe_push_many`](Producer::maybe_push_many)
+///   can return an error only for `bounded` queue
+///   (and for a [`capped`](new_capped_unbounded) queue at the maximum capacity).
+/// - [`push`](Producer::push), [`push_many`](Producer::push_many)
+///   writes to the [`SyncBatchReceiver`] only for `bounded` queue
+///   (and for a [`capped`](new_capped_unbounded) queue at the maximum capacity).
+/// - [`Consumer::steal_into`] and [`Consumer::pop_many`] can pop zero values even if the source
+///   queue is not empty for `unbounded` queue.
+/// - [`Consumer::capacity`] and [`Consumer::len`] can return old values for `unbounded` queue.
+/// - All methods of `bounded` queue work much faster than all methods of `unbounded` queue.
+///
+/// # Capacity
+///
+/// By default, the head and the tail are `u32`, so the queue holds up to `2^31` values.
+/// Enable the `wide_unbounded_index` feature to use `u64` indexes and lift this limit.
+///
+/// # Cache padding
+///
+/// Cache padding can improve the performance of the queue many times, but it also requires
+/// much more memory (likely 128 or 256 more bytes for the queue).
+/// If you can't sacrifice some memory for the performance, use [`new_unbounded`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::spmc::{Producer, Consumer, new_cache_padded_unbounded};
+///
+/// let (mut producer, mut consumer) = new_cache_padded_unbounded();
+/// let consumer2 = consumer.clone(); // You can clone the consumer
+///
+/// producer.maybe_push(1).unwrap();
+/// producer.maybe_push(2).unwrap();
+///
+/// let mut slice = [std::mem::MaybeUninit::uninit(); 3];
+/// let popped = consumer.pop_many(&mut slice);
+///
+/// assert_eq!(popped, 2);
+/// assert_eq!(unsafe { slice[0].assume_init() }, 1);
+/// assert_eq!(unsafe { slice[1].assume_init() }, 2);
+/// ```
+pub fn new_cache_padded_unbounded<T>() -> (
+    CachePaddedSPMCUnboundedProducer<T>,
+    CachePaddedSPMCUnboundedConsumer<T>,
+) {
+    new_cache_padded_unbounded_with_growth_policy(GrowthPolicy::new())
+}
+
+/// Creates a new single-producer, multi-consumer unbounded queue with the [`GrowthPolicy`].
+/// Returns [`producer`](CachePaddedSPMCUnboundedProducer)
+/// and [`consumer`](CachePaddedSPMCUnboundedConsumer).
+///
+/// Read more about the queue in [`new_cache_padded_unbounded`].
+pub fn new_cache_padded_unbounded_with_growth_policy<T>(
+    growth_policy: GrowthPolicy,
+) -> (
+    CachePaddedSPMCUnboundedProducer<T>,
+    CachePaddedSPMCUnboundedConsumer<T>,
+) {
+    CachePaddedSPMCUnboundedProducer::new_queue(growth_policy, false, DEFAULT_STEAL_THRESHOLD)
+}
+
+/// Creates a new single-producer, multi-consumer unbounded queue with the initial capacity.
+/// Returns [`producer`](CachePaddedSPMCUnboundedProducer)
+/// and [`consumer`](CachePaddedSPMCUnboundedConsumer).
+///
+/// The capacity is rounded up to a power of two.
+/// It is a shortcut for [`new_cache_padded_unbounded_with_growth_policy`]
+/// with [`GrowthPolicy::with_initial_capacity`].
+///
+/// # Panics
+///
+/// Panics if the rounded capacity is greater than the maximum capacity of [`GrowthPolicy::new`].
+pub fn new_cache_padded_unbounded_with_capacity<T>(
+    capacity: usize,
+) -> (
+    CachePaddedSPMCUnboundedProducer<T>,
+    CachePaddedSPMCUnboundedConsumer<T>,
+) {
+    new_cache_padded_unbounded_with_growth_policy(
+        GrowthPolicy::new().with_initial_capacity(capacity),
+    )
+}
+
+/// Creates a new single-producer, multi-consumer capped queue with the [`GrowthPolicy`].
+/// Returns [`producer`](CachePaddedSPMCUnboundedProducer)
+/// and [`consumer`](CachePaddedSPMCUnboundedConsumer).
+///
+/// Read more about the queue in [`new_capped_unbounded`]
+/// and about cache padding in [`new_cache_padded_unbounded`].
+pub fn new_cache_padded_capped_unbounded<T>(
+    growth_policy: GrowthPolicy,
+) -> (
+    CachePaddedSPMCUnboundedProducer<T>,
+    CachePaddedSPMCUnboundedConsumer<T>,
+) {
+    CachePaddedSPMCUnboundedProducer::new_queue(growth_policy, true, DEFAULT_STEAL_THRESHOLD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutex_vec_queue::MutexVecQueue;
+    use crate::zst_test::{test_zst_queue, Zst};
+    use std::collections::VecDeque;
+
+    const N: usize = 16000;
+    const BATCH_SIZE: usize = 10;
+
+    #[test]
+    fn test_spmc_unbounded_seq_insertions() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, _) = new_unbounded();
+
+        for i in 0..N {
+            producer.push(i, &global_queue);
+        }
+
+        assert!(global_queue.is_empty());
+
+        for i in 0..N {
+            assert_eq!(producer.pop().unwrap(), i);
+        }
+
+        let (producer, consumer) = new_unbounded();
+
+        for i in 0..N {
+            producer.maybe_push(i).unwrap();
+        }
+
+        for i in 0..N / BATCH_SIZE {
+            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+
+            assert_eq!(consumer.pop_many(slice.as_mut_slice()), BATCH_SIZE);
+
+            for (j, value) in slice.iter().enumerate() {
+                assert_eq!(unsafe { value.assume_init() }, i * BATCH_SIZE + j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spmc_unbounded_stealing() {
+        const TRIES: usize = 100;
+
+        let global_queue = MutexVecQueue::new();
+        let mut stolen = VecDeque::new();
+        let (producer1, consumer) = new_unbounded();
+        let (producer2, _) = new_unbounded();
+
+        producer2.reserve(512);
+
+        for _ in 0..TRIES * 2 {
+            for i in 0..N / 2 {
+                producer1.push(i, &global_queue);
+            }
+
+            consumer.steal_into(&producer2);
+
+            while let Some(task) = producer2.pop() {
+                stolen.push_back(task);
+            }
+
+            assert!(global_queue.is_empty());
+        }
+
+        assert!(producer2.is_empty());
+
+        let mut count = 0;
+
+        while producer1.pop().is_some() {
+            count += 1;
+        }
+
+        assert_eq!(count + stolen.len(), N * TRIES);
+    }
+
+    #[test]
+    fn test_spmc_unbounded_stealing_into_non_empty() {
+        let global_queue = MutexVecQueue::new();
+        let (producer1, consumer) = new_unbounded();
+        let (producer2, _) = new_unbounded();
+
+        producer2.reserve(64);
+
+        for i in 0..64 {
+            producer1.push(i, &global_queue);
+        }
+
+        for i in 0..54 {
+            producer2.push(64 + i, &global_queue);
+        }
+
+        assert_eq!(consumer.steal_into(&producer2), 10);
+        assert_eq!(producer2.len(), 64);
+
+        for i in 0..54 {
+            assert_eq!(producer2.pop(), Some(64 + i));
+        }
+
+        for i in 0..10 {
+            assert_eq!(producer2.pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_spmc_unbounded_stealing_into_any() {
+        let global_queue = MutexVecQueue::new();
+        let (producer1, consumer) = new_unbounded();
+        let (producer2, _) = crate::spmc::new_bounded::<_, 16>();
+
+        for i in 0..64 {
+            producer1.push(i, &global_queue);
+        }
+
+        for i in 0..12 {
+            producer2.push(60 + i, &global_queue);
+        }
+
+        for i in 0..4 {
+            assert_eq!(producer2.pop(), Some(60 + i));
+        }
+
+        // The reserved slots wrap around the end of the buffer.
+        assert_eq!(consumer.steal_into_any(&producer2), 8);
+        assert_eq!(producer2.len(), 16);
+        assert_eq!(consumer.steal_into_any(&producer2), 0);
+
+        for i in 0..8 {
+            assert_eq!(producer2.pop(), Some(64 + i));
+        }
+
+        for i in 0..8 {
+            assert_eq!(producer2.pop(), Some(i));
+        }
+
+        assert!(global_queue.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_unbounded_offload() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_unbounded();
+        let (checker, _) = crate::spmc::new_bounded::<_, 64>();
+
+        producer.reserve(16);
+
+        for i in 0..10 {
+            producer.push(i, &global_queue);
+        }
+
+        for i in 0..10 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        // The values wrap around the end of the buffer.
+        for i in 0..40 {
+            producer.push(i, &global_queue);
+        }
+
+        assert_eq!(producer.offload_into(&global_queue, 5), 35);
+        assert_eq!(producer.len(), 5);
+        assert_eq!(producer.offload_into(&global_queue, 5), 0);
+
+        global_queue.move_batch_to_producer(&checker, 64);
+
+        for i in 0..35 {
+            assert_eq!(checker.pop(), Some(i));
+        }
+
+        for i in 35..40 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+
+        assert!(global_queue.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_unbounded_many() {
+        const BATCH_SIZE: usize = 30;
+        const N: usize = BATCH_SIZE * 100;
+
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_unbounded();
+
+        for i in 0..N / BATCH_SIZE / 2 {
+            let slice = (0..BATCH_SIZE)
+                .map(|j| i * BATCH_SIZE + j)
+                .collect::<Vec<_>>();
+
+            unsafe {
+                producer.maybe_push_many(&slice).unwrap();
+            }
+
+            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+            producer.pop_many(slice.as_mut_slice());
+
+            for (j, value) in slice.iter().enumerate() {
+                let index = i * BATCH_SIZE + j;
+
+                assert_eq!(unsafe { value.assume_init() }, index);
+            }
+        }
+
+        for i in 0..N / BATCH_SIZE / 2 {
+            let slice = (0..BATCH_SIZE)
+                .map(|j| i * BATCH_SIZE + j)
+                .collect::<Vec<_>>();
+
+            unsafe {
+                producer.push_many(&slice, &global_queue);
+            }
+
+            assert!(global_queue.is_empty());
+
+            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+
+            consumer.pop_many(slice.as_mut_slice());
+
+            for (j, value) in slice.iter().enumerate() {
+                let index = i * BATCH_SIZE + j;
+
+                assert_eq!(unsafe { value.assume_init() }, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spmc_unbounded_raw_queue() {
+        let global_queue = MutexVecQueue::new();
+        let queue = SPMCUnboundedQueue::<usize>::with_growth_policy(
+            GrowthPolicy::new().with_initial_capacity(4),
+        );
+        let stealer_queue = SPMCUnboundedQueue::<usize>::new();
+        let mut cursor = queue.consumer_cursor();
+
+        assert_eq!(unsafe { queue.consumer_capacity(&mut cursor) }, 4);
+
+        unsafe {
+            for i in 0..N {
+                queue.producer_push(i, &global_queue);
+            }
+
+            assert!(global_queue.is_empty());
+            assert_eq!(queue.producer_len(), N);
+            assert_eq!(queue.producer_capacity(), N.next_power_of_two());
+        }
+
+        // The cursor has the first version, it updates it lazily.
+        assert_eq!(unsafe { queue.consumer_len(&mut cursor) }, N);
+        assert_eq!(
+            unsafe { queue.consumer_capacity(&mut cursor) },
+            N.next_power_of_two()
+        );
+
+        let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+
+        assert_eq!(
+            unsafe { queue.consumer_pop_many(&mut slice, &mut cursor) },
+            BATCH_SIZE
+        );
+
+        for (i, value) in slice.iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, i);
+        }
+
+        let stolen = unsafe { queue.steal_into(&stealer_queue, &mut cursor.clone()) };
+
+        assert_eq!(stolen, unsafe { stealer_queue.producer_len() });
+        assert_eq!(unsafe { stealer_queue.producer_pop() }, Some(BATCH_SIZE));
+
+        unsafe {
+            assert_eq!(queue.producer_pop(), Some(BATCH_SIZE + stolen));
+            assert!(queue.producer_maybe_push_many(&[N, N + 1]).is_ok());
+            assert_eq!(queue.producer_len(), N - BATCH_SIZE - stolen + 1);
+
+            let capacity = queue.producer_capacity();
+
+            queue.producer_shrink_to(usize::MAX);
+
+            assert_eq!(queue.producer_capacity(), capacity);
+
+            queue.producer_shrink_to(0);
+
+            assert_eq!(
+                queue.producer_capacity(),
+                queue.producer_len().next_power_of_two()
+            );
+        }
+    }
+
+    #[test]
+    fn test_spmc_unbounded_growth_policy() {
+        let policy = GrowthPolicy::new()
+            .with_initial_capacity(8)
+            .with_factor(4)
+            .with_max_capacity(128);
+        let (producer, consumer) = new_unbounded_with_growth_policy(policy);
+
+        assert_eq!(producer.growth_policy(), policy);
+        assert_eq!(producer.capacity(), 8);
+
+        for i in 0..9 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(producer.capacity(), 32);
+
+        unsafe {
+            producer
+                .maybe_push_many(&(9..40).collect::<Vec<_>>())
+                .unwrap();
+        }
+
+        assert_eq!(producer.capacity(), 128);
+
+        // The consumer still has the first version.
+        let stale_consumer = consumer.clone();
+
+        let mut slice = [MaybeUninit::uninit(); 20];
+
+        assert_eq!(consumer.pop_many(&mut slice), 20);
+
+        producer.shrink_to(0);
+
+        assert_eq!(producer.capacity(), 32);
+
+        producer.shrink_to(64);
+
+        assert_eq!(producer.capacity(), 32);
+
+        for i in 40..50 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(stale_consumer.capacity(), 32);
+        assert_eq!(stale_consumer.len(), 30);
+        assert_eq!(stale_consumer.pop_many(&mut slice), 20);
+
+        for (i, value) in slice[..20].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, 20 + i);
+        }
+
+        producer.shrink_to_fit();
+
+        assert_eq!(producer.capacity(), 16);
+
+        for i in 40..50 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_unbounded_zst() {
+        let (producer, consumer) = new_unbounded::<Zst>();
+
+        // No buffer is allocated for zero-sized values, so the queue never grows.
+        assert_eq!(producer.capacity(), GrowthPolicy::new().max_capacity());
+
+        test_zst_queue(
+            None,
+            |value| producer.maybe_push(value),
+            |dst| consumer.pop_many(dst),
+            || consumer.len(),
+            || consumer.steal_into(&new_unbounded().0),
+        );
+    }
+
+    #[test]
+    fn test_spmc_unbounded_try_reserve() {
+        let policy = GrowthPolicy::new()
+            .with_initial_capacity(4)
+            .with_max_capacity(64);
+        let (producer, consumer) = new_unbounded_with_growth_policy(policy);
+
+        assert_eq!(producer.try_reserve(65), Err(AllocError::CapacityOverflow));
+        assert_eq!(producer.capacity(), 4);
+
+        producer.reserve(20);
+
+        assert_eq!(producer.capacity(), 32);
+
+        producer.reserve(8);
+
+        assert_eq!(producer.capacity(), 32);
+        assert_eq!(producer.try_reserve(64), Ok(()));
+        assert_eq!(producer.capacity(), 64);
+
+        for i in 0..64 {
+            producer.fallible_push(i).unwrap();
+        }
+
+        assert_eq!(
+            producer.fallible_push(64),
+            Err((64, AllocError::CapacityOverflow))
+        );
+        assert_eq!(producer.len(), 64);
+
+        let mut slice = [MaybeUninit::uninit(); 64];
+
+        assert_eq!(consumer.pop_many(&mut slice), 64);
+
+        for (i, value) in slice[..64].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, i);
+        }
+    }
+
+    #[test]
+    fn test_spmc_unbounded_capped() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_capped_unbounded(
+            GrowthPolicy::new()
+                .with_initial_capacity(4)
+                .with_max_capacity(16),
+        );
+
+        for i in 0..16 {
+            producer.push(i, &global_queue);
+        }
+
+        assert!(global_queue.is_empty());
+        assert_eq!(producer.capacity(), 16);
+        assert_eq!(producer.maybe_push(16), Err(16));
+        assert_eq!(unsafe { producer.maybe_push_many(&[16, 17]) }, Err(()));
+        assert_eq!(producer.len(), 16);
+
+        producer.push(16, &global_queue);
+
+        assert_eq!(producer.capacity(), 16);
+        assert_eq!(producer.len(), 8);
+        assert_eq!(global_queue.len(), 9);
+
+        for i in 8..16 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        for i in 0..8 {
+            assert_eq!(global_queue.pop(), Some(i));
+        }
+
+        assert_eq!(global_queue.pop(), Some(16));
+
+        unsafe { producer.push_many(&(0..10).collect::<Vec<_>>(), &global_queue) };
+
+        assert_eq!(producer.len(), 10);
+        assert!(global_queue.is_empty());
+
+        unsafe { producer.push_many(&(10..20).collect::<Vec<_>>(), &global_queue) };
+
+        assert_eq!(producer.len(), 2);
+        assert_eq!(global_queue.len(), 18);
+
+        for i in (0..8).chain(10..20) {
+            assert_eq!(global_queue.pop(), Some(i));
+        }
+
+        for i in 8..10 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn test_spmc_unbounded_max_capacity() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_unbounded_with_growth_policy(
+            GrowthPolicy::new()
+                .with_initial_capacity(4)
+                .with_max_capacity(8),
+        );
 
-        loop {
-            if unlikely(version.id() < last_version_id) {
-                if unlikely(!self.update_version(version)) {
-                    // We can't reliably calculate the length in this situation.
-                    return 0;
-                }
+        for i in 0..8 {
+            producer.push(i, &global_queue);
+        }
 
-                (last_version_id, tail) = self.sync_load_version_and_tail(Acquire);
+        assert_eq!(producer.maybe_push(8), Err(8));
 
-                continue;
-            }
+        // The queue is capped by the maximum capacity, so it overflows instead of panicking.
+        producer.push(8, &global_queue);
 
-            let available = Self::len(head, tail);
-            let n = dst.len().min(available);
+        assert_eq!(producer.capacity(), 8);
+        assert_eq!(producer.len(), 4);
+        assert_eq!(global_queue.take_all(), vec![0, 1, 2, 3, 8]);
 
-            if n == 0 {
-                return 0;
-            }
+        for i in 4..8 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+    }
 
-            if unlikely(n > version.capacity()) {
-                // Inconsistent state (this thread has been preempted
-                // after we have loaded `head`,
-                // and before we have loaded `tail`).
+    #[test]
+    fn test_spmc_unbounded_capped_try_push() {
+        let policy = GrowthPolicy::new()
+            .with_initial_capacity(4)
+            .with_max_capacity(8);
+        let receiver = MutexVecQueue::with_capacity_and_max_len(8, 8);
+        let (producer, consumer) = new_capped_unbounded(policy);
+
+        for i in 100..104 {
+            receiver.push(i);
+        }
 
-                head = self.head.load(Acquire);
-                (last_version_id, tail) = self.sync_load_version_and_tail(Acquire);
+        for i in 0..8 {
+            producer.try_push(i, &receiver).unwrap();
+        }
 
-                continue;
-            }
+        // The receiver can't accept 5 more values.
+        assert_eq!(producer.try_push(8, &receiver), Err(8));
+        assert_eq!(producer.len(), 8);
+        assert_eq!(
+            unsafe { producer.try_push_many(&[8, 9], &receiver) },
+            Err(())
+        );
+        assert_eq!(producer.len(), 8);
 
-            let dst_ptr = dst.as_mut_ptr();
-            let head_idx = (head & version.mask()) as usize;
-            let right = version.capacity() - head_idx;
+        // The rejected values are returned to the head twice, so the order is kept.
+        for i in 0..8 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
 
-            // We optimistically copy the values from the buffer into the dst.
-            // On CAS failure, we forget the copied values and try again.
-            // It is safe because we can concurrently read from the head.
+        for i in 0..8 {
+            producer.maybe_push(i).unwrap();
+        }
 
-            if n <= right {
-                // No wraparound, copy in one shot
-                unsafe {
-                    ptr::copy_nonoverlapping(version.thin_mut_ptr().add(head_idx), dst_ptr, n);
-                }
-            } else {
-                unsafe {
-                    // Wraparound: copy right half then left half
-                    ptr::copy_nonoverlapping(version.thin_ptr().add(head_idx), dst_ptr, right);
-                    ptr::copy_nonoverlapping(version.thin_ptr(), dst_ptr.add(right), n - right);
-                }
-            }
+        for i in 100..104 {
+            assert_eq!(receiver.pop(), Some(i));
+        }
 
-            // Now claim ownership
-            // CAS is strong because we don't want to recopy the values
-            match self
-                .head
-                .compare_exchange(head, head.wrapping_add(n as u32), Release, Acquire)
-            {
-                Ok(_) => return n,
-                Err(actual_head) => {
-                    // CAS failed, forget read values (they're MaybeUninit, so it's fine)
-                    // But don't try to drop, just retry
+        assert_eq!(producer.try_push(8, &receiver), Ok(()));
+        assert_eq!(producer.len(), 4);
 
-                    head = actual_head;
-                    (last_version_id, tail) = self.sync_load_version_and_tail(Acquire);
-                }
-            }
+        for i in (0..4).chain(8..9) {
+            assert_eq!(receiver.pop(), Some(i));
+        }
+
+        for i in 4..8 {
+            assert_eq!(consumer.pop(), Some(i));
         }
     }
 
-    /// Steals many values from the consumer to the `dst`.
-    /// Returns the number of values stolen.
-    ///
-    /// It can return zero even if the source queue is not empty,
-    /// if the producer is preempted while pushing.
-    fn steal_into(
-        &self,
-        dst: &Self,
-        src_version: &mut CachedVersion<T>,
-        dst_version: &mut CachedVersion<T>,
-    ) -> usize {
-        let mut src_head = self.head.load(Acquire);
-        let (mut src_last_version_id, mut src_tail) = self.sync_load_version_and_tail(Acquire);
-        let dst_tail = unsafe { dst.unsync_load_tail() }; // only producer can change tail
+    #[test]
+    fn test_spmc_unbounded_locked_producer() {
+        let (capped, _) = new_capped_unbounded::<usize>(GrowthPolicy::new().with_max_capacity(8));
 
-        if cfg!(debug_assertions) {
-            let dst_head = dst.head.load(Relaxed);
+        assert!(capped.into_locked().is_err());
 
-            assert_eq!(
-                dst_head, dst_tail,
-                "steal_into should not be called when dst is not empty"
-            );
+        let (capped, _) =
+            new_unbounded_with_growth_policy::<usize>(GrowthPolicy::new().with_max_capacity(8));
+
+        assert!(capped.into_locked().is_err());
+
+        let (global_producer, global_consumer) = new_unbounded();
+        let global_queue = global_producer.into_locked().ok().unwrap();
+        let (producer, _consumer) = new_capped_unbounded(
+            GrowthPolicy::new()
+                .with_initial_capacity(4)
+                .with_max_capacity(4),
+        );
+
+        for i in 0..9 {
+            producer.push(i, &global_queue);
         }
 
-        loop {
-            if unlikely(src_version.id() < src_last_version_id) {
-                if unlikely(!self.update_version(src_version)) {
-                    // We can't reliably calculate the length in this situation.
-                    return 0;
-                }
+        assert_eq!(producer.len(), 3);
+        assert_eq!(global_queue.lock().len(), 6);
 
-                (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+        for i in [0, 1, 4, 2, 3, 7] {
+            assert_eq!(global_consumer.pop(), Some(i));
+        }
 
-                continue;
-            }
+        let global_producer = global_queue.into_inner();
 
-            let n = Self::len(src_head, src_tail) / 2;
-            if n > src_version.capacity() / 2 {
-                // Inconsistent state (this thread has been preempted
-                // after we have loaded `src_head`,
-                // and before we have loaded `src_tail`);
+        assert!(global_producer.is_empty());
+    }
 
-                src_head = self.head.load(Acquire);
-                (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
+    #[test]
+    fn test_spmc_unbounded_capped_drop_oldest() {
+        struct DropCounter(usize, LightArc<std::sync::atomic::AtomicUsize>);
 
-                continue;
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.1.fetch_add(1, Relaxed);
             }
+        }
 
-            if !cfg!(feature = "always_steal") && n < 4 || n == 0 {
-                // we don't steal less than 4 by default
-                // because else we may lose more because of cache locality and NUMA awareness
-                return 0;
-            }
+        let drops = LightArc::new(std::sync::atomic::AtomicUsize::new(0));
+        let new_value = |i| DropCounter(i, drops.clone());
+        let dropped = std::cell::RefCell::new(Vec::new());
+        let policy =
+            crate::spmc::DropOldest(|value: DropCounter| dropped.borrow_mut().push(value.0));
+        let (producer, consumer) = new_capped_unbounded(
+            GrowthPolicy::new()
+                .with_initial_capacity(2)
+                .with_max_capacity(4),
+        );
 
-            let n = n.min(dst_version.capacity());
-            let src_head_idx = (src_head & src_version.mask()) as usize;
+        for i in 0..6 {
+            assert!(producer
+                .push_with_policy(new_value(i), &PanickingReceiver, &policy)
+                .is_ok());
+        }
 
-            let (src_right, src_left): (&[T], &[T]) = unsafe {
-                let right_occupied = src_version.capacity() - src_head_idx;
-                if n <= right_occupied {
-                    (
-                        slice::from_raw_parts(src_version.thin_ptr().add(src_head_idx).cast(), n),
-                        &[],
-                    )
-                } else {
-                    (
-                        slice::from_raw_parts(
-                            src_version.thin_ptr().add(src_head_idx).cast(),
-                            right_occupied,
-                        ),
-                        slice::from_raw_parts(src_version.thin_ptr().cast(), n - right_occupied),
-                    )
-                }
-            };
+        assert_eq!(*dropped.borrow(), [0, 1]);
+        assert_eq!(drops.load(Relaxed), 2);
 
-            // We optimistically copy the values from the buffer into the dst.
-            // On CAS failure, we forget the copied values and try again.
-            // It is safe because we can concurrently read from the head.
-            Self::copy_slice(
-                unsafe { dst_version.thin_mut_ptr() }.cast::<T>(),
-                dst_tail,
-                src_right,
-                dst_version,
-            );
-            Self::copy_slice(
-                unsafe { dst_version.thin_mut_ptr() }.cast::<T>(),
-                dst_tail.wrapping_add(src_right.len() as u32),
-                src_left,
-                dst_version,
-            );
+        unsafe {
+            let values = [new_value(6), new_value(7), new_value(8)];
 
-            // CAS is strong because we don't want to recopy the values
-            let res = self.head.compare_exchange(
-                src_head,
-                src_head.wrapping_add(n as u32),
-                Release,
-                Acquire,
-            );
+            producer
+                .push_many_with_policy(&values, &PanickingReceiver, &policy)
+                .unwrap();
 
-            match res {
-                Ok(_) => {
-                    // Success, we can move dst tail and return
-                    dst.tail_and_version.store(
-                        pack_version_and_tail(dst_version.id(), dst_tail.wrapping_add(n as u32)),
-                        Release,
-                    );
+            // The values are moved into the queue.
+            mem::forget(values);
+        }
 
-                    return n;
-                }
-                Err(current_head) => {
-                    // Another thread has read the same values,
-                    // forget them and full retry
+        assert_eq!(*dropped.borrow(), [0, 1, 2, 3, 4]);
+        assert_eq!(drops.load(Relaxed), 5);
 
-                    src_head = current_head;
-                    (src_last_version_id, src_tail) = self.sync_load_version_and_tail(Acquire);
-                }
-            }
+        for i in 5..9 {
+            assert_eq!(consumer.pop().map(|value| value.0), Some(i));
         }
+
+        assert_eq!(drops.load(Relaxed), 9);
     }
-}
 
-#[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
-unsafe impl<T, AtomicU32Wrapper, AtomicU64Wrapper> Send
-    for SPMCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
-where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
-    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
-{
-}
-unsafe impl<T, AtomicU32Wrapper, AtomicU64Wrapper> Sync
-    for SPMCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
-where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
-    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
-{
-}
+    #[test]
+    fn test_spmc_unbounded_auto_shrink() {
+        let (producer, consumer) =
+            new_unbounded_with_growth_policy(GrowthPolicy::new().with_shrink_after(3));
 
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper> Drop
-    for SPMCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
-where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
-    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
-{
-    fn drop(&mut self) {
-        // While dropping there is no concurrency
+        for i in 0..1000 {
+            producer.maybe_push(i).unwrap();
+        }
 
-        if needs_drop::<T>() {
-            let version = self.last_version.try_read().unwrap();
-            let mut head = unsafe { self.head.unsync_load() };
-            let tail = unsafe { self.unsync_load_tail() };
+        assert_eq!(producer.capacity(), 1024);
 
-            while head != tail {
-                unsafe {
-                    ptr::drop_in_place(
-                        version
-                            .thin_mut_ptr()
-                            .add((head & version.mask()) as usize)
-                            .cast::<T>(),
-                    );
-                }
+        let mut slice = [MaybeUninit::uninit(); 1000];
 
-                head = head.wrapping_add(1);
-            }
-        }
-    }
-}
+        assert_eq!(consumer.pop_many(&mut slice[..990]), 990);
 
-/// Generates SPMC producer and consumer.
-macro_rules! generate_spmc_producer_and_consumer {
-    ($producer_name:ident, $consumer_name:ident, $atomic_u32_wrapper:ty, $long_atomic_wrapper:ty) => {
-        /// The producer of the [`SPMCUnboundedQueue`].
-        pub struct $producer_name<T> {
-            inner: LightArc<SPMCUnboundedQueue<T, $atomic_u32_wrapper, $long_atomic_wrapper>>,
-            cached_version: UnsafeCell<CachedVersion<T>>, // The producer is not Sync, it needs only shared references and it never gets two mutable references to this field
-            _non_sync: PhantomData<*const ()>,
-        }
+        // Two low occupancy observations are not enough.
+        assert_eq!(producer.pop(), Some(990));
+        assert_eq!(producer.pop(), Some(991));
+        assert_eq!(producer.capacity(), 1024);
 
-        impl<T> $producer_name<T> {
-            /// Returns a mutable reference to the cached version.
-            #[allow(clippy::mut_from_ref, reason = "It improves readability")]
-            #[inline]
-            fn cached_version(&self) -> &mut CachedVersion<T> {
-                unsafe { &mut *self.cached_version.get() }
-            }
+        assert_eq!(producer.pop(), Some(992));
+        assert_eq!(producer.capacity(), 16);
 
-            /// Updates the capacity of the queue to the given value.
-            ///
-            /// # Safety
-            ///
-            /// The provided capacity must be greater than the current capacity,
-            /// less than `u32::MAX` and be a power of two.
-            pub fn reserve(&self, capacity: usize) {
-                unsafe {
-                    self
-                        .inner
-                        .producer_reserve(capacity, self.cached_version())
-                };
-            }
+        // A high occupancy observation resets the streak.
+        for i in 1000..1008 {
+            producer.maybe_push(i).unwrap();
         }
 
-        impl<T: Send> Producer<T> for $producer_name<T> {
-            #[inline]
-            fn capacity(&self) -> usize {
-                // The producer always has the latest version.
-                unsafe { SPMCUnboundedQueue::<T, $atomic_u32_wrapper, $long_atomic_wrapper>::producer_capacity(self.cached_version()) }
-            }
+        assert_eq!(producer.capacity(), 16);
+        assert_eq!(consumer.pop_many(&mut slice), 15);
 
-            #[inline]
-            fn len(&self) -> usize {
-                unsafe { self.inner.producer_len() }
-            }
+        for (i, value) in slice[..15].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, 993 + i);
+        }
 
-            #[inline]
-            fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, _sync_batch_receiver: &SBR) {
-                unsafe { self.inner.producer_push(value, self.cached_version()) };
-            }
+        for _ in 0..3 {
+            assert_eq!(producer.pop(), None);
+        }
 
-            #[inline]
-            fn maybe_push(&self, value: T) -> Result<(), T> {
-                unsafe { self.inner.producer_push(value, self.cached_version()) };
+        assert_eq!(producer.capacity(), 4);
+    }
 
-                Ok(())
-            }
+    #[test]
+    fn test_spmc_unbounded_reuses_buffers() {
+        let (producer, consumer) = new_unbounded_with_growth_policy(
+            GrowthPolicy::new()
+                .with_initial_capacity(4)
+                .with_shrink_after(1),
+        );
+        let buffer = || unsafe { producer.inner.producer_version() }.thin_ptr();
+        let free_capacities = || {
+            unsafe { &*producer.inner.free_buffers.get() }
+                .iter()
+                .map(|buffer| buffer.len())
+                .collect::<Vec<_>>()
+        };
+        let small_buffer = buffer();
+        let mut slice = [MaybeUninit::uninit(); 16];
 
-            #[inline]
-            fn pop(&self) -> Option<T> {
-                unsafe { self.inner.producer_pop(self.cached_version()) }
-            }
+        for i in 0..16 {
+            producer.maybe_push(i).unwrap();
+        }
 
-            #[inline]
-            fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
-                unsafe { self.inner.producer_pop_many(dst, self.cached_version()) }
-            }
+        assert_eq!(producer.capacity(), 16);
+        assert_eq!(free_capacities(), [4, 8]);
 
-            #[inline]
-            unsafe fn push_many_unchecked(&self, first: &[T], last: &[T]) {
-                unsafe {
-                    self
-                    .inner
-                        .producer_push_many_unchecked(first, last, self.cached_version())
-                }
-            }
+        for _ in 0..3 {
+            assert_eq!(consumer.pop_many(&mut slice), 16);
 
-            #[inline]
-            unsafe fn maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
-                unsafe {
-                    self
-                    .inner
-                        .producer_push_many(slice, self.cached_version())
-                };
+            // The low occupancy shrinks the queue into the freed buffer of the capacity 4,
+            // and the larger buffers are freed.
+            producer.maybe_push(0).unwrap();
+
+            assert_eq!(producer.capacity(), 4);
+            assert_eq!(buffer(), small_buffer);
+            assert!(free_capacities().is_empty());
+            assert_eq!(producer.pop(), Some(0));
 
-                Ok(())
+            for i in 0..16 {
+                producer.maybe_push(i).unwrap();
             }
 
-            #[inline]
-            unsafe fn push_many<SBR: SyncBatchReceiver<T>>(
-                &self,
-                slice: &[T],
-                _sync_batch_receiver: &SBR,
-            ) {
-                unsafe {
-                    self.inner
-                        .producer_push_many(slice, self.cached_version())
-                };
-            }
+            assert_eq!(producer.capacity(), 16);
+            assert_eq!(free_capacities(), [4, 8]);
         }
 
-        impl<T: Send> ConsumerSpawner<T> for $producer_name<T> {
-            type Consumer = $consumer_name<T>;
+        assert_eq!(consumer.pop_many(&mut slice[..8]), 8);
 
-            fn spawn_consumer(&self) -> Self::Consumer {
-                $consumer_name {
-                    inner: self.inner.clone(),
-                    cached_version: UnsafeCell::new(self.cached_version().clone()),
-                    _non_sync: PhantomData,
-                }
-            }
-        }
+        producer.shrink_to(8);
 
-        #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
-        unsafe impl<T: Send> Send for $producer_name<T> {}
+        assert_eq!(producer.capacity(), 8);
+        assert!(free_capacities().is_empty());
+        assert_eq!(consumer.pop_many(&mut slice), 8);
 
-        /// The consumer of the [`SPMCUnboundedQueue`].
-        pub struct $consumer_name<T> {
-            inner: LightArc<SPMCUnboundedQueue<T, $atomic_u32_wrapper, $long_atomic_wrapper>>,
-            cached_version: UnsafeCell<CachedVersion<T>>,
-            _non_sync: PhantomData<*const ()>,
+        for (i, value) in slice[..8].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, 8 + i);
         }
+    }
 
-        impl<T> $consumer_name<T> {
-            /// Returns a mutable reference to the cached version.
-            #[allow(clippy::mut_from_ref, reason = "It improves readability")]
-            #[inline]
-            fn cached_version(&self) -> &mut CachedVersion<T> {
-                unsafe { &mut *self.cached_version.get() }
-            }
+    #[test]
+    fn test_spmc_unbounded_idle_consumer_does_not_pin_versions() {
+        let (producer, consumer) = new_unbounded();
+        let idle_consumer = consumer.clone();
+        let retired_versions = || unsafe { &*producer.inner.retired_versions.get() }.len();
+
+        for i in 0..1000 {
+            producer.maybe_push(i).unwrap();
         }
 
-        impl<T: Send> Consumer<T> for $consumer_name<T> {
-            type AssociatedProducer = $producer_name<T>;
+        // The idle consumer has never touched the queue after eight growths,
+        // but the producer has already freed all old versions.
+        assert_eq!(producer.capacity(), 1024);
+        assert_eq!(retired_versions(), 0);
 
-            #[inline]
-            fn capacity(&self) -> usize {
-                self.inner.consumer_capacity(self.cached_version())
-            }
+        let mut slice = [MaybeUninit::uninit(); 10];
 
-            #[inline]
-            fn len(&self) -> usize {
-                self.inner.consumer_len(self.cached_version())
-            }
+        // A pinned consumer delays freeing until the next producer operation.
+        {
+            let _pinned = producer.inner.pinned_consumers.pin();
 
-            #[inline]
-            fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
-                self.inner.consumer_pop_many(dst, self.cached_version())
-            }
+            producer.reserve(2048);
 
-            #[inline]
-            fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize {
-                self.inner.steal_into(
-                    &*dst.inner,
-                    self.cached_version(),
-                    dst.cached_version(),
-                )
-            }
+            assert_eq!(retired_versions(), 1);
         }
 
-        impl<T> Clone for $consumer_name<T> {
-            fn clone(&self) -> Self {
-                Self {
-                    cached_version: UnsafeCell::new(self.cached_version().clone()),
-                    inner: self.inner.clone(),
-                    _non_sync: PhantomData,
-                }
-            }
+        assert_eq!(producer.pop(), Some(0));
+        assert_eq!(retired_versions(), 0);
+
+        // The idle consumer loads the last version instead of reading a freed one.
+        assert_eq!(idle_consumer.pop_many(&mut slice), 10);
+
+        for (i, value) in slice[..10].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, 1 + i);
         }
 
-        #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
-        unsafe impl<T: Send> Send for $consumer_name<T> {}
-    };
+        assert_eq!(idle_consumer.capacity(), 2048);
+        assert_eq!(consumer.len(), 989);
+    }
 
-    ($producer_name:ident, $consumer_name:ident) => {
-        generate_spmc_producer_and_consumer!(
-            $producer_name,
-            $consumer_name,
-            NotCachePaddedAtomicU32,
-            NotCachePaddedAtomicU64
+    #[test]
+    fn test_spmc_unbounded_retired_versions_are_bounded_under_stealing() {
+        const STEALERS: usize = 3;
+        const ROUNDS: usize = 200;
+        const BURST: usize = 64;
+        // Growing from 1 to 64 and shrinking to fit.
+        const MAX_RETIREMENTS_PER_ROUND: usize = 7;
+
+        let (producer, consumer) = new_unbounded_with_growth_policy(
+            GrowthPolicy::new()
+                .with_initial_capacity(1)
+                .with_shrink_after(0),
         );
-    };
-}
+        let retired_versions = || unsafe { &*producer.inner.retired_versions.get() }.len();
+        let finished = std::sync::atomic::AtomicBool::new(false);
 
-generate_spmc_producer_and_consumer!(SPMCUnboundedProducer, SPMCUnboundedConsumer);
-
-/// Creates a new single-producer, multi-consumer unbounded queue.
-/// Returns [`producer`](SPMCUnboundedProducer) and [`consumer`](SPMCUnboundedConsumer).
-///
-/// The producer __should__ be only one while consumers can be cloned.
-/// If you want to use more than one producer, don't use this queue.
-///
-/// If you want to use only one consumer, look at the single-producer, single-consumer queue.
-///
-/// # Unbounded queue vs. [`bounded queue`](crate::spmc::new_bounded).
-///
-/// - [`maybe_push`](Producer::maybe_push), [`maybe_push_many`](Producer::maybe_push_many)
-///   can return an error only for `bounded` queue.
-/// - [`push`](Producer::push), [`push_many`](Producer::push_many)
-///   writes to the [`SyncBatchReceiver`] only for `bounded` queue.
-/// - [`Consumer::steal_into`] and [`Consumer::pop_many`] can pop zero values even if the source
-///   queue is not empty for `unbounded` queue.
-/// - [`Consumer::capacity`] and [`Consumer::len`] can return old values for `unbounded` queue.
-/// - All methods of `bounded` queue work much faster than all methods of `unbounded` queue.
-///
-/// # Cache padding
-///
-/// Cache padding can improve the performance of the queue many times, but it also requires
-/// much more memory (likely 128 or 256 more bytes for the queue).
-/// If you can sacrifice some memory for the performance, use [`new_cache_padded_unbounded`].
-///
-/// # Examples
-///
-/// ```
-/// use parcoll::spmc::{Producer, Consumer, new_unbounded};
-///
-/// let (mut producer, mut consumer) = new_unbounded();
-/// let consumer2 = consumer.clone(); // You can clone the consumer
-///
-/// producer.maybe_push(1).unwrap();
-/// producer.maybe_push(2).unwrap();
-///
-/// let mut slice = [std::mem::MaybeUninit::uninit(); 3];
-/// let popped = consumer.pop_many(&mut slice);
-///
-/// assert_eq!(popped, 2);
-/// assert_eq!(unsafe { slice[0].assume_init() }, 1);
-/// assert_eq!(unsafe { slice[1].assume_init() }, 2);
-/// ```
-pub fn new_unbounded<T>() -> (SPMCUnboundedProducer<T>, SPMCUnboundedConsumer<T>) {
-    let mut queue = SPMCUnboundedQueue::new();
-    let version = queue.last_version.get_mut().clone();
-    let queue = LightArc::new(queue);
-
-    (
-        SPMCUnboundedProducer {
-            inner: queue.clone(),
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version.clone())),
-            _non_sync: PhantomData,
-        },
-        SPMCUnboundedConsumer {
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version)),
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
-}
+        std::thread::scope(|scope| {
+            for _ in 0..STEALERS {
+                let consumer = consumer.clone();
+                let finished = &finished;
 
-generate_spmc_producer_and_consumer!(
-    CachePaddedSPMCUnboundedProducer,
-    CachePaddedSPMCUnboundedConsumer,
-    CachePaddedAtomicU32,
-    CachePaddedAtomicU64
-);
+                // The stealers never stop, so there is no moment when no consumer is pinned.
+                scope.spawn(move || {
+                    let (dst, _) = new_unbounded();
 
-/// Creates a new single-producer, multi-consumer unbounded queue.
-/// Returns [`producer`](SPMCUnboundedProducer) and [`consumer`](SPMCUnboundedConsumer).
-///
-/// The producer __should__ be only one while consumers can be cloned.
-/// If you want to use more than one producer, don't use this queue.
-///
-/// If you want to use only one consumer, look at the single-producer, single-consumer queue.
-///
-/// # Unbounded queue vs. [`bounded queue`](crate::spmc::new_bounded).
-///
-/// - [`maybe_push`](Producer::maybe_push), [`maybe_push_many`](Producer::maybe_push_many)
-///   can return an error only for `bounded` queue.
-/// - [`push`](Producer::push), [`push_many`](Producer::push_many)
-///   writes to the [`SyncBatchReceiver`] only for `bounded` queue.
-/// - [`Consumer::steal_into`] and [`Consumer::pop_many`] can pop zero values even if the source
-///   queue is not empty for `unbounded` queue.
-/// - [`Consumer::capacity`] and [`Consumer::len`] can return old values for `unbounded` queue.
-/// - All methods of `bounded` queue work much faster than all methods of `unbounded` queue.
-///
-/// # Cache padding
-///
-/// Cache padding can improve the performance of the queue many times, but it also requires
-/// much more memory (likely 128 or 256 more bytes for the queue).
-/// If you can't sacrifice some memory for the performance, use [`new_unbounded`].
-///
-/// # Examples
-///
-/// ```
-/// use parcoll::spmc::{Producer, Consumer, new_cache_padded_unbounded};
-///
-/// let (mut producer, mut consumer) = new_cache_padded_unbounded();
-/// let consumer2 = consumer.clone(); // You can clone the consumer
-///
-/// producer.maybe_push(1).unwrap();
-/// producer.maybe_push(2).unwrap();
-///
-/// let mut slice = [std::mem::MaybeUninit::uninit(); 3];
-/// let popped = consumer.pop_many(&mut slice);
-///
-/// assert_eq!(popped, 2);
-/// assert_eq!(unsafe { slice[0].assume_init() }, 1);
-/// assert_eq!(unsafe { slice[1].assume_init() }, 2);
-/// ```
-pub fn new_cache_padded_unbounded<T>() -> (
-    CachePaddedSPMCUnboundedProducer<T>,
-    CachePaddedSPMCUnboundedConsumer<T>,
-) {
-    let mut queue = SPMCUnboundedQueue::new();
-    let version = queue.last_version.get_mut().clone();
-    let queue = LightArc::new(queue);
-
-    (
-        CachePaddedSPMCUnboundedProducer {
-            inner: queue.clone(),
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version.clone())),
-            _non_sync: PhantomData,
-        },
-        CachePaddedSPMCUnboundedConsumer {
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version)),
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
-}
+                    while !finished.load(Acquire) {
+                        consumer.steal_into(&dst);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::mutex_vec_queue::MutexVecQueue;
-    use std::collections::VecDeque;
+                        while dst.pop().is_some() {}
+                    }
+                });
+            }
 
-    const N: usize = 16000;
-    const BATCH_SIZE: usize = 10;
+            for round in 0..ROUNDS {
+                for i in 0..BURST {
+                    producer.maybe_push(round * BURST + i).unwrap();
 
-    #[test]
-    fn test_spmc_unbounded_seq_insertions() {
-        let global_queue = MutexVecQueue::new();
-        let (producer, _) = new_unbounded();
+                    assert!(retired_versions() <= MAX_RETIREMENTS_PER_ROUND);
+                }
 
-        for i in 0..N {
-            producer.push(i, &global_queue);
-        }
+                while producer.pop().is_some() {}
 
-        assert!(global_queue.is_empty());
+                producer.shrink_to_fit();
 
-        for i in 0..N {
-            assert_eq!(producer.pop().unwrap(), i);
-        }
+                assert!(retired_versions() <= MAX_RETIREMENTS_PER_ROUND);
 
-        let (producer, consumer) = new_unbounded();
+                // All retired versions are freed once the consumers pinned before
+                // finish their current operations.
+                let mut attempts = 0;
 
-        for i in 0..N {
-            producer.maybe_push(i).unwrap();
-        }
+                while retired_versions() != 0 {
+                    attempts += 1;
 
-        for i in 0..N / BATCH_SIZE {
-            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+                    assert!(attempts < 1_000_000, "retired versions are never freed");
 
-            assert_eq!(consumer.pop_many(slice.as_mut_slice()), BATCH_SIZE);
+                    std::thread::yield_now();
 
-            for j in 0..BATCH_SIZE {
-                assert_eq!(unsafe { slice[j].assume_init() }, i * BATCH_SIZE + j);
+                    assert_eq!(producer.pop(), None);
+                }
             }
-        }
+
+            finished.store(true, Release);
+        });
     }
 
     #[test]
-    fn test_spmc_unbounded_stealing() {
-        const TRIES: usize = 100;
+    fn test_spmc_unbounded_indexes_past_u32_max() {
+        const START: UnboundedIndex = u32::MAX as UnboundedIndex - 100;
 
-        let global_queue = MutexVecQueue::new();
-        let mut stolen = VecDeque::new();
-        let (producer1, consumer) = new_unbounded();
-        let (mut producer2, _) = new_unbounded();
+        let (producer, consumer) = new_unbounded();
+        let (producer2, _) = new_unbounded();
 
-        producer2.reserve(512);
+        producer.inner.head.store(START, Relaxed);
+        unsafe { producer.inner.tail_and_version.store(0, START, Relaxed) };
+        producer2.reserve(256);
 
-        for _ in 0..TRIES * 2 {
-            for i in 0..N / 2 {
-                producer1.push(i, &global_queue);
-            }
+        // The queue grows while the indexes pass `u32::MAX`.
+        for i in 0..300 {
+            producer.maybe_push(i).unwrap();
+        }
 
-            consumer.steal_into(&mut producer2);
+        if cfg!(feature = "wide_unbounded_index") {
+            assert!(unsafe { producer.inner.unsync_load_tail() } > UnboundedIndex::from(u32::MAX));
+        }
 
-            while let Some(task) = producer2.pop() {
-                stolen.push_back(task);
-            }
+        assert_eq!(producer.len(), 300);
+        assert_eq!(consumer.len(), 300);
+        assert_eq!(consumer.steal_into(&producer2), 150);
 
-            assert!(global_queue.is_empty());
+        for i in 0..150 {
+            assert_eq!(producer2.pop(), Some(i));
         }
 
-        assert!(producer2.is_empty());
+        let mut slice = [MaybeUninit::uninit(); 100];
 
-        let mut count = 0;
+        assert_eq!(consumer.pop_many(&mut slice), 100);
 
-        while let Some(_) = producer1.pop() {
-            count += 1;
+        for (i, value) in slice[..100].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, 150 + i);
         }
 
-        assert_eq!(count + stolen.len(), N * TRIES);
+        for i in 250..300 {
+            assert_eq!(producer.pop(), Some(i));
+        }
+
+        assert_eq!(producer.pop(), None);
     }
 
     #[test]
-    fn test_spmc_unbounded_many() {
-        const BATCH_SIZE: usize = 30;
-        const N: usize = BATCH_SIZE * 100;
-
-        let global_queue = MutexVecQueue::new();
-        let (producer, consumer) = new_unbounded();
+    fn test_spmc_unbounded_auto_shrink_multi_threaded() {
+        const CONSUMERS: usize = 3;
+        const ROUNDS: usize = 200;
+        const BURST: usize = BATCH_SIZE * 20;
+
+        let (producer, consumer) =
+            new_unbounded_with_growth_policy(GrowthPolicy::new().with_shrink_after(4));
+        let popped = std::sync::Mutex::new(Vec::new());
+        let finished = std::sync::atomic::AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            for _ in 0..CONSUMERS {
+                let consumer = consumer.clone();
+                let popped = &popped;
+                let finished = &finished;
+
+                scope.spawn(move || {
+                    let mut local = Vec::new();
+                    let mut slice = [MaybeUninit::uninit(); 8];
+
+                    while !finished.load(Acquire) {
+                        let n = consumer.pop_many(&mut slice);
+
+                        if n == 0 {
+                            std::thread::yield_now();
+                        }
 
-        for i in 0..N / BATCH_SIZE / 2 {
-            let slice = (0..BATCH_SIZE)
-                .map(|j| i * BATCH_SIZE + j)
-                .collect::<Vec<_>>();
+                        for value in &slice[..n] {
+                            local.push(unsafe { value.assume_init() });
+                        }
+                    }
 
-            unsafe {
-                producer.maybe_push_many(&*slice).unwrap();
+                    popped.lock().unwrap().append(&mut local);
+                });
             }
 
-            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
-            producer.pop_many(slice.as_mut_slice());
-
-            for j in 0..BATCH_SIZE {
-                let index = i * BATCH_SIZE + j;
-
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
-            }
-        }
+            // Bursts grow the queue, and the producer pops to shrink it while it is sparse.
+            for round in 0..ROUNDS {
+                for i in 0..BURST {
+                    producer.push(round * BURST + i, &PanickingReceiver);
+                }
 
-        for i in 0..N / BATCH_SIZE / 2 {
-            let slice = (0..BATCH_SIZE)
-                .map(|j| i * BATCH_SIZE + j)
-                .collect::<Vec<_>>();
+                while producer.len() > BATCH_SIZE {
+                    std::thread::yield_now();
+                }
 
-            unsafe {
-                producer.push_many(&*slice, &global_queue);
+                while let Some(value) = producer.pop() {
+                    popped.lock().unwrap().push(value);
+                }
             }
 
-            assert!(global_queue.is_empty());
+            finished.store(true, Release);
+        });
 
-            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
-            
-            consumer.pop_many(slice.as_mut_slice());
+        let mut popped = popped.into_inner().unwrap();
 
-            for j in 0..BATCH_SIZE {
-                let index = i * BATCH_SIZE + j;
+        popped.sort_unstable();
 
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
-            }
-        }
+        assert_eq!(popped, (0..ROUNDS * BURST).collect::<Vec<_>>());
     }
 }
@@ -5,12 +5,17 @@
     clippy::cast_possible_truncation,
     reason = "LongNumber should be synonymous to usize"
 )]
-use crate::hints::unlikely;
+use crate::alloc_error::{try_alloc_array, AllocError};
+use crate::backoff::Backoff;
+use crate::const_bounded_ring::ConstBoundedRing;
+use crate::hints::{likely, unlikely};
 use crate::light_arc::LightArc;
 use crate::number_types::{
     CachePaddedLongAtomic, LongAtomic, LongNumber, NotCachePaddedLongAtomic,
 };
+use crate::queue_builder::DEFAULT_STEAL_THRESHOLD;
 use crate::spsc::{Consumer, Producer};
+use crate::sync_batch_receiver::SyncBatchReceiver;
 use std::marker::PhantomData;
 use std::mem::{needs_drop, MaybeUninit};
 use std::ops::Deref;
@@ -58,20 +63,56 @@ pub struct SPSCBoundedQueue<
     tail: AtomicWrapper,
     head: AtomicWrapper,
     buffer: *mut [MaybeUninit<T>; CAPACITY],
+    /// Consumers don't steal less than this number of values at once.
+    steal_threshold: usize,
+    /// The epoch of the overflow handling.
+    /// It is odd when the producer requests to move values from the head on overflow.
+    /// Only the producer can change it.
+    overflow_requested: LongAtomic,
+    /// The last `overflow_requested` the consumer has seen.
+    /// The consumer updates the head with CAS while it is odd,
+    /// so the producer can move values from the head only when they are equal.
+    /// Only the consumer can change it.
+    overflow_acked: LongAtomic,
 }
 
 impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default>
     SPSCBoundedQueue<T, CAPACITY, AtomicWrapper>
 {
     /// Creates a new [`SPSCBoundedQueue`].
+    ///
+    /// It calls [`handle_alloc_error`](std::alloc::handle_alloc_error) if the allocation fails.
     pub fn new() -> Self {
+        Self::with_steal_threshold(DEFAULT_STEAL_THRESHOLD)
+    }
+
+    /// Creates a new [`SPSCBoundedQueue`] or returns the [`AllocError`] if the allocation fails.
+    pub fn try_new() -> Result<Self, AllocError> {
+        Self::try_with_steal_threshold(DEFAULT_STEAL_THRESHOLD)
+    }
+
+    /// Creates a new [`SPSCBoundedQueue`] whose consumers
+    /// don't steal less than `steal_threshold` values at once.
+    pub(crate) fn with_steal_threshold(steal_threshold: usize) -> Self {
+        Self::try_with_steal_threshold(steal_threshold).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Creates a new [`SPSCBoundedQueue`] whose consumers
+    /// don't steal less than `steal_threshold` values at once,
+    /// or returns the [`AllocError`] if the allocation fails.
+    pub(crate) fn try_with_steal_threshold(steal_threshold: usize) -> Result<Self, AllocError> {
         debug_assert!(size_of::<MaybeUninit<T>>() == size_of::<T>()); // Assume that we can just cast it
 
-        Self {
-            buffer: Box::into_raw(Box::new([const { MaybeUninit::uninit() }; CAPACITY])),
+        Ok(Self {
+            buffer: try_alloc_array::<MaybeUninit<T>>(CAPACITY)?
+                .as_ptr()
+                .cast(),
             tail: AtomicWrapper::default(),
             head: AtomicWrapper::default(),
-        }
+            steal_threshold,
+            overflow_requested: LongAtomic::new(0),
+            overflow_acked: LongAtomic::new(0),
+        })
     }
 
     /// Returns the capacity of the queue.
@@ -79,21 +120,23 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
     pub fn capacity(&self) -> usize {
         CAPACITY
     }
+}
 
-    /// Returns a pointer to the buffer.
-    fn buffer_thin_ptr(&self) -> *const MaybeUninit<T> {
-        unsafe { &*self.buffer }.as_ptr()
+impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default>
+    ConstBoundedRing<T> for SPSCBoundedQueue<T, CAPACITY, AtomicWrapper>
+{
+    const CAPACITY: usize = CAPACITY;
+
+    fn head(&self) -> &LongAtomic {
+        &self.head
     }
 
-    /// Returns a mutable pointer to the buffer.
-    fn buffer_mut_thin_ptr(&self) -> *mut MaybeUninit<T> {
-        unsafe { &mut *self.buffer }.as_mut_ptr()
+    fn tail(&self) -> &LongAtomic {
+        &self.tail
     }
 
-    /// Returns the number of elements in the queue.
-    #[inline]
-    fn len(head: LongNumber, tail: LongNumber) -> usize {
-        tail.wrapping_sub(head) as usize
+    fn buffer_mut_thin_ptr(&self) -> *mut MaybeUninit<T> {
+        unsafe { &mut *self.buffer }.as_mut_ptr()
     }
 }
 
@@ -101,30 +144,6 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
 impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Default>
     SPSCBoundedQueue<T, CAPACITY, AtomicWrapper>
 {
-    /// Pushes a slice into the queue. Returns a new tail (not index).
-    fn copy_slice(buffer_ptr: *mut T, start_tail: LongNumber, slice: &[T]) -> LongNumber {
-        let tail_idx = start_tail as usize % CAPACITY;
-
-        if tail_idx + slice.len() <= CAPACITY {
-            unsafe {
-                ptr::copy_nonoverlapping(slice.as_ptr(), buffer_ptr.add(tail_idx), slice.len());
-            };
-        } else {
-            let right = CAPACITY - tail_idx;
-
-            unsafe {
-                ptr::copy_nonoverlapping(slice.as_ptr(), buffer_ptr.add(tail_idx), right);
-                ptr::copy_nonoverlapping(
-                    slice.as_ptr().add(right),
-                    buffer_ptr,
-                    slice.len() - right,
-                );
-            }
-        }
-
-        start_tail.wrapping_add(slice.len() as LongNumber)
-    }
-
     /// Return the number of elements in the queue.
     ///
     /// # Safety
@@ -154,6 +173,142 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
         self.tail.store(tail.wrapping_add(1), Release);
     }
 
+    /// Returns whether the producer can move values from the head.
+    ///
+    /// The consumer updates the head without CAS to keep the pops cheap,
+    /// so the producer requests it to use CAS and moves values from the head
+    /// only after the consumer has acknowledged the request.
+    ///
+    /// The consumer acknowledges the request on its next pop or steal,
+    /// so the producer waits for it with a bounded backoff when it makes the request.
+    fn producer_acquire_head(&self) -> bool {
+        let mut requested = unsafe { self.overflow_requested.unsync_load() }; // only producer can change it
+
+        if requested % 2 == 1 {
+            return self.overflow_acked.load(Acquire) == requested;
+        }
+
+        requested = requested.wrapping_add(1);
+
+        self.overflow_requested.store(requested, Release);
+
+        let backoff = Backoff::new();
+
+        loop {
+            if self.overflow_acked.load(Acquire) == requested {
+                return true;
+            }
+
+            if backoff.is_completed() {
+                // The consumer is idle, the request stays until its next pop.
+                return false;
+            }
+
+            backoff.spin();
+        }
+    }
+
+    /// Lets the consumer update the head without CAS again.
+    ///
+    /// It should be called after the producer has moved values from the head.
+    fn producer_release_head(&self) {
+        let requested = unsafe { self.overflow_requested.unsync_load() }; // only producer can change it
+
+        debug_assert!(requested % 2 == 1);
+
+        self.overflow_requested
+            .store(requested.wrapping_add(1), Release);
+    }
+
+    /// Moves a half of the queue and one value to the [`SyncBatchReceiver`]
+    /// if the consumer has acknowledged the overflow request,
+    /// or moves only the value if the consumer is idle.
+    #[inline(never)]
+    #[cold]
+    fn handle_overflow_one_or_request<SBR: SyncBatchReceiver<T>>(
+        &self,
+        tail: LongNumber,
+        head: LongNumber,
+        sbr: &SBR,
+        value: T,
+    ) {
+        if !self.producer_acquire_head() {
+            sbr.push_many_and_one(&[], &[], value);
+
+            return;
+        }
+
+        let _ = self.handle_overflow_one(
+            tail,
+            head,
+            Self::NUM_VALUES_TAKEN as usize,
+            |first, last, value| {
+                sbr.push_many_and_one(first, last, value);
+
+                Ok(())
+            },
+            value,
+        );
+
+        self.producer_release_head();
+    }
+
+    /// Moves a half of the queue and many values to the [`SyncBatchReceiver`]
+    /// if the consumer has acknowledged the overflow request,
+    /// or moves only the values if the consumer is idle.
+    #[inline(never)]
+    #[cold]
+    fn handle_overflow_many_or_request<SBR: SyncBatchReceiver<T>>(
+        &self,
+        tail: LongNumber,
+        head: LongNumber,
+        sbr: &SBR,
+        slice: &[T],
+    ) {
+        if !self.producer_acquire_head() {
+            sbr.push_many_and_slice(&[], &[], slice);
+
+            return;
+        }
+
+        let _ = self.handle_overflow_many(
+            tail,
+            head,
+            Self::NUM_VALUES_TAKEN as usize,
+            |first, last, slice| {
+                sbr.push_many_and_slice(first, last, slice);
+
+                Ok(())
+            },
+            slice,
+        );
+
+        self.producer_release_head();
+    }
+
+    /// Pushes a value to the queue or to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push<SBR: SyncBatchReceiver<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+    ) {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) == CAPACITY) {
+            self.handle_overflow_one_or_request(tail, head, sync_batch_receiver, value);
+
+            return;
+        }
+
+        unsafe { self.push_unchecked(value, tail) };
+    }
+
     /// Pushes a value to the queue or returns an error.
     ///
     /// # Safety
@@ -222,6 +377,31 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
 
         Ok(())
     }
+
+    /// Pushes many values to the queue or to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+    ) {
+        let head = self.head.load(Acquire);
+        let mut tail = unsafe { self.tail.unsync_load() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) + slice.len() > CAPACITY) {
+            self.handle_overflow_many_or_request(tail, head, sync_batch_receiver, slice);
+
+            return;
+        }
+
+        tail = Self::copy_slice(self.buffer_mut_thin_ptr().cast(), tail, slice);
+
+        self.tail.store(tail, Release);
+    }
 }
 
 // Consumers
@@ -235,12 +415,30 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
     /// The called should be the only consumer.
     #[inline]
     pub unsafe fn consumer_len(&self) -> usize {
+        // The producer can move values to the `SyncBatchReceiver`, so we load the head first
+        // to never get the head that is greater than the tail.
+        let head = self.head.load(Relaxed);
         let tail = self.tail.load(Relaxed);
-        let head = unsafe { self.head.unsync_load() }; // only consumer can change head
 
         Self::len(head, tail)
     }
 
+    /// Acknowledges the last overflow request of the producer
+    /// and returns whether the head should be updated with CAS.
+    ///
+    /// It should be called before the consumer loads the head.
+    #[inline]
+    fn consumer_ack_overflow_request(&self) -> bool {
+        let requested = self.overflow_requested.load(Acquire);
+
+        if unlikely(requested != unsafe { self.overflow_acked.unsync_load() }) {
+            // only consumer can change it
+            self.overflow_acked.store(requested, Release);
+        }
+
+        requested % 2 == 1
+    }
+
     /// Pops many values from the queue to the `dst`.
     /// Returns the number of values popped.
     ///
@@ -249,105 +447,160 @@ impl<T, const CAPACITY: usize, AtomicWrapper: Deref<Target = LongAtomic> + Defau
     /// The called should be the only consumer.
     #[inline]
     pub unsafe fn consumer_pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
-        let tail = self.tail.load(Acquire);
-        let head = unsafe { self.head.unsync_load() }; // only consumer can change head
-        let available = Self::len(head, tail);
-        let n = dst.len().min(available);
+        // The producer can move values to the `SyncBatchReceiver` on overflow,
+        // so the head should be loaded before the tail and be updated with CAS
+        // while the producer can do it.
+        let is_head_shared = self.consumer_ack_overflow_request();
+        let mut head = self.head.load(Acquire);
+        let mut tail = self.tail.load(Acquire);
+
+        loop {
+            let available = Self::len(head, tail);
+            let n = dst.len().min(available);
+
+            if n == 0 {
+                return 0;
+            }
 
-        if n == 0 {
-            return 0;
-        }
+            let dst_ptr = dst.as_mut_ptr();
+            let head_idx = head as usize % CAPACITY;
+            let right = CAPACITY - head_idx;
 
-        let dst_ptr = dst.as_mut_ptr();
-        let head_idx = head as usize % CAPACITY;
-        let right = CAPACITY - head_idx;
+            // We optimistically copy the values from the buffer into the dst.
+            // On CAS failure, we forget the copied values and try again.
 
-        if n <= right {
-            // No wraparound, copy in one shot
-            unsafe {
-                ptr::copy_nonoverlapping(self.buffer_thin_ptr().add(head_idx), dst_ptr, n);
-            }
-        } else {
-            unsafe {
-                // Wraparound: copy right half then left half
-                ptr::copy_nonoverlapping(self.buffer_thin_ptr().add(head_idx), dst_ptr, right);
-                ptr::copy_nonoverlapping(self.buffer_thin_ptr(), dst_ptr.add(right), n - right);
+            if n <= right {
+                // No wraparound, copy in one shot
+                unsafe {
+                    ptr::copy_nonoverlapping(self.buffer_thin_ptr().add(head_idx), dst_ptr, n);
+                }
+            } else {
+                unsafe {
+                    // Wraparound: copy right half then left half
+                    ptr::copy_nonoverlapping(self.buffer_thin_ptr().add(head_idx), dst_ptr, right);
+                    ptr::copy_nonoverlapping(self.buffer_thin_ptr(), dst_ptr.add(right), n - right);
+                }
             }
-        }
 
-        self.head.store(head.wrapping_add(n as LongNumber), Release);
+            if likely(!is_head_shared) {
+                self.head.store(head.wrapping_add(n as LongNumber), Release);
+
+                return n;
+            }
 
-        n
+            // CAS is strong, because we don't want to recopy the values
+            match self.head.compare_exchange(
+                head,
+                head.wrapping_add(n as LongNumber),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => return n,
+                Err(actual_head) => {
+                    // The producer has moved the values to the `SyncBatchReceiver`, retry
+                    head = actual_head;
+                    tail = self.tail.load(Acquire);
+                }
+            }
+        }
     }
 
     /// Steals many values from the consumer to the `dst`.
     /// Returns the number of values stolen.
     ///
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
+    ///
     /// # Safety
     ///
     /// The called should be the only consumer.
-    ///
-    /// # Panics
-    ///
-    /// If `dst` is not empty.
     pub unsafe fn steal_into(&self, dst: &Self) -> usize {
-        let src_tail = self.tail.load(Acquire);
-        let src_head = unsafe { self.head.unsync_load() }; // only consumer can change head
+        // The producer can move values to the `SyncBatchReceiver` on overflow,
+        // so the head should be loaded before the tail and be updated with CAS
+        // while the producer can do it.
+        let is_head_shared = self.consumer_ack_overflow_request();
+        let mut src_head = self.head.load(Acquire);
         let dst_tail = unsafe { dst.tail.unsync_load() }; // only producer can change tail
 
-        if cfg!(debug_assertions) {
-            let dst_head = dst.head.load(Relaxed);
-
-            assert_eq!(
-                dst_head, dst_tail,
-                "steal_into should not be called when dst is not empty"
-            );
-        }
+        // The consumer of the `dst` can only increase the number of free slots,
+        // so it is safe to load the head only once.
+        let dst_free_slots = CAPACITY - Self::len(dst.head.load(Acquire), dst_tail);
+
+        loop {
+            let src_tail = self.tail.load(Acquire);
+            let n = Self::len(src_head, src_tail) / 2;
+            if n < self.steal_threshold || n == 0 {
+                // we don't steal less than the threshold (4 by default)
+                // because else we may lose more because of cache locality and NUMA awareness
+                return 0;
+            }
 
-        let n = Self::len(src_head, src_tail) / 2;
-        if !cfg!(feature = "always_steal") && n < 4 || n == 0 {
-            // we don't steal less than 4 by default
-            // because else we may lose more because of cache locality and NUMA awareness
-            return 0;
-        }
+            let n = n.min(dst_free_slots);
+            if n == 0 {
+                return 0;
+            }
 
-        let src_head_idx = src_head as usize % CAPACITY;
+            let src_head_idx = src_head as usize % CAPACITY;
+
+            let (src_right, src_left): (&[T], &[T]) = unsafe {
+                let right_occupied = CAPACITY - src_head_idx;
+                if n <= right_occupied {
+                    (
+                        slice::from_raw_parts(self.buffer_thin_ptr().add(src_head_idx).cast(), n),
+                        &[],
+                    )
+                } else {
+                    (
+                        slice::from_raw_parts(
+                            self.buffer_thin_ptr().add(src_head_idx).cast(),
+                            right_occupied,
+                        ),
+                        slice::from_raw_parts(self.buffer_thin_ptr().cast(), n - right_occupied),
+                    )
+                }
+            };
 
-        let (src_right, src_left): (&[T], &[T]) = unsafe {
-            let right_occupied = CAPACITY - src_head_idx;
-            if n <= right_occupied {
-                (
-                    slice::from_raw_parts(self.buffer_thin_ptr().add(src_head_idx).cast(), n),
-                    &[],
-                )
-            } else {
-                (
-                    slice::from_raw_parts(
-                        self.buffer_thin_ptr().add(src_head_idx).cast(),
-                        right_occupied,
-                    ),
-                    slice::from_raw_parts(self.buffer_thin_ptr().cast(), n - right_occupied),
-                )
-            }
-        };
+            // We optimistically copy the values from the buffer into the dst.
+            // On CAS failure, we forget the copied values and try again.
+            Self::copy_slice(
+                dst.buffer_mut_thin_ptr().cast::<T>(),
+                dst_tail % CAPACITY as LongNumber,
+                src_right,
+            );
+            Self::copy_slice(
+                dst.buffer_mut_thin_ptr().cast::<T>(),
+                (dst_tail.wrapping_add(src_right.len() as LongNumber)) % CAPACITY as LongNumber,
+                src_left,
+            );
 
-        Self::copy_slice(
-            dst.buffer_mut_thin_ptr().cast::<T>(),
-            dst_tail % CAPACITY as LongNumber,
-            src_right,
-        );
-        Self::copy_slice(
-            dst.buffer_mut_thin_ptr().cast::<T>(),
-            (dst_tail.wrapping_add(src_right.len() as LongNumber)) % CAPACITY as LongNumber,
-            src_left,
-        );
+            if likely(!is_head_shared) {
+                self.head
+                    .store(src_head.wrapping_add(n as LongNumber), Release);
+                dst.tail
+                    .store(dst_tail.wrapping_add(n as LongNumber), Release);
 
-        self.head
-            .store(src_head.wrapping_add(n as LongNumber), Release);
-        dst.tail
-            .store(dst_tail.wrapping_add(n as LongNumber), Release);
+                return n;
+            }
 
-        n
+            // CAS is strong, because we don't want to recopy the values
+            match self.head.compare_exchange(
+                src_head,
+                src_head.wrapping_add(n as LongNumber),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => {
+                    dst.tail
+                        .store(dst_tail.wrapping_add(n as LongNumber), Release);
+
+                    return n;
+                }
+                Err(current_head) => {
+                    // The producer has moved the values to the `SyncBatchReceiver`, retry
+                    src_head = current_head;
+                }
+            }
+        }
     }
 }
 
@@ -411,6 +664,25 @@ macro_rules! generate_spsc_producer_and_consumer {
             _non_sync: PhantomData<*const ()>,
         }
 
+        impl<T, const CAPACITY: usize> $producer_name<T, CAPACITY> {
+            /// Creates a new queue whose consumers don't steal less than `steal_threshold`
+            /// values at once and returns its producer and consumer.
+            pub(crate) fn new_queue(steal_threshold: usize) -> (Self, $consumer_name<T, CAPACITY>) {
+                let queue = LightArc::new(SPSCBoundedQueue::with_steal_threshold(steal_threshold));
+
+                (
+                    Self {
+                        inner: queue.clone(),
+                        _non_sync: PhantomData,
+                    },
+                    $consumer_name {
+                        inner: queue,
+                        _non_sync: PhantomData,
+                    },
+                )
+            }
+        }
+
         impl<T: Send, const CAPACITY: usize> Producer<T> for $producer_name<T, CAPACITY> {
             #[inline]
             fn capacity(&self) -> usize {
@@ -422,6 +694,11 @@ macro_rules! generate_spsc_producer_and_consumer {
                 unsafe { self.inner.producer_len() }
             }
 
+            #[inline]
+            fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR) {
+                unsafe { self.inner.producer_push(value, sync_batch_receiver) };
+            }
+
             #[inline]
             fn maybe_push(&self, value: T) -> Result<(), T> {
                 unsafe { self.inner.producer_maybe_push(value) }
@@ -436,6 +713,15 @@ macro_rules! generate_spsc_producer_and_consumer {
             unsafe fn maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
                 unsafe { self.inner.producer_maybe_push_many(slice) }
             }
+
+            #[inline]
+            unsafe fn push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+            ) {
+                unsafe { self.inner.producer_push_many(slice, sync_batch_receiver) };
+            }
         }
 
         unsafe impl<T: Send, const CAPACITY: usize> Send for $producer_name<T, CAPACITY> {}
@@ -528,18 +814,7 @@ generate_spsc_producer_and_consumer!(SPSCProducer, SPSCConsumer);
 /// ```
 pub fn new_bounded<T, const CAPACITY: usize>(
 ) -> (SPSCProducer<T, CAPACITY>, SPSCConsumer<T, CAPACITY>) {
-    let queue = LightArc::new(SPSCBoundedQueue::new());
-
-    (
-        SPSCProducer {
-            inner: queue.clone(),
-            _non_sync: PhantomData,
-        },
-        SPSCConsumer {
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
+    SPSCProducer::new_queue(DEFAULT_STEAL_THRESHOLD)
 }
 
 generate_spsc_producer_and_consumer!(
@@ -594,24 +869,16 @@ pub fn new_cache_padded_bounded<T, const CAPACITY: usize>() -> (
     CachePaddedSPSCProducer<T, CAPACITY>,
     CachePaddedSPSCConsumer<T, CAPACITY>,
 ) {
-    let queue = LightArc::new(SPSCBoundedQueue::new());
-
-    (
-        CachePaddedSPSCProducer {
-            inner: queue.clone(),
-            _non_sync: PhantomData,
-        },
-        CachePaddedSPSCConsumer {
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
+    CachePaddedSPSCProducer::new_queue(DEFAULT_STEAL_THRESHOLD)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mutex_vec_queue::MutexVecQueue;
+    use crate::zst_test::{test_zst_queue, Zst};
     use std::collections::VecDeque;
+    use std::iter;
 
     const CAPACITY: usize = 256;
 
@@ -621,15 +888,20 @@ mod tests {
 
         assert_eq!(
             size_of_val(&queue),
-            size_of::<usize>() + size_of::<LongAtomic>() * 2
+            size_of::<usize>() * 2 + size_of::<LongAtomic>() * 4
         );
 
         let cache_padded_queue = SPSCBoundedQueue::<(), CAPACITY, CachePaddedLongAtomic>::new();
 
         assert_eq!(
             size_of_val(&cache_padded_queue),
-            size_of::<CachePaddedLongAtomic>() * 2 + size_of::<usize>()
+            size_of::<CachePaddedLongAtomic>() * 2
+                + size_of::<usize>() * 2
+                + size_of::<LongAtomic>() * 2
         );
+        let queue = SPSCBoundedQueue::<u64, CAPACITY>::try_new().unwrap();
+
+        assert_eq!(queue.capacity(), CAPACITY);
     }
 
     #[test]
@@ -651,6 +923,89 @@ mod tests {
         assert_eq!(consumer.capacity(), CAPACITY);
     }
 
+    #[test]
+    fn test_spsc_bounded_push_with_overflow() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_bounded::<_, CAPACITY>();
+
+        for i in 0..=CAPACITY {
+            producer.push(i, &global_queue);
+        }
+
+        // The consumer hasn't acknowledged the overflow request yet,
+        // so only the pushed value is moved.
+        assert_eq!(producer.len(), CAPACITY);
+        assert_eq!(global_queue.len(), 1);
+
+        assert_eq!(consumer.pop(), Some(0));
+
+        for i in CAPACITY + 1..CAPACITY + 10 {
+            producer.push(i, &global_queue);
+        }
+
+        assert_eq!(producer.len(), CAPACITY / 2 + 7);
+        assert_eq!(global_queue.len(), CAPACITY / 2 + 2);
+
+        let slice = (CAPACITY + 10..CAPACITY * 2).collect::<Vec<_>>();
+
+        unsafe { producer.push_many(&slice, &global_queue) };
+
+        assert_eq!(producer.len(), CAPACITY / 2 + 7);
+        assert_eq!(global_queue.len(), CAPACITY / 2 + 2 + slice.len());
+
+        assert_eq!(consumer.pop(), Some(CAPACITY / 2 + 1));
+
+        let slice = (CAPACITY * 2..CAPACITY * 2 + CAPACITY / 2).collect::<Vec<_>>();
+
+        unsafe { producer.push_many(&slice, &global_queue) };
+
+        assert_eq!(producer.len(), 6);
+
+        let expected = iter::once(CAPACITY)
+            .chain(1..=CAPACITY / 2)
+            .chain([CAPACITY + 2])
+            .chain(CAPACITY + 10..CAPACITY * 2)
+            .chain(CAPACITY / 2 + 2..CAPACITY)
+            .chain([CAPACITY + 1, CAPACITY + 3])
+            .chain(CAPACITY * 2..CAPACITY * 2 + CAPACITY / 2);
+
+        assert!(global_queue.drain().eq(expected));
+
+        for i in CAPACITY + 4..CAPACITY + 10 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_spsc_bounded_push_with_idle_consumer() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_bounded::<_, CAPACITY>();
+
+        for i in 0..CAPACITY {
+            producer.push(i, &global_queue);
+        }
+
+        for i in 0..10 {
+            producer.push(CAPACITY + i, &global_queue);
+
+            // The idle consumer doesn't acknowledge the overflow request,
+            // so each overflow moves only the pushed value.
+            assert_eq!(producer.len(), CAPACITY);
+            assert_eq!(global_queue.len(), i + 1);
+        }
+
+        // The pop acknowledges the request, so the next overflow moves a half of the queue.
+        assert_eq!(consumer.pop(), Some(0));
+
+        producer.push(CAPACITY + 10, &global_queue);
+        producer.push(CAPACITY + 11, &global_queue);
+
+        assert_eq!(producer.len(), CAPACITY / 2);
+        assert_eq!(global_queue.len(), 10 + CAPACITY / 2 + 1);
+    }
+
     #[test]
     fn test_spsc_bounded_stealing() {
         const TRIES: usize = 10;
@@ -676,13 +1031,52 @@ mod tests {
 
         let mut count = 0;
 
-        while let Some(_) = consumer1.pop() {
+        while consumer1.pop().is_some() {
             count += 1;
         }
 
         assert_eq!(count + stolen.len(), CAPACITY * TRIES);
     }
 
+    #[test]
+    fn test_spsc_bounded_stealing_into_non_empty() {
+        let (producer1, consumer1) = new_bounded::<_, CAPACITY>();
+        let (producer2, consumer2) = new_bounded::<_, CAPACITY>();
+
+        for i in 0..CAPACITY {
+            producer1.maybe_push(i).unwrap();
+        }
+
+        for i in 0..CAPACITY - 10 {
+            producer2.maybe_push(CAPACITY + i).unwrap();
+        }
+
+        assert_eq!(consumer1.steal_into(&producer2), 10);
+        assert_eq!(producer2.len(), CAPACITY);
+        assert_eq!(consumer1.steal_into(&producer2), 0);
+
+        for i in 0..CAPACITY - 10 {
+            assert_eq!(consumer2.pop(), Some(CAPACITY + i));
+        }
+
+        for i in 0..10 {
+            assert_eq!(consumer2.pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_spsc_bounded_zst() {
+        let (producer, consumer) = new_bounded::<Zst, CAPACITY>();
+
+        test_zst_queue(
+            Some(CAPACITY),
+            |value| producer.maybe_push(value),
+            |dst| consumer.pop_many(dst),
+            || consumer.len(),
+            || consumer.steal_into(&new_bounded().0),
+        );
+    }
+
     #[test]
     fn test_spsc_bounded_many() {
         const BATCH_SIZE: usize = 30;
@@ -696,16 +1090,16 @@ mod tests {
                 .collect::<Vec<_>>();
 
             unsafe {
-                producer.maybe_push_many(&*slice).unwrap();
+                producer.maybe_push_many(&slice).unwrap();
             }
 
             let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
             consumer.pop_many(slice.as_mut_slice());
 
-            for j in 0..BATCH_SIZE {
+            for (j, value) in slice.iter().enumerate() {
                 let index = i * BATCH_SIZE + j;
 
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
+                assert_eq!(unsafe { value.assume_init() }, index);
             }
         }
     }
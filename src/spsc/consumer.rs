@@ -41,7 +41,8 @@ pub trait Consumer<T> {
     /// Steals some values from the consumer and places them into `dst`.
     /// Returns the number of stolen values.
     ///
-    /// It requires that the other queue to be empty.
+    /// It steals no more than [`dst.free_slots()`](Producer::free_slots) values
+    /// and appends them after the values that are already in `dst`.
     /// Expected to steal the half of the queue,
     /// but other implementations may steal another number of values.
     fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize;
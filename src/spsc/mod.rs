@@ -13,9 +13,11 @@ mod consumer;
 mod producer;
 #[cfg(test)]
 mod tests;
+#[cfg(not(feature = "disable_unbounded"))]
 mod unbounded;
 
 pub use const_bounded::*;
 pub use consumer::*;
 pub use producer::*;
+#[cfg(not(feature = "disable_unbounded"))]
 pub use unbounded::*;
\ No newline at end of file
@@ -1,4 +1,5 @@
 //! This module provides the [`Producer`] trait for the single-producer, single-consumer queue.
+use crate::sync_batch_receiver::SyncBatchReceiver;
 
 /// A producer of the single-producer, single-consumer queue.
 ///
@@ -22,6 +23,18 @@ pub trait Producer<T> {
         self.capacity() - self.len()
     }
 
+    /// Pushes a value into the queue. If the queue is full, up to half of the queue values
+    /// are pushed into the global queue (or any other [`SyncBatchReceiver`]).
+    ///
+    /// The producer of a bounded queue can move values from the head only after the consumer
+    /// has acknowledged it on its pop or steal, and it waits for it only for a short bounded time.
+    /// Therefore, while the consumer is idle, each overflow moves only the pushed value.
+    ///
+    /// An unbounded queue grows instead, and once it has reached
+    /// the [`maximum capacity`](crate::GrowthPolicy::with_max_capacity),
+    /// it moves only the pushed value, because the consumer may be reading the oldest ones.
+    fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR);
+
     /// Pushes a value only if the queue is not full.
     /// It returns an error if the queue is full.
     fn maybe_push(&self, value: T) -> Result<(), T>;
@@ -43,4 +56,17 @@ pub trait Producer<T> {
     ///
     /// If the `T` is not `Copy`, the caller must [`forget`](core::mem::forget) the provided slice.
     unsafe fn maybe_push_many(&self, slice: &[T]) -> Result<(), ()>;
+
+    /// Pushes a slice of value into the queue.
+    /// If the queue doesn't have enough space, up to half of the queue values
+    /// are pushed into the global queue (or any other [`SyncBatchReceiver`]).
+    ///
+    /// Like in [`push`](Self::push), while the consumer of a bounded queue is idle
+    /// or an unbounded queue has reached the maximum capacity,
+    /// each overflow moves only the pushed values.
+    ///
+    /// # Safety
+    ///
+    /// If the `T` is not `Copy`, the caller must [`forget`](core::mem::forget) the provided slice.
+    unsafe fn push_many<SBR: SyncBatchReceiver<T>>(&self, values: &[T], sync_batch_receiver: &SBR);
 }
@@ -1,6 +1,10 @@
 use crate::backoff::Backoff;
 use crate::loom_bindings::thread::yield_now;
-use crate::spsc::{new_bounded, new_cache_padded_bounded, new_cache_padded_unbounded, new_unbounded, Consumer as ConsumerExt, Producer as ProducerExt};
+use crate::mutex_vec_queue::MutexVecQueue;
+use crate::spmc::Producer as SPMCProducerExt;
+use crate::spsc::{new_bounded, new_cache_padded_bounded, Consumer as ConsumerExt, Producer as ProducerExt};
+#[cfg(not(feature = "disable_unbounded"))]
+use crate::spsc::{new_cache_padded_unbounded, new_unbounded};
 use crate::test_lock::TEST_LOCK;
 use std::mem::MaybeUninit;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -56,7 +60,7 @@ where
 
         'outer: loop {
             for _ in 0..RAND.fetch_add(1, Ordering::Relaxed) % 1000 {
-                while let Err(_) = producer.maybe_push(TestValue::new(i)) {}
+                while producer.maybe_push(TestValue::new(i)).is_err() {}
 
                 i += 1;
 
@@ -123,8 +127,8 @@ where
         }
     }
 
-    for i in 0..check_to {
-        assert_eq!(stats[i], 1, "stats[{i}] = {}", stats[i]);
+    for (i, &count) in stats[..check_to].iter().enumerate() {
+        assert_eq!(count, 1, "stats[{i}] = {count}");
     }
 }
 
@@ -144,8 +148,8 @@ where
         for _ in 0..N {
             let popped = consumer.pop_many(&mut slice);
 
-            for i in 0..popped {
-                let res = count.fetch_add(unsafe { *slice[i].assume_init() }, Ordering::Relaxed);
+            for value in &slice[..popped] {
+                let res = count.fetch_add(unsafe { *value.assume_init() }, Ordering::Relaxed);
 
                 if res == RES {
                     break;
@@ -168,8 +172,8 @@ where
 
     let mut slice = [TestValue(0); BATCH_SIZE];
     for i in 0..N / BATCH_SIZE {
-        for j in 0..BATCH_SIZE {
-            slice[j] = TestValue(i * BATCH_SIZE + j);
+        for (j, value) in slice.iter_mut().enumerate() {
+            *value = TestValue(i * BATCH_SIZE + j);
         }
 
         let backoff = Backoff::new();
@@ -184,6 +188,83 @@ where
     assert_eq!(count.load(Ordering::Relaxed), RES);
 }
 
+fn test_spsc_multi_threaded_push_with_overflow<Producer, Consumer>(
+    creator: fn() -> (Producer, Consumer),
+) where
+    Producer: ProducerExt<TestValue<usize>> + Send + 'static,
+    Consumer: ConsumerExt<TestValue<usize>, AssociatedProducer = Producer> + Send + 'static,
+{
+    const N: usize = if cfg!(miri) { 200 } else { 1_000_000 };
+    const BATCH_SIZE: usize = 5;
+
+    let (producer, consumer) = creator();
+
+    // Producer thread.
+    //
+    // Push all numbers from 0 to N, overflowing to the global queue.
+    let t0 = spawn(move || {
+        let global_queue = MutexVecQueue::new();
+        let mut slice = [TestValue(0); BATCH_SIZE];
+
+        for i in 0..N / BATCH_SIZE / 2 {
+            producer.push(TestValue::new(i * BATCH_SIZE * 2), &global_queue);
+
+            for j in 1..BATCH_SIZE {
+                slice[j - 1] = TestValue::new(i * BATCH_SIZE * 2 + j);
+            }
+
+            unsafe { producer.push_many(&slice[..BATCH_SIZE - 1], &global_queue) };
+
+            for j in BATCH_SIZE..BATCH_SIZE * 2 {
+                producer.push(TestValue::new(i * BATCH_SIZE * 2 + j), &global_queue);
+            }
+        }
+
+        global_queue
+    });
+
+    // Consumer thread.
+    //
+    // Pop values until the producer is done.
+    let t1 = spawn(move || {
+        let mut stats = vec![0; N];
+        let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+
+        for _ in 0..N / BATCH_SIZE {
+            let popped = consumer.pop_many(&mut slice);
+
+            for i in 0..popped {
+                stats[*unsafe { slice[i].assume_init() }] += 1;
+            }
+
+            yield_now();
+        }
+
+        (stats, consumer)
+    });
+
+    let global_queue = t0.join().unwrap();
+    let (mut stats, consumer) = t1.join().unwrap();
+
+    while let Some(value) = consumer.pop() {
+        stats[*value] += 1;
+    }
+
+    let (checker, _) = crate::spmc::new_bounded::<_, 256>();
+
+    while !global_queue.is_empty() {
+        global_queue.move_batch_to_producer(&checker, 256);
+
+        while let Some(value) = SPMCProducerExt::pop(&checker) {
+            stats[*value] += 1;
+        }
+    }
+
+    for (i, &count) in stats.iter().enumerate() {
+        assert_eq!(count, 1, "stats[{i}] = {count}");
+    }
+}
+
 #[test]
 fn test_bounded_spsc_multi_threaded_steal() {
     let test_guard = TEST_LOCK.lock();
@@ -197,6 +278,7 @@ fn test_bounded_spsc_multi_threaded_steal() {
     drop(test_guard);
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 #[test]
 fn test_unbounded_spsc_multi_threaded_steal() {
     let test_guard = TEST_LOCK.lock();
@@ -235,6 +317,7 @@ fn test_bounded_spsc_multi_threaded_pop_many() {
     drop(test_guard);
 }
 
+#[cfg(not(feature = "disable_unbounded"))]
 #[test]
 fn test_unbounded_spsc_multi_threaded_pop_many() {
     let test_guard = TEST_LOCK.lock();
@@ -247,3 +330,16 @@ fn test_unbounded_spsc_multi_threaded_pop_many() {
 
     drop(test_guard);
 }
+
+#[test]
+fn test_bounded_spsc_multi_threaded_push_with_overflow() {
+    let test_guard = TEST_LOCK.lock();
+
+    test_spsc_multi_threaded_push_with_overflow(new_bounded::<TestValue<usize>, 256>);
+
+    println!("Non cache padded done, start cache padded");
+
+    test_spsc_multi_threaded_push_with_overflow(new_cache_padded_bounded::<TestValue<usize>, 256>);
+
+    drop(test_guard);
+}
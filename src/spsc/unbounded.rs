@@ -4,68 +4,69 @@
     clippy::cast_possible_truncation,
     reason = "LongNumber should be synonymous to usize"
 )]
-use crate::cache_padded::{CachePaddedAtomicU32, CachePaddedAtomicU64};
+use crate::alloc_error::{try_alloc_array, AllocError};
+use crate::backoff::Backoff;
+use crate::cache_padded::CachePaddedAtomicU64;
+use crate::growth_policy::GrowthPolicy;
 use crate::hints::{cold_path, unlikely};
 use crate::light_arc::LightArc;
-use crate::loom_bindings::sync::atomic::{AtomicU32, AtomicU64};
+use crate::loom_bindings::sync::atomic::AtomicU64;
 use crate::naive_rw_lock::NaiveRWLock;
-use crate::number_types::{NotCachePaddedAtomicU32, NotCachePaddedAtomicU64};
+use crate::number_types::{
+    AtomicUnboundedIndex, CachePaddedAtomicUnboundedIndex, NotCachePaddedAtomicU64,
+    NotCachePaddedAtomicUnboundedIndex, UnboundedIndex,
+};
+use crate::queue_builder::DEFAULT_STEAL_THRESHOLD;
 use crate::spsc::{Consumer, Producer};
-use std::alloc::{alloc, Layout};
+use crate::sync_batch_receiver::SyncBatchReceiver;
+use crate::tail_and_version::TailAndVersion;
 use std::cell::UnsafeCell;
 use std::marker::PhantomData;
-use std::mem::{needs_drop, MaybeUninit};
+use std::mem::{self, needs_drop, MaybeUninit};
 use std::ops::Deref;
 use std::sync::atomic::Ordering;
 use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
 use std::{ptr, slice};
 
-/// Packs the version and the tail into a single 64-bit value.
-#[inline(always)]
-fn pack_version_and_tail(version: u32, tail: u32) -> u64 {
-    (u64::from(version) << 32) | u64::from(tail)
-}
-
-/// Unpacks the version and the tail from a single 64-bit value.
-#[inline(always)]
-fn unpack_version_and_tail(value: u64) -> (u32, u32) {
-    ((value >> 32) as u32, value as u32)
-}
-
 /// A version of the ring-based queue.
 #[repr(C)]
 struct Version<T> {
     ptr: *mut [MaybeUninit<T>],
-    mask: u32,
+    mask: UnboundedIndex,
     id: u32,
 }
 
 impl<T> Version<T> {
     /// Returns the mask for the capacity of the underlying buffer.
     #[inline(always)]
-    fn mask(&self) -> u32 {
+    fn mask(&self) -> UnboundedIndex {
         self.mask
     }
 
     /// Allocates a new version with the given `capacity` and `id`.
-    fn alloc_new(capacity: usize, id: u32) -> LightArc<Self> {
+    fn try_alloc_new(capacity: usize, id: u32) -> Result<LightArc<Self>, AllocError> {
         debug_assert!(
-            capacity > 0 && u32::try_from(capacity).is_ok() && capacity.is_power_of_two()
+            capacity > 0
+                && UnboundedIndex::try_from(capacity).is_ok()
+                && capacity.is_power_of_two()
         );
 
-        let slice_ptr = unsafe {
-            slice::from_raw_parts_mut(
-                alloc(Layout::array::<MaybeUninit<T>>(capacity).unwrap_unchecked())
-                    .cast::<MaybeUninit<T>>(),
-                capacity,
-            )
-        };
+        let slice_ptr = ptr::slice_from_raw_parts_mut(
+            try_alloc_array::<MaybeUninit<T>>(capacity)?.as_ptr(),
+            capacity,
+        );
 
-        LightArc::new(Self {
+        Ok(LightArc::new(Self {
             ptr: slice_ptr,
-            mask: (capacity - 1) as u32,
+            mask: (capacity - 1) as UnboundedIndex,
             id,
-        })
+        }))
+    }
+
+    /// Allocates a new version with the given `capacity` and `id`
+    /// and [`handles`](AllocError::handle) the error.
+    fn alloc_new(capacity: usize, id: u32) -> LightArc<Self> {
+        Self::try_alloc_new(capacity, id).unwrap_or_else(|err| err.handle())
     }
 
     /// Returns a raw pointer to the underlying buffer.
@@ -85,7 +86,7 @@ impl<T> Drop for Version<T> {
 #[repr(C)]
 struct CachedVersion<T> {
     ptr: *const [MaybeUninit<T>],
-    mask: u32,
+    mask: UnboundedIndex,
     id: u32,
     /// Needs to be dropped to release the memory.
     real: LightArc<Version<T>>,
@@ -116,7 +117,7 @@ impl<T> CachedVersion<T> {
 
     /// Returns the mask for the capacity of the underlying buffer.
     #[inline(always)]
-    fn mask(&self) -> u32 {
+    fn mask(&self) -> UnboundedIndex {
         self.mask
     }
 
@@ -144,58 +145,119 @@ impl<T> Clone for CachedVersion<T> {
     }
 }
 
+/// The cursor of the consumer of the [`SPSCUnboundedQueue`].
+///
+/// It caches the version of the buffer that the consumer reads,
+/// so the consumer doesn't load the version for every operation.
+/// It is created by [`SPSCUnboundedQueue::consumer_cursor`]
+/// and should be used only with the same queue.
+pub struct ConsumerCursor<T> {
+    version: CachedVersion<T>,
+}
+
+impl<T> Clone for ConsumerCursor<T> {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version.clone(),
+        }
+    }
+}
+
+#[allow(
+    clippy::non_send_fields_in_send_ty,
+    reason = "The cursor only points to the buffer of the queue"
+)]
+unsafe impl<T: Send> Send for ConsumerCursor<T> {}
+
 /// The single-producer, single-consumer ring-based _unbounded_ queue.
 ///
 /// It is safe to use when and only when only one thread is writing to the queue at the same time,
 /// and only one thread is reading from the queue at the same time.
 ///
+/// You can call `producer_` methods for the producer and `consumer_` methods for the consumer.
+/// The consumer passes its [`ConsumerCursor`] to `consumer_` methods,
+/// because it is too expansive to load the last version of the buffer for every call.
+///
 /// It accepts two atomic wrappers as generic parameters.
 /// It allows using cache-padded atomics or not.
 /// You should create types aliases not to write this large type name.
 ///
-/// # Why it is private?
+/// # Using directly the [`SPSCUnboundedQueue`] vs. using [`new_unbounded`] or [`new_cache_padded_unbounded`].
 ///
-/// It is private because it needs [`CachedVersion`] to work,
-/// and it is useless to use [`CachedVersion`] without separate consumers.
-/// It is too expansive to load the [`Version`] for any consumer method.
-/// This behavior may be changed in the future.
+/// Functions [`new_unbounded`] and [`new_cache_padded_unbounded`] allocate the
+/// [`SPSCUnboundedQueue`] on the heap in [`LightArc`] and provide separate producer and consumer.
+/// It hurts the performance if you don't need to allocate the queue separately, but improve
+/// the readability when you need to separate producer and consumer logic and share them.
 ///
-/// It doesn't implement the [`Producer`] and [`Consumer`] traits because all producer methods
-/// are unsafe (can be called only by one thread).
+/// It doesn't implement the [`Producer`] and [`Consumer`] traits because all producer and consumer
+/// methods are unsafe (can be called only by one thread for each).
 #[repr(C)]
-pub(crate) struct SPSCUnboundedQueue<
+pub struct SPSCUnboundedQueue<
     T,
-    AtomicU32Wrapper = NotCachePaddedAtomicU32,
+    AtomicIndexWrapper = NotCachePaddedAtomicUnboundedIndex,
     AtomicU64Wrapper = NotCachePaddedAtomicU64,
 > where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
     /// First the producer updates the real version,
     /// and next sets a new id. The version id is monotonic.
-    tail_and_version: AtomicU64Wrapper,
-    head: AtomicU32Wrapper,
+    tail_and_version: TailAndVersion<AtomicU64Wrapper>,
+    head: AtomicIndexWrapper,
     last_version: NaiveRWLock<LightArc<Version<T>>>,
+    growth_policy: GrowthPolicy,
+    /// The consumer doesn't steal less than this number of values at once.
+    steal_threshold: usize,
+    /// The number of low occupancy observations in a row.
+    /// Only the producer can access it.
+    low_occupancy_streak: UnsafeCell<usize>,
+    /// The cached last version. Only the producer can access it.
+    producer_version: UnsafeCell<CachedVersion<T>>,
 }
 
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper>
-SPSCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
-    /// Creates a new queue with the given capacity.
-    fn with_capacity(capacity: usize) -> Self {
+    /// Creates a new [`SPSCUnboundedQueue`] with the default [`GrowthPolicy`].
+    pub fn new() -> Self {
+        Self::with_growth_policy(GrowthPolicy::default())
+    }
+
+    /// Creates a new [`SPSCUnboundedQueue`] with the given [`GrowthPolicy`].
+    pub fn with_growth_policy(growth_policy: GrowthPolicy) -> Self {
+        Self::with_options(growth_policy, DEFAULT_STEAL_THRESHOLD)
+    }
+
+    /// Creates a new queue with the given growth policy.
+    fn with_options(growth_policy: GrowthPolicy, steal_threshold: usize) -> Self {
+        let last_version = Version::alloc_new(Self::initial_capacity(growth_policy), 0);
+
         Self {
-            tail_and_version: AtomicU64Wrapper::default(),
-            head: AtomicU32Wrapper::default(),
-            last_version: NaiveRWLock::new(Version::alloc_new(capacity, 0)),
+            tail_and_version: TailAndVersion::default(),
+            head: AtomicIndexWrapper::default(),
+            producer_version: UnsafeCell::new(CachedVersion::from_arc_version(
+                last_version.clone(),
+            )),
+            last_version: NaiveRWLock::new(last_version),
+            growth_policy,
+            steal_threshold,
+            low_occupancy_streak: UnsafeCell::new(0),
         }
     }
 
-    /// Creates a new queue with the default capacity.
-    fn new() -> Self {
-        Self::with_capacity(4)
+    /// Returns the capacity of the first version.
+    ///
+    /// Zero-sized values need no buffer, so the queue starts with the maximum capacity
+    /// and only moves its indexes.
+    const fn initial_capacity(growth_policy: GrowthPolicy) -> usize {
+        if size_of::<T>() == 0 {
+            growth_policy.max_capacity()
+        } else {
+            growth_policy.initial_capacity()
+        }
     }
 
     /// Updates the version of the queue or returns `false`.
@@ -240,9 +302,42 @@ where
         true
     }
 
+    /// Returns the [`GrowthPolicy`] of the queue.
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy
+    }
+
+    /// Returns a new [`ConsumerCursor`] for the consumer of the queue.
+    pub fn consumer_cursor(&self) -> ConsumerCursor<T> {
+        let backoff = Backoff::new();
+
+        loop {
+            // The producer holds the lock only while it replaces the version.
+            if let Some(version) = self.last_version.try_read() {
+                return ConsumerCursor {
+                    version: CachedVersion::from_arc_version(version.clone()),
+                };
+            }
+
+            backoff.snooze();
+        }
+    }
+
+    /// Returns a mutable reference to the cached version of the producer.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer,
+    /// and the producer never gets two mutable references to the version at the same time.
+    #[allow(clippy::mut_from_ref, reason = "Only the producer can access it")]
+    #[inline(always)]
+    unsafe fn producer_version(&self) -> &mut CachedVersion<T> {
+        unsafe { &mut *self.producer_version.get() }
+    }
+
     /// Returns the length of the queue by the given `head` and `tail`.
     #[inline]
-    fn len(head: u32, tail: u32) -> usize {
+    fn len(head: UnboundedIndex, tail: UnboundedIndex) -> usize {
         tail.wrapping_sub(head) as usize
     }
 
@@ -251,32 +346,26 @@ where
     /// # Safety
     ///
     /// It is called only by the producer.
-    unsafe fn unsync_load_tail(&self) -> u32 {
-        let tail_and_version = unsafe { self.tail_and_version.unsync_load() };
-
-        tail_and_version as u32
+    unsafe fn unsync_load_tail(&self) -> UnboundedIndex {
+        unsafe { self.tail_and_version.unsync_load_tail() }
     }
 
     /// Synchronously loads the tail and version.
-    fn sync_load_version_and_tail(&self, ordering: Ordering) -> (u32, u32) {
-        let tail_and_version = self.tail_and_version.load(ordering);
-
-        unpack_version_and_tail(tail_and_version)
+    fn sync_load_version_and_tail(&self, ordering: Ordering) -> (u32, UnboundedIndex) {
+        self.tail_and_version.load(ordering)
     }
 
     /// Synchronously loads the version.
     fn sync_load_version(&self, ordering: Ordering) -> u32 {
-        let tail_and_version = self.tail_and_version.load(ordering);
-
-        (tail_and_version >> 32) as u32
+        self.tail_and_version.load_version(ordering)
     }
 }
 
 // Producer
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper>
-SPSCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
     /// Returns the length of the queue.
@@ -285,7 +374,7 @@ where
     ///
     /// It is called only by the producer.
     #[inline]
-    unsafe fn producer_len(&self) -> usize {
+    pub unsafe fn producer_len(&self) -> usize {
         let head = self.head.load(Acquire);
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
@@ -301,18 +390,18 @@ where
     ///
     /// It is called only by the producer.
     #[inline]
-    unsafe fn producer_capacity(version: &CachedVersion<T>) -> usize {
+    pub unsafe fn producer_capacity(&self) -> usize {
         // The producer always has the latest version.
-        version.capacity()
+        unsafe { self.producer_version() }.capacity()
     }
 
     /// Pushes a slice into the queue. Returns a new tail (not index).
     fn copy_slice(
         buffer_ptr: *mut T,
-        start_tail: u32,
+        start_tail: UnboundedIndex,
         slice: &[T],
         version: &CachedVersion<T>,
-    ) -> u32 {
+    ) -> UnboundedIndex {
         let tail_idx = (start_tail & version.mask) as usize;
 
         if tail_idx + slice.len() <= version.capacity() {
@@ -332,20 +421,19 @@ where
             }
         }
 
-        start_tail.wrapping_add(slice.len() as u32)
+        start_tail.wrapping_add(slice.len() as UnboundedIndex)
     }
 
-    /// Creates a new version and writes it but not updates the tail.
+    /// Copies the values to the allocated `new_version` and writes it but not updates the tail.
     /// Returns the new version and the new tail.
     fn create_new_version_and_write_it_but_not_update_tail(
         &self,
-        head: u32,
-        mut tail: u32,
-        new_capacity: usize,
+        head: UnboundedIndex,
+        mut tail: UnboundedIndex,
+        new_version: LightArc<Version<T>>,
         old_version: &CachedVersion<T>,
-    ) -> (CachedVersion<T>, u32) {
-        let new_version: LightArc<Version<T>> =
-            Version::alloc_new(new_capacity, old_version.id() + 1);
+    ) -> (CachedVersion<T>, UnboundedIndex) {
+        debug_assert_eq!(new_version.id, old_version.id() + 1);
 
         // The key idea is to transform the buffer viewed as:
         // [ 7 8 1 2 3 4 5 6 ]
@@ -404,48 +492,150 @@ where
         (cached_version, tail)
     }
 
-    /// Updates the capacity of the queue.
+    /// Updates the capacity of the queue to the given value rounded up to a power of two.
+    /// It does nothing if the capacity is already not less.
+    ///
+    /// It returns [`AllocError::CapacityOverflow`] if the rounded capacity is greater than
+    /// the maximum capacity of the [`GrowthPolicy`].
     ///
     /// # Safety
     ///
-    /// It is called only by the producer,
-    /// and the provided capacity should be more than the current capacity,
-    /// and less than `u32::MAX` and be a power of two.
-    unsafe fn producer_reserve(&self, new_capacity: usize, version: &mut CachedVersion<T>) {
-        debug_assert!(
-            new_capacity > version.capacity(),
-            "new_capacity should be more than version.capacity()"
-        );
-        debug_assert!(
-            u32::try_from(new_capacity).is_ok(),
-            "new_capacity should be less than u32::MAX"
-        );
-        debug_assert!(
-            new_capacity.is_power_of_two(),
-            "new_capacity should be power of two"
-        );
+    /// It is called only by the producer.
+    unsafe fn producer_try_grow_version_to(
+        &self,
+        capacity: usize,
+        version: &mut CachedVersion<T>,
+    ) -> Result<(), AllocError> {
+        let new_capacity = capacity
+            .checked_next_power_of_two()
+            .filter(|&new_capacity| new_capacity <= self.growth_policy.max_capacity())
+            .ok_or(AllocError::CapacityOverflow)?;
+
+        if new_capacity <= version.capacity() {
+            return Ok(());
+        }
 
+        let new_version = Version::try_alloc_new(new_capacity, version.id() + 1)?;
+
+        unsafe { self.producer_grow_into(new_version, version) };
+
+        Ok(())
+    }
+
+    /// Moves the values to the allocated `new_version` that has a greater capacity.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_grow_into(
+        &self,
+        new_version: LightArc<Version<T>>,
+        version: &mut CachedVersion<T>,
+    ) {
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
         let (cached_version, tail) = self.create_new_version_and_write_it_but_not_update_tail(
             self.head.load(Acquire),
             tail,
-            new_capacity,
+            new_version,
+            version,
+        );
+
+        unsafe {
+            self.tail_and_version
+                .store(cached_version.id(), tail, Release);
+        }
+
+        *version = cached_version;
+    }
+
+    /// Shrinks the capacity of the queue to the given value rounded up to a power of two,
+    /// but never below the length of the queue.
+    /// It does nothing if the capacity is already not greater.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    unsafe fn producer_shrink_version_to(&self, capacity: usize, version: &mut CachedVersion<T>) {
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+        let new_capacity = capacity
+            .max(Self::len(head, tail))
+            .max(1)
+            .checked_next_power_of_two();
+
+        unsafe { *self.low_occupancy_streak.get() = 0 };
+
+        // The capacity that overflows `usize` is not less than the current one either.
+        let Some(new_capacity) =
+            new_capacity.filter(|&new_capacity| new_capacity < version.capacity())
+        else {
+            return;
+        };
+
+        // Shrinking works like growing: the values keep their indexes,
+        // and the consumer that still uses the old version reads the same values from it.
+        // The consumer can only increase the head, so the values still fit into the new version.
+        let (cached_version, tail) = self.create_new_version_and_write_it_but_not_update_tail(
+            head,
+            tail,
+            Version::alloc_new(new_capacity, version.id() + 1),
             version,
         );
 
-        self.tail_and_version
-            .store(pack_version_and_tail(cached_version.id(), tail), Release);
+        unsafe {
+            self.tail_and_version
+                .store(cached_version.id(), tail, Release);
+        }
 
         *version = cached_version;
     }
 
+    /// Shrinks the queue if its occupancy has been low
+    /// for [`GrowthPolicy::shrink_after`] observations in a row.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    unsafe fn producer_track_occupancy(&self, version: &mut CachedVersion<T>) {
+        // Zero-sized values occupy no memory, so there is nothing to free.
+        if size_of::<T>() != 0 && self.growth_policy.can_shrink(version.capacity()) {
+            unsafe { self.producer_track_low_occupancy(version) };
+        }
+    }
+
+    /// The slow path of [`producer_track_occupancy`](Self::producer_track_occupancy).
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(never)]
+    unsafe fn producer_track_low_occupancy(&self, version: &mut CachedVersion<T>) {
+        let len = unsafe { self.producer_len() };
+        let streak = unsafe { &mut *self.low_occupancy_streak.get() };
+
+        if !GrowthPolicy::is_low_occupancy(len, version.capacity()) {
+            *streak = 0;
+
+            return;
+        }
+
+        *streak += 1;
+
+        if *streak >= self.growth_policy.shrink_after() {
+            let capacity = self.growth_policy.shrunk_capacity(len);
+
+            unsafe { self.producer_shrink_version_to(capacity, version) };
+        }
+    }
+
     /// Pushes a value to the queue.
     ///
     /// # Safety
     ///
     /// The called should be the only producer and the queue should not be full.
     #[inline(always)]
-    unsafe fn push_unchecked(&self, value: T, tail: u32, version: &CachedVersion<T>) {
+    unsafe fn push_unchecked(&self, value: T, tail: UnboundedIndex, version: &CachedVersion<T>) {
         // The producer always has the latest version.
 
         unsafe {
@@ -456,15 +646,18 @@ where
                 .write(MaybeUninit::new(value));
         }
 
-        self.tail_and_version.store(
-            pack_version_and_tail(version.id, tail.wrapping_add(1)),
-            Release,
-        );
+        unsafe {
+            self.tail_and_version
+                .store(version.id, tail.wrapping_add(1), Release);
+        }
     }
 
-    /// Updates the version and resizes the queue to the capacity * 2.
+    /// Updates the version and resizes the queue according to the [`GrowthPolicy`].
     /// Then it insets the provided slice.
     ///
+    /// It returns `false` and changes nothing if the values don't fit into
+    /// the [`maximum capacity`](GrowthPolicy::max_capacity).
+    ///
     /// # Safety
     ///
     /// The called should be the only producer.
@@ -472,20 +665,24 @@ where
     #[cold]
     unsafe fn handle_overflow(
         &self,
-        head: u32,
-        tail: u32,
+        head: UnboundedIndex,
+        tail: UnboundedIndex,
         version: &mut CachedVersion<T>,
         values: &[T],
-    ) {
-        let mut new_capacity = version.capacity() * 2;
-        while new_capacity <= version.capacity() + values.len() {
-            new_capacity *= 2;
-        }
+    ) -> bool {
+        let Some(new_capacity) = self
+            .growth_policy
+            .try_grown_capacity(version.capacity(), Self::len(head, tail) + values.len())
+        else {
+            return false;
+        };
+
+        unsafe { *self.low_occupancy_streak.get() = 0 };
 
         let (cached_version, tail) = self.create_new_version_and_write_it_but_not_update_tail(
             head,
             tail,
-            new_capacity,
+            Version::alloc_new(new_capacity, version.id() + 1),
             version,
         );
 
@@ -495,33 +692,103 @@ where
             values,
             &cached_version,
         );
-        self.tail_and_version.store(
-            pack_version_and_tail(cached_version.id(), new_tail),
-            Release,
-        );
+        unsafe {
+            self.tail_and_version
+                .store(cached_version.id(), new_tail, Release);
+        }
 
         // Here we don't need the previous version anymore.
         *version = cached_version;
+
+        true
+    }
+
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue has reached the maximum capacity,
+    /// it moves the value to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push<SBR: SyncBatchReceiver<T>>(
+        &self,
+        value: T,
+        sync_batch_receiver: &SBR,
+    ) {
+        if let Err(value) = unsafe { self.producer_maybe_push(value) } {
+            sync_batch_receiver.push_many_and_one(&[], &[], value);
+        }
     }
 
     /// Pushes a value to the queue.
-    /// Because the queue is unbounded, this method always succeeds.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue has reached the maximum capacity, it returns the value back.
     ///
     /// # Safety
     ///
     /// The called should be the only producer.
     #[inline]
-    unsafe fn producer_push(&self, value: T, version: &mut CachedVersion<T>) {
+    pub unsafe fn producer_maybe_push(&self, value: T) -> Result<(), T> {
+        let version = unsafe { self.producer_version() };
         let head = self.head.load(Acquire);
         let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
         if unlikely(Self::len(head, tail) == version.capacity()) {
-            unsafe { self.handle_overflow(head, tail, version, &[value]) };
+            if !unsafe { self.handle_overflow(head, tail, version, slice::from_ref(&value)) } {
+                return Err(value);
+            }
 
-            return;
+            // The value is moved into the queue.
+            mem::forget(value);
+
+            return Ok(());
         }
 
         unsafe { self.push_unchecked(value, tail, version) };
+        unsafe { self.producer_track_occupancy(version) };
+
+        Ok(())
+    }
+
+    /// Pushes a value to the queue.
+    /// If the queue is full, it grows according to the [`GrowthPolicy`].
+    ///
+    /// It returns the value back with the [`AllocError`] if the queue can't grow.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_fallible_push(&self, value: T) -> Result<(), (T, AllocError)> {
+        let version = unsafe { self.producer_version() };
+        let head = self.head.load(Acquire);
+        let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
+
+        if unlikely(Self::len(head, tail) == version.capacity()) {
+            let reserved = self
+                .growth_policy
+                .try_grown_capacity(version.capacity(), version.capacity() + 1)
+                .ok_or(AllocError::CapacityOverflow)
+                .and_then(|new_capacity| unsafe {
+                    self.producer_try_grow_version_to(new_capacity, version)
+                });
+
+            if let Err(err) = reserved {
+                return Err((value, err));
+            }
+
+            unsafe { *self.low_occupancy_streak.get() = 0 };
+        }
+
+        // Growing keeps the indexes of the values, so the loaded tail is still valid.
+        unsafe { self.push_unchecked(value, tail, version) };
+        unsafe { self.producer_track_occupancy(version) };
+
+        Ok(())
     }
 
     /// Pushes many values to the queue.
@@ -530,12 +797,8 @@ where
     ///
     /// The called should be the only producer and the space is enough.
     #[inline]
-    unsafe fn producer_push_many_unchecked(
-        &self,
-        first: &[T],
-        last: &[T],
-        version: &CachedVersion<T>,
-    ) {
+    pub unsafe fn producer_push_many_unchecked(&self, first: &[T], last: &[T]) {
+        let version = unsafe { self.producer_version() };
         if cfg!(debug_assertions) {
             let head = self.head.load(Acquire);
             let tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
@@ -560,24 +823,48 @@ where
             version,
         );
 
-        self.tail_and_version
-            .store(pack_version_and_tail(version.id(), tail), Release);
+        unsafe { self.tail_and_version.store(version.id(), tail, Release) };
+    }
+
+    /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue can't grow enough, it moves the values to the [`SyncBatchReceiver`].
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    #[inline]
+    pub unsafe fn producer_push_many<SBR: SyncBatchReceiver<T>>(
+        &self,
+        slice: &[T],
+        sync_batch_receiver: &SBR,
+    ) {
+        if unsafe { self.producer_maybe_push_many(slice) }.is_err() {
+            sync_batch_receiver.push_many_and_slice(&[], &[], slice);
+        }
     }
 
     /// Pushes many values to the queue.
+    /// If the queue doesn't have enough space, it grows according to the [`GrowthPolicy`].
+    ///
+    /// If the queue can't grow enough, it returns an error.
     ///
     /// # Safety
     ///
     /// The called should be the only producer.
     #[inline]
-    unsafe fn producer_push_many(&self, slice: &[T], version: &mut CachedVersion<T>) {
+    pub unsafe fn producer_maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
+        let version = unsafe { self.producer_version() };
         let head = self.head.load(Acquire);
         let mut tail = unsafe { self.unsync_load_tail() }; // only producer can change tail
 
         if unlikely(Self::len(head, tail) + slice.len() > version.capacity()) {
-            unsafe { self.handle_overflow(head, tail, version, slice) };
+            if unsafe { self.handle_overflow(head, tail, version, slice) } {
+                return Ok(());
+            }
 
-            return;
+            return Err(());
         }
 
         tail = Self::copy_slice(
@@ -587,21 +874,74 @@ where
             version,
         );
 
-        self.tail_and_version
-            .store(pack_version_and_tail(version.id(), tail), Release);
+        unsafe { self.tail_and_version.store(version.id(), tail, Release) };
+
+        unsafe { self.producer_track_occupancy(version) };
+
+        Ok(())
+    }
+
+    /// Updates the capacity of the queue to the given value rounded up to a power of two.
+    /// It does nothing if the capacity is already not less.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rounded capacity is greater than
+    /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy.
+    /// It calls [`handle_alloc_error`](std::alloc::handle_alloc_error)
+    /// if the allocation fails.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    pub unsafe fn producer_reserve(&self, capacity: usize) {
+        if let Err(err) = unsafe { self.producer_try_reserve(capacity) } {
+            err.handle();
+        }
+    }
+
+    /// Updates the capacity of the queue to the given value rounded up to a power of two.
+    /// It does nothing if the capacity is already not less.
+    ///
+    /// It returns [`AllocError::CapacityOverflow`] if the rounded capacity is greater than
+    /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy,
+    /// and [`AllocError::AllocFailed`] if the allocation fails.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    pub unsafe fn producer_try_reserve(&self, capacity: usize) -> Result<(), AllocError> {
+        unsafe { self.producer_try_grow_version_to(capacity, self.producer_version()) }
+    }
+
+    /// Shrinks the capacity of the queue to the given value rounded up to a power of two.
+    ///
+    /// The capacity never becomes less than the length of the queue.
+    /// It does nothing if the capacity is already not greater.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only producer.
+    pub unsafe fn producer_shrink_to(&self, capacity: usize) {
+        unsafe { self.producer_shrink_version_to(capacity, self.producer_version()) };
     }
 }
 
 // Consumers
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper>
-SPSCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper>
+SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
     /// Returns the capacity of the queue.
+    ///
+    /// # Safety
+    ///
+    /// The the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
     #[inline]
-    fn consumer_capacity(&self, version: &mut CachedVersion<T>) -> usize {
+    pub unsafe fn consumer_capacity(&self, cursor: &mut ConsumerCursor<T>) -> usize {
+        let version = &mut cursor.version;
         let last_version_id = self.sync_load_version(Relaxed);
         if version.id() == last_version_id {
             return version.capacity();
@@ -618,8 +958,14 @@ where
     }
 
     /// Returns the length of the queue.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only consumer,
+    /// and the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
     #[inline]
-    fn consumer_len(&self, version: &mut CachedVersion<T>) -> usize {
+    pub unsafe fn consumer_len(&self, cursor: &mut ConsumerCursor<T>) -> usize {
+        let version = &mut cursor.version;
         let (last_version_id, tail) = self.sync_load_version_and_tail(Relaxed);
         let head = unsafe { self.head.unsync_load() }; // only consumer can change head
         let len = Self::len(head, tail);
@@ -656,12 +1002,18 @@ where
     ///
     /// It can return zero even if the queue is not empty,
     /// if the producer is preempted while pushing.
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only consumer,
+    /// and the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
     #[inline]
-    fn consumer_pop_many(
+    pub unsafe fn consumer_pop_many(
         &self,
         dst: &mut [MaybeUninit<T>],
-        version: &mut CachedVersion<T>,
+        cursor: &mut ConsumerCursor<T>,
     ) -> usize {
+        let version = &mut cursor.version;
         let (mut last_version_id, mut tail) = self.sync_load_version_and_tail(Acquire);
         let head = unsafe { self.head.unsync_load() }; // only consumer can change head
 
@@ -701,7 +1053,8 @@ where
                 }
             }
             
-            self.head.store(head.wrapping_add(n as u32), Release);
+            self.head
+                .store(head.wrapping_add(n as UnboundedIndex), Release);
             
             return n;
         }
@@ -710,26 +1063,26 @@ where
     /// Steals many values from the consumer to the `dst`.
     /// Returns the number of values stolen.
     ///
+    /// It steals no more than the number of free slots in the `dst`
+    /// and appends the stolen values after the values of the `dst`.
+    ///
     /// It can return zero even if the source queue is not empty,
     /// if the producer is preempted while pushing.
-    fn steal_into(
-        &self,
-        dst: &Self,
-        src_version: &mut CachedVersion<T>,
-        dst_version: &mut CachedVersion<T>,
-    ) -> usize {
+    ///
+    /// # Safety
+    ///
+    /// The called should be the only consumer and the only producer of the `dst`,
+    /// and the `cursor` should be created by [`consumer_cursor`](Self::consumer_cursor) of this queue.
+    pub unsafe fn steal_into(&self, dst: &Self, cursor: &mut ConsumerCursor<T>) -> usize {
+        let src_version = &mut cursor.version;
+        let dst_version = unsafe { dst.producer_version() };
         let (mut src_last_version_id, mut src_tail) = self.sync_load_version_and_tail(Acquire);
         let src_head = unsafe { self.head.unsync_load() }; // only producer can change head
         let dst_tail = unsafe { dst.unsync_load_tail() }; // only producer can change tail
 
-        if cfg!(debug_assertions) {
-            let dst_head = dst.head.load(Relaxed);
-
-            assert_eq!(
-                dst_head, dst_tail,
-                "steal_into should not be called when dst is not empty"
-            );
-        }
+        // The consumer of the `dst` can only increase the number of free slots,
+        // so it is safe to load the head only once.
+        let dst_free_slots = dst_version.capacity() - Self::len(dst.head.load(Acquire), dst_tail);
 
         loop {
             if unlikely(src_version.id() < src_last_version_id) {
@@ -744,13 +1097,17 @@ where
             }
 
             let n = Self::len(src_head, src_tail) / 2;
-            if !cfg!(feature = "always_steal") && n < 4 || n == 0 {
-                // we don't steal less than 4 by default
+            if n < self.steal_threshold || n == 0 {
+                // we don't steal less than the threshold (4 by default)
                 // because else we may lose more because of cache locality and NUMA awareness
                 return 0;
             }
 
-            let n = n.min(dst_version.capacity());
+            let n = n.min(dst_free_slots);
+            if n == 0 {
+                return 0;
+            }
+
             let src_head_idx = (src_head & src_version.mask()) as usize;
 
             let (src_right, src_left): (&[T], &[T]) = unsafe {
@@ -779,18 +1136,22 @@ where
             );
             Self::copy_slice(
                 unsafe { dst_version.thin_mut_ptr() }.cast::<T>(),
-                dst_tail.wrapping_add(src_right.len() as u32),
+                dst_tail.wrapping_add(src_right.len() as UnboundedIndex),
                 src_left,
                 dst_version,
             );
             
-            self.head.store(src_head.wrapping_add(n as u32), Release);
+            self.head
+                .store(src_head.wrapping_add(n as UnboundedIndex), Release);
 
             // Success, we can move dst tail and return
-            dst.tail_and_version.store(
-                pack_version_and_tail(dst_version.id(), dst_tail.wrapping_add(n as u32)),
-                Release,
-            );
+            unsafe {
+                dst.tail_and_version.store(
+                    dst_version.id(),
+                    dst_tail.wrapping_add(n as UnboundedIndex),
+                    Release,
+                );
+            }
 
             return n;
         }
@@ -798,25 +1159,36 @@ where
 }
 
 #[allow(clippy::non_send_fields_in_send_ty, reason = "We guarantee it is Send")]
-unsafe impl<T, AtomicU32Wrapper, AtomicU64Wrapper> Send
-for SPSCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+unsafe impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Send
+for SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
 }
-unsafe impl<T, AtomicU32Wrapper, AtomicU64Wrapper> Sync
-for SPSCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+unsafe impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Sync
+for SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
 }
 
-impl<T, AtomicU32Wrapper, AtomicU64Wrapper> Drop
-for SPSCUnboundedQueue<T, AtomicU32Wrapper, AtomicU64Wrapper>
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Default
+for SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
+where
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, AtomicIndexWrapper, AtomicU64Wrapper> Drop
+for SPSCUnboundedQueue<T, AtomicIndexWrapper, AtomicU64Wrapper>
 where
-    AtomicU32Wrapper: Deref<Target = AtomicU32> + Default,
+    AtomicIndexWrapper: Deref<Target = AtomicUnboundedIndex> + Default,
     AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
 {
     fn drop(&mut self) {
@@ -845,42 +1217,92 @@ where
 
 /// Generates SPSC producer and consumer.
 macro_rules! generate_spsc_producer_and_consumer {
-    ($producer_name:ident, $consumer_name:ident, $atomic_u32_wrapper:ty, $long_atomic_wrapper:ty) => {
+    ($producer_name:ident, $consumer_name:ident, $atomic_index_wrapper:ty, $long_atomic_wrapper:ty) => {
         /// The producer of the [`SPSCUnboundedQueue`].
         pub struct $producer_name<T> {
-            inner: LightArc<SPSCUnboundedQueue<T, $atomic_u32_wrapper, $long_atomic_wrapper>>,
-            cached_version: UnsafeCell<CachedVersion<T>>, // The producer is not Sync, it needs only shared references and it never gets two mutable references to this field
+            inner: LightArc<SPSCUnboundedQueue<T, $atomic_index_wrapper, $long_atomic_wrapper>>,
             _non_sync: PhantomData<*const ()>,
         }
 
         impl<T> $producer_name<T> {
-            /// Returns a mutable reference to the cached version.
-            #[allow(clippy::mut_from_ref, reason = "It improves readability")]
-            #[inline]
-            fn cached_version(&self) -> &mut CachedVersion<T> {
-                unsafe { &mut *self.cached_version.get() }
+            /// Creates a new queue with the given growth policy
+            /// and returns its producer and consumer.
+            pub(crate) fn new_queue(
+                growth_policy: GrowthPolicy,
+                steal_threshold: usize,
+            ) -> (Self, $consumer_name<T>) {
+                let queue = SPSCUnboundedQueue::with_options(growth_policy, steal_threshold);
+                let cursor = queue.consumer_cursor();
+                let queue = LightArc::new(queue);
+
+                (
+                    Self {
+                        inner: queue.clone(),
+                        _non_sync: PhantomData,
+                    },
+                    $consumer_name {
+                        cursor: UnsafeCell::new(cursor),
+                        inner: queue,
+                        _non_sync: PhantomData,
+                    },
+                )
             }
 
-            /// Updates the capacity of the queue to the given value.
+            /// Updates the capacity of the queue to the given value rounded up to a power of two.
+            /// It does nothing if the capacity is already not less.
             ///
-            /// # Safety
+            /// # Panics
             ///
-            /// The provided capacity must be greater than the current capacity,
-            /// less than `u32::MAX` and be a power of two.
+            /// Panics if the rounded capacity is greater than
+            /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy.
+            /// It calls [`handle_alloc_error`](std::alloc::handle_alloc_error)
+            /// if the allocation fails.
             pub fn reserve(&self, capacity: usize) {
-                unsafe {
-                    self
-                        .inner
-                        .producer_reserve(capacity, self.cached_version())
-                };
+                unsafe { self.inner.producer_reserve(capacity) };
+            }
+
+            /// Updates the capacity of the queue to the given value rounded up to a power of two.
+            /// It does nothing if the capacity is already not less.
+            ///
+            /// It returns [`AllocError::CapacityOverflow`] if the rounded capacity is greater than
+            /// the [`maximum capacity`](GrowthPolicy::max_capacity) of the growth policy,
+            /// and [`AllocError::AllocFailed`] if the allocation fails.
+            pub fn try_reserve(&self, capacity: usize) -> Result<(), AllocError> {
+                unsafe { self.inner.producer_try_reserve(capacity) }
+            }
+
+            /// Pushes the value to the queue.
+            /// If the queue is full, it grows according to the [`GrowthPolicy`].
+            ///
+            /// Unlike [`Producer::push`], it never panics or aborts if the queue can't grow:
+            /// it returns the value back with the [`AllocError`].
+            pub fn fallible_push(&self, value: T) -> Result<(), (T, AllocError)> {
+                unsafe { self.inner.producer_fallible_push(value) }
+            }
+
+            /// Shrinks the capacity of the queue to the given value rounded up to a power of two.
+            ///
+            /// The capacity never becomes less than the length of the queue.
+            /// It does nothing if the capacity is already not greater.
+            pub fn shrink_to(&self, capacity: usize) {
+                unsafe { self.inner.producer_shrink_to(capacity) };
+            }
+
+            /// Shrinks the capacity of the queue as much as possible.
+            pub fn shrink_to_fit(&self) {
+                self.shrink_to(0);
+            }
+
+            /// Returns the [`GrowthPolicy`] of the queue.
+            pub fn growth_policy(&self) -> GrowthPolicy {
+                self.inner.growth_policy()
             }
         }
 
         impl<T: Send> Producer<T> for $producer_name<T> {
             #[inline]
             fn capacity(&self) -> usize {
-                // The producer always has the latest version.
-                unsafe { SPSCUnboundedQueue::<T, $atomic_u32_wrapper, $long_atomic_wrapper>::producer_capacity(self.cached_version()) }
+                unsafe { self.inner.producer_capacity() }
             }
 
             #[inline]
@@ -889,30 +1311,32 @@ macro_rules! generate_spsc_producer_and_consumer {
             }
 
             #[inline]
-            fn maybe_push(&self, value: T) -> Result<(), T> {
-                unsafe { self.inner.producer_push(value, self.cached_version()) };
+            fn push<SBR: SyncBatchReceiver<T>>(&self, value: T, sync_batch_receiver: &SBR) {
+                unsafe { self.inner.producer_push(value, sync_batch_receiver) };
+            }
 
-                Ok(())
+            #[inline]
+            fn maybe_push(&self, value: T) -> Result<(), T> {
+                unsafe { self.inner.producer_maybe_push(value) }
             }
 
             #[inline]
             unsafe fn push_many_unchecked(&self, first: &[T], last: &[T]) {
-                unsafe {
-                    self
-                    .inner
-                        .producer_push_many_unchecked(first, last, self.cached_version())
-                }
+                unsafe { self.inner.producer_push_many_unchecked(first, last) }
             }
 
             #[inline]
             unsafe fn maybe_push_many(&self, slice: &[T]) -> Result<(), ()> {
-                unsafe {
-                    self
-                    .inner
-                        .producer_push_many(slice, self.cached_version())
-                };
+                unsafe { self.inner.producer_maybe_push_many(slice) }
+            }
 
-                Ok(())
+            #[inline]
+            unsafe fn push_many<SBR: SyncBatchReceiver<T>>(
+                &self,
+                slice: &[T],
+                sync_batch_receiver: &SBR,
+            ) {
+                unsafe { self.inner.producer_push_many(slice, sync_batch_receiver) };
             }
         }
 
@@ -921,17 +1345,17 @@ macro_rules! generate_spsc_producer_and_consumer {
 
         /// The consumer of the [`SPSCUnboundedQueue`].
         pub struct $consumer_name<T> {
-            inner: LightArc<SPSCUnboundedQueue<T, $atomic_u32_wrapper, $long_atomic_wrapper>>,
-            cached_version: UnsafeCell<CachedVersion<T>>,
+            inner: LightArc<SPSCUnboundedQueue<T, $atomic_index_wrapper, $long_atomic_wrapper>>,
+            cursor: UnsafeCell<ConsumerCursor<T>>,
             _non_sync: PhantomData<*const ()>,
         }
 
         impl<T> $consumer_name<T> {
-            /// Returns a mutable reference to the cached version.
+            /// Returns a mutable reference to the cursor.
             #[allow(clippy::mut_from_ref, reason = "It improves readability")]
             #[inline]
-            fn cached_version(&self) -> &mut CachedVersion<T> {
-                unsafe { &mut *self.cached_version.get() }
+            fn cursor(&self) -> &mut ConsumerCursor<T> {
+                unsafe { &mut *self.cursor.get() }
             }
         }
 
@@ -940,33 +1364,30 @@ macro_rules! generate_spsc_producer_and_consumer {
 
             #[inline]
             fn capacity(&self) -> usize {
-                self.inner.consumer_capacity(self.cached_version())
+                unsafe { self.inner.consumer_capacity(self.cursor()) }
             }
 
             #[inline]
             fn len(&self) -> usize {
-                self.inner.consumer_len(self.cached_version())
+                unsafe { self.inner.consumer_len(self.cursor()) }
             }
 
             #[inline]
             fn pop_many(&self, dst: &mut [MaybeUninit<T>]) -> usize {
-                self.inner.consumer_pop_many(dst, self.cached_version())
+                unsafe { self.inner.consumer_pop_many(dst, self.cursor()) }
             }
 
             #[inline]
             fn steal_into(&self, dst: &Self::AssociatedProducer) -> usize {
-                self.inner.steal_into(
-                    &*dst.inner,
-                    self.cached_version(),
-                    dst.cached_version(),
-                )
+                // Only this thread can use the `dst`, because the producer is not `Sync`.
+                unsafe { self.inner.steal_into(&dst.inner, self.cursor()) }
             }
         }
 
         impl<T> Clone for $consumer_name<T> {
             fn clone(&self) -> Self {
                 Self {
-                    cached_version: UnsafeCell::new(self.cached_version().clone()),
+                    cursor: UnsafeCell::new(self.cursor().clone()),
                     inner: self.inner.clone(),
                     _non_sync: PhantomData,
                 }
@@ -981,7 +1402,7 @@ macro_rules! generate_spsc_producer_and_consumer {
         generate_spsc_producer_and_consumer!(
             $producer_name,
             $consumer_name,
-            NotCachePaddedAtomicU32,
+            NotCachePaddedAtomicUnboundedIndex,
             NotCachePaddedAtomicU64
         );
     };
@@ -1004,6 +1425,11 @@ generate_spsc_producer_and_consumer!(SPSCUnboundedProducer, SPSCUnboundedConsume
 /// - [`Consumer::capacity`] and [`Consumer::len`] can return old values for `unbounded` queue.
 /// - All methods of `bounded` queue work much faster than all methods of `unbounded` queue.
 ///
+/// # Capacity
+///
+/// By default, the head and the tail are `u32`, so the queue holds up to `2^31` values.
+/// Enable the `wide_unbounded_index` feature to use `u64` indexes and lift this limit.
+///
 /// # Cache padding
 ///
 /// Cache padding can improve the performance of the queue many times, but it also requires
@@ -1028,28 +1454,85 @@ generate_spsc_producer_and_consumer!(SPSCUnboundedProducer, SPSCUnboundedConsume
 /// assert_eq!(unsafe { slice[1].assume_init() }, 2);
 /// ```
 pub fn new_unbounded<T>() -> (SPSCUnboundedProducer<T>, SPSCUnboundedConsumer<T>) {
-    let mut queue = SPSCUnboundedQueue::new();
-    let version = queue.last_version.get_mut().clone();
-    let queue = LightArc::new(queue);
-
-    (
-        SPSCUnboundedProducer {
-            inner: queue.clone(),
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version.clone())),
-            _non_sync: PhantomData,
-        },
-        SPSCUnboundedConsumer {
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version)),
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
+    new_unbounded_with_growth_policy(GrowthPolicy::new())
+}
+
+/// Creates a new single-producer, single-consumer unbounded queue with the [`GrowthPolicy`].
+/// Returns [`producer`](SPSCUnboundedProducer) and [`consumer`](SPSCUnboundedConsumer).
+///
+/// Read more about the queue in [`new_unbounded`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::GrowthPolicy;
+/// use parcoll::spsc::{Producer, Consumer, new_unbounded_with_growth_policy};
+///
+/// let (producer, consumer) = new_unbounded_with_growth_policy(
+///     GrowthPolicy::new().with_shrink_after(4),
+/// );
+///
+/// for i in 0..100 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 128);
+///
+/// let mut slice = [std::mem::MaybeUninit::uninit(); 99];
+///
+/// assert_eq!(consumer.pop_many(&mut slice), 99);
+///
+/// // The producer sees the low occupancy four times in a row
+/// // and shrinks the queue to twice its length rounded up to a power of two.
+/// for i in 0..4 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 16);
+/// ```
+pub fn new_unbounded_with_growth_policy<T>(
+    growth_policy: GrowthPolicy,
+) -> (SPSCUnboundedProducer<T>, SPSCUnboundedConsumer<T>) {
+    SPSCUnboundedProducer::new_queue(growth_policy, DEFAULT_STEAL_THRESHOLD)
+}
+
+/// Creates a new single-producer, single-consumer unbounded queue with the initial capacity.
+/// Returns [`producer`](SPSCUnboundedProducer)
+/// and [`consumer`](SPSCUnboundedConsumer).
+///
+/// The capacity is rounded up to a power of two.
+/// It is a shortcut for [`new_unbounded_with_growth_policy`]
+/// with [`GrowthPolicy::with_initial_capacity`].
+///
+/// # Panics
+///
+/// Panics if the rounded capacity is greater than the maximum capacity of [`GrowthPolicy::new`].
+///
+/// # Examples
+///
+/// ```
+/// use parcoll::spsc::{Producer, new_unbounded_with_capacity};
+///
+/// let (producer, _consumer) = new_unbounded_with_capacity(100);
+///
+/// assert_eq!(producer.capacity(), 128);
+///
+/// for i in 0..128 {
+///     producer.maybe_push(i).unwrap();
+/// }
+///
+/// assert_eq!(producer.capacity(), 128);
+/// ```
+pub fn new_unbounded_with_capacity<T>(
+    capacity: usize,
+) -> (SPSCUnboundedProducer<T>, SPSCUnboundedConsumer<T>) {
+    new_unbounded_with_growth_policy(GrowthPolicy::new().with_initial_capacity(capacity))
 }
 
 generate_spsc_producer_and_consumer!(
     CachePaddedSPSCUnboundedProducer,
     CachePaddedSPSCUnboundedConsumer,
-    CachePaddedAtomicU32,
+    CachePaddedAtomicUnboundedIndex,
     CachePaddedAtomicU64
 );
 
@@ -1068,6 +1551,11 @@ generate_spsc_producer_and_consumer!(
 /// - [`Consumer::capacity`] and [`Consumer::len`] can return old values for `unbounded` queue.
 /// - All methods of `bounded` queue work much faster than all methods of `unbounded` queue.
 ///
+/// # Capacity
+///
+/// By default, the head and the tail are `u32`, so the queue holds up to `2^31` values.
+/// Enable the `wide_unbounded_index` feature to use `u64` indexes and lift this limit.
+///
 /// # Cache padding
 ///
 /// Cache padding can improve the performance of the queue many times, but it also requires
@@ -1095,28 +1583,48 @@ pub fn new_cache_padded_unbounded<T>() -> (
     CachePaddedSPSCUnboundedProducer<T>,
     CachePaddedSPSCUnboundedConsumer<T>,
 ) {
-    let mut queue = SPSCUnboundedQueue::new();
-    let version = queue.last_version.get_mut().clone();
-    let queue = LightArc::new(queue);
-
-    (
-        CachePaddedSPSCUnboundedProducer {
-            inner: queue.clone(),
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version.clone())),
-            _non_sync: PhantomData,
-        },
-        CachePaddedSPSCUnboundedConsumer {
-            cached_version: UnsafeCell::new(CachedVersion::from_arc_version(version)),
-            inner: queue,
-            _non_sync: PhantomData,
-        },
-    )
+    new_cache_padded_unbounded_with_growth_policy(GrowthPolicy::new())
+}
+
+/// Creates a new single-producer, single-consumer unbounded queue with the [`GrowthPolicy`].
+/// Returns [`producer`](CachePaddedSPSCUnboundedProducer)
+/// and [`consumer`](CachePaddedSPSCUnboundedConsumer).
+///
+/// Read more about the queue in [`new_cache_padded_unbounded`].
+pub fn new_cache_padded_unbounded_with_growth_policy<T>(
+    growth_policy: GrowthPolicy,
+) -> (
+    CachePaddedSPSCUnboundedProducer<T>,
+    CachePaddedSPSCUnboundedConsumer<T>,
+) {
+    CachePaddedSPSCUnboundedProducer::new_queue(growth_policy, DEFAULT_STEAL_THRESHOLD)
+}
+
+/// Creates a new single-producer, single-consumer unbounded queue with the initial capacity.
+/// Returns [`producer`](CachePaddedSPSCUnboundedProducer)
+/// and [`consumer`](CachePaddedSPSCUnboundedConsumer).
+///
+/// The capacity is rounded up to a power of two.
+/// It is a shortcut for [`new_cache_padded_unbounded_with_growth_policy`]
+/// with [`GrowthPolicy::with_initial_capacity`].
+///
+/// # Panics
+///
+/// Panics if the rounded capacity is greater than the maximum capacity of [`GrowthPolicy::new`].
+pub fn new_cache_padded_unbounded_with_capacity<T>(
+    capacity: usize,
+) -> (
+    CachePaddedSPSCUnboundedProducer<T>,
+    CachePaddedSPSCUnboundedConsumer<T>,
+) {
+    new_cache_padded_unbounded_with_growth_policy(GrowthPolicy::new().with_initial_capacity(capacity))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::mutex_vec_queue::VecQueue;
+    use crate::mutex_vec_queue::{MutexVecQueue, VecQueue};
     use super::*;
+    use crate::zst_test::{test_zst_queue, Zst};
 
     const N: usize = 16000;
     const BATCH_SIZE: usize = 10;
@@ -1144,8 +1652,8 @@ mod tests {
 
             assert_eq!(consumer.pop_many(slice.as_mut_slice()), BATCH_SIZE);
 
-            for j in 0..BATCH_SIZE {
-                assert_eq!(unsafe { slice[j].assume_init() }, i * BATCH_SIZE + j);
+            for (j, value) in slice.iter().enumerate() {
+                assert_eq!(unsafe { value.assume_init() }, i * BATCH_SIZE + j);
             }
         }
     }
@@ -1155,7 +1663,7 @@ mod tests {
         const TRIES: usize = 100;
 
         let (producer1, consumer) = new_unbounded();
-        let (mut producer2, consumer2) = new_unbounded();
+        let (producer2, consumer2) = new_unbounded();
         let mut stolen = VecQueue::new();
 
         producer2.reserve(512);
@@ -1165,7 +1673,7 @@ mod tests {
                 producer1.maybe_push(i).unwrap();
             }
 
-            consumer.steal_into(&mut producer2);
+            consumer.steal_into(&producer2);
 
             while let Some(task) = consumer2.pop() {
                 stolen.push(task);
@@ -1176,13 +1684,40 @@ mod tests {
 
         let mut count = 0;
 
-        while let Some(_) = consumer.pop() {
+        while consumer.pop().is_some() {
             count += 1;
         }
 
         assert_eq!(count + stolen.len(), N * TRIES);
     }
 
+    #[test]
+    fn test_spsc_unbounded_stealing_into_non_empty() {
+        let (producer1, consumer1) = new_unbounded();
+        let (producer2, consumer2) = new_unbounded();
+
+        producer2.reserve(64);
+
+        for i in 0..64 {
+            producer1.maybe_push(i).unwrap();
+        }
+
+        for i in 0..54 {
+            producer2.maybe_push(64 + i).unwrap();
+        }
+
+        assert_eq!(consumer1.steal_into(&producer2), 10);
+        assert_eq!(producer2.len(), 64);
+
+        for i in 0..54 {
+            assert_eq!(consumer2.pop(), Some(64 + i));
+        }
+
+        for i in 0..10 {
+            assert_eq!(consumer2.pop(), Some(i));
+        }
+    }
+
     #[test]
     fn test_spsc_unbounded_many() {
         const BATCH_SIZE: usize = 30;
@@ -1196,17 +1731,17 @@ mod tests {
                 .collect::<Vec<_>>();
 
             unsafe {
-                producer.maybe_push_many(&*slice).unwrap();
+                producer.maybe_push_many(&slice).unwrap();
             }
 
             let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
 
             consumer.pop_many(slice.as_mut_slice());
 
-            for j in 0..BATCH_SIZE {
+            for (j, value) in slice.iter().enumerate() {
                 let index = i * BATCH_SIZE + j;
 
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
+                assert_eq!(unsafe { value.assume_init() }, index);
             }
         }
 
@@ -1216,18 +1751,251 @@ mod tests {
                 .collect::<Vec<_>>();
 
             unsafe {
-                producer.maybe_push_many(&*slice).unwrap();
+                producer.maybe_push_many(&slice).unwrap();
             }
 
             let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
             
             consumer.pop_many(slice.as_mut_slice());
 
-            for j in 0..BATCH_SIZE {
+            for (j, value) in slice.iter().enumerate() {
                 let index = i * BATCH_SIZE + j;
 
-                assert_eq!(unsafe { slice[j].assume_init() }, index);
+                assert_eq!(unsafe { value.assume_init() }, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spsc_unbounded_raw_queue() {
+        let queue = SPSCUnboundedQueue::<usize>::with_growth_policy(
+            GrowthPolicy::new().with_initial_capacity(4),
+        );
+        let stealer_queue = SPSCUnboundedQueue::<usize>::new();
+        let mut cursor = queue.consumer_cursor();
+
+        assert_eq!(unsafe { queue.consumer_capacity(&mut cursor) }, 4);
+
+        unsafe {
+            for i in 0..N {
+                queue.producer_maybe_push(i).unwrap();
+            }
+
+            assert_eq!(queue.producer_len(), N);
+            assert_eq!(queue.producer_capacity(), N.next_power_of_two());
+
+            // The cursor has the first version, it updates it lazily.
+            assert_eq!(queue.consumer_len(&mut cursor), N);
+            assert_eq!(queue.consumer_capacity(&mut cursor), N.next_power_of_two());
+
+            let mut slice = [MaybeUninit::uninit(); BATCH_SIZE];
+
+            assert_eq!(queue.consumer_pop_many(&mut slice, &mut cursor), BATCH_SIZE);
+
+            for (i, value) in slice.iter().enumerate() {
+                assert_eq!(value.assume_init(), i);
             }
+
+            let stolen = queue.steal_into(&stealer_queue, &mut cursor);
+
+            assert_eq!(stolen, stealer_queue.producer_len());
+            assert_eq!(queue.consumer_len(&mut cursor), N - BATCH_SIZE - stolen);
+
+            queue.producer_maybe_push_many(&[N, N + 1]).unwrap();
+
+            assert_eq!(queue.producer_len(), N - BATCH_SIZE - stolen + 2);
+
+            let capacity = queue.producer_capacity();
+
+            queue.producer_shrink_to(usize::MAX);
+
+            assert_eq!(queue.producer_capacity(), capacity);
+
+            queue.producer_shrink_to(0);
+
+            assert_eq!(
+                queue.producer_capacity(),
+                queue.producer_len().next_power_of_two()
+            );
+        }
+    }
+
+    #[test]
+    fn test_spsc_unbounded_growth_policy() {
+        let policy = GrowthPolicy::new()
+            .with_initial_capacity(8)
+            .with_factor(4)
+            .with_max_capacity(128);
+        let (producer, consumer) = new_unbounded_with_growth_policy(policy);
+
+        assert_eq!(producer.growth_policy(), policy);
+        assert_eq!(producer.capacity(), 8);
+
+        unsafe {
+            producer.maybe_push_many(&(0..40).collect::<Vec<_>>()).unwrap();
+        }
+
+        assert_eq!(producer.capacity(), 128);
+
+        let mut slice = [MaybeUninit::uninit(); 30];
+
+        assert_eq!(consumer.pop_many(&mut slice[..20]), 20);
+
+        producer.shrink_to_fit();
+
+        assert_eq!(producer.capacity(), 32);
+
+        for i in 40..50 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        // The consumer still has the old version, it updates it lazily.
+        assert_eq!(consumer.capacity(), 32);
+        assert_eq!(consumer.pop_many(&mut slice), 30);
+
+        for (i, value) in slice[..30].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, 20 + i);
+        }
+
+        let (producer, consumer) =
+            new_unbounded_with_growth_policy(GrowthPolicy::new().with_shrink_after(2));
+
+        for i in 0..100 {
+            producer.maybe_push(i).unwrap();
         }
+
+        let mut slice = [MaybeUninit::uninit(); 100];
+
+        assert_eq!(consumer.pop_many(&mut slice), 100);
+
+        producer.maybe_push(100).unwrap();
+
+        assert_eq!(producer.capacity(), 128);
+
+        producer.maybe_push(101).unwrap();
+
+        assert_eq!(producer.capacity(), 4);
+        assert_eq!(consumer.pop_many(&mut slice), 2);
+        assert_eq!(unsafe { slice[0].assume_init() }, 100);
+        assert_eq!(unsafe { slice[1].assume_init() }, 101);
+    }
+
+    #[test]
+    fn test_spsc_unbounded_max_capacity() {
+        let global_queue = MutexVecQueue::new();
+        let (producer, consumer) = new_unbounded_with_growth_policy(
+            GrowthPolicy::new()
+                .with_initial_capacity(4)
+                .with_max_capacity(8),
+        );
+
+        for i in 0..8 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        assert_eq!(producer.capacity(), 8);
+        assert_eq!(producer.maybe_push(8), Err(8));
+        assert_eq!(unsafe { producer.maybe_push_many(&[8, 9]) }, Err(()));
+
+        producer.push(8, &global_queue);
+        unsafe { producer.push_many(&[9, 10], &global_queue) };
+
+        assert_eq!(producer.capacity(), 8);
+        assert_eq!(producer.len(), 8);
+        assert_eq!(global_queue.take_all(), vec![8, 9, 10]);
+
+        for i in 0..8 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_spsc_unbounded_zst() {
+        let (producer, consumer) = new_unbounded::<Zst>();
+
+        // No buffer is allocated for zero-sized values, so the queue never grows.
+        assert_eq!(producer.capacity(), GrowthPolicy::new().max_capacity());
+
+        test_zst_queue(
+            None,
+            |value| producer.maybe_push(value),
+            |dst| consumer.pop_many(dst),
+            || consumer.len(),
+            || consumer.steal_into(&new_unbounded().0),
+        );
+    }
+
+    #[test]
+    fn test_spsc_unbounded_try_reserve() {
+        let policy = GrowthPolicy::new()
+            .with_initial_capacity(4)
+            .with_max_capacity(64);
+        let (producer, consumer) = new_unbounded_with_growth_policy(policy);
+
+        assert_eq!(producer.try_reserve(65), Err(AllocError::CapacityOverflow));
+        assert_eq!(producer.capacity(), 4);
+
+        producer.reserve(20);
+
+        assert_eq!(producer.capacity(), 32);
+
+        producer.reserve(8);
+
+        assert_eq!(producer.capacity(), 32);
+        assert_eq!(producer.try_reserve(64), Ok(()));
+        assert_eq!(producer.capacity(), 64);
+
+        for i in 0..64 {
+            producer.fallible_push(i).unwrap();
+        }
+
+        assert_eq!(
+            producer.fallible_push(64),
+            Err((64, AllocError::CapacityOverflow))
+        );
+        assert_eq!(producer.len(), 64);
+
+        let mut slice = [MaybeUninit::uninit(); 64];
+
+        assert_eq!(consumer.pop_many(&mut slice), 64);
+
+        for (i, value) in slice[..64].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, i);
+        }
+    }
+
+    #[test]
+    fn test_spsc_unbounded_indexes_past_u32_max() {
+        const START: UnboundedIndex = u32::MAX as UnboundedIndex - 100;
+
+        let (producer, consumer) = new_unbounded();
+        let (producer2, consumer2) = new_unbounded();
+
+        producer.inner.head.store(START, Relaxed);
+        unsafe { producer.inner.tail_and_version.store(0, START, Relaxed) };
+        producer2.reserve(256);
+
+        // The queue grows while the indexes pass `u32::MAX`.
+        for i in 0..300 {
+            producer.maybe_push(i).unwrap();
+        }
+
+        if cfg!(feature = "wide_unbounded_index") {
+            assert!(unsafe { producer.inner.unsync_load_tail() } > UnboundedIndex::from(u32::MAX));
+        }
+
+        assert_eq!(producer.len(), 300);
+        assert_eq!(consumer.len(), 300);
+        assert_eq!(consumer.steal_into(&producer2), 150);
+
+        for i in 0..150 {
+            assert_eq!(consumer2.pop(), Some(i));
+        }
+
+        for i in 150..300 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert_eq!(consumer.pop(), None);
     }
 }
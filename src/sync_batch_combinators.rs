@@ -0,0 +1,527 @@
+//! This module provides combinators over the [`SyncBatchReceiver`].
+//!
+//! They allow composing overflow destinations:
+//!
+//! - [`Fallback`] pushes to the second receiver when the first one rejects the batch;
+//! - [`Tee`] mirrors the batch into the second receiver;
+//! - [`Map`] transforms the values before pushing them;
+//! - [`Filter`] drops the values that don't match the predicate;
+//! - [`Counting`] counts the received batches and values.
+//!
+//! All combinators preserve the order of the values:
+//! the first slice, then the last slice and finally the value (or the slice).
+//!
+//! # Example
+//!
+//! ```
+//! use parcoll::spmc::{new_bounded, Producer};
+//! use parcoll::{Counting, Fallback, MutexVecQueue, SyncBatchReceiver};
+//! use std::sync::mpsc;
+//!
+//! let (sender, receiver) = mpsc::channel();
+//! let global_queue = MutexVecQueue::new();
+//! let overflow = Counting::new(Fallback(sender, &global_queue));
+//! let (producer, _) = new_bounded::<_, 4>();
+//!
+//! for i in 0..5 {
+//!     producer.push(i, &overflow);
+//! }
+//!
+//! assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0, 1, 4]);
+//!
+//! drop(receiver);
+//!
+//! for i in 5..8 {
+//!     producer.push(i, &overflow);
+//! }
+//!
+//! // The channel is disconnected, so the batch falls back to the global queue.
+//! assert_eq!(global_queue.len(), 3);
+//! assert_eq!(overflow.batches(), 2);
+//! assert_eq!(overflow.values(), 6);
+//! ```
+use crate::loom_bindings::sync::atomic::AtomicUsize;
+use crate::sync_batch_receiver::{BatchDrain, SyncBatchReceiver};
+use std::ptr;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Pushes the `values` to the receiver as a batch split at the given lengths.
+///
+/// # Safety
+///
+/// The caller must own the values of the `values` and must not use them after the call.
+unsafe fn push_vec_split_at<T, R: SyncBatchReceiver<T>>(
+    receiver: &R,
+    mut values: Vec<T>,
+    first_len: usize,
+    last_len: usize,
+    value: Option<T>,
+) {
+    let (first, rest) = values.split_at(first_len);
+    let (last, slice) = rest.split_at(last_len);
+
+    match value {
+        Some(value) => receiver.push_many_and_one(first, last, value),
+        None => receiver.push_many_and_slice(first, last, slice),
+    }
+
+    // The values are moved to the receiver
+    unsafe { values.set_len(0) };
+}
+
+/// Pushes the batch to the first receiver
+/// and pushes it to the second one if the first one rejects it.
+///
+/// The first receiver should implement [`try_push_many_and_one`] and
+/// [`try_push_many_and_slice`], otherwise it never rejects the batch.
+/// The combinator can be chained: `Fallback(a, Fallback(b, c))`.
+///
+/// [`try_push_many_and_one`]: SyncBatchReceiver::try_push_many_and_one
+/// [`try_push_many_and_slice`]: SyncBatchReceiver::try_push_many_and_slice
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fallback<A, B>(pub A, pub B);
+
+unsafe impl<T, A: SyncBatchReceiver<T>, B: SyncBatchReceiver<T>> SyncBatchReceiver<T>
+    for Fallback<A, B>
+{
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        if let Err(value) = self.0.try_push_many_and_one(first, last, value) {
+            self.1.push_many_and_one(first, last, value);
+        }
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        if self.0.try_push_many_and_slice(first, last, slice).is_err() {
+            self.1.push_many_and_slice(first, last, slice);
+        }
+    }
+
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        match self.0.try_push_many_and_one(first, last, value) {
+            Ok(()) => Ok(()),
+            Err(value) => self.1.try_push_many_and_one(first, last, value),
+        }
+    }
+
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        self.0
+            .try_push_many_and_slice(first, last, slice)
+            .or_else(|()| self.1.try_push_many_and_slice(first, last, slice))
+    }
+}
+
+/// Pushes the batch to the first receiver and its clone to the second receiver.
+///
+/// It is useful to mirror the overflowed batches into a recorder.
+/// If the first receiver rejects the batch, the second one doesn't receive it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tee<A, B>(pub A, pub B);
+
+/// Clones the values of the slices in order.
+fn clone_slices<T: Clone>(first: &[T], last: &[T], slice: &[T]) -> Vec<T> {
+    let mut clones = Vec::with_capacity(first.len() + last.len() + slice.len());
+
+    clones.extend_from_slice(first);
+    clones.extend_from_slice(last);
+    clones.extend_from_slice(slice);
+
+    clones
+}
+
+unsafe impl<T: Clone, A: SyncBatchReceiver<T>, B: SyncBatchReceiver<T>> SyncBatchReceiver<T>
+    for Tee<A, B>
+{
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        let clones = clone_slices(first, last, &[]);
+        let cloned_value = value.clone();
+
+        self.0.push_many_and_one(first, last, value);
+
+        unsafe { push_vec_split_at(&self.1, clones, first.len(), last.len(), Some(cloned_value)) };
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        let clones = clone_slices(first, last, slice);
+
+        self.0.push_many_and_slice(first, last, slice);
+
+        unsafe { push_vec_split_at(&self.1, clones, first.len(), last.len(), None) };
+    }
+
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        let clones = clone_slices(first, last, &[]);
+        let cloned_value = value.clone();
+
+        self.0.try_push_many_and_one(first, last, value)?;
+
+        unsafe { push_vec_split_at(&self.1, clones, first.len(), last.len(), Some(cloned_value)) };
+
+        Ok(())
+    }
+
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        let clones = clone_slices(first, last, slice);
+
+        self.0.try_push_many_and_slice(first, last, slice)?;
+
+        unsafe { push_vec_split_at(&self.1, clones, first.len(), last.len(), None) };
+
+        Ok(())
+    }
+}
+
+/// Transforms the values with the closure and pushes them to the receiver.
+///
+/// It consumes the values before pushing them,
+/// so it can't return them back and never rejects the batch.
+#[derive(Debug, Clone, Copy)]
+pub struct Map<R, F>(pub R, pub F);
+
+unsafe impl<T, U, R: SyncBatchReceiver<U>, F: Fn(T) -> U> SyncBatchReceiver<T> for Map<R, F> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        let mapped = unsafe { BatchDrain::from_many_and_slice(first, last, &[]) }
+            .map(&self.1)
+            .collect();
+        let mapped_value = (self.1)(value);
+
+        unsafe { push_vec_split_at(&self.0, mapped, first.len(), last.len(), Some(mapped_value)) };
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        let mapped = unsafe { BatchDrain::from_many_and_slice(first, last, slice) }
+            .map(&self.1)
+            .collect();
+
+        unsafe { push_vec_split_at(&self.0, mapped, first.len(), last.len(), None) };
+    }
+}
+
+/// Pushes to the receiver only the values that match the predicate
+/// and drops the others.
+///
+/// If the receiver rejects the batch, no value is dropped.
+/// The predicate is called exactly once for every value.
+#[derive(Debug, Clone, Copy)]
+pub struct Filter<R, F>(pub R, pub F);
+
+impl<R, F> Filter<R, F> {
+    /// Calls the predicate for every value in order and returns the results.
+    fn matches<T>(&self, first: &[T], last: &[T], slice: &[T]) -> Vec<bool>
+    where
+        F: Fn(&T) -> bool,
+    {
+        first
+            .iter()
+            .chain(last)
+            .chain(slice)
+            .map(|value| (self.1)(value))
+            .collect()
+    }
+
+    /// Copies the values that match by the `matches` in order
+    /// without taking ownership of them.
+    fn copy_matching<T>(first: &[T], last: &[T], slice: &[T], matches: &[bool]) -> Vec<T> {
+        first
+            .iter()
+            .chain(last)
+            .chain(slice)
+            .zip(matches)
+            .filter(|(_, matches)| **matches)
+            // The copies are owned by the caller only after it takes ownership of the values
+            .map(|(value, _)| unsafe { ptr::read(value) })
+            .collect()
+    }
+
+    /// Drops the values that don't match by the `matches`.
+    ///
+    /// It doesn't call the predicate, because the matching values
+    /// can be already dropped by the receiver.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the values of the slices that don't match.
+    unsafe fn drop_not_matching<T>(first: &[T], last: &[T], slice: &[T], matches: &[bool]) {
+        for (value, matches) in first.iter().chain(last).chain(slice).zip(matches) {
+            if !matches {
+                unsafe { ptr::drop_in_place(ptr::from_ref(value).cast_mut()) };
+            }
+        }
+    }
+}
+
+unsafe impl<T, R: SyncBatchReceiver<T>, F: Fn(&T) -> bool> SyncBatchReceiver<T> for Filter<R, F> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        let kept = unsafe { BatchDrain::from_many_and_slice(first, last, &[]) }
+            .filter(&self.1)
+            .collect::<Vec<_>>();
+        let len = kept.len();
+
+        if (self.1)(&value) {
+            unsafe { push_vec_split_at(&self.0, kept, len, 0, Some(value)) };
+        } else if len > 0 {
+            unsafe { push_vec_split_at(&self.0, kept, len, 0, None) };
+        }
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        let kept = unsafe { BatchDrain::from_many_and_slice(first, last, slice) }
+            .filter(&self.1)
+            .collect::<Vec<_>>();
+        let len = kept.len();
+
+        if len > 0 {
+            unsafe { push_vec_split_at(&self.0, kept, len, 0, None) };
+        }
+    }
+
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        let matches = self.matches(first, last, &[]);
+        let mut kept = Self::copy_matching(first, last, &[], &matches);
+        let result = if (self.1)(&value) {
+            self.0.try_push_many_and_one(&kept, &[], value)
+        } else {
+            match self.0.try_push_many_and_slice(&kept, &[], &[]) {
+                Ok(()) => {
+                    drop(value);
+
+                    Ok(())
+                }
+                Err(()) => Err(value),
+            }
+        };
+
+        // The copies are either moved to the receiver or still owned by the caller
+        unsafe { kept.set_len(0) };
+
+        if result.is_ok() {
+            unsafe { Self::drop_not_matching(first, last, &[], &matches) };
+        }
+
+        result
+    }
+
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        let matches = self.matches(first, last, slice);
+        let mut kept = Self::copy_matching(first, last, slice, &matches);
+        let result = self.0.try_push_many_and_slice(&kept, &[], &[]);
+
+        // The copies are either moved to the receiver or still owned by the caller
+        unsafe { kept.set_len(0) };
+
+        if result.is_ok() {
+            unsafe { Self::drop_not_matching(first, last, slice, &matches) };
+        }
+
+        result
+    }
+}
+
+/// Counts the batches and the values accepted by the receiver.
+#[derive(Debug, Default)]
+pub struct Counting<R> {
+    inner: R,
+    batches: AtomicUsize,
+    values: AtomicUsize,
+}
+
+impl<R> Counting<R> {
+    /// Creates a new [`Counting`] over the receiver.
+    pub const fn new(inner: R) -> Self {
+        Self {
+            inner,
+            batches: AtomicUsize::new(0),
+            values: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of accepted batches.
+    pub fn batches(&self) -> usize {
+        self.batches.load(Relaxed)
+    }
+
+    /// Returns the number of accepted values.
+    pub fn values(&self) -> usize {
+        self.values.load(Relaxed)
+    }
+
+    /// Returns a reference to the inner receiver.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the inner receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Records an accepted batch of the given length.
+    fn record(&self, len: usize) {
+        self.batches.fetch_add(1, Relaxed);
+        self.values.fetch_add(len, Relaxed);
+    }
+}
+
+unsafe impl<T, R: SyncBatchReceiver<T>> SyncBatchReceiver<T> for Counting<R> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        self.inner.push_many_and_one(first, last, value);
+        self.record(first.len() + last.len() + 1);
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        self.inner.push_many_and_slice(first, last, slice);
+        self.record(first.len() + last.len() + slice.len());
+    }
+
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        self.inner.try_push_many_and_one(first, last, value)?;
+        self.record(first.len() + last.len() + 1);
+
+        Ok(())
+    }
+
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        self.inner.try_push_many_and_slice(first, last, slice)?;
+        self.record(first.len() + last.len() + slice.len());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync_batch_receiver::DiscardingReceiver;
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+
+    fn strings(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sync_batch_combinators() {
+        let (closed_sender, _) = mpsc::channel();
+        let vec_receiver = Mutex::new(Vec::new());
+        let fallback = Counting::new(Fallback(
+            closed_sender,
+            Fallback(DiscardingRejecter, &vec_receiver),
+        ));
+        let mut values = strings(0..5);
+
+        fallback.push_many_and_one(&values[..2], &values[2..], "5".to_string());
+        unsafe { values.set_len(0) }; // the values are moved
+
+        let mut values = strings(6..9);
+
+        fallback.push_many_and_slice(&values[..1], &[], &values[1..]);
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(
+            vec_receiver.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            strings(0..9)
+        );
+        assert_eq!(fallback.batches(), 2);
+        assert_eq!(fallback.values(), 9);
+
+        let recorder = Mutex::new(Vec::new());
+        let tee = Tee(&vec_receiver, &recorder);
+        let mut values = strings(0..2);
+
+        tee.push_many_and_one(&values[..1], &values[1..], "2".to_string());
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(*vec_receiver.lock().unwrap(), strings(0..3));
+        assert_eq!(*recorder.lock().unwrap(), strings(0..3));
+
+        let mapped = Mutex::new(Vec::new());
+        let map = Map(&mapped, |value: String| value.len());
+        let mut values = vec!["a".to_string(), "bb".to_string()];
+
+        map.push_many_and_slice(&[], &values[..1], &values[1..]);
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(*mapped.lock().unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_sync_batch_filter() {
+        let filtered = Mutex::new(Vec::new());
+        let filter = Filter(&filtered, |value: &String| value.len() == 1);
+        let mut values = strings(8..12);
+
+        filter.push_many_and_one(&values[..2], &values[2..], "12".to_string());
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(*filtered.lock().unwrap(), strings(8..10));
+
+        let (sender, receiver) = mpsc::channel();
+        let filter = Filter(sender, |value: &String| value.len() == 1);
+        let mut values = strings(8..12);
+
+        assert_eq!(
+            filter.try_push_many_and_one(&values[..1], &values[1..], "7".to_string()),
+            Ok(())
+        );
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["8", "9", "7"]);
+
+        drop(receiver);
+
+        let values = strings(8..12);
+
+        assert_eq!(
+            filter.try_push_many_and_slice(&values[..1], &values[1..2], &values[2..]),
+            Err(())
+        );
+        assert_eq!(
+            filter.try_push_many_and_one(&values[..1], &values[1..], "12".to_string()),
+            Err("12".to_string())
+        );
+
+        // The caller still owns the values
+        assert_eq!(values, strings(8..12));
+
+        // The receiver drops the values, so the predicate must not see them again.
+        let calls = std::cell::Cell::new(0);
+        let filter = Filter(DiscardingReceiver, |value: &String| {
+            calls.set(calls.get() + 1);
+
+            value.len() == 1
+        });
+        let mut values = strings(8..12);
+
+        assert_eq!(
+            filter.try_push_many_and_slice(&values[..1], &values[1..2], &values[2..]),
+            Ok(())
+        );
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(
+            filter.try_push_many_and_one(&[], &[], "12".to_string()),
+            Ok(())
+        );
+        assert_eq!(calls.get(), 5);
+    }
+
+    /// A receiver that rejects every batch.
+    struct DiscardingRejecter;
+
+    unsafe impl<T> SyncBatchReceiver<T> for DiscardingRejecter {
+        fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+            drop(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+        }
+
+        fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+            drop(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+        }
+
+        fn try_push_many_and_one(&self, _first: &[T], _last: &[T], value: T) -> Result<(), T> {
+            Err(value)
+        }
+
+        fn try_push_many_and_slice(&self, _: &[T], _: &[T], _: &[T]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+}
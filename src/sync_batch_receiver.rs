@@ -1,15 +1,71 @@
-//! This module provides the [`SyncBatchReceiver`] trait.
+//! This module provides the [`SyncBatchReceiver`] trait, the [`BatchDrain`]
+//! and the implementations of the trait for std types and simple receivers.
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use std::mem::{self, ManuallyDrop};
+use std::sync::mpsc::{SendError, Sender};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{ptr, slice};
 
 /// A batch receiver of the multi-consumer queue.
 /// It is used to move half of the values from the queue to this receiver on overflow.
 ///
-/// This library provides the [`MutexVecQueue`](crate::MutexVecQueue) that implements this trait.
-pub trait SyncBatchReceiver<T> {
+/// This library implements this trait for:
+///
+/// - the [`MutexVecQueue`](crate::MutexVecQueue);
+/// - `Mutex<Vec<T>>` and `Mutex<VecDeque<T>>`;
+/// - [`Sender<T>`];
+/// - not capped unbounded [`spmc`](crate::spmc) producers behind a
+///   [`LockedProducer`](crate::spmc::LockedProducer);
+/// - the [`FnSyncBatchReceiver`] that passes the [`BatchDrain`] to a closure;
+/// - the [`DiscardingReceiver`] and the [`PanickingReceiver`] that are useful for tests;
+/// - references to receivers and the combinators [`Fallback`](crate::Fallback),
+///   [`Tee`](crate::Tee), [`Map`](crate::Map), [`Filter`](crate::Filter)
+///   and [`Counting`](crate::Counting).
+///
+/// # Safety
+///
+/// The queue moves the values to the receiver by passing slices,
+/// and it forgets the values of the slices after the call.
+/// So, the implementor takes ownership of every value of the provided slices and must
+/// drop or move out each of them exactly once.
+/// Cloning or ignoring the values leads to leaks or double drops.
+/// The only exception is a rejected batch, read
+/// [`try_push_many_and_one`](Self::try_push_many_and_one).
+///
+/// The implementor that doesn't want to work with raw slices can wrap them into the
+/// [`BatchDrain`] with [`BatchDrain::from_many_and_one`] or [`BatchDrain::from_many_and_slice`]
+/// and move the values out of it safely.
+///
+/// # Example
+///
+/// ```
+/// use parcoll::{BatchDrain, SyncBatchReceiver};
+/// use std::sync::Mutex;
+///
+/// struct VecReceiver(Mutex<Vec<String>>);
+///
+/// unsafe impl SyncBatchReceiver<String> for VecReceiver {
+///     fn push_many_and_one(&self, first: &[String], last: &[String], value: String) {
+///         let batch = unsafe { BatchDrain::from_many_and_one(first, last, value) };
+///
+///         self.0.lock().unwrap().extend(batch);
+///     }
+///
+///     fn push_many_and_slice(&self, first: &[String], last: &[String], slice: &[String]) {
+///         let batch = unsafe { BatchDrain::from_many_and_slice(first, last, slice) };
+///
+///         self.0.lock().unwrap().extend(batch);
+///     }
+/// }
+/// ```
+pub unsafe trait SyncBatchReceiver<T> {
     /// Pushes a batch of values to the receiver.
     ///
     /// It first pushes the first slice, then the last slice and finally the `value`.
     ///
     /// It has such an interesting signature because it can be used in ring-based queues.
+    /// It takes ownership of the values of the slices, read the [`trait docs`](SyncBatchReceiver).
     fn push_many_and_one(&self, first: &[T], last: &[T], value: T);
 
     /// Pushes a batch of values to the receiver.
@@ -17,5 +73,513 @@ pub trait SyncBatchReceiver<T> {
     /// It first pushes the first slice, then the last slice and finally the `slice`.
     ///
     /// It has such an interesting signature because it can be used in ring-based queues.
+    /// It takes ownership of the values of the slices, read the [`trait docs`](SyncBatchReceiver).
     fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]);
+
+    /// Tries to push a batch of values to the receiver as
+    /// [`push_many_and_one`](Self::push_many_and_one) does.
+    ///
+    /// The receiver can reject the batch (for example, if it is bounded and full
+    /// or if it is shutting down), then it returns the `value` back,
+    /// and the ownership of the values of the slices stays with the caller.
+    ///
+    /// By default, it never rejects the batch.
+    #[inline]
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        self.push_many_and_one(first, last, value);
+
+        Ok(())
+    }
+
+    /// Tries to push a batch of values to the receiver as
+    /// [`push_many_and_slice`](Self::push_many_and_slice) does.
+    ///
+    /// The receiver can reject the batch (for example, if it is bounded and full
+    /// or if it is shutting down), then it returns an error,
+    /// and the ownership of the values of the slices stays with the caller.
+    ///
+    /// By default, it never rejects the batch.
+    #[inline]
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        self.push_many_and_slice(first, last, slice);
+
+        Ok(())
+    }
+}
+
+/// An owning iterator over a batch passed to the [`SyncBatchReceiver`].
+///
+/// It moves the values out of the slices in the order they should be pushed
+/// and drops the values that were not moved out.
+pub struct BatchDrain<'slices, T> {
+    first: slice::Iter<'slices, T>,
+    last: slice::Iter<'slices, T>,
+    slice: slice::Iter<'slices, T>,
+    value: Option<T>,
+}
+
+impl<'slices, T> BatchDrain<'slices, T> {
+    /// Creates a new [`BatchDrain`] from the arguments of
+    /// [`SyncBatchReceiver::push_many_and_one`].
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the values of the slices,
+    /// it is guaranteed inside [`SyncBatchReceiver::push_many_and_one`].
+    /// After the call, the values must be used only through the [`BatchDrain`].
+    #[inline]
+    pub unsafe fn from_many_and_one(first: &'slices [T], last: &'slices [T], value: T) -> Self {
+        Self {
+            first: first.iter(),
+            last: last.iter(),
+            slice: [].iter(),
+            value: Some(value),
+        }
+    }
+
+    /// Creates a new [`BatchDrain`] from the arguments of
+    /// [`SyncBatchReceiver::push_many_and_slice`].
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the values of the slices,
+    /// it is guaranteed inside [`SyncBatchReceiver::push_many_and_slice`].
+    /// After the call, the values must be used only through the [`BatchDrain`].
+    #[inline]
+    pub unsafe fn from_many_and_slice(
+        first: &'slices [T],
+        last: &'slices [T],
+        slice: &'slices [T],
+    ) -> Self {
+        Self {
+            first: first.iter(),
+            last: last.iter(),
+            slice: slice.iter(),
+            value: None,
+        }
+    }
+
+    /// Returns the remaining values without moving them out.
+    ///
+    /// The values are returned as three slices and an optional value,
+    /// they should be pushed in this order.
+    #[inline]
+    pub fn as_slices(&self) -> (&[T], &[T], &[T], Option<&T>) {
+        (
+            self.first.as_slice(),
+            self.last.as_slice(),
+            self.slice.as_slice(),
+            self.value.as_ref(),
+        )
+    }
+
+    /// Returns the remaining values as three slices and an optional value
+    /// and passes ownership of them to the caller.
+    ///
+    /// It allows copying the values in bulk.
+    /// The values of the slices are leaked unless the caller moves them out.
+    #[inline]
+    pub fn into_raw_parts(self) -> (&'slices [T], &'slices [T], &'slices [T], Option<T>) {
+        let mut this = ManuallyDrop::new(self);
+
+        (
+            this.first.as_slice(),
+            this.last.as_slice(),
+            this.slice.as_slice(),
+            this.value.take(),
+        )
+    }
+}
+
+impl<T> Iterator for BatchDrain<'_, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.first
+            .next()
+            .or_else(|| self.last.next())
+            .or_else(|| self.slice.next())
+            // The values are owned by the `BatchDrain` and each of them is read only once
+            .map(|value| unsafe { ptr::read(value) })
+            .or_else(|| self.value.take())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.first.len()
+            + self.last.len()
+            + self.slice.len()
+            + usize::from(self.value.is_some());
+
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for BatchDrain<'_, T> {}
+
+impl<T> FusedIterator for BatchDrain<'_, T> {}
+
+impl<T> Drop for BatchDrain<'_, T> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// Locks the mutex ignoring the poisoning,
+/// because the receivers never leave the collection in an inconsistent state.
+pub(crate) fn lock_ignoring_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+unsafe impl<T> SyncBatchReceiver<T> for Mutex<Vec<T>> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        lock_ignoring_poison(self)
+            .extend(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        lock_ignoring_poison(self)
+            .extend(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+    }
+}
+
+unsafe impl<T> SyncBatchReceiver<T> for Mutex<VecDeque<T>> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        lock_ignoring_poison(self)
+            .extend(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        lock_ignoring_poison(self)
+            .extend(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+    }
+}
+
+/// Tries to send the values of the `batch` to the `sender`.
+///
+/// If the first value can't be sent because the receiver is disconnected,
+/// it rejects the batch and returns its `value` back (if any).
+/// If the receiver is disconnected after the first value is sent,
+/// the rest values are dropped as the channel drops its values.
+fn try_send_batch<T>(sender: &Sender<T>, mut batch: BatchDrain<'_, T>) -> Result<(), Option<T>> {
+    let (first, last, slice, _) = batch.as_slices();
+    let is_first_value_from_slices = !(first.is_empty() && last.is_empty() && slice.is_empty());
+
+    let Some(first_value) = batch.next() else {
+        return Ok(());
+    };
+
+    if let Err(SendError(first_value)) = sender.send(first_value) {
+        // The caller keeps ownership of the rest values of the slices.
+        let (_, _, _, value) = batch.into_raw_parts();
+
+        if is_first_value_from_slices {
+            // And of this value too.
+            mem::forget(first_value);
+
+            return Err(value);
+        }
+
+        return Err(Some(first_value));
+    }
+
+    for value in batch {
+        if sender.send(value).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+unsafe impl<T> SyncBatchReceiver<T> for Sender<T> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        for value in unsafe { BatchDrain::from_many_and_one(first, last, value) } {
+            // If the receiver is disconnected, the value is dropped as the channel does
+            let _ = self.send(value);
+        }
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        for value in unsafe { BatchDrain::from_many_and_slice(first, last, slice) } {
+            // If the receiver is disconnected, the value is dropped as the channel does
+            let _ = self.send(value);
+        }
+    }
+
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        try_send_batch(self, unsafe {
+            BatchDrain::from_many_and_one(first, last, value)
+        })
+        .map_err(|value| value.expect("the value of the batch is always returned"))
+    }
+
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        try_send_batch(self, unsafe {
+            BatchDrain::from_many_and_slice(first, last, slice)
+        })
+        .map_err(|_| ())
+    }
+}
+
+/// A [`SyncBatchReceiver`] that passes the [`BatchDrain`] to the closure.
+///
+/// # Example
+///
+/// ```
+/// use parcoll::{BatchDrain, FnSyncBatchReceiver};
+/// use parcoll::spmc::{new_bounded, Producer};
+/// use std::cell::RefCell;
+///
+/// let values = RefCell::new(Vec::new());
+/// let receiver = FnSyncBatchReceiver(|batch: BatchDrain<'_, i32>| {
+///     values.borrow_mut().extend(batch);
+/// });
+/// let (producer, _) = new_bounded::<_, 4>();
+///
+/// for i in 0..5 {
+///     producer.push(i, &receiver);
+/// }
+///
+/// assert_eq!(*values.borrow(), [0, 1, 4]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct FnSyncBatchReceiver<F>(pub F);
+
+unsafe impl<T, F: Fn(BatchDrain<'_, T>)> SyncBatchReceiver<T> for FnSyncBatchReceiver<F> {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        (self.0)(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        (self.0)(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+    }
+}
+
+/// A [`SyncBatchReceiver`] that drops all received values.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiscardingReceiver;
+
+unsafe impl<T> SyncBatchReceiver<T> for DiscardingReceiver {
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        drop(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+    }
+
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        drop(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+    }
+}
+
+/// A [`SyncBatchReceiver`] that panics when it receives values.
+///
+/// It is useful in tests to check that the queue never overflows.
+/// It drops the received values before it panics, so they don't leak.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PanickingReceiver;
+
+unsafe impl<T> SyncBatchReceiver<T> for PanickingReceiver {
+    #[track_caller]
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        let len = first.len() + last.len() + 1;
+
+        drop(unsafe { BatchDrain::from_many_and_one(first, last, value) });
+
+        panic!("PanickingReceiver received a batch of {len} values");
+    }
+
+    #[track_caller]
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        let len = first.len() + last.len() + slice.len();
+
+        drop(unsafe { BatchDrain::from_many_and_slice(first, last, slice) });
+
+        panic!("PanickingReceiver received a batch of {len} values");
+    }
+}
+
+unsafe impl<T, R: SyncBatchReceiver<T> + ?Sized> SyncBatchReceiver<T> for &R {
+    #[inline]
+    fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+        (**self).push_many_and_one(first, last, value);
+    }
+
+    #[inline]
+    fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+        (**self).push_many_and_slice(first, last, slice);
+    }
+
+    #[inline]
+    fn try_push_many_and_one(&self, first: &[T], last: &[T], value: T) -> Result<(), T> {
+        (**self).try_push_many_and_one(first, last, value)
+    }
+
+    #[inline]
+    fn try_push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) -> Result<(), ()> {
+        (**self).try_push_many_and_slice(first, last, slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::mem::ManuallyDrop;
+    use std::panic::AssertUnwindSafe;
+    use std::rc::Rc;
+
+    struct VecReceiver<T>(RefCell<Vec<T>>);
+
+    unsafe impl<T> SyncBatchReceiver<T> for VecReceiver<T> {
+        fn push_many_and_one(&self, first: &[T], last: &[T], value: T) {
+            let batch = unsafe { BatchDrain::from_many_and_one(first, last, value) };
+
+            self.0.borrow_mut().extend(batch);
+        }
+
+        fn push_many_and_slice(&self, first: &[T], last: &[T], slice: &[T]) {
+            let batch = unsafe { BatchDrain::from_many_and_slice(first, last, slice) };
+
+            self.0.borrow_mut().extend(batch);
+        }
+    }
+
+    #[test]
+    fn test_batch_drain() {
+        let receiver = VecReceiver(RefCell::new(Vec::new()));
+        let mut values = (0..6).map(|i| i.to_string()).collect::<Vec<_>>();
+        let one = "6".to_string();
+
+        receiver.push_many_and_one(&values[..2], &values[2..], one);
+        unsafe { values.set_len(0) }; // the values are moved
+
+        let mut values = (7..13).map(|i| i.to_string()).collect::<Vec<_>>();
+
+        receiver.push_many_and_slice(&values[..1], &values[1..3], &values[3..]);
+        unsafe { values.set_len(0) }; // the values are moved
+
+        assert_eq!(
+            receiver.0.take(),
+            (0..13).map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+
+        // Not moved out values are dropped
+
+        let mut values = (0..3).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut batch =
+            unsafe { BatchDrain::from_many_and_one(&values[..1], &values[1..], "3".to_string()) };
+
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch.next().as_deref(), Some("0"));
+        assert_eq!(batch.as_slices().1, ["1", "2"]);
+
+        drop(batch);
+        unsafe { values.set_len(0) }; // the values are moved
+    }
+
+    #[test]
+    fn test_std_receivers() {
+        let vec_receiver = Mutex::new(Vec::new());
+        let deque_receiver = Mutex::new(VecDeque::new());
+        let (sender, channel_receiver) = std::sync::mpsc::channel();
+
+        for i in 0..3 {
+            vec_receiver.push_many_and_one(&[], &[], i);
+            deque_receiver.push_many_and_one(&[], &[], i);
+            sender.push_many_and_one(&[], &[], i);
+        }
+
+        assert_eq!(vec_receiver.into_inner().unwrap(), [0, 1, 2]);
+        assert_eq!(deque_receiver.into_inner().unwrap(), [0, 1, 2]);
+        assert_eq!(channel_receiver.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+
+        drop(channel_receiver);
+
+        let (sender, channel_receiver) = std::sync::mpsc::channel::<String>();
+
+        drop(channel_receiver);
+
+        let mut values = (0..3).map(|i| i.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            sender.try_push_many_and_one(&[], &[], "3".to_string()),
+            Err("3".to_string())
+        );
+        assert_eq!(
+            sender.try_push_many_and_one(&values[..1], &values[1..], "3".to_string()),
+            Err("3".to_string())
+        );
+        assert_eq!(
+            sender.try_push_many_and_slice(&values[..1], &values[1..2], &values[2..]),
+            Err(())
+        );
+
+        // The caller still owns the values
+        assert_eq!(values, ["0", "1", "2"]);
+
+        sender.push_many_and_slice(&values[..1], &values[1..2], &values[2..]);
+        unsafe { values.set_len(0) }; // the values are moved
+    }
+
+    #[test]
+    fn test_helper_receivers() {
+        let received = RefCell::new(Vec::new());
+        let fn_receiver = FnSyncBatchReceiver(|batch: BatchDrain<'_, usize>| {
+            received.borrow_mut().extend(batch);
+        });
+
+        fn_receiver.push_many_and_one(&[0], &[1], 2);
+        fn_receiver.push_many_and_slice(&[3], &[], &[4, 5]);
+
+        assert_eq!(received.take(), [0, 1, 2, 3, 4, 5]);
+
+        let mut values = (0..3).map(|i| i.to_string()).collect::<Vec<_>>();
+
+        DiscardingReceiver.push_many_and_one(&values[..1], &values[1..], "3".to_string());
+        unsafe { values.set_len(0) }; // the values are moved
+
+        let result = std::panic::catch_unwind(|| {
+            PanickingReceiver.push_many_and_one(&[0], &[1], 2);
+        });
+
+        assert!(result.is_err());
+
+        // The panicking receiver drops the values of the batch.
+        let value = Rc::new(());
+        let batch = ManuallyDrop::new([value.clone(), value.clone(), value.clone()]);
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            PanickingReceiver.push_many_and_one(&batch[..1], &batch[1..2], value.clone());
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&value), 2);
+
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            PanickingReceiver.push_many_and_slice(&[], &[], &batch[2..]);
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable_unbounded"))]
+    fn test_locked_unbounded_producer_receiver() {
+        use crate::spmc::{new_unbounded, Consumer};
+        use std::mem::MaybeUninit;
+
+        let (global_producer, global_consumer) = new_unbounded();
+        let global_queue = global_producer.into_locked().ok().unwrap();
+
+        global_queue.push_many_and_one(&[0, 1], &[2], 3);
+        global_queue.push_many_and_slice(&[4], &[5], &[6, 7]);
+
+        let mut values = [MaybeUninit::uninit(); 10];
+
+        assert_eq!(global_consumer.pop_many(&mut values), 8);
+
+        for (i, value) in values[..8].iter().enumerate() {
+            assert_eq!(unsafe { value.assume_init() }, i);
+        }
+    }
 }
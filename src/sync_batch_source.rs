@@ -0,0 +1,107 @@
+//! This module provides the [`SyncBatchSource`] trait.
+use crate::spmc::{reserve_slots_up_to, Consumer, Producer};
+
+/// A batch source of the multi-producer queue.
+/// It is used to refill the local queue from the global queue.
+///
+/// It is the pull-side counterpart of the [`SyncBatchReceiver`](crate::SyncBatchReceiver).
+///
+/// This library implements this trait for the [`MutexVecQueue`](crate::MutexVecQueue)
+/// and for the consumers of all [`spmc`](crate::spmc) queues.
+pub trait SyncBatchSource<T> {
+    /// Moves up to `limit` values from the source to the `producer`.
+    /// Returns the number of moved values.
+    ///
+    /// It moves no more than [`producer.free_slots()`](Producer::free_slots) values
+    /// and appends them after the values that are already in the `producer`.
+    fn move_batch_to<P: Producer<T>>(&self, producer: &P, limit: usize) -> usize;
+}
+
+/// Moves up to `limit` values from the `consumer` to the `producer`.
+/// It is used to implement the [`SyncBatchSource`] for the [`spmc::Consumer`](Consumer).
+pub(crate) fn move_batch_from_consumer<T, C: Consumer<T>, P: Producer<T>>(
+    consumer: &C,
+    producer: &P,
+    limit: usize,
+) -> usize {
+    // The reserved slots are used before any other method of the `producer` is called.
+    let (first, last, limit) = unsafe { reserve_slots_up_to(producer, limit) };
+    let in_first = limit.min(first.len());
+    let mut n = consumer.pop_many(&mut first[..in_first]);
+
+    if n == in_first && limit > in_first {
+        n += consumer.pop_many(&mut last[..limit - in_first]);
+    }
+
+    unsafe { producer.commit_reserved_slots(n) };
+
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutex_vec_queue::MutexVecQueue;
+    use crate::spmc::new_bounded;
+    #[cfg(not(feature = "disable_unbounded"))]
+    use crate::spmc::new_unbounded;
+
+    fn refill<S: SyncBatchSource<usize>>(source: &S, expected: &[usize]) {
+        let (producer, _) = new_bounded::<_, 16>();
+
+        for i in 0..10 {
+            producer.push(100 + i, &MutexVecQueue::new());
+        }
+
+        for i in 0..10 {
+            assert_eq!(producer.pop(), Some(100 + i));
+        }
+
+        // The free slots wrap around the end of the buffer.
+        assert_eq!(source.move_batch_to(&producer, 8), 8);
+        assert_eq!(source.move_batch_to(&producer, 100), expected.len() - 8);
+
+        for &value in expected {
+            assert_eq!(producer.pop(), Some(value));
+        }
+
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn test_sync_batch_source() {
+        let global_queue = MutexVecQueue::new();
+
+        for i in 0..20 {
+            global_queue.push(i);
+        }
+
+        refill(&global_queue, &(0..16).collect::<Vec<_>>());
+
+        assert_eq!(global_queue.len(), 4);
+
+        let (bounded_producer, bounded_consumer) = new_bounded::<_, 32>();
+
+        for i in 0..12 {
+            bounded_producer.push(i, &global_queue);
+        }
+
+        refill(&bounded_consumer, &(0..12).collect::<Vec<_>>());
+
+        assert!(bounded_producer.is_empty());
+
+        #[cfg(not(feature = "disable_unbounded"))]
+        {
+            let (unbounded_producer, unbounded_consumer) = new_unbounded();
+
+            for i in 0..20 {
+                unbounded_producer.push(i, &global_queue);
+            }
+
+            refill(&unbounded_consumer, &(0..16).collect::<Vec<_>>());
+
+            assert_eq!(unbounded_producer.len(), 4);
+            assert_eq!(global_queue.len(), 4);
+        }
+    }
+}
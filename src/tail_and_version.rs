@@ -0,0 +1,165 @@
+//! This module provides [`TailAndVersion`] of unbounded queues.
+//!
+//! By default, the tail is an `u32` and it is packed with the version id
+//! into a single [`AtomicU64`], so they are loaded and stored in one atomic operation.
+//!
+//! With the `wide_unbounded_index` feature, the tail is an `u64`,
+//! and the version id is published separately before the tail.
+#![allow(
+    clippy::cast_possible_truncation,
+    reason = "The tail and the version are packed into a single 64-bit value"
+)]
+#[cfg(feature = "wide_unbounded_index")]
+use crate::loom_bindings::sync::atomic::AtomicU32;
+use crate::loom_bindings::sync::atomic::AtomicU64;
+use crate::number_types::UnboundedIndex;
+use std::ops::Deref;
+use std::sync::atomic::Ordering;
+#[cfg(feature = "wide_unbounded_index")]
+use std::sync::atomic::Ordering::{Acquire, Release, SeqCst};
+
+/// Packs the version and the tail into a single 64-bit value.
+#[cfg(not(feature = "wide_unbounded_index"))]
+#[inline(always)]
+fn pack_version_and_tail(version: u32, tail: UnboundedIndex) -> u64 {
+    (u64::from(version) << 32) | u64::from(tail)
+}
+
+/// Unpacks the version and the tail from a single 64-bit value.
+#[cfg(not(feature = "wide_unbounded_index"))]
+#[inline(always)]
+fn unpack_version_and_tail(value: u64) -> (u32, UnboundedIndex) {
+    ((value >> 32) as u32, value as UnboundedIndex)
+}
+
+/// The tail of an unbounded queue and the id of the version it belongs to.
+///
+/// Only the producer can store it.
+/// Consumers always load the tail with the version that holds the values before it.
+pub(crate) struct TailAndVersion<AtomicU64Wrapper>
+where
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    #[cfg(not(feature = "wide_unbounded_index"))]
+    tail_and_version: AtomicU64Wrapper,
+    #[cfg(feature = "wide_unbounded_index")]
+    tail: AtomicU64Wrapper,
+    #[cfg(feature = "wide_unbounded_index")]
+    version: AtomicU32,
+}
+
+#[cfg(not(feature = "wide_unbounded_index"))]
+impl<AtomicU64Wrapper> TailAndVersion<AtomicU64Wrapper>
+where
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    /// Loads the tail without synchronization.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    pub(crate) unsafe fn unsync_load_tail(&self) -> UnboundedIndex {
+        unsafe { self.tail_and_version.unsync_load() as UnboundedIndex }
+    }
+
+    /// Loads the version id and the tail.
+    #[inline(always)]
+    pub(crate) fn load(&self, ordering: Ordering) -> (u32, UnboundedIndex) {
+        unpack_version_and_tail(self.tail_and_version.load(ordering))
+    }
+
+    /// Loads the version id.
+    #[inline(always)]
+    pub(crate) fn load_version(&self, ordering: Ordering) -> u32 {
+        (self.tail_and_version.load(ordering) >> 32) as u32
+    }
+
+    /// Stores the version id and the tail.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    pub(crate) unsafe fn store(&self, version: u32, tail: UnboundedIndex, ordering: Ordering) {
+        self.tail_and_version
+            .store(pack_version_and_tail(version, tail), ordering);
+    }
+}
+
+#[cfg(feature = "wide_unbounded_index")]
+impl<AtomicU64Wrapper> TailAndVersion<AtomicU64Wrapper>
+where
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    /// Loads the tail without synchronization.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    pub(crate) unsafe fn unsync_load_tail(&self) -> UnboundedIndex {
+        unsafe { self.tail.unsync_load() }
+    }
+
+    /// Loads the version id and the tail.
+    ///
+    /// It retries if the version has been changed while it loads the tail.
+    #[inline(always)]
+    pub(crate) fn load(&self, ordering: Ordering) -> (u32, UnboundedIndex) {
+        // `SeqCst` is kept for the first load because consumers pin themselves before it.
+        let version_ordering = if ordering == SeqCst { SeqCst } else { Acquire };
+        let mut version = self.version.load(version_ordering);
+
+        loop {
+            let tail = self.tail.load(Acquire);
+            let current_version = self.version.load(Acquire);
+
+            // The producer stores the new version before the tail that belongs to it.
+            // So if the version is not changed, the loaded tail is not after the values
+            // of this version, and the values before the tail are in it.
+            if current_version == version {
+                return (version, tail);
+            }
+
+            version = current_version;
+        }
+    }
+
+    /// Loads the version id.
+    #[inline(always)]
+    pub(crate) fn load_version(&self, ordering: Ordering) -> u32 {
+        self.version.load(ordering)
+    }
+
+    /// Stores the version id and the tail.
+    ///
+    /// # Safety
+    ///
+    /// It is called only by the producer.
+    #[inline(always)]
+    pub(crate) unsafe fn store(&self, version: u32, tail: UnboundedIndex, ordering: Ordering) {
+        // only producer can change version
+        if unsafe { self.version.unsync_load() } != version {
+            self.version.store(version, Release);
+        }
+
+        self.tail.store(tail, ordering);
+    }
+}
+
+impl<AtomicU64Wrapper> Default for TailAndVersion<AtomicU64Wrapper>
+where
+    AtomicU64Wrapper: Deref<Target = AtomicU64> + Default,
+{
+    fn default() -> Self {
+        Self {
+            #[cfg(not(feature = "wide_unbounded_index"))]
+            tail_and_version: AtomicU64Wrapper::default(),
+            #[cfg(feature = "wide_unbounded_index")]
+            tail: AtomicU64Wrapper::default(),
+            #[cfg(feature = "wide_unbounded_index")]
+            version: AtomicU32::new(0),
+        }
+    }
+}
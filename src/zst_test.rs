@@ -0,0 +1,86 @@
+//! This module contains the test of queues of zero-sized values.
+use std::cell::Cell;
+use std::mem::MaybeUninit;
+
+/// How many values are pushed to an unbounded queue.
+const N: usize = 1000;
+/// How many values are popped at once.
+const BATCH_SIZE: usize = 10;
+
+thread_local! {
+    /// How many [`Zst`] values have been dropped on this thread.
+    static DROPS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A zero-sized value that counts its drops.
+#[derive(Debug)]
+pub(crate) struct Zst;
+
+impl Drop for Zst {
+    fn drop(&mut self) {
+        DROPS.set(DROPS.get() + 1);
+    }
+}
+
+/// Tests a queue of zero-sized values: it moves only its counters,
+/// and each value is dropped exactly once.
+///
+/// - `capacity` is the capacity of a bounded queue or `None` for an unbounded one.
+/// - `push` pushes a value or returns it if the queue is full.
+/// - `pop_many` pops values to the slice and returns their number.
+/// - `len` returns the length of the queue.
+/// - `steal` moves some values from the queue to another one, drops it
+///   and returns the number of moved values.
+pub(crate) fn test_zst_queue(
+    capacity: Option<usize>,
+    push: impl Fn(Zst) -> Result<(), Zst>,
+    pop_many: impl Fn(&mut [MaybeUninit<Zst>]) -> usize,
+    len: impl Fn() -> usize,
+    steal: impl FnOnce() -> usize,
+) {
+    let n = capacity.unwrap_or(N);
+
+    DROPS.set(0);
+
+    for _ in 0..n {
+        push(Zst).unwrap();
+    }
+
+    assert_eq!(len(), n);
+
+    if capacity.is_some() {
+        drop(push(Zst).unwrap_err());
+
+        assert_eq!(DROPS.get(), 1);
+        assert_eq!(len(), n);
+
+        DROPS.set(0);
+    }
+
+    let stolen = steal();
+
+    assert!(stolen > 0 && stolen < n);
+    assert_eq!(DROPS.get(), stolen);
+    assert_eq!(len(), n - stolen);
+
+    let mut slice = [const { MaybeUninit::uninit() }; BATCH_SIZE];
+    let mut popped = 0;
+
+    loop {
+        let batch = pop_many(&mut slice);
+
+        if batch == 0 {
+            break;
+        }
+
+        for value in &mut slice[..batch] {
+            unsafe { value.assume_init_drop() };
+        }
+
+        popped += batch;
+    }
+
+    assert_eq!(popped, n - stolen);
+    assert_eq!(DROPS.get(), n);
+    assert_eq!(len(), 0);
+}